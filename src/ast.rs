@@ -1,4 +1,4 @@
-use crate::operations::Operation;
+use crate::operations::{Cpu, Operation};
 use std::collections::LinkedList;
 use std::str::FromStr;
 
@@ -7,6 +7,20 @@ pub type Span = std::ops::Range<usize>;
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Label(pub Span, pub String);
 
+impl Label {
+    /// Local labels start with a '.' and are scoped to the previous non-local label.
+    #[inline]
+    pub fn is_local(&self) -> bool {
+        self.1.starts_with('.')
+    }
+
+    /// Anonymous labels (`@@`) are referenced with `@b` (previous) or `@f` (next).
+    #[inline]
+    pub fn is_anonymous(&self) -> bool {
+        self.1 == "@@"
+    }
+}
+
 impl std::fmt::Display for Label {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.1)
@@ -121,10 +135,67 @@ impl WordRegister {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DWordRegister {
+    Eax = 0,
+    Ecx = 1,
+    Edx = 2,
+    Ebx = 3,
+    Esp = 4,
+    Ebp = 5,
+    Esi = 6,
+    Edi = 7,
+}
+
+impl std::fmt::Display for DWordRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DWordRegister::Eax => write!(f, "EAX"),
+            DWordRegister::Ecx => write!(f, "ECX"),
+            DWordRegister::Edx => write!(f, "EDX"),
+            DWordRegister::Ebx => write!(f, "EBX"),
+            DWordRegister::Esp => write!(f, "ESP"),
+            DWordRegister::Ebp => write!(f, "EBP"),
+            DWordRegister::Esi => write!(f, "ESI"),
+            DWordRegister::Edi => write!(f, "EDI"),
+        }
+    }
+}
+
+impl FromStr for DWordRegister {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "eax" => Self::Eax,
+            "ecx" => Self::Ecx,
+            "edx" => Self::Edx,
+            "ebx" => Self::Ebx,
+            "esp" => Self::Esp,
+            "ebp" => Self::Ebp,
+            "esi" => Self::Esi,
+            "edi" => Self::Edi,
+
+            _ => return Err(()),
+        })
+    }
+}
+
+impl DWordRegister {
+    #[inline]
+    pub fn encoding(&self) -> u8 {
+        *self as u8
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Register {
     Byte(ByteRegister),
     Word(WordRegister),
+    DWord(DWordRegister),
+    /// A register on the 8087 stack, ST0 to ST7.
+    St(u8),
 }
 
 impl Register {
@@ -132,6 +203,8 @@ impl Register {
         match self {
             Register::Byte(_) => DataSize::Byte,
             Register::Word(_) => DataSize::Word,
+            Register::DWord(_) => DataSize::DWord,
+            Register::St(_) => DataSize::TWord,
         }
     }
 }
@@ -141,6 +214,8 @@ impl std::fmt::Display for Register {
         match self {
             Register::Byte(byte) => write!(f, "{}", byte),
             Register::Word(word) => write!(f, "{}", word),
+            Register::DWord(dword) => write!(f, "{}", dword),
+            Register::St(index) => write!(f, "ST{}", index),
         }
     }
 }
@@ -153,8 +228,17 @@ impl FromStr for Register {
             Ok(Register::Byte(byte_register))
         } else if let Ok(word_register) = WordRegister::from_str(s) {
             Ok(Register::Word(word_register))
+        } else if let Ok(dword_register) = DWordRegister::from_str(s) {
+            Ok(Register::DWord(dword_register))
         } else {
-            Err(())
+            // "st" on its own is the top of the stack, same as "st0".
+            match s.to_lowercase().as_str() {
+                "st" => Ok(Register::St(0)),
+                s => match s.strip_prefix("st").map(|index| index.parse::<u8>()) {
+                    Some(Ok(index)) if index < 8 => Ok(Register::St(index)),
+                    _ => Err(()),
+                },
+            }
         }
     }
 }
@@ -165,6 +249,8 @@ impl Register {
         match self {
             Register::Byte(r) => r.encoding(),
             Register::Word(r) => r.encoding(),
+            Register::DWord(r) => r.encoding(),
+            Register::St(index) => *index,
         }
     }
 }
@@ -176,6 +262,8 @@ pub enum Segment {
     CS = 1,
     SS = 2,
     DS = 3,
+    FS = 4,
+    GS = 5,
 }
 
 impl std::fmt::Display for Segment {
@@ -185,6 +273,8 @@ impl std::fmt::Display for Segment {
             Segment::CS => write!(f, "CS"),
             Segment::SS => write!(f, "SS"),
             Segment::DS => write!(f, "DS"),
+            Segment::FS => write!(f, "FS"),
+            Segment::GS => write!(f, "GS"),
         }
     }
 }
@@ -198,6 +288,8 @@ impl FromStr for Segment {
             "cs" => Self::CS,
             "ss" => Self::SS,
             "ds" => Self::DS,
+            "fs" => Self::FS,
+            "gs" => Self::GS,
             _ => return Err(()),
         })
     }
@@ -215,6 +307,9 @@ impl Segment {
 pub enum DataSize {
     Byte,
     Word,
+    DWord,
+    QWord,
+    TWord,
 }
 
 impl std::fmt::Display for DataSize {
@@ -222,6 +317,22 @@ impl std::fmt::Display for DataSize {
         match self {
             DataSize::Byte => write!(f, "BYTE"),
             DataSize::Word => write!(f, "WORD"),
+            DataSize::DWord => write!(f, "DWORD"),
+            DataSize::QWord => write!(f, "QWORD"),
+            DataSize::TWord => write!(f, "TWORD"),
+        }
+    }
+}
+
+impl DataSize {
+    #[inline]
+    pub fn size_in_bytes(&self) -> u16 {
+        match self {
+            DataSize::Byte => 1,
+            DataSize::Word => 2,
+            DataSize::DWord => 4,
+            DataSize::QWord => 8,
+            DataSize::TWord => 10,
         }
     }
 }
@@ -233,6 +344,9 @@ impl FromStr for DataSize {
         Ok(match s.to_lowercase().as_str() {
             "byte" => Self::Byte,
             "word" => Self::Word,
+            "dword" => Self::DWord,
+            "qword" => Self::QWord,
+            "tword" => Self::TWord,
             _ => return Err(()),
         })
     }
@@ -273,9 +387,33 @@ impl std::fmt::Display for IndirectEncoding {
     }
 }
 
+/// A 32-bit memory address of the form [base + index * scale].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Address32 {
+    pub base: Option<DWordRegister>,
+    pub index: Option<(DWordRegister, u8)>,
+}
+
+impl std::fmt::Display for Address32 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(base) = &self.base {
+            write!(f, "{}", base)?;
+            if self.index.is_some() {
+                write!(f, " + ")?;
+            }
+        }
+
+        match self.index {
+            Some((index, 1)) => write!(f, "{}", index),
+            Some((index, scale)) => write!(f, "{} * {}", index, scale),
+            None => Ok(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Value {
-    Constant(i32),
+    Constant(i64),
     Label(Label),
 }
 
@@ -294,6 +432,31 @@ pub enum Operator {
     Subtract,
     Multiply,
     Divide,
+    SignedDivide,
+    Modulo,
+    SignedModulo,
+    ShiftLeft,
+    ShiftRight,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseNot,
+    LogicalAnd,
+    LogicalOr,
+    LogicalXor,
+    LogicalNot,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    Segment,
+    WithRespectTo,
+    Low,
+    High,
+    SizeOf,
+    LengthOf,
 }
 
 impl std::fmt::Display for Operator {
@@ -303,6 +466,31 @@ impl std::fmt::Display for Operator {
             Operator::Subtract => write!(f, "-"),
             Operator::Multiply => write!(f, "*"),
             Operator::Divide => write!(f, "/"),
+            Operator::SignedDivide => write!(f, "//"),
+            Operator::Modulo => write!(f, "%"),
+            Operator::SignedModulo => write!(f, "%%"),
+            Operator::ShiftLeft => write!(f, "<<"),
+            Operator::ShiftRight => write!(f, ">>"),
+            Operator::BitwiseAnd => write!(f, "&"),
+            Operator::BitwiseOr => write!(f, "|"),
+            Operator::BitwiseXor => write!(f, "^"),
+            Operator::BitwiseNot => write!(f, "~"),
+            Operator::LogicalAnd => write!(f, "&&"),
+            Operator::LogicalOr => write!(f, "||"),
+            Operator::LogicalXor => write!(f, "^^"),
+            Operator::LogicalNot => write!(f, "!"),
+            Operator::Equal => write!(f, "=="),
+            Operator::NotEqual => write!(f, "!="),
+            Operator::LessThan => write!(f, "<"),
+            Operator::LessThanOrEqual => write!(f, "<="),
+            Operator::GreaterThan => write!(f, ">"),
+            Operator::GreaterThanOrEqual => write!(f, ">="),
+            Operator::Segment => write!(f, "seg"),
+            Operator::WithRespectTo => write!(f, "wrt"),
+            Operator::Low => write!(f, "low"),
+            Operator::High => write!(f, "high"),
+            Operator::SizeOf => write!(f, "sizeof"),
+            Operator::LengthOf => write!(f, "lengthof"),
         }
     }
 }
@@ -349,7 +537,9 @@ impl<'a> IterValues<'a> {
 
         loop {
             match current {
-                Expression::PrefixOperator(..) => todo!(),
+                Expression::PrefixOperator(_, _, right) => {
+                    current = right;
+                }
                 Expression::InfixOperator(_, _, left, _) => {
                     self.stack.push_back(current);
                     current = left;
@@ -369,7 +559,7 @@ impl<'a> Iterator for IterValues<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(current) = self.stack.pop_back() {
             match current {
-                Expression::PrefixOperator(_, _, _) => todo!(),
+                Expression::PrefixOperator(_, _, _) => unreachable!(),
                 Expression::InfixOperator(_, _, _, right) => {
                     self.push_all_left(right);
                     self.next()
@@ -398,7 +588,7 @@ impl<'a> std::fmt::Display for Expression {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Operand {
-    Immediate(Span, Expression),
+    Immediate(Span, Expression, Option<DataSize>),
     Direct(Span, Expression, Option<DataSize>, Option<Segment>),
     Indirect(
         Span,
@@ -407,6 +597,13 @@ pub enum Operand {
         Option<DataSize>,
         Option<Segment>,
     ),
+    Indirect32(
+        Span,
+        Address32,
+        Option<Expression>,
+        Option<DataSize>,
+        Option<Segment>,
+    ),
     Far(Span, Expression, Expression),
     Register(Span, Register),
     Segment(Span, Segment),
@@ -415,9 +612,10 @@ pub enum Operand {
 impl Operand {
     pub fn span(&self) -> &Span {
         match self {
-            Self::Immediate(span, _)
+            Self::Immediate(span, _, _)
             | Self::Direct(span, _, _, _)
             | Self::Indirect(span, _, _, _, _)
+            | Self::Indirect32(span, _, _, _, _)
             | Self::Far(span, _, _)
             | Self::Register(span, _)
             | Self::Segment(span, _) => span,
@@ -426,20 +624,49 @@ impl Operand {
 
     pub fn data_size(&self) -> Option<DataSize> {
         match self {
-            Operand::Immediate(_, _) => None,
+            Operand::Immediate(_, _, data_size) => *data_size,
             Operand::Direct(_, _, data_size, _) => *data_size,
             Operand::Indirect(_, _, _, data_size, _) => *data_size,
+            Operand::Indirect32(_, _, _, data_size, _) => *data_size,
             Operand::Far(_, _, _) => None,
             Operand::Register(_, register) => Some(register.data_size()),
             Operand::Segment(_, _) => Some(DataSize::Word),
         }
     }
+
+    /// The segment override of an operand that addresses memory.  Returns [None] for all other
+    /// operands.
+    pub fn segment_override_mut(&mut self) -> Option<&mut Option<Segment>> {
+        match self {
+            Operand::Direct(_, _, _, segment)
+            | Operand::Indirect(_, _, _, _, segment)
+            | Operand::Indirect32(_, _, _, _, segment) => Some(segment),
+            _ => None,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Self::Immediate(span, _, _)
+            | Self::Direct(span, _, _, _)
+            | Self::Indirect(span, _, _, _, _)
+            | Self::Indirect32(span, _, _, _, _)
+            | Self::Far(span, _, _)
+            | Self::Register(span, _)
+            | Self::Segment(span, _) => span,
+        }
+    }
 }
 
 impl<'a> std::fmt::Display for Operand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Operand::Immediate(_, expr) => expr.fmt(f),
+            Operand::Immediate(_, expr, data_size) => {
+                if let Some(data_size) = data_size {
+                    write!(f, "{} ", data_size)?;
+                }
+                expr.fmt(f)
+            }
 
             Operand::Direct(_, expr, data_size, segment) => {
                 if let Some(data_size) = data_size {
@@ -476,6 +703,26 @@ impl<'a> std::fmt::Display for Operand {
                 write!(f, "]")
             }
 
+            Operand::Indirect32(_, address, expr, data_size, segment) => {
+                if let Some(data_size) = data_size {
+                    write!(f, "{} ", data_size)?;
+                }
+                write!(f, "[")?;
+
+                if let Some(segment) = segment {
+                    write!(f, "{}:", segment)?;
+                }
+
+                address.fmt(f)?;
+
+                if let Some(expr) = expr {
+                    " ".fmt(f)?;
+                    expr.fmt(f)?;
+                }
+
+                write!(f, "]")
+            }
+
             Operand::Far(_, offset, segment) => {
                 write!(f, "{}:{}", segment, offset)
             }
@@ -492,6 +739,8 @@ pub enum Operands {
     None(Span),
     Destination(Span, Operand),
     DestinationAndSource(Span, Operand, Operand),
+    /// Used by instructions with an additional operand, e.g. `imul ax, bx, 10`.
+    DestinationSourceAndThird(Span, Operand, Operand, Operand),
 }
 
 impl<'a> Operands {
@@ -499,7 +748,8 @@ impl<'a> Operands {
         match self {
             Operands::None(span)
             | Operands::Destination(span, _)
-            | Operands::DestinationAndSource(span, _, _) => span,
+            | Operands::DestinationAndSource(span, _, _)
+            | Operands::DestinationSourceAndThird(span, _, _, _) => span,
         }
     }
 }
@@ -512,6 +762,9 @@ impl<'a> std::fmt::Display for Operands {
             Operands::DestinationAndSource(_, destination, source) => {
                 write!(f, "{}, {}", destination, source)
             }
+            Operands::DestinationSourceAndThird(_, destination, source, third) => {
+                write!(f, "{}, {}, {}", destination, source, third)
+            }
         }
     }
 }
@@ -527,19 +780,121 @@ impl<'a> std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Operands::None(_) = self.operands {
             write!(f, "{:?}", self.operation)
+        } else if let (true, Operands::Destination(_, Operand::Segment(_, segment))) =
+            (self.operation.has_implicit_memory_operand(), &self.operands)
+        {
+            // The segment override of an instruction without operands is written as a prefix.
+            write!(f, "{} {:?}", segment, self.operation)
         } else {
             write!(f, "{:?} {}", self.operation, self.operands)
         }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataItem {
+    /// Raw bytes, e.g. from a string literal.
+    Bytes(Span, Vec<u8>),
+
+    /// A value stored with the size of the data definition.
+    Expression(Expression),
+}
+
+impl DataItem {
+    /// The number of bytes the item takes up.  Strings are padded with zeros to a multiple of the
+    /// data size.
+    pub fn size_in_bytes(&self, data_size: DataSize) -> u16 {
+        let width = data_size.size_in_bytes();
+        match self {
+            DataItem::Bytes(_, bytes) => (bytes.len() as u16).div_ceil(width) * width,
+            DataItem::Expression(_) => width,
+        }
+    }
+}
+
+impl std::fmt::Display for DataItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataItem::Bytes(_, bytes) => write!(f, "'{}'", String::from_utf8_lossy(bytes)),
+            DataItem::Expression(expr) => expr.fmt(f),
+        }
+    }
+}
+
+/// A field in a structure definition, e.g. `.field: resw 2`.  Fields without a label only
+/// reserve space.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructField(pub Span, pub Option<Label>, pub DataSize, pub Expression);
+
+impl std::fmt::Display for StructField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(label) = &self.1 {
+            write!(f, "{}: ", label)?;
+        }
+        match self.2 {
+            DataSize::Byte => write!(f, "resb {}", self.3),
+            DataSize::Word => write!(f, "resw {}", self.3),
+            DataSize::DWord => write!(f, "resd {}", self.3),
+            DataSize::QWord => write!(f, "resq {}", self.3),
+            DataSize::TWord => write!(f, "rest {}", self.3),
+        }
+    }
+}
+
+/// Initialized data for a field in a structure instance, e.g. `at point.x, dw 10`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructInstanceField(pub Span, pub Expression, pub Box<Line>);
+
+impl std::fmt::Display for StructInstanceField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at {}, {}", self.1, self.2)
+    }
+}
+
+/// The default operand and address size of instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Bits {
+    #[default]
+    Bits16,
+    Bits32,
+}
+
+impl std::fmt::Display for Bits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bits::Bits16 => write!(f, "16"),
+            Bits::Bits32 => write!(f, "32"),
+        }
+    }
+}
+
+impl FromStr for Bits {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "16" => Self::Bits16,
+            "32" => Self::Bits32,
+            _ => return Err(()),
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Line {
     Label(Label),
     Instruction(Instruction),
-    Data(Span, Vec<u8>),
+    Data(Span, DataSize, Vec<DataItem>),
     Constant(Span, Expression),
     Times(Span, Expression, Box<Line>),
+    Struct(Span, Label, Vec<StructField>),
+    StructInstance(Span, Label, Vec<StructInstanceField>),
+    /// Pad the output up to a multiple of the given alignment, with the fill byte if specified.
+    Align(Span, Expression, Option<Expression>),
+    /// Select the CPU that following instructions are assembled for.
+    Cpu(Span, Cpu),
+    /// Select the default operand and address size for following instructions.
+    Bits(Span, Bits),
 }
 
 impl Line {
@@ -547,9 +902,96 @@ impl Line {
         match self {
             Line::Label(Label(span, _))
             | Line::Instruction(Instruction { span, .. })
-            | Line::Data(span, _)
+            | Line::Data(span, _, _)
             | Line::Times(span, _, _)
-            | Line::Constant(span, _) => span,
+            | Line::Constant(span, _)
+            | Line::Struct(span, _, _)
+            | Line::StructInstance(span, _, _)
+            | Line::Align(span, _, _)
+            | Line::Cpu(span, _)
+            | Line::Bits(span, _) => span,
+        }
+    }
+
+    /// Every label that the line defines or refers to, in the order they are written.
+    pub fn labels(&self) -> Vec<&Label> {
+        let mut labels = vec![];
+        self.collect_labels(&mut labels);
+        labels
+    }
+
+    fn collect_labels<'a>(&'a self, labels: &mut Vec<&'a Label>) {
+        fn expression<'a>(expr: &'a Expression, labels: &mut Vec<&'a Label>) {
+            labels.extend(expr.iter_values().filter_map(|value| match value {
+                Value::Label(label) => Some(label),
+                Value::Constant(_) => None,
+            }));
+        }
+
+        fn operand<'a>(operand: &'a Operand, labels: &mut Vec<&'a Label>) {
+            match operand {
+                Operand::Immediate(_, expr, _) | Operand::Direct(_, expr, ..) => {
+                    expression(expr, labels)
+                }
+                Operand::Indirect(_, _, Some(expr), ..)
+                | Operand::Indirect32(_, _, Some(expr), ..) => expression(expr, labels),
+                // The segment is written first, e.g. "0x1000:start".
+                Operand::Far(_, offset, segment) => {
+                    expression(segment, labels);
+                    expression(offset, labels);
+                }
+                _ => {}
+            }
+        }
+
+        match self {
+            Line::Label(label) => labels.push(label),
+            Line::Instruction(instruction) => match &instruction.operands {
+                Operands::None(_) => {}
+                Operands::Destination(_, destination) => operand(destination, labels),
+                Operands::DestinationAndSource(_, destination, source) => {
+                    operand(destination, labels);
+                    operand(source, labels);
+                }
+                Operands::DestinationSourceAndThird(_, destination, source, third) => {
+                    operand(destination, labels);
+                    operand(source, labels);
+                    operand(third, labels);
+                }
+            },
+            Line::Data(_, _, items) => {
+                for item in items {
+                    if let DataItem::Expression(expr) = item {
+                        expression(expr, labels);
+                    }
+                }
+            }
+            Line::Constant(_, expr) => expression(expr, labels),
+            Line::Times(_, expr, line) => {
+                expression(expr, labels);
+                line.collect_labels(labels);
+            }
+            Line::Struct(_, name, fields) => {
+                labels.push(name);
+                for StructField(_, label, _, count) in fields {
+                    labels.extend(label);
+                    expression(count, labels);
+                }
+            }
+            Line::StructInstance(_, name, fields) => {
+                labels.push(name);
+                for StructInstanceField(_, field, line) in fields {
+                    expression(field, labels);
+                    line.collect_labels(labels);
+                }
+            }
+            Line::Align(_, alignment, fill) => {
+                expression(alignment, labels);
+                if let Some(fill) = fill {
+                    expression(fill, labels);
+                }
+            }
+            Line::Cpu(..) | Line::Bits(..) => {}
         }
     }
 }
@@ -559,14 +1001,41 @@ impl<'a> std::fmt::Display for Line {
         match self {
             Line::Label(label) => write!(f, "{}:", label),
             Line::Instruction(instruction) => write!(f, "{}", instruction),
-            Line::Data(_, data) => data
-                .iter()
-                .map(|b| format!("{:#04X}", b))
-                .collect::<Vec<String>>()
-                .join(", ")
-                .fmt(f),
+            Line::Data(_, data_size, items) => {
+                match data_size {
+                    DataSize::Byte => write!(f, "db ")?,
+                    DataSize::Word => write!(f, "dw ")?,
+                    DataSize::DWord => write!(f, "dd ")?,
+                    DataSize::QWord => write!(f, "dq ")?,
+                    DataSize::TWord => write!(f, "dt ")?,
+                }
+                items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+                    .fmt(f)
+            }
             Line::Constant(_, expr) => write!(f, "equ {}", expr),
             Line::Times(_, expr, content) => write!(f, "times {} {}", expr, content),
+            Line::Struct(_, name, fields) => {
+                writeln!(f, "struc {}", name)?;
+                for field in fields {
+                    writeln!(f, "    {}", field)?;
+                }
+                write!(f, "endstruc")
+            }
+            Line::Align(_, alignment, Some(fill)) => write!(f, "align {}, {}", alignment, fill),
+            Line::Align(_, alignment, None) => write!(f, "align {}", alignment),
+            Line::Cpu(_, cpu) => write!(f, "cpu {}", cpu),
+            Line::Bits(_, bits) => write!(f, "bits {}", bits),
+            Line::StructInstance(_, name, fields) => {
+                writeln!(f, "istruc {}", name)?;
+                for field in fields {
+                    writeln!(f, "    {}", field)?;
+                }
+                write!(f, "iend")
+            }
         }
     }
 }
@@ -575,6 +1044,18 @@ impl<'a> std::fmt::Display for Line {
 mod tests {
     use super::*;
 
+    #[test]
+    fn line_labels() {
+        let source = "times count dw one, 2 + two\nstruc point\n.x: resw size\nendstruc\n";
+        let mut parser = crate::parser::Parser::new(source);
+        let mut names = vec![];
+        while let Some(line) = parser.parse_line().unwrap() {
+            names.extend(line.labels().into_iter().map(|label| label.1.clone()));
+        }
+
+        assert_eq!(names, vec!["count", "one", "two", "point", ".x", "size"]);
+    }
+
     #[test]
     fn expression_iter_values() {
         let expr = Expression::Value(0..0, Value::Constant(10));
@@ -616,5 +1097,25 @@ mod tests {
             ],
             expr.iter_values().collect::<Vec<&Value>>()
         );
+
+        let expr = Expression::InfixOperator(
+            0..0,
+            Operator::Add,
+            Box::new(Expression::PrefixOperator(
+                0..0,
+                Operator::Subtract,
+                Box::new(Expression::Value(0..0, Value::Constant(10))),
+            )),
+            Box::new(Expression::PrefixOperator(
+                0..0,
+                Operator::Segment,
+                Box::new(Expression::Value(0..0, Value::Constant(20))),
+            )),
+        );
+
+        assert_eq!(
+            vec![&Value::Constant(10), &Value::Constant(20)],
+            expr.iter_values().collect::<Vec<&Value>>()
+        );
     }
 }
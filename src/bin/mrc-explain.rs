@@ -0,0 +1,25 @@
+//! Print the long-form explanation of an error code, e.g. `mrc-explain E0012`.  Without an
+//! argument, all codes are listed with a short description.
+
+use mrc_compiler::explain;
+
+fn main() {
+    match std::env::args().nth(1) {
+        Some(code) => match explain::explain(&code) {
+            Some(explanation) => print!("{}", explanation),
+            None => {
+                eprintln!("Error code \"{}\" is not known.", code);
+                std::process::exit(1);
+            }
+        },
+
+        None => {
+            for code in explain::codes() {
+                let summary = explain::explain(code)
+                    .and_then(|explanation| explanation.lines().next())
+                    .unwrap_or_default();
+                println!("{}: {}", code, summary);
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Format source files in place, e.g. `mrc-fmt main.asm`.  Without files, the source is read from
+//! stdin and the formatted source is written to stdout.  With `--check` nothing is written, every
+//! file that is not formatted is reported instead and the exit code is 1.
+
+use mrc_compiler::diagnostics::{DiagnosticKind, Diagnostics};
+use mrc_compiler::formatter::{self, FormatError, Options};
+use std::io::{Read, Write};
+
+fn report(source: &str, path: &str, err: &FormatError) {
+    let mut diags = Diagnostics::new(source, path.to_owned());
+    match err {
+        FormatError::ParserError(parser_error) => {
+            diags.diag_with_code(
+                DiagnosticKind::Error,
+                parser_error.code(),
+                err,
+                err.span().clone(),
+            );
+        }
+        FormatError::ChangedMeaning(_) => {
+            diags.error(err, err.span().clone());
+        }
+    }
+    let _ = diags.print(&mut std::io::stderr());
+}
+
+/// Format one source.  Returns the formatted source if it is different from the original.
+fn format(source: &str, path: &str, options: &Options) -> Result<Option<String>, ()> {
+    match formatter::format(source, options) {
+        Ok(formatted) if formatted == source => Ok(None),
+        Ok(formatted) => Ok(Some(formatted)),
+        Err(err) => {
+            report(source, path, &err);
+            Err(())
+        }
+    }
+}
+
+fn main() {
+    let mut check = false;
+    let mut paths = vec![];
+    for arg in std::env::args().skip(1) {
+        if arg == "--check" {
+            check = true;
+        } else {
+            paths.push(arg);
+        }
+    }
+
+    let options = Options::default();
+    let mut failed = false;
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("Could not read from stdin: {}", err);
+            std::process::exit(1);
+        }
+
+        match format(&source, "<stdin>", &options) {
+            Ok(Some(_)) if check => {
+                eprintln!("<stdin> is not formatted.");
+                failed = true;
+            }
+            Ok(formatted) => {
+                if !check {
+                    let formatted = formatted.unwrap_or(source);
+                    let _ = std::io::stdout().write_all(formatted.as_bytes());
+                }
+            }
+            Err(()) => failed = true,
+        }
+    }
+
+    for path in &paths {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", path, err);
+                failed = true;
+                continue;
+            }
+        };
+
+        match format(&source, path, &options) {
+            Ok(Some(_)) if check => {
+                eprintln!("{} is not formatted.", path);
+                failed = true;
+            }
+            Ok(Some(formatted)) => {
+                if let Err(err) = std::fs::write(path, formatted) {
+                    eprintln!("Could not write {}: {}", path, err);
+                    failed = true;
+                }
+            }
+            Ok(None) => {}
+            Err(()) => failed = true,
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
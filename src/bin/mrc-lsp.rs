@@ -0,0 +1,13 @@
+//! A language server for mrc assembly, speaking JSON-RPC over stdin and stdout.
+
+fn main() {
+    let mut server = mrc_compiler::lsp::Server::default();
+
+    if let Err(err) = server.run(&mut std::io::stdin().lock(), &mut std::io::stdout().lock()) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+
+    // The protocol asks for a non-zero exit code when the client did not shut down the server.
+    std::process::exit(if server.received_shutdown() { 0 } else { 1 });
+}
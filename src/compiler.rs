@@ -1,22 +1,38 @@
 use crate::ast;
 use crate::encoder as enc;
-use crate::encoder::{encode, value_is_signed_word, EncodeError, OperandData};
+use crate::encoder::{encode, EncodeError, OperandData};
+use crate::operations::{Cpu, Operation};
+use crate::suggest;
 use std::collections::{HashMap, LinkedList};
 use std::fmt::Formatter;
 
 const START_OFFSET: u16 = 0x0;
 
+/// The most passes [Compiler::resolve_labels] makes while sizes keep changing.  A short jump that
+/// is only in range while it takes no space itself keeps changing sizes forever.
+const MAX_PASSES: usize = 100;
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum CompileError {
     InvalidOperands(ast::Span, ast::Instruction),
-    LabelNotFound(ast::Label),
+    LabelNotFound(ast::Label, Option<String>),
     ConstantValueContainsVariables(ast::Span),
     ConstantValueContainsLabel(ast::Label),
     ConstantWithoutLabel(ast::Span),
-    ImmediateValueOutOfRange(ast::Span, i32),
-    UnresolvedReference(ast::Label),
+    ImmediateValueOutOfRange(ast::Span, i64),
+    UnresolvedReference(ast::Label, Option<String>),
     DataSizeNotSpecified(ast::Span),
+    DivisionByZero(ast::Span),
+    SegmentNotKnown(ast::Span),
+    ExpressionHasNoSegment(ast::Span),
+    DataLabelExpected(ast::Span),
+    StructFieldOverlap(ast::Span),
+    StructInstanceTooLarge(ast::Span),
+    InvalidAlignment(ast::Span, i64),
+    InstructionRequiresCpu(ast::Span, Operation, Cpu),
+    LabelOffsetsDoNotSettle(ast::Span),
+    StructTooLarge(ast::Span),
     EncodeError(EncodeError),
 }
 
@@ -24,16 +40,52 @@ impl CompileError {
     pub fn span(&self) -> &ast::Span {
         match self {
             CompileError::InvalidOperands(span, _)
-            | CompileError::LabelNotFound(ast::Label(span, _))
+            | CompileError::LabelNotFound(ast::Label(span, _), _)
             | CompileError::ConstantValueContainsVariables(span)
             | CompileError::ConstantValueContainsLabel(ast::Label(span, _))
             | CompileError::ConstantWithoutLabel(span)
             | CompileError::ImmediateValueOutOfRange(span, _)
-            | CompileError::UnresolvedReference(ast::Label(span, _))
-            | CompileError::DataSizeNotSpecified(span) => span,
+            | CompileError::InvalidAlignment(span, _)
+            | CompileError::InstructionRequiresCpu(span, _, _)
+            | CompileError::UnresolvedReference(ast::Label(span, _), _)
+            | CompileError::DataSizeNotSpecified(span)
+            | CompileError::DivisionByZero(span)
+            | CompileError::SegmentNotKnown(span)
+            | CompileError::ExpressionHasNoSegment(span)
+            | CompileError::DataLabelExpected(span)
+            | CompileError::StructFieldOverlap(span)
+            | CompileError::StructInstanceTooLarge(span)
+            | CompileError::LabelOffsetsDoNotSettle(span)
+            | CompileError::StructTooLarge(span) => span,
             CompileError::EncodeError(err) => err.span(),
         }
     }
+
+    /// A stable identifier for the kind of error, which can be looked up with
+    /// [crate::explain::explain].
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompileError::InvalidOperands(..) => "E0101",
+            CompileError::LabelNotFound(..) => "E0102",
+            CompileError::ConstantValueContainsVariables(_) => "E0103",
+            CompileError::ConstantValueContainsLabel(_) => "E0104",
+            CompileError::ConstantWithoutLabel(_) => "E0105",
+            CompileError::ImmediateValueOutOfRange(..) => "E0106",
+            CompileError::UnresolvedReference(..) => "E0107",
+            CompileError::DataSizeNotSpecified(_) => "E0108",
+            CompileError::DivisionByZero(_) => "E0109",
+            CompileError::SegmentNotKnown(_) => "E0110",
+            CompileError::ExpressionHasNoSegment(_) => "E0111",
+            CompileError::DataLabelExpected(_) => "E0112",
+            CompileError::StructFieldOverlap(_) => "E0113",
+            CompileError::StructInstanceTooLarge(_) => "E0114",
+            CompileError::InvalidAlignment(..) => "E0115",
+            CompileError::InstructionRequiresCpu(..) => "E0116",
+            CompileError::LabelOffsetsDoNotSettle(_) => "E0117",
+            CompileError::StructTooLarge(_) => "E0118",
+            CompileError::EncodeError(err) => err.code(),
+        }
+    }
 }
 
 impl std::fmt::Display for CompileError {
@@ -42,8 +94,9 @@ impl std::fmt::Display for CompileError {
             CompileError::InvalidOperands(_, operands) => {
                 write!(f, "Invalid operands: {}", operands,)
             }
-            CompileError::LabelNotFound(ast::Label(_, label)) => {
-                write!(f, "Label \"{}\" not found.", label)
+            CompileError::LabelNotFound(ast::Label(_, label), suggestion) => {
+                write!(f, "Label \"{}\" not found.", label)?;
+                write_suggestion(f, suggestion)
             }
             CompileError::ConstantValueContainsVariables(_) => {
                 write!(f, "Constant value contains variables.")
@@ -57,14 +110,74 @@ impl std::fmt::Display for CompileError {
             CompileError::ImmediateValueOutOfRange(_, value) => {
                 write!(f, "Immediate value out of range ({})", value)
             }
-            CompileError::UnresolvedReference(label) => {
-                write!(f, "Unresolved reference: {}", label.1)
+            CompileError::UnresolvedReference(label, suggestion) => {
+                write!(f, "Unresolved reference: {}", label.1)?;
+                if suggestion.is_some() {
+                    write!(f, ".")?;
+                }
+                write_suggestion(f, suggestion)
             }
 
             CompileError::DataSizeNotSpecified(_) => {
                 write!(f, "Data size not specified.")
             }
 
+            CompileError::DivisionByZero(_) => {
+                write!(f, "Division by zero.")
+            }
+
+            CompileError::SegmentNotKnown(_) => {
+                write!(
+                    f,
+                    "Segment not known. The output requires a base segment to resolve segment references."
+                )
+            }
+
+            CompileError::ExpressionHasNoSegment(_) => {
+                write!(
+                    f,
+                    "Expression does not reference a label and has no segment."
+                )
+            }
+
+            CompileError::DataLabelExpected(_) => {
+                write!(f, "Label pointing to a data definition expected.")
+            }
+
+            CompileError::StructFieldOverlap(_) => {
+                write!(f, "Structure field overlaps with a previous field.")
+            }
+
+            CompileError::StructInstanceTooLarge(_) => {
+                write!(
+                    f,
+                    "Structure instance is larger than the structure definition."
+                )
+            }
+
+            CompileError::InvalidAlignment(_, value) => {
+                write!(f, "Alignment must be a power of two ({})", value)
+            }
+
+            CompileError::InstructionRequiresCpu(_, operation, cpu) => {
+                write!(
+                    f,
+                    "The \"{}\" instruction requires a {} CPU, select it with \"cpu {}\".",
+                    operation, cpu, cpu
+                )
+            }
+
+            CompileError::LabelOffsetsDoNotSettle(_) => {
+                write!(
+                    f,
+                    "Label offsets do not settle, the size of this line keeps changing."
+                )
+            }
+
+            CompileError::StructTooLarge(_) => {
+                write!(f, "Structure is larger than 65535 bytes.")
+            }
+
             CompileError::EncodeError(err) => {
                 write!(f, "{}", err)
             }
@@ -72,12 +185,25 @@ impl std::fmt::Display for CompileError {
     }
 }
 
+fn write_suggestion(f: &mut Formatter<'_>, suggestion: &Option<String>) -> std::fmt::Result {
+    match suggestion {
+        Some(suggestion) => write!(f, " Did you mean \"{}\"?", suggestion),
+        None => Ok(()),
+    }
+}
+
 #[derive(Debug)]
 pub struct Output {
     line: ast::Line,
     size: u16,
     times: u16,
     unresolved_references: bool,
+
+    /// The CPU that was selected when the line was pushed.
+    cpu: Cpu,
+
+    /// The default operand and address size when the line was pushed.
+    bits: ast::Bits,
 }
 
 #[derive(Debug)]
@@ -88,17 +214,116 @@ pub struct LabelInfo {
 
     /// Used as the original name and span of the label.
     original: ast::Label,
+
+    /// The size in bytes and the number of elements of the data definition the label points to.
+    /// [None] if the label does not point to a data definition.
+    data: Option<(u16, u16)>,
+}
+
+/// Tracks the labels passed so far, used to resolve local and anonymous labels relative to the
+/// current position in the source.
+#[derive(Default)]
+pub(crate) struct Scope {
+    /// The last non-local label we passed.  Local labels are qualified with this name.
+    global: Option<String>,
+
+    /// The number of anonymous labels (`@@`) we passed.
+    anonymous: usize,
+}
+
+impl Scope {
+    /// Returns the name a label is stored under.  Local labels are prefixed with the current
+    /// scope, so ".loop" after "func" becomes "func.loop".  Anonymous labels are numbered in the
+    /// order they appear; `@b` refers to the previous and `@f` to the next one.
+    pub(crate) fn qualified_name(&self, label: &ast::Label) -> String {
+        if label.is_anonymous() {
+            return format!("@@{}", self.anonymous);
+        }
+
+        match label.1.to_lowercase().as_str() {
+            "@b" | "@r" if self.anonymous > 0 => return format!("@@{}", self.anonymous - 1),
+            "@f" => return format!("@@{}", self.anonymous),
+            _ => {}
+        }
+
+        match &self.global {
+            Some(global) if label.is_local() => format!("{}{}", global, label.1),
+            _ => label.1.clone(),
+        }
+    }
+
+    /// Move the scope past the given label.
+    pub(crate) fn enter(&mut self, label: &ast::Label) {
+        if label.is_anonymous() {
+            self.anonymous += 1;
+        } else if !label.is_local() {
+            self.global = Some(label.1.clone());
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct Compiler {
     outputs: Vec<Output>,
     labels: HashMap<String, LabelInfo>,
-    constants: HashMap<String, i32>,
+    constants: HashMap<String, i64>,
+
+    /// The position in the source used to resolve local and anonymous labels.
+    scope: Scope,
+
+    /// The segment the output will be loaded at.  [None] if the output is position independent, in
+    /// which case segment references can not be resolved.
+    base_segment: Option<u16>,
+
+    /// The CPU instructions are assembled for.  Changed by the `cpu` directive.
+    cpu: Cpu,
+
+    /// The default operand and address size.  Changed by the `bits` directive.
+    bits: ast::Bits,
 }
 
 impl Compiler {
+    /// Set the segment the output will be loaded at, used to resolve `seg` and `wrt`.
+    pub fn set_base_segment(&mut self, segment: u16) {
+        self.base_segment = Some(segment);
+    }
+
+    /// Set the CPU instructions are assembled for, until a `cpu` directive changes it.
+    pub fn set_cpu(&mut self, cpu: Cpu) {
+        self.cpu = cpu;
+    }
+
+    /// Set the default operand and address size, until a `bits` directive changes it.
+    pub fn set_bits(&mut self, bits: ast::Bits) {
+        self.bits = bits;
+    }
+
     pub fn compile(&mut self) -> Result<Vec<u8>, CompileError> {
+        self.assemble().map_err(|err| self.with_suggestion(err))
+    }
+
+    /// The offset of a label, after [Compiler::compile] resolved it.  Local labels are looked up by
+    /// their qualified name, e.g. "func.loop".
+    pub fn label_offset(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).and_then(|li| li.offset)
+    }
+
+    /// The value of a constant defined with `equ` or by a structure definition.
+    pub fn constant(&self, name: &str) -> Option<i64> {
+        self.constants.get(name).copied()
+    }
+
+    /// Every line that was pushed with its offset and size in the output, after
+    /// [Compiler::compile] calculated them.  Lines repeated with `times` include all repetitions.
+    pub fn lines(&self) -> impl Iterator<Item = (&ast::Line, u16, u16)> {
+        self.outputs.iter().scan(START_OFFSET, |offset, output| {
+            let line_offset = *offset;
+            *offset = offset.wrapping_add(output.size);
+            Some((&output.line, line_offset, output.size))
+        })
+    }
+
+    fn assemble(&mut self) -> Result<Vec<u8>, CompileError> {
         if self.resolve_labels()? > 0 {
             let label = self
                 .labels
@@ -107,18 +332,27 @@ impl Compiler {
                 .map(|(_, li)| li.original.clone())
                 .next()
                 .unwrap();
-            return Err(CompileError::UnresolvedReference(label));
+            return Err(CompileError::UnresolvedReference(label, None));
         }
 
         // self._debug_print_outputs();
 
         let mut result = vec![];
 
+        self.scope = Scope::default();
+
+        // Alignment without a fill byte pads with NOPs after code and with zeroes after data.
+        let mut in_code = true;
+
         for output in &self.outputs {
             match &output.line {
+                ast::Line::Label(label) => self.scope.enter(label),
+
                 ast::Line::Instruction(insn) => {
-                    debug_assert_ne!(output.size, 0, "Output size should not be 0 at this point.");
-                    let instruction_data = self.build_instruction_data(insn)?;
+                    in_code = true;
+
+                    let instruction_data =
+                        self.build_instruction_data(insn, output.cpu, output.bits)?;
                     for _ in 0..output.times {
                         let offset = START_OFFSET + result.len() as u16;
                         encode(&instruction_data, offset, &mut result)
@@ -126,14 +360,41 @@ impl Compiler {
                     }
                 }
 
-                ast::Line::Data(_, data) => {
+                ast::Line::Data(_, data_size, items) => {
+                    in_code = false;
+                    let data = self.build_data(*data_size, items)?;
                     for _ in 0..output.times {
-                        for byte in data.iter() {
-                            result.push(*byte);
-                        }
+                        result.extend_from_slice(&data);
                     }
                 }
 
+                ast::Line::StructInstance(span, _, fields) => {
+                    in_code = false;
+                    let data =
+                        self.build_struct_instance(span, output.size / output.times, fields)?;
+                    for _ in 0..output.times {
+                        result.extend_from_slice(&data);
+                    }
+                }
+
+                ast::Line::Align(_, _, fill) => {
+                    let fill = match fill {
+                        Some(fill) => {
+                            let value = self.evaluate_expression(fill)?;
+                            if !value_fits(value, ast::DataSize::Byte) {
+                                return Err(CompileError::ImmediateValueOutOfRange(
+                                    fill.span().clone(),
+                                    value,
+                                ));
+                            }
+                            value as u8
+                        }
+                        None if in_code => 0x90, // NOP
+                        None => 0x00,
+                    };
+                    result.resize(result.len() + output.size as usize, fill);
+                }
+
                 _ => {}
             }
         }
@@ -180,23 +441,73 @@ impl Compiler {
         }
     }
 
+    /// Suggest a close match for a label that could not be found.
+    fn with_suggestion(&self, err: CompileError) -> CompileError {
+        match err {
+            CompileError::LabelNotFound(label, None) => {
+                let suggestion = self.suggest_label(&label);
+                CompileError::LabelNotFound(label, suggestion)
+            }
+            CompileError::UnresolvedReference(label, None) => {
+                let suggestion = self.suggest_label(&label);
+                CompileError::UnresolvedReference(label, suggestion)
+            }
+            err => err,
+        }
+    }
+
+    /// The defined label, constant or register with a name closest to [label].  Local labels are
+    /// compared to the local part of other labels.
+    fn suggest_label(&self, label: &ast::Label) -> Option<String> {
+        let mut names = self
+            .labels
+            .iter()
+            .filter(|(_, li)| li.offset.is_some())
+            .map(|(name, _)| name.as_str())
+            .chain(self.constants.keys().map(String::as_str))
+            .filter(|name| !name.starts_with("@@"))
+            .map(|name| match name.find('.') {
+                Some(dot) if label.is_local() => &name[dot..],
+                _ => name,
+            })
+            .collect::<Vec<_>>();
+        // Sort the names, so a tie always picks the same one.
+        names.sort_unstable();
+
+        suggest::best_match(
+            &label.1,
+            names.into_iter().chain(suggest::REGISTERS.iter().copied()),
+        )
+        .map(str::to_owned)
+    }
+
     /// Runs over all [Output]'s and calculate the size for each. If a label is not found, we
-    /// know we have unresolved references, so we have to run another pass. If we run a pass without
-    /// resolving any references, we return with the current number of unresolved references.
+    /// know we have unresolved references, so we have to run another pass. Once a pass does not move
+    /// any labels, we return with the number of references to labels that were never declared.
     fn resolve_labels(&mut self) -> Result<usize, CompileError> {
-        let mut last_offset = u16::MAX;
-
         let mut labels = LinkedList::new();
 
-        let mut forward_references = 0;
+        let mut passes = 0;
 
         loop {
+            passes += 1;
+
             // When we start a pass, there should not be any labels left over from a previous pass.
             debug_assert!(labels.is_empty());
 
             let mut unresolved_references = 0;
             let mut offset = START_OFFSET;
 
+            // A short jump that is out of range in this pass, which might still come in range if
+            // the sizes of the instructions it jumps over change.
+            let mut out_of_range = None;
+
+            // The last line that changed its size in this pass, which moves all the labels after it.
+            let mut size_changed = None;
+            let mut labels_changed = false;
+
+            self.scope = Scope::default();
+
             let outputs = unsafe {
                 &mut *std::ptr::slice_from_raw_parts_mut(
                     self.outputs.as_mut_ptr(),
@@ -207,32 +518,42 @@ impl Compiler {
             for output in outputs {
                 match &mut output.line {
                     ast::Line::Label(label) => {
-                        labels.push_back(label.clone());
+                        labels.push_back((self.scope.qualified_name(label), label.clone()));
+                        self.scope.enter(label);
                     }
 
                     ast::Line::Instruction(insn) => {
-                        while let Some(label) = labels.pop_back() {
-                            self.set_label_offset(&label, Some(offset));
+                        while let Some((name, label)) = labels.pop_back() {
+                            labels_changed |= self.set_label_offset(name, &label, Some(offset));
                         }
 
                         let mut size = 0;
                         for _ in 0..output.times {
-                            size += match self.calculate_instruction_size(insn, offset + size) {
-                                Ok(Some(size)) => {
+                            size += match self.calculate_instruction_size(
+                                insn,
+                                offset + size,
+                                output.cpu,
+                                output.bits,
+                            ) {
+                                Ok(size) => {
                                     output.unresolved_references = false;
                                     size
                                 }
 
-                                Ok(None) => {
-                                    if output.unresolved_references {
-                                        forward_references += 1;
-                                    }
+                                Err(CompileError::EncodeError(
+                                    err @ EncodeError::RelativeJumpOutOfRange(..),
+                                )) => {
+                                    out_of_range.get_or_insert(err);
                                     output.unresolved_references = true;
                                     0
                                 }
 
-                                Err(CompileError::LabelNotFound(label)) => {
-                                    self.set_label_offset(&label, None);
+                                Err(CompileError::LabelNotFound(label, _)) => {
+                                    labels_changed |= self.set_label_offset(
+                                        self.scope.qualified_name(&label),
+                                        &label,
+                                        None,
+                                    );
                                     unresolved_references += 1;
                                     output.unresolved_references = true;
                                     0
@@ -241,92 +562,208 @@ impl Compiler {
                                 Err(err) => return Err(err),
                             };
                         }
+                        if output.size != size {
+                            size_changed = Some(insn.span.clone());
+                        }
                         output.size = size;
                         offset += size;
                     }
 
-                    ast::Line::Data(_, data) => {
-                        while let Some(label) = labels.pop_back() {
-                            self.set_label_offset(&label, Some(offset));
-                        }
-
+                    ast::Line::Data(_, data_size, items) => {
                         let mut size = 0;
                         for _ in 0..output.times {
-                            size += data.len() as u16;
+                            size += items
+                                .iter()
+                                .map(|item| item.size_in_bytes(*data_size))
+                                .sum::<u16>();
                         }
+
+                        while let Some((name, label)) = labels.pop_back() {
+                            labels_changed |=
+                                self.set_label_offset(name.clone(), &label, Some(offset));
+                            if let Some(li) = self.labels.get_mut(name.as_str()) {
+                                li.data = Some((size, size / data_size.size_in_bytes()));
+                            }
+                        }
+
                         output.size = size;
                         offset += size;
                     }
 
                     ast::Line::Constant(span, expr) => {
-                        if let Some(label) = labels.pop_back() {
+                        if let Some((name, _)) = labels.pop_back() {
                             let value = self.evaluate_expression(expr)?;
-                            self.constants.insert(label.1.clone(), value);
+                            self.constants.insert(name, value);
                         } else {
                             return Err(CompileError::ConstantWithoutLabel(span.clone()));
                         }
                     }
 
-                    ast::Line::Times(..) => {
-                        // We convert ::Times lines to normal instruction lines with a times value,
-                        // so encountering this should not be possible.
+                    ast::Line::Struct(_, name, fields) => {
+                        self.define_struct(name, fields)?;
+                    }
+
+                    ast::Line::StructInstance(span, name, _) => {
+                        // The size is checked to fit in a word when the structure is defined.
+                        let size = match self.constants.get(format!("{}_size", name.1).as_str()) {
+                            Some(size) => *size as u16,
+                            None => return Err(CompileError::LabelNotFound(name.clone(), None)),
+                        };
+
+                        while let Some((name, label)) = labels.pop_back() {
+                            labels_changed |= self.set_label_offset(name, &label, Some(offset));
+                        }
+
+                        output.size = size
+                            .checked_mul(output.times)
+                            .ok_or_else(|| CompileError::StructTooLarge(span.clone()))?;
+                        offset += output.size;
+                    }
+
+                    ast::Line::Align(span, alignment, _) => {
+                        let alignment = self.evaluate_alignment(alignment)?;
+
+                        // Labels are not assigned here, so that they point to the aligned
+                        // instruction or data that follows.
+                        let mut size = 0;
+                        for _ in 0..output.times {
+                            size += (alignment - (offset + size) % alignment) % alignment;
+                        }
+                        if output.size != size {
+                            size_changed = Some(span.clone());
+                        }
+                        output.size = size;
+                        offset += size;
+                    }
+
+                    ast::Line::Times(..) | ast::Line::Cpu(..) | ast::Line::Bits(..) => {
+                        // We convert ::Times lines to normal instruction lines with a times value
+                        // and apply ::Cpu and ::Bits lines when they are pushed, so encountering
+                        // these should not be possible.
                         unreachable!()
                     }
                 }
             }
 
-            while let Some(label) = labels.pop_back() {
-                self.set_label_offset(&label, Some(offset));
+            while let Some((name, label)) = labels.pop_back() {
+                labels_changed |= self.set_label_offset(name, &label, Some(offset));
             }
 
-            // We finished a pass now, so if there were any forward references, then we try to
-            // resolve them with another pass.
-            // println!("forward_references: {}", forward_references);
-            if forward_references != 0 {
-                forward_references = 0;
+            // We finished a pass now, so if any labels moved, then the instructions referring to
+            // them have to be sized again with another pass.
+            if labels_changed {
+                if passes == MAX_PASSES {
+                    // After the first pass, labels only move when a line before them changes size.
+                    return Err(CompileError::LabelOffsetsDoNotSettle(
+                        size_changed.unwrap_or_default(),
+                    ));
+                }
                 continue;
             }
 
-            // If there are no more unresolved references, then we can stop.
-            if unresolved_references == 0 {
-                return Ok(0);
+            // The sizes did not change, so a jump that is out of range will never fit.
+            if let Some(err) = out_of_range {
+                return Err(CompileError::EncodeError(err));
+            }
+
+            // Any references that are left are to labels that were never declared.
+            return Ok(unresolved_references);
+        }
+    }
+
+    /// Define the constants for a structure: the structure name is 0, each field is its offset
+    /// from the start of the structure and `name_size` is the total size.  Local field names are
+    /// qualified with the structure name.
+    fn define_struct(
+        &mut self,
+        name: &ast::Label,
+        fields: &[ast::StructField],
+    ) -> Result<(), CompileError> {
+        self.constants.insert(name.1.clone(), 0);
+
+        let mut offset = 0;
+        for ast::StructField(span, label, data_size, count) in fields {
+            if let Some(label) = label {
+                let field_name = if label.is_local() {
+                    format!("{}{}", name.1, label.1)
+                } else {
+                    label.1.clone()
+                };
+                self.constants.insert(field_name, offset);
             }
 
-            // Now we know there are unsolved references, but if we did not get new sizes for any
-            // of them, then we are done.
-            if offset == last_offset {
-                return Ok(unresolved_references);
+            let count_value = self.evaluate_expression(count)?;
+            if count_value < 0 {
+                return Err(CompileError::ImmediateValueOutOfRange(
+                    count.span().clone(),
+                    count_value,
+                ));
             }
 
-            last_offset = offset;
+            offset = count_value
+                .checked_mul(data_size.size_in_bytes() as i64)
+                .and_then(|size| offset.checked_add(size))
+                .filter(|offset| *offset <= u16::MAX as i64)
+                .ok_or_else(|| CompileError::StructTooLarge(span.clone()))?;
         }
+
+        self.constants.insert(format!("{}_size", name.1), offset);
+
+        Ok(())
     }
 
-    fn set_label_offset(&mut self, label: &ast::Label, offset: Option<u16>) {
+    fn evaluate_alignment(&self, expression: &ast::Expression) -> Result<u16, CompileError> {
+        let value = self.evaluate_expression(expression)?;
+        if value <= 0 || value > u16::MAX as i64 || !(value as u16).is_power_of_two() {
+            return Err(CompileError::InvalidAlignment(
+                expression.span().clone(),
+                value,
+            ));
+        }
+        Ok(value as u16)
+    }
+
+    /// Set the offset of a label and return true if it moved.
+    fn set_label_offset(&mut self, name: String, label: &ast::Label, offset: Option<u16>) -> bool {
         // if let Some(offset) = offset {
         //     println!("setting \"{}\" to {} ({:#04x})", label, offset, offset);
         // } else {
         //     println!("clearing label \"{}\"", label);
         // }
 
-        if let Some(li) = self.labels.get_mut(label.1.as_str()) {
-            li.offset = offset;
+        if let Some(li) = self.labels.get_mut(name.as_str()) {
+            std::mem::replace(&mut li.offset, offset) != offset
         } else {
             self.labels.insert(
-                label.1.clone(),
+                name,
                 LabelInfo {
                     offset,
                     original: label.clone(),
+                    data: None,
                 },
             );
+            offset.is_some()
         }
     }
 
-    fn build_operand_data(&self, operand: &ast::Operand) -> Result<OperandData, CompileError> {
+    fn build_operand_data(
+        &self,
+        operation: Operation,
+        operand: &ast::Operand,
+        bits: ast::Bits,
+    ) -> Result<OperandData, CompileError> {
         Ok(match operand {
-            ast::Operand::Immediate(span, expr) => {
-                let value = self.evaluate_expression(expr)?;
-                OperandData::immediate(span.clone(), value)
+            ast::Operand::Immediate(span, expr, data_size) => {
+                let mut operand =
+                    OperandData::immediate(span.clone(), self.evaluate_operand_value(expr)?);
+                operand.size = enc::operand_size_from_data_size(
+                    self.operand_data_size(operation, span, data_size)?,
+                );
+                operand
+            }
+
+            ast::Operand::Register(span, ast::Register::St(index)) => {
+                OperandData::fpu_register(span.clone(), *index)
             }
 
             ast::Operand::Register(span, reg) => OperandData::register(
@@ -335,15 +772,29 @@ impl Compiler {
                 match reg {
                     ast::Register::Byte(_) => enc::OperandSize::Byte,
                     ast::Register::Word(_) => enc::OperandSize::Word,
+                    ast::Register::DWord(_) => enc::OperandSize::DWord,
+                    ast::Register::St(_) => unreachable!(),
                 },
             ),
 
             ast::Operand::Segment(span, seg) => OperandData::segment(span.clone(), seg.encoding()),
 
+            // In 32-bit code, direct addresses are 32-bit as well.
+            ast::Operand::Direct(span, expr, data_size, seg) if bits == ast::Bits::Bits32 => {
+                OperandData::indirect32(
+                    span.clone(),
+                    None,
+                    None,
+                    self.evaluate_operand_value(expr)?,
+                    self.operand_data_size(operation, span, data_size)?,
+                    seg,
+                )
+            }
+
             ast::Operand::Direct(span, expr, data_size, seg) => OperandData::direct(
                 span.clone(),
-                self.evaluate_expression(expr)?,
-                data_size,
+                self.evaluate_operand_value(expr)?,
+                self.operand_data_size(operation, span, data_size)?,
                 seg,
             ),
 
@@ -353,33 +804,150 @@ impl Compiler {
                 } else {
                     0
                 };
-                if !value_is_signed_word(value) {
-                    return Err(CompileError::ImmediateValueOutOfRange(span.clone(), value));
-                }
+                let displacement = i16::try_from(value)
+                    .map_err(|_| CompileError::ImmediateValueOutOfRange(span.clone(), value))?;
 
                 OperandData::indirect(
                     span.clone(),
                     indirect_encoding.encoding(),
-                    value as i16,
-                    data_size,
+                    displacement,
+                    self.operand_data_size(operation, span, data_size)?,
+                    seg,
+                )
+            }
+
+            ast::Operand::Indirect32(span, address, expr, data_size, seg) => {
+                let displacement = if let Some(expr) = expr {
+                    self.evaluate_operand_value(expr)?
+                } else {
+                    0
+                };
+
+                OperandData::indirect32(
+                    span.clone(),
+                    address.base.map(|base| base.encoding()),
+                    address
+                        .index
+                        .map(|(index, scale)| (index.encoding(), scale)),
+                    displacement,
+                    self.operand_data_size(operation, span, data_size)?,
                     seg,
                 )
             }
 
             ast::Operand::Far(span, offset, segment) => {
-                let offset = self.evaluate_expression(offset)?;
-                let segment = self.evaluate_expression(segment)?;
+                let offset = self.evaluate_operand_value(offset)?;
+                let segment = self.evaluate_operand_value(segment)?;
 
                 OperandData::far(span.clone(), offset, segment)
             }
         })
     }
 
-    fn build_instruction_data(
+    /// Evaluate an expression used as an operand.  The encoder does the range checks for the
+    /// operand size, so we only make sure the value fits into 32 bits here.  Unsigned 32-bit values
+    /// are stored with the same bits as a signed value.
+    fn evaluate_operand_value(&self, expression: &ast::Expression) -> Result<i32, CompileError> {
+        let value = self.evaluate_expression(expression)?;
+        if (i32::MIN as i64..=u32::MAX as i64).contains(&value) {
+            Ok(value as i32)
+        } else {
+            Err(CompileError::ImmediateValueOutOfRange(
+                expression.span().clone(),
+                value,
+            ))
+        }
+    }
+
+    /// Memory operands can only be up to a dword in size, except for the 8087 instructions, which
+    /// also load and store larger values.
+    fn operand_data_size<'d>(
+        &self,
+        operation: Operation,
+        span: &ast::Span,
+        data_size: &'d Option<ast::DataSize>,
+    ) -> Result<&'d Option<ast::DataSize>, CompileError> {
+        match data_size {
+            Some(ast::DataSize::QWord | ast::DataSize::TWord) if !operation.is_fpu() => Err(
+                CompileError::EncodeError(EncodeError::InvalidOperandSize(span.clone())),
+            ),
+            _ => Ok(data_size),
+        }
+    }
+
+    fn build_data(
+        &self,
+        data_size: ast::DataSize,
+        items: &[ast::DataItem],
+    ) -> Result<Vec<u8>, CompileError> {
+        let mut data = vec![];
+
+        for item in items {
+            match item {
+                ast::DataItem::Bytes(_, bytes) => {
+                    let end = data.len() + item.size_in_bytes(data_size) as usize;
+                    data.extend_from_slice(bytes);
+                    data.resize(end, 0);
+                }
+
+                ast::DataItem::Expression(expr) => {
+                    let value = self.evaluate_expression(expr)?;
+                    if !value_fits(value, data_size) {
+                        return Err(CompileError::ImmediateValueOutOfRange(
+                            expr.span().clone(),
+                            value,
+                        ));
+                    }
+                    // Values wider than 64 bits are sign extended.
+                    let mut bytes = value.to_le_bytes().to_vec();
+                    bytes.resize(
+                        data_size.size_in_bytes() as usize,
+                        if value < 0 { 0xFF } else { 0x00 },
+                    );
+                    data.extend_from_slice(&bytes);
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn build_struct_instance(
+        &self,
+        span: &ast::Span,
+        size: u16,
+        fields: &[ast::StructInstanceField],
+    ) -> Result<Vec<u8>, CompileError> {
+        let mut data = vec![];
+
+        for ast::StructInstanceField(field_span, field, line) in fields {
+            let field_offset = self.evaluate_expression(field)?;
+            if field_offset < data.len() as i64 {
+                return Err(CompileError::StructFieldOverlap(field_span.clone()));
+            }
+            data.resize(field_offset as usize, 0);
+
+            if let ast::Line::Data(_, data_size, items) = line.as_ref() {
+                data.extend(self.build_data(*data_size, items)?);
+            }
+        }
+
+        if data.len() > size as usize {
+            return Err(CompileError::StructInstanceTooLarge(span.clone()));
+        }
+        data.resize(size as usize, 0);
+
+        Ok(data)
+    }
+
+    /// Build the data for the encoder and make sure the instruction is supported by the [cpu].
+    pub(crate) fn build_instruction_data(
         &self,
         instruction: &ast::Instruction,
+        cpu: Cpu,
+        bits: ast::Bits,
     ) -> Result<crate::encoder::InstructionData, CompileError> {
-        Ok(match &instruction.operands {
+        let mut insn_data = match &instruction.operands {
             ast::Operands::None(span) => {
                 crate::encoder::InstructionData::none(span.clone(), instruction.operation)
             }
@@ -387,47 +955,145 @@ impl Compiler {
             ast::Operands::Destination(span, dst) => crate::encoder::InstructionData::dst(
                 span.clone(),
                 instruction.operation,
-                self.build_operand_data(dst)?,
+                self.build_operand_data(instruction.operation, dst, bits)?,
             ),
 
             ast::Operands::DestinationAndSource(span, dst, src) => {
                 crate::encoder::InstructionData::dst_and_src(
                     span.clone(),
                     instruction.operation,
-                    self.build_operand_data(dst)?,
-                    self.build_operand_data(src)?,
+                    self.build_operand_data(instruction.operation, dst, bits)?,
+                    self.build_operand_data(instruction.operation, src, bits)?,
                 )
             }
-        })
+
+            ast::Operands::DestinationSourceAndThird(span, dst, src, third) => {
+                crate::encoder::InstructionData::dst_src_and_third(
+                    span.clone(),
+                    instruction.operation,
+                    self.build_operand_data(instruction.operation, dst, bits)?,
+                    self.build_operand_data(instruction.operation, src, bits)?,
+                    self.build_operand_data(instruction.operation, third, bits)?,
+                )
+            }
+        };
+
+        // Only the 8087 needs the CPU to wait before each instruction.
+        insn_data.fpu_wait = !cpu.supports(Cpu::I286);
+        insn_data.bits = bits;
+
+        let required = insn_data.minimum_cpu();
+        if !cpu.supports(required) {
+            return Err(CompileError::InstructionRequiresCpu(
+                instruction.span.clone(),
+                instruction.operation,
+                required,
+            ));
+        }
+
+        Ok(insn_data)
     }
 }
 
 impl Compiler {
-    fn evaluate_expression(&self, expression: &ast::Expression) -> Result<i32, CompileError> {
+    fn evaluate_expression(&self, expression: &ast::Expression) -> Result<i64, CompileError> {
         match expression {
-            ast::Expression::PrefixOperator(_, operator, expr) => {
+            ast::Expression::PrefixOperator(span, ast::Operator::Segment, expr) => {
+                // Make sure any labels are resolved before checking for relocation.
+                self.evaluate_expression(expr)?;
+
+                if !self.is_relocatable(expr) {
+                    return Err(CompileError::ExpressionHasNoSegment(expr.span().clone()));
+                }
+
+                self.base_segment
+                    .map(|segment| segment as i64)
+                    .ok_or_else(|| CompileError::SegmentNotKnown(span.clone()))
+            }
+
+            ast::Expression::PrefixOperator(
+                _,
+                operator @ (ast::Operator::SizeOf | ast::Operator::LengthOf),
+                expr,
+            ) => {
+                let label = match expr.as_ref() {
+                    ast::Expression::Value(_, ast::Value::Label(label)) => label,
+                    _ => return Err(CompileError::DataLabelExpected(expr.span().clone())),
+                };
+
+                match self.labels.get(self.scope.qualified_name(label).as_str()) {
+                    Some(LabelInfo {
+                        data: Some((size, length)),
+                        ..
+                    }) => Ok(if *operator == ast::Operator::SizeOf {
+                        *size as i64
+                    } else {
+                        *length as i64
+                    }),
+                    Some(LabelInfo {
+                        offset: Some(_), ..
+                    }) => Err(CompileError::DataLabelExpected(expr.span().clone())),
+                    _ => Err(CompileError::LabelNotFound(label.clone(), None)),
+                }
+            }
+
+            ast::Expression::InfixOperator(span, ast::Operator::WithRespectTo, left, right) => {
+                let offset = self.evaluate_expression(left)?;
+
+                if !self.is_relocatable(left) {
+                    return Err(CompileError::ExpressionHasNoSegment(left.span().clone()));
+                }
+
+                // An offset relative to the segment of a label in the output stays the same.
+                if let ast::Expression::PrefixOperator(_, ast::Operator::Segment, expr) =
+                    right.as_ref()
+                {
+                    if self.is_relocatable(expr) {
+                        return Ok(offset);
+                    }
+                }
+
+                let segment = self.evaluate_expression(right)?;
+                let base_segment = self
+                    .base_segment
+                    .ok_or_else(|| CompileError::SegmentNotKnown(span.clone()))?;
+
+                let value = (base_segment as i64 - segment) * 16 + offset;
+                if !(0..=u16::MAX as i64).contains(&value) {
+                    return Err(CompileError::ImmediateValueOutOfRange(span.clone(), value));
+                }
+
+                Ok(value)
+            }
+
+            ast::Expression::PrefixOperator(span, operator, expr) => {
                 let value = self.evaluate_expression(expr)?;
-                Ok(operator.evaluate(0, value))
+                operator
+                    .evaluate(0, value)
+                    .ok_or_else(|| CompileError::DivisionByZero(span.clone()))
             }
 
-            ast::Expression::InfixOperator(_, operator, left, right) => {
+            ast::Expression::InfixOperator(span, operator, left, right) => {
                 let left = self.evaluate_expression(left)?;
                 let right = self.evaluate_expression(right)?;
 
-                Ok(operator.evaluate(left, right))
+                operator
+                    .evaluate(left, right)
+                    .ok_or_else(|| CompileError::DivisionByZero(span.clone()))
             }
 
             ast::Expression::Value(_, ast::Value::Label(label)) => {
-                if let Some(value) = self.constants.get(label.1.as_str()) {
+                let name = self.scope.qualified_name(label);
+                if let Some(value) = self.constants.get(name.as_str()) {
                     Ok(*value)
                 } else if let Some(LabelInfo {
                     offset: Some(label_offset),
                     ..
-                }) = self.labels.get(label.1.as_str())
+                }) = self.labels.get(name.as_str())
                 {
-                    Ok(*label_offset as i32)
+                    Ok(*label_offset as i64)
                 } else {
-                    Err(CompileError::LabelNotFound(label.clone()))
+                    Err(CompileError::LabelNotFound(label.clone(), None))
                 }
             }
 
@@ -437,28 +1103,58 @@ impl Compiler {
 }
 
 impl Compiler {
+    /// Returns true if the value of the expression depends on the location of a label in the
+    /// output.
+    fn is_relocatable(&self, expression: &ast::Expression) -> bool {
+        expression.iter_values().any(|value| match value {
+            ast::Value::Label(label) => self
+                .labels
+                .contains_key(self.scope.qualified_name(label).as_str()),
+            ast::Value::Constant(_) => false,
+        })
+    }
+
     fn calculate_instruction_size(
         &self,
         instruction: &ast::Instruction,
         offset: u16,
-    ) -> Result<Option<u16>, CompileError> {
-        let insn_data = self.build_instruction_data(instruction)?;
+        cpu: Cpu,
+        bits: ast::Bits,
+    ) -> Result<u16, CompileError> {
+        let insn_data = self.build_instruction_data(instruction, cpu, bits)?;
         let mut size_in_bytes = 0_u16;
 
-        if let Err(err) = encode(&insn_data, offset, &mut size_in_bytes) {
-            match err {
-                EncodeError::RelativeJumpOutOfRange(..) => Ok(None),
-                _ => Err(CompileError::EncodeError(err)),
-            }
-        } else {
-            Ok(Some(size_in_bytes))
-        }
+        encode(&insn_data, offset, &mut size_in_bytes).map_err(CompileError::EncodeError)?;
+
+        Ok(size_in_bytes)
+    }
+}
+
+/// Returns true if the value can be stored in the given size, either as a signed or as an unsigned
+/// value.
+fn value_fits(value: i64, data_size: ast::DataSize) -> bool {
+    match data_size {
+        ast::DataSize::Byte => (i8::MIN as i64..=u8::MAX as i64).contains(&value),
+        ast::DataSize::Word => (i16::MIN as i64..=u16::MAX as i64).contains(&value),
+        ast::DataSize::DWord => (i32::MIN as i64..=u32::MAX as i64).contains(&value),
+        ast::DataSize::QWord | ast::DataSize::TWord => true,
     }
 }
 
 impl Compiler {
     pub fn push_line(&mut self, line: ast::Line) -> Result<(), CompileError> {
         match line {
+            ast::Line::Cpu(_, cpu) => self.cpu = cpu,
+
+            ast::Line::Bits(_, bits) => self.bits = bits,
+
+            // Repeating a directive has no effect, so we apply it once.
+            ast::Line::Times(_, _, line)
+                if matches!(*line, ast::Line::Cpu(..) | ast::Line::Bits(..)) =>
+            {
+                return self.push_line(*line);
+            }
+
             ast::Line::Times(_, expr, line) => {
                 let times = self.evaluate_expression(&expr)? as u16;
                 self.outputs.push(Output {
@@ -466,15 +1162,23 @@ impl Compiler {
                     size: 0,
                     times,
                     unresolved_references: false,
+                    cpu: self.cpu,
+                    bits: self.bits,
                 })
             }
 
             _ => {
+                if let ast::Line::Label(label) = &line {
+                    self.scope.enter(label);
+                }
+
                 self.outputs.push(Output {
                     line,
                     size: 0,
                     times: 1,
                     unresolved_references: false,
+                    cpu: self.cpu,
+                    bits: self.bits,
                 });
             }
         }
@@ -485,6 +1189,9 @@ impl Compiler {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
     macro_rules! _compile_test {
         ($source:literal, $binary:literal) => {{
             use std::path::Path;
@@ -512,7 +1219,12 @@ mod tests {
                     Ok(Some(line)) => compiler.push_line(line).unwrap(),
                     Ok(None) => break,
                     Err(err) => {
-                        diag.error(&err, err.span().clone());
+                        diag.diag_with_code(
+                            DiagnosticKind::Error,
+                            err.code(),
+                            &err,
+                            err.span().clone(),
+                        );
                         diag.print(&mut std::io::stderr())
                             .expect("Could not write to stderr.");
                         panic!()
@@ -525,7 +1237,12 @@ mod tests {
                     assert_eq!(expected, actual.as_slice());
                 }
                 Err(err) => {
-                    diag.error(&err, err.span().clone());
+                    diag.diag_with_code(
+                        DiagnosticKind::Error,
+                        err.code(),
+                        &err,
+                        err.span().clone(),
+                    );
                     diag.print(&mut std::io::stderr())
                         .expect("Could not write to stderr.");
                     panic!()
@@ -534,6 +1251,510 @@ mod tests {
         }};
     }
 
+    #[test]
+    fn local_labels() {
+        let source = "first:\n.loop: jmp .loop\nsecond:\n.loop: jmp .loop\njmp first.loop\n";
+        assert_eq!(
+            crate::compile(source).unwrap(),
+            vec![0xE9, 0xFD, 0xFF, 0xE9, 0xFD, 0xFF, 0xE9, 0xF7, 0xFF]
+        );
+
+        // Diagnostics refer to the local label by its short name.
+        match crate::compile("first:\njmp .missing\n") {
+            Err(crate::CompileError::CompileError(super::CompileError::UnresolvedReference(
+                label,
+                None,
+            ))) => assert_eq!(label.1, ".missing"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn suggestions() {
+        macro_rules! assert_message {
+            ($source:literal, $message:literal) => {
+                assert_eq!(crate::compile($source).unwrap_err().to_string(), $message)
+            };
+        }
+
+        assert_message!(
+            "jmp strat\nstart:\n",
+            "Unresolved reference: strat. Did you mean \"start\"?"
+        );
+        assert_message!(
+            "first:\n.loop: jmp .lop\n",
+            "Unresolved reference: .lop. Did you mean \".loop\"?"
+        );
+        assert_message!(
+            "mov ax, bz\n",
+            "Unresolved reference: bz. Did you mean \"bx\"?"
+        );
+        assert_message!("jmp elsewhere\n", "Unresolved reference: elsewhere");
+        assert_message!(
+            "struc point\n.x: resw 1\nendstruc\np: istruc pint\niend\n",
+            "Label \"pint\" not found. Did you mean \"point\"?"
+        );
+        assert_message!(
+            "mvo ax, 1\n",
+            "Unknown instruction \"mvo\". Did you mean \"mov\"?"
+        );
+    }
+
+    #[test]
+    fn short_jump_out_of_range() {
+        for source in [
+            "target:\ntimes 200 nop\njcxz target\n",
+            "jcxz target\ntimes 200 nop\ntarget:\n",
+        ] {
+            assert_eq!(crate::compile(source).unwrap_err().code(), "E0206");
+        }
+
+        // A target that is just in range.
+        assert_eq!(
+            crate::compile("jcxz target\ntimes 127 nop\ntarget:\n").unwrap()[..2],
+            [0xE3, 0x7F]
+        );
+    }
+
+    #[test]
+    fn label_offsets_do_not_settle() {
+        // The jump is only in range while it takes no space itself.
+        let err = crate::compile("jcxz target\ntimes 128 nop\ntarget:\n").unwrap_err();
+        assert_eq!(err.code(), "E0117");
+        assert_eq!(err.span(), &(0..11));
+    }
+
+    #[test]
+    fn anonymous_labels() {
+        let source = "@@: jmp @f\n@@: jmp @b\njmp @b\n";
+        assert_eq!(
+            crate::compile(source).unwrap(),
+            vec![0xE9, 0x00, 0x00, 0xE9, 0xFD, 0xFF, 0xE9, 0xFA, 0xFF]
+        );
+
+        // Anonymous labels do not start a new scope for local labels.
+        let source = "first:\n@@:\n.loop: jmp @b\njmp first.loop\n";
+        assert_eq!(
+            crate::compile(source).unwrap(),
+            vec![0xE9, 0xFD, 0xFF, 0xE9, 0xFA, 0xFF]
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            crate::compile("mov ax, 1 << 4 | 3").unwrap(),
+            vec![0xB8, 0x13, 0x00]
+        );
+        assert_eq!(
+            crate::compile("mov ax, 17 % 5 + (3 > 2) + (2 == 2 && 0 || 1)").unwrap(),
+            vec![0xB8, 0x04, 0x00]
+        );
+        assert_eq!(
+            crate::compile("mov ax, 100 // -10 + 20").unwrap(),
+            vec![0xB8, 0x0A, 0x00]
+        );
+        assert_eq!(
+            crate::compile("mov ax, ~0 & 0xFF").unwrap(),
+            vec![0xB8, 0xFF, 0x00]
+        );
+
+        match crate::compile("mov ax, 1 + 2 / (1 - 1)") {
+            Err(crate::CompileError::CompileError(super::CompileError::DivisionByZero(span))) => {
+                assert_eq!(span, 12..23)
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn data() {
+        assert_eq!(
+            crate::compile("db 1, -1, 'ab'\ndw 0x1234, 'abc'").unwrap(),
+            vec![0x01, 0xFF, b'a', b'b', 0x34, 0x12, b'a', b'b', b'c', 0x00]
+        );
+
+        match crate::compile("db 256") {
+            Err(crate::CompileError::CompileError(
+                super::CompileError::ImmediateValueOutOfRange(span, 256),
+            )) => assert_eq!(span, 3..6),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn large_values() {
+        assert_eq!(
+            crate::compile("dd 0x1234_5678, -1\ndq 1 << 40\ndb 'ab'\nmov ax, (1 << 40) >> 32")
+                .unwrap(),
+            vec![
+                0x78, 0x56, 0x34, 0x12, 0xFF, 0xFF, 0xFF, 0xFF, // dd
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, // dq
+                0x61, 0x62, // db
+                0xB8, 0x00, 0x01, // mov ax, 0x100
+            ]
+        );
+
+        assert!(matches!(
+            crate::compile("dd 0x1_0000_0000"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::ImmediateValueOutOfRange(_, 0x1_0000_0000)
+            ))
+        ));
+
+        assert!(matches!(
+            crate::compile("mov ax, 1 << 32"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::ImmediateValueOutOfRange(_, 0x1_0000_0000)
+            ))
+        ));
+
+        assert!(matches!(
+            crate::compile("mov dword [0x100], 1"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::MOV, Cpu::I386)
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("mov qword [0x100], 1"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::EncodeError(EncodeError::InvalidOperandSize(..))
+            ))
+        ));
+    }
+
+    #[test]
+    fn cpu() {
+        assert_eq!(crate::compile("cpu 186\nnop").unwrap(), vec![0x90]);
+
+        assert!(matches!(
+            crate::compile("cpu 8086\nshl ax, 4"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::SHL, Cpu::I186)
+            ))
+        ));
+        assert_eq!(
+            crate::compile("cpu 8086\ncpu 186\nshl ax, 4").unwrap(),
+            vec![0xC1, 0xE0, 0x04]
+        );
+
+        assert_eq!(crate::compile("times 2 cpu 186\nnop").unwrap(), vec![0x90]);
+
+        assert!(matches!(
+            crate::compile("pusha"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::PUSHA, Cpu::I186)
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("shl ax, 4"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::SHL, Cpu::I186)
+            ))
+        ));
+        assert_eq!(crate::compile("shl ax, 1").unwrap(), vec![0xD1, 0xE0]);
+        assert_eq!(
+            crate::compile("cpu 186\npusha\nshl ax, 4\nimul ax, bx, 3\npush 1\ncpu 8086\npopf")
+                .unwrap(),
+            vec![0x60, 0xC1, 0xE0, 0x04, 0x6B, 0xC3, 0x03, 0x6A, 0x01, 0x9D]
+        );
+        assert!(matches!(
+            crate::compile("cpu 186\nnop\ncpu 8086\npush 1"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::PUSH, Cpu::I186)
+            ))
+        ));
+
+        assert!(matches!(
+            crate::compile("cpu 186\nlgdt [0x2000]"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::LGDT, Cpu::I286)
+            ))
+        ));
+        assert_eq!(
+            crate::compile("cpu 286\nlgdt [0x2000]\nsmsw ax\nlar ax, [bx]\narpl ax, dx\nclts")
+                .unwrap(),
+            vec![
+                0x0F, 0x01, 0x16, 0x00, 0x20, 0x0F, 0x01, 0xE0, 0x0F, 0x02, 0x07, 0x63, 0xD0, 0x0F,
+                0x06
+            ]
+        );
+    }
+
+    #[test]
+    fn fpu() {
+        // The 8087 needs a WAIT before every instruction, except the "no wait" variants.
+        assert_eq!(
+            crate::compile("fninit\nfld dword [0x10]\nfaddp st1, st0\nfstsw [0x20]").unwrap(),
+            vec![
+                0xDB, 0xE3, 0x9B, 0xD9, 0x06, 0x10, 0x00, 0x9B, 0xDE, 0xC1, 0x9B, 0xDD, 0x3E, 0x20,
+                0x00
+            ]
+        );
+        assert_eq!(
+            crate::compile("cpu 286\nfninit\nfld dword [0x10]\nfaddp st1, st0\nfstsw ax").unwrap(),
+            vec![0xDB, 0xE3, 0xD9, 0x06, 0x10, 0x00, 0xDE, 0xC1, 0x9B, 0xDF, 0xE0]
+        );
+
+        // fwait is another name for wait.
+        assert_eq!(
+            crate::compile("fnstcw [0x10]\nfwait\nwait").unwrap(),
+            vec![0xD9, 0x3E, 0x10, 0x00, 0x9B, 0x9B]
+        );
+
+        // Labels after FPU instructions include the WAIT prefix.
+        assert_eq!(
+            crate::compile("fldz\njmp next\nnext:").unwrap(),
+            vec![0x9B, 0xD9, 0xEE, 0xE9, 0x00, 0x00]
+        );
+
+        assert!(matches!(
+            crate::compile("fnstsw ax"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::FNSTSW, Cpu::I286)
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("mov ax, qword [0x10]"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::EncodeError(EncodeError::InvalidOperandSize(_))
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("mov ax, st0"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::EncodeError(EncodeError::InvalidOperands(_))
+            ))
+        ));
+    }
+
+    #[test]
+    fn segment_override() {
+        assert_eq!(
+            crate::compile("cs lodsw\nmov ax, es:[bx]\nmovs word [di], [ss:si]\nrep es movsb")
+                .unwrap(),
+            vec![0x2E, 0xAD, 0x26, 0x8B, 0x07, 0x36, 0xA5, 0xF3, 0x26, 0xA4]
+        );
+        assert_eq!(
+            crate::compile("xlatb\ncs xlatb\nes rep movsb\nss repne cmpsb").unwrap(),
+            vec![0xD7, 0x2E, 0xD7, 0xF3, 0x26, 0xA4, 0xF2, 0x36, 0xA6]
+        );
+
+        assert!(matches!(
+            crate::compile("es stosb"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::EncodeError(EncodeError::InvalidSegmentOverride(_))
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("cpu 186\nouts dx, byte [fs:si]"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::OUTS, Cpu::I386)
+            ))
+        ));
+    }
+
+    #[test]
+    fn v20() {
+        assert_eq!(
+            crate::compile("cpu v20\nrol4 al\nset1 bx, 3\nadd4s\npusha").unwrap(),
+            vec![0x0F, 0x28, 0xC0, 0x0F, 0x1D, 0xC3, 0x03, 0x0F, 0x20, 0x60]
+        );
+
+        assert!(matches!(
+            crate::compile("cpu 286\nnot1 al, cl"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::NOT1, Cpu::V20)
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("cpu v20\nlgdt [0x2000]"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::LGDT, Cpu::I286)
+            ))
+        ));
+    }
+
+    #[test]
+    fn bits() {
+        assert_eq!(
+            crate::compile("cpu 386\nmov eax, 0xFFFFFFFF\nmov eax, [ebx+4]\npush fs").unwrap(),
+            vec![0x66, 0xB8, 0xFF, 0xFF, 0xFF, 0xFF, 0x67, 0x66, 0x8B, 0x43, 0x04, 0x0F, 0xA0]
+        );
+        assert_eq!(
+            crate::compile("cpu 386\nbits 32\nmov eax, [0x2000]\nmov ax, bx\njmp next\nnext:")
+                .unwrap(),
+            vec![0xA1, 0x00, 0x20, 0x00, 0x00, 0x66, 0x89, 0xD8, 0xE9, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            crate::compile("cpu 386\npush dword 5\npush dword 0x12345678\npush word 0x1234")
+                .unwrap(),
+            vec![0x66, 0x6A, 0x05, 0x66, 0x68, 0x78, 0x56, 0x34, 0x12, 0x68, 0x34, 0x12]
+        );
+
+        let mut compiler = Compiler::default();
+        compiler.set_bits(ast::Bits::Bits32);
+        assert_eq!(compiler.bits, ast::Bits::Bits32);
+
+        assert!(matches!(
+            crate::compile("mov eax, 1"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::MOV, Cpu::I386)
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("mov ax, [fs:0x10]"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InstructionRequiresCpu(_, Operation::MOV, Cpu::I386)
+            ))
+        ));
+    }
+
+    #[test]
+    fn align() {
+        assert_eq!(
+            crate::compile("nop\nalign 4\ndb 1\nalign 4\nalignb 4\ndb 2\nalign 4, 0xFF").unwrap(),
+            vec![0x90, 0x90, 0x90, 0x90, 0x01, 0x00, 0x00, 0x00, 0x02, 0xFF, 0xFF, 0xFF]
+        );
+
+        // Labels before an alignment point to the aligned offset.
+        assert_eq!(
+            crate::compile("jmp table\ntable:\nalign 8\ndw table").unwrap(),
+            vec![0xE9, 0x05, 0x00, 0x90, 0x90, 0x90, 0x90, 0x90, 0x08, 0x00]
+        );
+
+        assert!(matches!(
+            crate::compile("align 3"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::InvalidAlignment(_, 3)
+            ))
+        ));
+    }
+
+    #[test]
+    fn structures() {
+        let source = "struc point\n  .x: resw 1\n  .y: resw 1\n  .tag: resb 3\nendstruc\n\
+                      mov ax, point_size\n\
+                      mov bx, [origin + point.y]\n\
+                      origin: istruc point\n  at point.x, dw 1\n  at point.tag, db 'a'\niend\n\
+                      db 0xFF";
+        assert_eq!(
+            crate::compile(source).unwrap(),
+            vec![
+                0xB8, 0x07, 0x00, // mov ax, 7
+                0x8B, 0x1E, 0x09, 0x00, // mov bx, [9]
+                0x01, 0x00, 0x00, 0x00, 0x61, 0x00, 0x00, // origin
+                0xFF,
+            ]
+        );
+
+        assert!(matches!(
+            crate::compile(
+                "struc point\n  .x: resw 1\n  .y: resw 1\nendstruc\n\
+                 istruc point\n  at point.y, dw 1\n  at point.x, dw 2\niend"
+            ),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::StructFieldOverlap(..)
+            ))
+        ));
+
+        assert!(matches!(
+            crate::compile(
+                "struc point\n  .x: resw 1\nendstruc\n\
+                 istruc point\n  at point.x, dw 1, 2\niend"
+            ),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::StructInstanceTooLarge(..)
+            ))
+        ));
+
+        // Structure sizes have to fit in a word.
+        for (source, code, span) in [
+            (
+                "struc pt\n.x: resq 0x7FFFFFFFFFFFFFFF\nendstruc",
+                "E0118",
+                9..36,
+            ),
+            ("struc pt\n.x: resw 0x8000\nendstruc", "E0118", 9..24),
+            (
+                "struc pt\n.x: resb 0x8000\n.y: resb 0x8000\nendstruc",
+                "E0118",
+                25..40,
+            ),
+            (
+                "struc pt\n.x: resb -1\nendstruc\nistruc pt\niend",
+                "E0106",
+                18..20,
+            ),
+            (
+                "struc pt\n.x: resb 100\nendstruc\ntimes 1000 istruc pt\niend",
+                "E0118",
+                42..56,
+            ),
+        ] {
+            let err = crate::compile(source).unwrap_err();
+            assert_eq!((err.code(), err.span()), (code, &span), "{}", source);
+        }
+
+        // The largest structure that fits.
+        let source = "struc pt\n.x: resb 0xFFFF\nendstruc\nmov ax, pt_size";
+        assert_eq!(crate::compile(source).unwrap(), vec![0xB8, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn expression_functions() {
+        let source = "table: db low(target), high(target)\ntimes 0x1230 nop\ntarget: hlt\n";
+        assert_eq!(&crate::compile(source).unwrap()[..2], &[0x32, 0x12]);
+
+        let source = "mov ax, sizeof(msg)\nmov ax, lengthof msg\nmsg: dw 1, 2, 3\n";
+        assert_eq!(
+            crate::compile(source).unwrap(),
+            vec![0xB8, 0x06, 0x00, 0xB8, 0x03, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00]
+        );
+
+        fn compile_with_base_segment(source: &str) -> Result<Vec<u8>, super::CompileError> {
+            let mut parser = Parser::new(source);
+            let mut compiler = Compiler::default();
+            compiler.set_base_segment(0x1000);
+            while let Some(line) = parser.parse_line().unwrap() {
+                compiler.push_line(line)?;
+            }
+            compiler.compile()
+        }
+
+        let source = "mov ax, seg start\nmov ax, start wrt 0x0FFF\nstart: hlt\n";
+        assert_eq!(
+            compile_with_base_segment(source).unwrap(),
+            vec![0xB8, 0x00, 0x10, 0xB8, 0x16, 0x00, 0xF4]
+        );
+
+        // The offset relative to the segment of the output stays the same.
+        let source = "mov ax, start wrt seg start\nstart: hlt\n";
+        assert_eq!(
+            crate::compile(source).unwrap(),
+            vec![0xB8, 0x03, 0x00, 0xF4]
+        );
+
+        assert!(matches!(
+            crate::compile("mov ax, seg start\nstart: hlt\n"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::SegmentNotKnown(_)
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("value equ 10\nmov ax, seg value\n"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::ExpressionHasNoSegment(_)
+            ))
+        ));
+        assert!(matches!(
+            crate::compile("mov ax, sizeof start\nstart: hlt\n"),
+            Err(crate::CompileError::CompileError(
+                super::CompileError::DataLabelExpected(_)
+            ))
+        ));
+    }
+
     #[test]
     fn compile() {
         // compile_test!("../tests/calljmp.asm", "../tests/calljmp.bin");
@@ -0,0 +1,1674 @@
+//! Decodes machine code back into [ast::Instruction]s, the reverse of what the encoder does.
+
+use crate::ast;
+use crate::operations::Operation;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum DecodeError {
+    UnexpectedEndOfInput(ast::Span),
+    InvalidOpCode(ast::Span),
+    InvalidPrefix(ast::Span),
+}
+
+impl DecodeError {
+    pub fn span(&self) -> &ast::Span {
+        match self {
+            DecodeError::UnexpectedEndOfInput(span)
+            | DecodeError::InvalidOpCode(span)
+            | DecodeError::InvalidPrefix(span) => span,
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEndOfInput(_) => write!(f, "Unexpected end of input."),
+            DecodeError::InvalidOpCode(_) => write!(f, "Invalid or unsupported op code."),
+            DecodeError::InvalidPrefix(_) => {
+                write!(f, "The prefix does not apply to the instruction.")
+            }
+        }
+    }
+}
+
+/// An instruction decoded from a byte stream.
+#[derive(Debug)]
+pub struct DecodedInstruction {
+    /// The address of the first byte of the instruction, including its prefixes.
+    pub offset: u16,
+    /// The number of bytes the instruction takes up.
+    pub size: u16,
+    /// All the spans in the instruction cover its bytes in the input.
+    pub instruction: ast::Instruction,
+}
+
+/// Decodes the instructions in a byte stream one by one.  After an error, decoding continues at
+/// the byte following the first byte of the failed instruction.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    origin: u16,
+    position: usize,
+    bits: ast::Bits,
+}
+
+impl<'a> Decoder<'a> {
+    /// Create a decoder for the given bytes, with the first byte located at [origin].
+    pub fn new(bytes: &'a [u8], origin: u16) -> Self {
+        Self {
+            bytes,
+            origin,
+            position: 0,
+            bits: ast::Bits::default(),
+        }
+    }
+
+    pub fn set_bits(&mut self, bits: ast::Bits) {
+        self.bits = bits;
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Result<DecodedInstruction, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.bytes.len() {
+            return None;
+        }
+
+        let decoder = InstructionDecoder::new(self.bytes, self.position, self.origin, self.bits);
+        match decoder.decode() {
+            Ok(decoded) => {
+                self.position += decoded.size as usize;
+                Some(Ok(decoded))
+            }
+            Err(err) => {
+                self.position += 1;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Decode all the instructions in [bytes], with the first byte located at [origin].
+pub fn decode(bytes: &[u8], origin: u16) -> Result<Vec<DecodedInstruction>, DecodeError> {
+    Decoder::new(bytes, origin).collect()
+}
+
+/// Operands are built before the size of the instruction is known, so they get this span until
+/// the whole instruction is decoded.
+const PENDING_SPAN: ast::Span = 0..0;
+
+const BYTE_REGISTERS: [ast::ByteRegister; 8] = [
+    ast::ByteRegister::Al,
+    ast::ByteRegister::Cl,
+    ast::ByteRegister::Dl,
+    ast::ByteRegister::Bl,
+    ast::ByteRegister::Ah,
+    ast::ByteRegister::Ch,
+    ast::ByteRegister::Dh,
+    ast::ByteRegister::Bh,
+];
+
+const WORD_REGISTERS: [ast::WordRegister; 8] = [
+    ast::WordRegister::Ax,
+    ast::WordRegister::Cx,
+    ast::WordRegister::Dx,
+    ast::WordRegister::Bx,
+    ast::WordRegister::Sp,
+    ast::WordRegister::Bp,
+    ast::WordRegister::Si,
+    ast::WordRegister::Di,
+];
+
+const DWORD_REGISTERS: [ast::DWordRegister; 8] = [
+    ast::DWordRegister::Eax,
+    ast::DWordRegister::Ecx,
+    ast::DWordRegister::Edx,
+    ast::DWordRegister::Ebx,
+    ast::DWordRegister::Esp,
+    ast::DWordRegister::Ebp,
+    ast::DWordRegister::Esi,
+    ast::DWordRegister::Edi,
+];
+
+const SEGMENTS: [ast::Segment; 6] = [
+    ast::Segment::ES,
+    ast::Segment::CS,
+    ast::Segment::SS,
+    ast::Segment::DS,
+    ast::Segment::FS,
+    ast::Segment::GS,
+];
+
+const INDIRECT_ENCODINGS: [ast::IndirectEncoding; 8] = [
+    ast::IndirectEncoding::BxSi,
+    ast::IndirectEncoding::BxDi,
+    ast::IndirectEncoding::BpSi,
+    ast::IndirectEncoding::BpDi,
+    ast::IndirectEncoding::Si,
+    ast::IndirectEncoding::Di,
+    ast::IndirectEncoding::Bp,
+    ast::IndirectEncoding::Bx,
+];
+
+/// The operations in the rows of 0x00 to 0x3F and the [reg] values of the 0x80 to 0x83 group.
+const ALU_OPERATIONS: [Operation; 8] = [
+    Operation::ADD,
+    Operation::OR,
+    Operation::ADC,
+    Operation::SBB,
+    Operation::AND,
+    Operation::SUB,
+    Operation::XOR,
+    Operation::CMP,
+];
+
+/// The [reg] values of the shift group, /6 is not used.
+const SHIFT_OPERATIONS: [Option<Operation>; 8] = [
+    Some(Operation::ROL),
+    Some(Operation::ROR),
+    Some(Operation::RCL),
+    Some(Operation::RCR),
+    Some(Operation::SHL),
+    Some(Operation::SHR),
+    None,
+    Some(Operation::SAR),
+];
+
+const JUMP_OPERATIONS: [Operation; 16] = [
+    Operation::JO,
+    Operation::JNO,
+    Operation::JB,
+    Operation::JNB,
+    Operation::JE,
+    Operation::JNE,
+    Operation::JBE,
+    Operation::JNBE,
+    Operation::JS,
+    Operation::JNS,
+    Operation::JP,
+    Operation::JNP,
+    Operation::JL,
+    Operation::JNL,
+    Operation::JLE,
+    Operation::JNLE,
+];
+
+/// The [reg] values of the floating point arithmetic op codes 0xD8 and 0xDC.
+const FPU_OPERATIONS: [Operation; 8] = [
+    Operation::FADD,
+    Operation::FMUL,
+    Operation::FCOM,
+    Operation::FCOMP,
+    Operation::FSUB,
+    Operation::FSUBR,
+    Operation::FDIV,
+    Operation::FDIVR,
+];
+
+/// The [reg] values of the integer arithmetic op codes 0xDA and 0xDE.
+const FPU_INTEGER_OPERATIONS: [Operation; 8] = [
+    Operation::FIADD,
+    Operation::FIMUL,
+    Operation::FICOM,
+    Operation::FICOMP,
+    Operation::FISUB,
+    Operation::FISUBR,
+    Operation::FIDIV,
+    Operation::FIDIVR,
+];
+
+/// The operations without operands in the 0xD9 op code, indexed by the mod reg r/m byte - 0xD0.
+const FPU_D9_OPERATIONS: [Option<Operation>; 48] = {
+    let mut operations = [None; 48];
+    operations[0x00] = Some(Operation::FNOP);
+    operations[0x10] = Some(Operation::FCHS);
+    operations[0x11] = Some(Operation::FABS);
+    operations[0x14] = Some(Operation::FTST);
+    operations[0x15] = Some(Operation::FXAM);
+    operations[0x18] = Some(Operation::FLD1);
+    operations[0x19] = Some(Operation::FLDL2T);
+    operations[0x1A] = Some(Operation::FLDL2E);
+    operations[0x1B] = Some(Operation::FLDPI);
+    operations[0x1C] = Some(Operation::FLDLG2);
+    operations[0x1D] = Some(Operation::FLDLN2);
+    operations[0x1E] = Some(Operation::FLDZ);
+    operations[0x20] = Some(Operation::F2XM1);
+    operations[0x21] = Some(Operation::FYL2X);
+    operations[0x22] = Some(Operation::FPTAN);
+    operations[0x23] = Some(Operation::FPATAN);
+    operations[0x24] = Some(Operation::FXTRACT);
+    operations[0x26] = Some(Operation::FDECSTP);
+    operations[0x27] = Some(Operation::FINCSTP);
+    operations[0x28] = Some(Operation::FPREM);
+    operations[0x29] = Some(Operation::FYL2XP1);
+    operations[0x2A] = Some(Operation::FSQRT);
+    operations[0x2C] = Some(Operation::FRNDINT);
+    operations[0x2D] = Some(Operation::FSCALE);
+    operations
+};
+
+type Decoded = Result<(Operation, Vec<ast::Operand>), DecodeError>;
+
+#[derive(Clone, Copy)]
+struct ModRegRM {
+    mode: u8,
+    reg: u8,
+    rm: u8,
+}
+
+/// Decodes a single instruction, including its prefixes.
+struct InstructionDecoder<'a> {
+    bytes: &'a [u8],
+    start: usize,
+    position: usize,
+    origin: u16,
+    bits: ast::Bits,
+    operand_size_prefix: bool,
+    address_size_prefix: bool,
+    segment: Option<ast::Segment>,
+    segment_used: bool,
+    /// Set when a WAIT is merged with the following floating point instruction.
+    wait: bool,
+}
+
+impl<'a> InstructionDecoder<'a> {
+    fn new(bytes: &'a [u8], start: usize, origin: u16, bits: ast::Bits) -> Self {
+        Self {
+            bytes,
+            start,
+            position: start,
+            origin,
+            bits,
+            operand_size_prefix: false,
+            address_size_prefix: false,
+            segment: None,
+            segment_used: false,
+            wait: false,
+        }
+    }
+
+    fn decode(mut self) -> Result<DecodedInstruction, DecodeError> {
+        let (operation, operands) = self.decode_prefixes_and_op_code()?;
+
+        // A segment override that was not used by any memory operand.
+        if self.segment.is_some() && !self.segment_used {
+            return Err(DecodeError::InvalidPrefix(self.span()));
+        }
+
+        let span = self.span();
+
+        let mut operands = operands.into_iter().map(|mut operand| {
+            set_operand_span(&mut operand, &span);
+            operand
+        });
+        let operands = match (operands.next(), operands.next(), operands.next()) {
+            (None, _, _) => ast::Operands::None(span.clone()),
+            (Some(destination), None, _) => ast::Operands::Destination(span.clone(), destination),
+            (Some(destination), Some(source), None) => {
+                ast::Operands::DestinationAndSource(span.clone(), destination, source)
+            }
+            (Some(destination), Some(source), Some(third)) => {
+                ast::Operands::DestinationSourceAndThird(span.clone(), destination, source, third)
+            }
+        };
+
+        Ok(DecodedInstruction {
+            offset: self.origin.wrapping_add(self.start as u16),
+            size: (self.position - self.start) as u16,
+            instruction: ast::Instruction {
+                span,
+                operation,
+                operands,
+            },
+        })
+    }
+
+    fn span(&self) -> ast::Span {
+        self.start..self.position
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        match self.bytes.get(self.position) {
+            Some(byte) => {
+                self.position += 1;
+                Ok(*byte)
+            }
+            None => Err(DecodeError::UnexpectedEndOfInput(self.span())),
+        }
+    }
+
+    fn word(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes([self.byte()?, self.byte()?]))
+    }
+
+    fn dword(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes([
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+        ]))
+    }
+
+    fn mod_reg_rm(&mut self) -> Result<ModRegRM, DecodeError> {
+        let byte = self.byte()?;
+        Ok(ModRegRM {
+            mode: byte >> 6,
+            reg: (byte >> 3) & 0x07,
+            rm: byte & 0x07,
+        })
+    }
+
+    fn invalid_op_code<T>(&self) -> Result<T, DecodeError> {
+        Err(DecodeError::InvalidOpCode(self.span()))
+    }
+
+    fn operand_size(&self) -> ast::DataSize {
+        match (self.bits, self.operand_size_prefix) {
+            (ast::Bits::Bits16, false) | (ast::Bits::Bits32, true) => ast::DataSize::Word,
+            _ => ast::DataSize::DWord,
+        }
+    }
+
+    fn address_size_is_32(&self) -> bool {
+        matches!(
+            (self.bits, self.address_size_prefix),
+            (ast::Bits::Bits16, true) | (ast::Bits::Bits32, false)
+        )
+    }
+
+    /// Pick the 16-bit or 32-bit variant of an operation by the operand size.
+    fn sized(&self, word: Operation, dword: Operation) -> Operation {
+        if self.operand_size() == ast::DataSize::DWord {
+            dword
+        } else {
+            word
+        }
+    }
+
+    /// Pick the variant of a floating point control instruction by whether it was preceded by a
+    /// WAIT.
+    fn waited(&self, wait: Operation, no_wait: Operation) -> Operation {
+        if self.wait {
+            wait
+        } else {
+            no_wait
+        }
+    }
+
+    fn take_segment(&mut self) -> Option<ast::Segment> {
+        self.segment_used = true;
+        self.segment
+    }
+
+    /// Is the WAIT at the current position followed by one of the floating point control
+    /// instructions that have a form with and without a WAIT, e.g. FINIT and FNINIT?
+    fn is_wait_form(&self) -> bool {
+        let mut rest = self.bytes[self.position..]
+            .iter()
+            .skip_while(|b| matches!(b, 0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65 | 0x66 | 0x67));
+
+        match (rest.next(), rest.next()) {
+            (Some(0xDB), Some(0xE0..=0xE3)) | (Some(0xDF), Some(0xE0)) => true,
+            (Some(0xD9 | 0xDD), Some(modrm)) => modrm >> 6 != 0b11 && (modrm >> 3) & 0x07 >= 6,
+            _ => false,
+        }
+    }
+
+    fn decode_prefixes_and_op_code(&mut self) -> Decoded {
+        loop {
+            let op_code = self.byte()?;
+            match op_code {
+                0x26 | 0x2E | 0x36 | 0x3E => {
+                    self.segment = Some(SEGMENTS[(op_code >> 3) as usize & 0x03])
+                }
+                0x64 => self.segment = Some(ast::Segment::FS),
+                0x65 => self.segment = Some(ast::Segment::GS),
+                0x66 => self.operand_size_prefix = true,
+                0x67 => self.address_size_prefix = true,
+                0x9B if !self.wait && self.is_wait_form() => self.wait = true,
+                _ => return self.decode_op_code(op_code),
+            }
+        }
+    }
+
+    fn decode_op_code(&mut self, op_code: u8) -> Decoded {
+        use ast::DataSize::{Byte, Word};
+
+        let size = self.operand_size();
+
+        match op_code {
+            0x00..=0x3F if op_code & 0x07 < 6 => {
+                self.decode_alu(ALU_OPERATIONS[(op_code >> 3) as usize], op_code & 0x07)
+            }
+
+            0x06 | 0x0E | 0x16 | 0x1E => Ok((Operation::PUSH, vec![segment(op_code >> 3)])),
+            0x07 | 0x17 | 0x1F => Ok((Operation::POP, vec![segment(op_code >> 3)])),
+            0x0F => self.decode_extended(),
+
+            0x27 => Ok((Operation::DAA, vec![])),
+            0x2F => Ok((Operation::DAS, vec![])),
+            0x37 => Ok((Operation::AAA, vec![])),
+            0x3F => Ok((Operation::AAS, vec![])),
+
+            0x40..=0x47 => Ok((Operation::INC, vec![register(size, op_code)])),
+            0x48..=0x4F => Ok((Operation::DEC, vec![register(size, op_code)])),
+            0x50..=0x57 => Ok((Operation::PUSH, vec![register(size, op_code)])),
+            0x58..=0x5F => Ok((Operation::POP, vec![register(size, op_code)])),
+
+            0x60 => Ok((self.sized(Operation::PUSHA, Operation::PUSHAD), vec![])),
+            0x61 => Ok((self.sized(Operation::POPA, Operation::POPAD), vec![])),
+            0x62 => self.decode_reg_memory(Operation::BOUND, size),
+            0x63 => self.decode_rm_reg(Operation::ARPL, Word),
+            0x68 => Ok((Operation::PUSH, vec![self.immediate(size)?])),
+            0x69 | 0x6B => {
+                let modrm = self.mod_reg_rm()?;
+                let rm = self.rm_operand(modrm, size, None)?;
+                let imm = if op_code == 0x6B {
+                    self.signed_immediate_byte()?
+                } else {
+                    self.immediate(size)?
+                };
+                Ok((Operation::IMUL, vec![register(size, modrm.reg), rm, imm]))
+            }
+            0x6A => Ok((Operation::PUSH, vec![self.signed_immediate_byte()?])),
+            0x6C => self.decode_string(Operation::INSB),
+            0x6D => self.decode_string(self.sized(Operation::INSW, Operation::INSD)),
+            0x6E => self.decode_string(Operation::OUTSB),
+            0x6F => self.decode_string(self.sized(Operation::OUTSW, Operation::OUTSD)),
+
+            0x70..=0x7F => Ok((
+                JUMP_OPERATIONS[(op_code & 0x0F) as usize],
+                vec![self.relative_byte()?],
+            )),
+
+            0x80..=0x83 => {
+                let modrm = self.mod_reg_rm()?;
+                let size = if op_code & 0x01 == 0 { Byte } else { size };
+                let rm = self.rm_operand(modrm, size, Some(size))?;
+                let imm = if op_code == 0x83 {
+                    self.signed_immediate_byte()?
+                } else {
+                    self.immediate(size)?
+                };
+                Ok((ALU_OPERATIONS[modrm.reg as usize], vec![rm, imm]))
+            }
+
+            0x84 => self.decode_rm_reg(Operation::TEST, Byte),
+            0x85 => self.decode_rm_reg(Operation::TEST, size),
+            0x86 => self.decode_rm_reg(Operation::XCHG, Byte),
+            0x87 => self.decode_rm_reg(Operation::XCHG, size),
+            0x88 => self.decode_rm_reg(Operation::MOV, Byte),
+            0x89 => self.decode_rm_reg(Operation::MOV, size),
+            0x8A => self.decode_reg_rm(Operation::MOV, Byte),
+            0x8B => self.decode_reg_rm(Operation::MOV, size),
+            0x8C | 0x8E => {
+                let modrm = self.mod_reg_rm()?;
+                if modrm.reg as usize >= SEGMENTS.len() {
+                    return self.invalid_op_code();
+                }
+                let rm = self.rm_operand(modrm, Word, None)?;
+                let segment = segment(modrm.reg);
+                if op_code == 0x8C {
+                    Ok((Operation::MOV, vec![rm, segment]))
+                } else {
+                    Ok((Operation::MOV, vec![segment, rm]))
+                }
+            }
+            0x8D => self.decode_reg_memory(Operation::LEA, size),
+            0x8F => {
+                let modrm = self.mod_reg_rm()?;
+                if modrm.reg != 0 {
+                    return self.invalid_op_code();
+                }
+                Ok((
+                    Operation::POP,
+                    vec![self.rm_operand(modrm, size, Some(size))?],
+                ))
+            }
+
+            0x90 => Ok((Operation::NOP, vec![])),
+            0x91..=0x97 => Ok((
+                Operation::XCHG,
+                vec![register(size, 0), register(size, op_code)],
+            )),
+            0x98 => Ok((self.sized(Operation::CBW, Operation::CWDE), vec![])),
+            0x99 => Ok((self.sized(Operation::CWD, Operation::CDQ), vec![])),
+            0x9A => Ok((Operation::CALL, vec![self.far_pointer()?])),
+            0x9B => Ok((Operation::WAIT, vec![])),
+            0x9C => Ok((self.sized(Operation::PUSHF, Operation::PUSHFD), vec![])),
+            0x9D => Ok((self.sized(Operation::POPF, Operation::POPFD), vec![])),
+            0x9E => Ok((Operation::SAHF, vec![])),
+            0x9F => Ok((Operation::LAHF, vec![])),
+
+            0xA0..=0xA3 => {
+                let size = if op_code & 0x01 == 0 { Byte } else { size };
+                let memory = self.memory_offset()?;
+                if op_code < 0xA2 {
+                    Ok((Operation::MOV, vec![register(size, 0), memory]))
+                } else {
+                    Ok((Operation::MOV, vec![memory, register(size, 0)]))
+                }
+            }
+            0xA4 => self.decode_string(Operation::MOVSB),
+            0xA5 => self.decode_string(self.sized(Operation::MOVSW, Operation::MOVSD)),
+            0xA6 => self.decode_string(Operation::CMPSB),
+            0xA7 => self.decode_string(self.sized(Operation::CMPSW, Operation::CMPSD)),
+            0xA8 => Ok((
+                Operation::TEST,
+                vec![register(Byte, 0), self.immediate(Byte)?],
+            )),
+            0xA9 => Ok((
+                Operation::TEST,
+                vec![register(size, 0), self.immediate(size)?],
+            )),
+            0xAA => self.decode_string(Operation::STOSB),
+            0xAB => self.decode_string(self.sized(Operation::STOSW, Operation::STOSD)),
+            0xAC => self.decode_string(Operation::LODSB),
+            0xAD => self.decode_string(self.sized(Operation::LODSW, Operation::LODSD)),
+            0xAE => self.decode_string(Operation::SCASB),
+            0xAF => self.decode_string(self.sized(Operation::SCASW, Operation::SCASD)),
+
+            0xB0..=0xB7 => Ok((
+                Operation::MOV,
+                vec![register(Byte, op_code), self.immediate(Byte)?],
+            )),
+            0xB8..=0xBF => Ok((
+                Operation::MOV,
+                vec![register(size, op_code), self.immediate(size)?],
+            )),
+
+            0xC0 | 0xC1 | 0xD0..=0xD3 => self.decode_shift(op_code),
+
+            0xC2 => Ok((Operation::RET, vec![self.immediate(Word)?])),
+            0xC3 => Ok((Operation::RET, vec![])),
+            0xC4 => self.decode_reg_memory(Operation::LES, size),
+            0xC5 => self.decode_reg_memory(Operation::LDS, size),
+            0xC6 | 0xC7 => {
+                let modrm = self.mod_reg_rm()?;
+                if modrm.reg != 0 {
+                    return self.invalid_op_code();
+                }
+                let size = if op_code == 0xC6 { Byte } else { size };
+                let rm = self.rm_operand(modrm, size, Some(size))?;
+                Ok((Operation::MOV, vec![rm, self.immediate(size)?]))
+            }
+            0xC8 => Ok((
+                Operation::ENTER,
+                vec![self.immediate(Word)?, self.immediate(Byte)?],
+            )),
+            0xC9 => Ok((Operation::LEAVE, vec![])),
+            0xCC => Ok((Operation::INT3, vec![])),
+            0xCD => Ok((Operation::INT, vec![self.immediate(Byte)?])),
+            0xCE => Ok((Operation::INTO, vec![])),
+            0xCF => Ok((self.sized(Operation::IRET, Operation::IRETD), vec![])),
+
+            0xD4 | 0xD5 => {
+                let operation = if op_code == 0xD4 {
+                    Operation::AAM
+                } else {
+                    Operation::AAD
+                };
+                // The base of 10 is implied when there is no operand.
+                match self.byte()? {
+                    0x0A => Ok((operation, vec![])),
+                    base => Ok((operation, vec![constant(base as i64)])),
+                }
+            }
+            0xD6 => Ok((Operation::SALC, vec![])),
+            0xD7 => {
+                let memory = ast::Operand::Indirect(
+                    PENDING_SPAN,
+                    ast::IndirectEncoding::Bx,
+                    None,
+                    Some(Word),
+                    self.take_segment(),
+                );
+                Ok((Operation::XLATB, vec![memory]))
+            }
+            0xD8..=0xDF => self.decode_fpu(op_code),
+
+            0xE0 => Ok((Operation::LOOPNZ, vec![self.relative_byte()?])),
+            0xE1 => Ok((Operation::LOOPZ, vec![self.relative_byte()?])),
+            0xE2 => Ok((Operation::LOOP, vec![self.relative_byte()?])),
+            0xE3 => Ok((Operation::JCXZ, vec![self.relative_byte()?])),
+            0xE4 => Ok((
+                Operation::IN,
+                vec![register(Byte, 0), self.immediate(Byte)?],
+            )),
+            0xE5 => Ok((
+                Operation::IN,
+                vec![register(size, 0), self.immediate(Byte)?],
+            )),
+            0xE6 => Ok((
+                Operation::OUT,
+                vec![self.immediate(Byte)?, register(Byte, 0)],
+            )),
+            0xE7 => Ok((
+                Operation::OUT,
+                vec![self.immediate(Byte)?, register(size, 0)],
+            )),
+            0xE8 => Ok((Operation::CALL, vec![self.relative()?])),
+            0xE9 => Ok((Operation::JMP, vec![self.relative()?])),
+            0xEA => Ok((Operation::JMP, vec![self.far_pointer()?])),
+            0xEB => Ok((Operation::JMP, vec![self.relative_byte()?])),
+            0xEC => Ok((Operation::IN, vec![register(Byte, 0), register(Word, 2)])),
+            0xED => Ok((Operation::IN, vec![register(size, 0), register(Word, 2)])),
+            0xEE => Ok((Operation::OUT, vec![register(Word, 2), register(Byte, 0)])),
+            0xEF => Ok((Operation::OUT, vec![register(Word, 2), register(size, 0)])),
+
+            0xF0 => Ok((Operation::LOCK, vec![])),
+            0xF1 => Ok((Operation::INT1, vec![])),
+            0xF2 => Ok((Operation::REPNE, vec![])),
+            0xF3 => Ok((Operation::REP, vec![])),
+            0xF4 => Ok((Operation::HLT, vec![])),
+            0xF5 => Ok((Operation::CMC, vec![])),
+            0xF6 | 0xF7 => {
+                let modrm = self.mod_reg_rm()?;
+                let size = if op_code == 0xF6 { Byte } else { size };
+                let rm = self.rm_operand(modrm, size, Some(size))?;
+                let operation = match modrm.reg {
+                    0 | 1 => return Ok((Operation::TEST, vec![rm, self.immediate(size)?])),
+                    2 => Operation::NOT,
+                    3 => Operation::NEG,
+                    4 => Operation::MUL,
+                    5 => Operation::IMUL,
+                    6 => Operation::DIV,
+                    _ => Operation::IDIV,
+                };
+                Ok((operation, vec![rm]))
+            }
+            0xF8 => Ok((Operation::CLC, vec![])),
+            0xF9 => Ok((Operation::STC, vec![])),
+            0xFA => Ok((Operation::CLI, vec![])),
+            0xFB => Ok((Operation::STI, vec![])),
+            0xFC => Ok((Operation::CLD, vec![])),
+            0xFD => Ok((Operation::STD, vec![])),
+            0xFE => {
+                let modrm = self.mod_reg_rm()?;
+                let operation = match modrm.reg {
+                    0 => Operation::INC,
+                    1 => Operation::DEC,
+                    _ => return self.invalid_op_code(),
+                };
+                Ok((operation, vec![self.rm_operand(modrm, Byte, Some(Byte))?]))
+            }
+            0xFF => {
+                let modrm = self.mod_reg_rm()?;
+                match modrm.reg {
+                    0 => Ok((
+                        Operation::INC,
+                        vec![self.rm_operand(modrm, size, Some(size))?],
+                    )),
+                    1 => Ok((
+                        Operation::DEC,
+                        vec![self.rm_operand(modrm, size, Some(size))?],
+                    )),
+                    2 => Ok((Operation::CALL, vec![self.rm_operand(modrm, size, None)?])),
+                    3 => Ok((
+                        Operation::CALL,
+                        vec![self.memory_only(modrm, Some(ast::DataSize::DWord))?],
+                    )),
+                    4 => Ok((Operation::JMP, vec![self.rm_operand(modrm, size, None)?])),
+                    5 => Ok((
+                        Operation::JMP,
+                        vec![self.memory_only(modrm, Some(ast::DataSize::DWord))?],
+                    )),
+                    6 => Ok((
+                        Operation::PUSH,
+                        vec![self.rm_operand(modrm, size, Some(size))?],
+                    )),
+                    _ => self.invalid_op_code(),
+                }
+            }
+
+            _ => self.invalid_op_code(),
+        }
+    }
+
+    /// Op codes following the 0x0F prefix.
+    fn decode_extended(&mut self) -> Decoded {
+        use ast::DataSize::{Byte, Word};
+
+        let op_code = self.byte()?;
+        let size = self.operand_size();
+
+        match op_code {
+            0x00 => {
+                let modrm = self.mod_reg_rm()?;
+                let operation = match modrm.reg {
+                    0 => Operation::SLDT,
+                    1 => Operation::STR,
+                    2 => Operation::LLDT,
+                    3 => Operation::LTR,
+                    4 => Operation::VERR,
+                    5 => Operation::VERW,
+                    _ => return self.invalid_op_code(),
+                };
+                Ok((operation, vec![self.rm_operand(modrm, Word, None)?]))
+            }
+            0x01 => {
+                let modrm = self.mod_reg_rm()?;
+                match modrm.reg {
+                    0 => Ok((Operation::SGDT, vec![self.memory_only(modrm, None)?])),
+                    1 => Ok((Operation::SIDT, vec![self.memory_only(modrm, None)?])),
+                    2 => Ok((Operation::LGDT, vec![self.memory_only(modrm, None)?])),
+                    3 => Ok((Operation::LIDT, vec![self.memory_only(modrm, None)?])),
+                    4 => Ok((Operation::SMSW, vec![self.rm_operand(modrm, Word, None)?])),
+                    6 => Ok((Operation::LMSW, vec![self.rm_operand(modrm, Word, None)?])),
+                    _ => self.invalid_op_code(),
+                }
+            }
+            0x02 => self.decode_reg_rm_sized(Operation::LAR, size, Word),
+            0x03 => self.decode_reg_rm_sized(Operation::LSL, size, Word),
+            0x06 => Ok((Operation::CLTS, vec![])),
+
+            // NEC V20
+            0x10..=0x1F => {
+                let modrm = self.mod_reg_rm()?;
+                if modrm.reg != 0 {
+                    return self.invalid_op_code();
+                }
+                let operation = match (op_code >> 1) & 0x03 {
+                    0 => Operation::TEST1,
+                    1 => Operation::CLR1,
+                    2 => Operation::SET1,
+                    _ => Operation::NOT1,
+                };
+                let size = if op_code & 0x01 == 0 { Byte } else { Word };
+                let rm = self.rm_operand(modrm, size, Some(size))?;
+                let bit = if op_code & 0x08 == 0 {
+                    register(Byte, 1)
+                } else {
+                    self.immediate(Byte)?
+                };
+                Ok((operation, vec![rm, bit]))
+            }
+            0x20 => Ok((Operation::ADD4S, vec![])),
+            0x22 => Ok((Operation::SUB4S, vec![])),
+            0x26 => Ok((Operation::CMP4S, vec![])),
+            0x28 | 0x2A => {
+                let modrm = self.mod_reg_rm()?;
+                if modrm.reg != 0 {
+                    return self.invalid_op_code();
+                }
+                let operation = if op_code == 0x28 {
+                    Operation::ROL4
+                } else {
+                    Operation::ROR4
+                };
+                Ok((operation, vec![self.rm_operand(modrm, Byte, None)?]))
+            }
+            0x31 | 0x33 => {
+                let modrm = self.mod_reg_rm()?;
+                if modrm.mode != 0b11 {
+                    return self.invalid_op_code();
+                }
+                let operation = if op_code == 0x31 {
+                    Operation::INS
+                } else {
+                    Operation::EXT
+                };
+                Ok((
+                    operation,
+                    vec![register(Byte, modrm.rm), register(Byte, modrm.reg)],
+                ))
+            }
+            0x39 | 0x3B => {
+                let modrm = self.mod_reg_rm()?;
+                if modrm.mode != 0b11 || modrm.reg != 0 {
+                    return self.invalid_op_code();
+                }
+                let operation = if op_code == 0x39 {
+                    Operation::INS
+                } else {
+                    Operation::EXT
+                };
+                Ok((
+                    operation,
+                    vec![register(Byte, modrm.rm), self.immediate(Byte)?],
+                ))
+            }
+            0xFF => Ok((Operation::BRKEM, vec![self.immediate(Byte)?])),
+
+            // 80386
+            0xA0 => Ok((
+                Operation::PUSH,
+                vec![ast::Operand::Segment(PENDING_SPAN, ast::Segment::FS)],
+            )),
+            0xA1 => Ok((
+                Operation::POP,
+                vec![ast::Operand::Segment(PENDING_SPAN, ast::Segment::FS)],
+            )),
+            0xA8 => Ok((
+                Operation::PUSH,
+                vec![ast::Operand::Segment(PENDING_SPAN, ast::Segment::GS)],
+            )),
+            0xA9 => Ok((
+                Operation::POP,
+                vec![ast::Operand::Segment(PENDING_SPAN, ast::Segment::GS)],
+            )),
+
+            _ => self.invalid_op_code(),
+        }
+    }
+
+    /// The ALU rows 0x00 to 0x3F, where the low 3 bits of the op code select the form.
+    fn decode_alu(&mut self, operation: Operation, form: u8) -> Decoded {
+        let size = self.operand_size();
+
+        match form {
+            // r/m8, reg8
+            0 => self.decode_rm_reg(operation, ast::DataSize::Byte),
+            // r/m16, reg16
+            1 => self.decode_rm_reg(operation, size),
+            // reg8, r/m8
+            2 => self.decode_reg_rm(operation, ast::DataSize::Byte),
+            // reg16, r/m16
+            3 => self.decode_reg_rm(operation, size),
+            // al, imm8
+            4 => Ok((
+                operation,
+                vec![
+                    register(ast::DataSize::Byte, 0),
+                    self.immediate(ast::DataSize::Byte)?,
+                ],
+            )),
+            // ax, imm16
+            _ => Ok((operation, vec![register(size, 0), self.immediate(size)?])),
+        }
+    }
+
+    fn decode_shift(&mut self, op_code: u8) -> Decoded {
+        let modrm = self.mod_reg_rm()?;
+        let Some(operation) = SHIFT_OPERATIONS[modrm.reg as usize] else {
+            return self.invalid_op_code();
+        };
+
+        let size = if op_code & 0x01 == 0 {
+            ast::DataSize::Byte
+        } else {
+            self.operand_size()
+        };
+        let rm = self.rm_operand(modrm, size, Some(size))?;
+
+        let count = match op_code {
+            0xC0 | 0xC1 => self.immediate(ast::DataSize::Byte)?,
+            0xD0 | 0xD1 => constant(1),
+            _ => register(ast::DataSize::Byte, 1),
+        };
+
+        Ok((operation, vec![rm, count]))
+    }
+
+    fn decode_rm_reg(&mut self, operation: Operation, size: ast::DataSize) -> Decoded {
+        let modrm = self.mod_reg_rm()?;
+        let rm = self.rm_operand(modrm, size, None)?;
+        Ok((operation, vec![rm, register(size, modrm.reg)]))
+    }
+
+    fn decode_reg_rm(&mut self, operation: Operation, size: ast::DataSize) -> Decoded {
+        self.decode_reg_rm_sized(operation, size, size)
+    }
+
+    /// Same as [decode_reg_rm], but the r/m operand has a different size than the register.
+    fn decode_reg_rm_sized(
+        &mut self,
+        operation: Operation,
+        size: ast::DataSize,
+        rm_size: ast::DataSize,
+    ) -> Decoded {
+        let modrm = self.mod_reg_rm()?;
+        let rm = self.rm_operand(modrm, rm_size, None)?;
+        Ok((operation, vec![register(size, modrm.reg), rm]))
+    }
+
+    /// A register and a memory operand that can not be a register, e.g. LEA and LES.
+    fn decode_reg_memory(&mut self, operation: Operation, size: ast::DataSize) -> Decoded {
+        let modrm = self.mod_reg_rm()?;
+        let memory = self.memory_only(modrm, None)?;
+        Ok((operation, vec![register(size, modrm.reg), memory]))
+    }
+
+    fn decode_string(&mut self, operation: Operation) -> Decoded {
+        // Without an address size prefix the instruction is decoded as is, with the segment
+        // override as an operand for the instructions that have a source.
+        if !self.address_size_prefix {
+            return match operation {
+                Operation::MOVSB
+                | Operation::MOVSW
+                | Operation::MOVSD
+                | Operation::CMPSB
+                | Operation::CMPSW
+                | Operation::CMPSD
+                | Operation::LODSB
+                | Operation::LODSW
+                | Operation::LODSD
+                | Operation::OUTSB
+                | Operation::OUTSW
+                | Operation::OUTSD => match self.take_segment() {
+                    Some(segment) => Ok((
+                        operation,
+                        vec![ast::Operand::Segment(PENDING_SPAN, segment)],
+                    )),
+                    None => Ok((operation, vec![])),
+                },
+                _ => Ok((operation, vec![])),
+            };
+        }
+
+        // The other address size can only be written with explicit operands.
+        let (operation, data_size) = match operation {
+            Operation::MOVSB | Operation::MOVSW | Operation::MOVSD => {
+                (Operation::MOVS, string_data_size(operation))
+            }
+            Operation::CMPSB | Operation::CMPSW | Operation::CMPSD => {
+                (Operation::CMPS, string_data_size(operation))
+            }
+            Operation::LODSB | Operation::LODSW | Operation::LODSD => {
+                (Operation::LODS, string_data_size(operation))
+            }
+            Operation::STOSB | Operation::STOSW | Operation::STOSD => {
+                (Operation::STOS, string_data_size(operation))
+            }
+            Operation::SCASB | Operation::SCASW | Operation::SCASD => {
+                (Operation::SCAS, string_data_size(operation))
+            }
+            Operation::OUTSB | Operation::OUTSW | Operation::OUTSD => {
+                (Operation::OUTS, string_data_size(operation))
+            }
+            _ => return Err(DecodeError::InvalidPrefix(self.span())),
+        };
+
+        let destination = self.string_address(ast::WordRegister::Di, data_size, None);
+        let source = {
+            let segment = self.take_segment();
+            self.string_address(ast::WordRegister::Si, data_size, segment)
+        };
+
+        let operands = match operation {
+            Operation::MOVS => vec![destination, source],
+            Operation::CMPS => vec![source, destination],
+            Operation::LODS => vec![source],
+            Operation::OUTS => vec![register(ast::DataSize::Word, 2), source],
+            _ => vec![destination],
+        };
+
+        Ok((operation, operands))
+    }
+
+    /// The memory addressed by a string instruction, "[si]" or "[di]" and their 32-bit versions.
+    fn string_address(
+        &self,
+        register: ast::WordRegister,
+        data_size: ast::DataSize,
+        segment: Option<ast::Segment>,
+    ) -> ast::Operand {
+        if self.address_size_is_32() {
+            let base = DWORD_REGISTERS[register.encoding() as usize];
+            ast::Operand::Indirect32(
+                PENDING_SPAN,
+                ast::Address32 {
+                    base: Some(base),
+                    index: None,
+                },
+                None,
+                Some(data_size),
+                segment,
+            )
+        } else {
+            let encoding = if register == ast::WordRegister::Si {
+                ast::IndirectEncoding::Si
+            } else {
+                ast::IndirectEncoding::Di
+            };
+            ast::Operand::Indirect(PENDING_SPAN, encoding, None, Some(data_size), segment)
+        }
+    }
+
+    fn decode_fpu(&mut self, op_code: u8) -> Decoded {
+        use ast::DataSize::{DWord, QWord, TWord, Word};
+
+        let modrm = self.mod_reg_rm()?;
+
+        if modrm.mode != 0b11 {
+            let (operation, data_size) = match (op_code, modrm.reg) {
+                (0xD8, reg) => (FPU_OPERATIONS[reg as usize], Some(DWord)),
+                (0xDC, reg) => (FPU_OPERATIONS[reg as usize], Some(QWord)),
+                (0xDA, reg) => (FPU_INTEGER_OPERATIONS[reg as usize], Some(DWord)),
+                (0xDE, reg) => (FPU_INTEGER_OPERATIONS[reg as usize], Some(Word)),
+
+                (0xD9, 0) => (Operation::FLD, Some(DWord)),
+                (0xD9, 2) => (Operation::FST, Some(DWord)),
+                (0xD9, 3) => (Operation::FSTP, Some(DWord)),
+                (0xD9, 4) => (Operation::FLDENV, None),
+                (0xD9, 5) => (Operation::FLDCW, None),
+                (0xD9, 6) => (self.waited(Operation::FSTENV, Operation::FNSTENV), None),
+                (0xD9, 7) => (self.waited(Operation::FSTCW, Operation::FNSTCW), None),
+
+                (0xDB, 0) => (Operation::FILD, Some(DWord)),
+                (0xDB, 2) => (Operation::FIST, Some(DWord)),
+                (0xDB, 3) => (Operation::FISTP, Some(DWord)),
+                (0xDB, 5) => (Operation::FLD, Some(TWord)),
+                (0xDB, 7) => (Operation::FSTP, Some(TWord)),
+
+                (0xDD, 0) => (Operation::FLD, Some(QWord)),
+                (0xDD, 2) => (Operation::FST, Some(QWord)),
+                (0xDD, 3) => (Operation::FSTP, Some(QWord)),
+                (0xDD, 4) => (Operation::FRSTOR, None),
+                (0xDD, 6) => (self.waited(Operation::FSAVE, Operation::FNSAVE), None),
+                (0xDD, 7) => (self.waited(Operation::FSTSW, Operation::FNSTSW), None),
+
+                (0xDF, 0) => (Operation::FILD, Some(Word)),
+                (0xDF, 2) => (Operation::FIST, Some(Word)),
+                (0xDF, 3) => (Operation::FISTP, Some(Word)),
+                (0xDF, 4) => (Operation::FBLD, Some(TWord)),
+                (0xDF, 5) => (Operation::FILD, Some(QWord)),
+                (0xDF, 6) => (Operation::FBSTP, Some(TWord)),
+                (0xDF, 7) => (Operation::FISTP, Some(QWord)),
+
+                _ => return self.decode_esc(op_code, modrm),
+            };
+
+            return Ok((operation, vec![self.memory_operand(modrm, data_size)?]));
+        }
+
+        let st = register_st(modrm.rm);
+        let st0 = register_st(0);
+
+        match (op_code, modrm.reg) {
+            // st(i)
+            (0xD8, 2 | 3) => Ok((FPU_OPERATIONS[modrm.reg as usize], vec![st])),
+            // st0, st(i)
+            (0xD8, reg) => Ok((FPU_OPERATIONS[reg as usize], vec![st0, st])),
+            // st(i), st0, where the reverse forms have their [reg] values swapped.
+            (0xDC, 0 | 1) => Ok((FPU_OPERATIONS[modrm.reg as usize], vec![st, st0])),
+            (0xDC, 4..=7) => Ok((FPU_OPERATIONS[(modrm.reg ^ 1) as usize], vec![st, st0])),
+
+            (0xDE, 0) => Ok((Operation::FADDP, vec![st, st0])),
+            (0xDE, 1) => Ok((Operation::FMULP, vec![st, st0])),
+            (0xDE, 3) if modrm.rm == 1 => Ok((Operation::FCOMPP, vec![])),
+            (0xDE, 4) => Ok((Operation::FSUBRP, vec![st, st0])),
+            (0xDE, 5) => Ok((Operation::FSUBP, vec![st, st0])),
+            (0xDE, 6) => Ok((Operation::FDIVRP, vec![st, st0])),
+            (0xDE, 7) => Ok((Operation::FDIVP, vec![st, st0])),
+
+            (0xD9, 0) => Ok((Operation::FLD, vec![st])),
+            (0xD9, 1) => Ok((Operation::FXCH, vec![st])),
+            (0xD9, reg) if reg >= 2 => {
+                let index = (((reg - 2) << 3) | modrm.rm) as usize;
+                match FPU_D9_OPERATIONS[index] {
+                    Some(operation) => Ok((operation, vec![])),
+                    None => self.decode_esc(op_code, modrm),
+                }
+            }
+
+            (0xDB, 4) if modrm.rm <= 3 => {
+                let operation = match modrm.rm {
+                    0 => self.waited(Operation::FENI, Operation::FNENI),
+                    1 => self.waited(Operation::FDISI, Operation::FNDISI),
+                    2 => self.waited(Operation::FCLEX, Operation::FNCLEX),
+                    _ => self.waited(Operation::FINIT, Operation::FNINIT),
+                };
+                Ok((operation, vec![]))
+            }
+
+            (0xDD, 0) => Ok((Operation::FFREE, vec![st])),
+            (0xDD, 2) => Ok((Operation::FST, vec![st])),
+            (0xDD, 3) => Ok((Operation::FSTP, vec![st])),
+
+            (0xDF, 4) if modrm.rm == 0 => Ok((
+                self.waited(Operation::FSTSW, Operation::FNSTSW),
+                vec![register(ast::DataSize::Word, 0)],
+            )),
+
+            _ => self.decode_esc(op_code, modrm),
+        }
+    }
+
+    /// Coprocessor op codes that are not known are decoded as ESC with the external op code.
+    fn decode_esc(&mut self, op_code: u8, modrm: ModRegRM) -> Decoded {
+        let external = ((op_code & 0x07) << 3) | modrm.reg;
+        let rm = self.rm_operand(modrm, ast::DataSize::Word, None)?;
+        Ok((Operation::ESC, vec![constant(external as i64), rm]))
+    }
+
+    /// The r/m operand of a mod reg r/m byte.  [data_size] is only set on memory operands where
+    /// no other operand determines the size.
+    fn rm_operand(
+        &mut self,
+        modrm: ModRegRM,
+        size: ast::DataSize,
+        data_size: Option<ast::DataSize>,
+    ) -> Result<ast::Operand, DecodeError> {
+        if modrm.mode == 0b11 {
+            Ok(register(size, modrm.rm))
+        } else {
+            self.memory_operand(modrm, data_size)
+        }
+    }
+
+    fn memory_only(
+        &mut self,
+        modrm: ModRegRM,
+        data_size: Option<ast::DataSize>,
+    ) -> Result<ast::Operand, DecodeError> {
+        if modrm.mode == 0b11 {
+            self.invalid_op_code()
+        } else {
+            self.memory_operand(modrm, data_size)
+        }
+    }
+
+    fn memory_operand(
+        &mut self,
+        modrm: ModRegRM,
+        data_size: Option<ast::DataSize>,
+    ) -> Result<ast::Operand, DecodeError> {
+        if self.address_size_is_32() {
+            return self.memory_operand32(modrm, data_size);
+        }
+
+        let segment = self.take_segment();
+
+        // Displacements are signed, the same as the compiler expects them.
+        let displacement = match modrm.mode {
+            0b00 if modrm.rm == 0b110 => {
+                let address = self.word()?;
+                return Ok(ast::Operand::Direct(
+                    PENDING_SPAN,
+                    constant_expression(address as i64),
+                    data_size,
+                    segment,
+                ));
+            }
+            0b00 => 0,
+            0b01 => self.byte()? as i8 as i64,
+            _ => self.word()? as i16 as i64,
+        };
+
+        Ok(ast::Operand::Indirect(
+            PENDING_SPAN,
+            INDIRECT_ENCODINGS[modrm.rm as usize].clone(),
+            displacement_expression(displacement),
+            data_size,
+            segment,
+        ))
+    }
+
+    fn memory_operand32(
+        &mut self,
+        modrm: ModRegRM,
+        data_size: Option<ast::DataSize>,
+    ) -> Result<ast::Operand, DecodeError> {
+        let segment = self.take_segment();
+
+        let (base, index) = if modrm.rm == 0b100 {
+            let sib = self.byte()?;
+            let scale = 1 << (sib >> 6);
+            let index = (sib >> 3) & 0x07;
+            let base = sib & 0x07;
+
+            let index = if index == 0b100 {
+                None
+            } else {
+                Some((DWORD_REGISTERS[index as usize], scale))
+            };
+            let base = if modrm.mode == 0b00 && base == 0b101 {
+                None
+            } else {
+                Some(DWORD_REGISTERS[base as usize])
+            };
+            (base, index)
+        } else if modrm.mode == 0b00 && modrm.rm == 0b101 {
+            (None, None)
+        } else {
+            (Some(DWORD_REGISTERS[modrm.rm as usize]), None)
+        };
+
+        let displacement = match modrm.mode {
+            0b00 if base.is_none() => self.dword()? as i64,
+            0b00 => 0,
+            0b01 => self.byte()? as i8 as i64,
+            _ => self.dword()? as i64,
+        };
+
+        // A plain address in 32-bit code is written the same as in 16-bit code.
+        if base.is_none() && index.is_none() && self.bits == ast::Bits::Bits32 {
+            return Ok(ast::Operand::Direct(
+                PENDING_SPAN,
+                constant_expression(displacement),
+                data_size,
+                segment,
+            ));
+        }
+
+        Ok(ast::Operand::Indirect32(
+            PENDING_SPAN,
+            ast::Address32 { base, index },
+            displacement_expression(displacement),
+            data_size,
+            segment,
+        ))
+    }
+
+    /// The address of the MOV op codes 0xA0 to 0xA3, which does not use a mod reg r/m byte.
+    fn memory_offset(&mut self) -> Result<ast::Operand, DecodeError> {
+        let segment = self.take_segment();
+
+        if self.address_size_is_32() {
+            let address = self.dword()? as i64;
+            if self.bits == ast::Bits::Bits32 {
+                Ok(ast::Operand::Direct(
+                    PENDING_SPAN,
+                    constant_expression(address),
+                    None,
+                    segment,
+                ))
+            } else {
+                Ok(ast::Operand::Indirect32(
+                    PENDING_SPAN,
+                    ast::Address32 {
+                        base: None,
+                        index: None,
+                    },
+                    displacement_expression(address),
+                    None,
+                    segment,
+                ))
+            }
+        } else {
+            let address = self.word()? as i64;
+            Ok(ast::Operand::Direct(
+                PENDING_SPAN,
+                constant_expression(address),
+                None,
+                segment,
+            ))
+        }
+    }
+
+    fn immediate(&mut self, size: ast::DataSize) -> Result<ast::Operand, DecodeError> {
+        let value = match size {
+            ast::DataSize::Byte => self.byte()? as i64,
+            ast::DataSize::Word => self.word()? as i64,
+            _ => self.dword()? as i64,
+        };
+        Ok(constant(value))
+    }
+
+    /// An immediate byte that is sign extended to the operand size.
+    fn signed_immediate_byte(&mut self) -> Result<ast::Operand, DecodeError> {
+        let value = self.byte()? as i8 as i64;
+        Ok(ast::Operand::Immediate(
+            PENDING_SPAN,
+            signed_expression(value),
+            None,
+        ))
+    }
+
+    /// A relative jump target is converted into the absolute address it jumps to.  The relative
+    /// offset is always the last part of the instruction.
+    fn relative_byte(&mut self) -> Result<ast::Operand, DecodeError> {
+        let relative = self.byte()? as i8 as i64;
+        Ok(self.jump_target(relative))
+    }
+
+    fn relative(&mut self) -> Result<ast::Operand, DecodeError> {
+        let relative = if self.operand_size() == ast::DataSize::DWord {
+            self.dword()? as i32 as i64
+        } else {
+            self.word()? as i16 as i64
+        };
+        Ok(self.jump_target(relative))
+    }
+
+    fn jump_target(&self, relative: i64) -> ast::Operand {
+        let next = self.origin as i64 + self.position as i64;
+        let mask = match self.bits {
+            ast::Bits::Bits16 => 0xFFFF,
+            ast::Bits::Bits32 => 0xFFFF_FFFF,
+        };
+        constant((next + relative) & mask)
+    }
+
+    /// The offset and segment of the far jump and call op codes.
+    fn far_pointer(&mut self) -> Result<ast::Operand, DecodeError> {
+        let offset = if self.operand_size() == ast::DataSize::DWord {
+            self.dword()? as i64
+        } else {
+            self.word()? as i64
+        };
+        let segment = self.word()? as i64;
+
+        Ok(ast::Operand::Far(
+            PENDING_SPAN,
+            constant_expression(offset),
+            constant_expression(segment),
+        ))
+    }
+}
+
+fn register(size: ast::DataSize, encoding: u8) -> ast::Operand {
+    let encoding = (encoding & 0x07) as usize;
+    let register = match size {
+        ast::DataSize::Byte => ast::Register::Byte(BYTE_REGISTERS[encoding].clone()),
+        ast::DataSize::Word => ast::Register::Word(WORD_REGISTERS[encoding].clone()),
+        _ => ast::Register::DWord(DWORD_REGISTERS[encoding]),
+    };
+    ast::Operand::Register(PENDING_SPAN, register)
+}
+
+fn register_st(index: u8) -> ast::Operand {
+    ast::Operand::Register(PENDING_SPAN, ast::Register::St(index))
+}
+
+fn segment(encoding: u8) -> ast::Operand {
+    ast::Operand::Segment(PENDING_SPAN, SEGMENTS[encoding as usize & 0x07])
+}
+
+fn constant(value: i64) -> ast::Operand {
+    ast::Operand::Immediate(PENDING_SPAN, constant_expression(value), None)
+}
+
+fn constant_expression(value: i64) -> ast::Expression {
+    ast::Expression::Value(PENDING_SPAN, ast::Value::Constant(value))
+}
+
+/// A negative value is written as a constant with a minus sign in front, the same way the parser
+/// reads it.
+fn signed_expression(value: i64) -> ast::Expression {
+    if value < 0 {
+        ast::Expression::PrefixOperator(
+            PENDING_SPAN,
+            ast::Operator::Subtract,
+            Box::new(constant_expression(-value)),
+        )
+    } else {
+        constant_expression(value)
+    }
+}
+
+/// The displacement of an indirect memory operand, written as "+ 0x10" or "- 0x10".
+fn displacement_expression(value: i64) -> Option<ast::Expression> {
+    match value {
+        0 => None,
+        value if value < 0 => Some(signed_expression(value)),
+        value => Some(ast::Expression::PrefixOperator(
+            PENDING_SPAN,
+            ast::Operator::Add,
+            Box::new(constant_expression(value)),
+        )),
+    }
+}
+
+fn string_data_size(operation: Operation) -> ast::DataSize {
+    match operation {
+        Operation::MOVSB
+        | Operation::CMPSB
+        | Operation::LODSB
+        | Operation::STOSB
+        | Operation::SCASB
+        | Operation::OUTSB => ast::DataSize::Byte,
+        Operation::MOVSD
+        | Operation::CMPSD
+        | Operation::LODSD
+        | Operation::STOSD
+        | Operation::SCASD
+        | Operation::OUTSD => ast::DataSize::DWord,
+        _ => ast::DataSize::Word,
+    }
+}
+
+fn set_operand_span(operand: &mut ast::Operand, span: &ast::Span) {
+    *operand.span_mut() = span.clone();
+
+    match operand {
+        ast::Operand::Immediate(_, expr, _) | ast::Operand::Direct(_, expr, _, _) => {
+            set_expression_span(expr, span)
+        }
+        ast::Operand::Indirect(_, _, Some(expr), _, _)
+        | ast::Operand::Indirect32(_, _, Some(expr), _, _) => set_expression_span(expr, span),
+        ast::Operand::Far(_, offset, segment) => {
+            set_expression_span(offset, span);
+            set_expression_span(segment, span);
+        }
+        _ => {}
+    }
+}
+
+fn set_expression_span(expr: &mut ast::Expression, span: &ast::Span) {
+    match expr {
+        ast::Expression::PrefixOperator(expr_span, _, right) => {
+            *expr_span = span.clone();
+            set_expression_span(right, span);
+        }
+        ast::Expression::InfixOperator(expr_span, _, left, right) => {
+            *expr_span = span.clone();
+            set_expression_span(left, span);
+            set_expression_span(right, span);
+        }
+        ast::Expression::Value(expr_span, _) => *expr_span = span.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! assert_decode {
+        ($bytes:expr, $text:literal) => {{
+            assert_decode!($bytes, ast::Bits::Bits16, $text)
+        }};
+
+        ($bytes:expr, $bits:expr, $text:literal) => {{
+            let bytes: &[u8] = $bytes;
+            let mut decoder = Decoder::new(bytes, 0x100);
+            decoder.set_bits($bits);
+            let decoded = decoder.next().unwrap().unwrap();
+            assert_eq!($text, decoded.instruction.to_string());
+            assert_eq!(bytes.len(), decoded.size as usize);
+            assert!(decoder.next().is_none());
+        }};
+    }
+
+    macro_rules! assert_decode_err {
+        ($bytes:expr, $err:pat) => {{
+            let bytes: &[u8] = $bytes;
+            assert!(matches!(decode(bytes, 0x100), Err($err)));
+        }};
+    }
+
+    #[test]
+    fn offsets_and_sizes() {
+        let decoded = decode(&[0x90, 0xB8, 0x34, 0x12, 0x26, 0x8B, 0x07], 0x100).unwrap();
+        let offsets = decoded
+            .iter()
+            .map(|d| (d.offset, d.size, d.instruction.span.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(0x100, 1, 0..1), (0x101, 3, 1..4), (0x104, 3, 4..7)],
+            offsets
+        );
+
+        // Every span in the instruction covers its bytes.
+        match &decoded[2].instruction.operands {
+            ast::Operands::DestinationAndSource(span, destination, source) => {
+                assert_eq!(&(4..7), span);
+                assert_eq!(&(4..7), destination.span());
+                assert_eq!(&(4..7), source.span());
+            }
+            operands => panic!("unexpected operands: {:?}", operands),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_decode_err!(&[0xB8, 0x34], DecodeError::UnexpectedEndOfInput(_));
+        assert_decode_err!(&[0xFF, 0xF8], DecodeError::InvalidOpCode(_));
+        assert_decode_err!(&[0x8D, 0xC0], DecodeError::InvalidOpCode(_));
+        assert_decode_err!(&[0x26, 0x90], DecodeError::InvalidPrefix(_));
+        assert_decode_err!(&[0x26, 0xAA], DecodeError::InvalidPrefix(_));
+
+        // Decoding continues after the byte that could not be decoded.
+        let results = Decoder::new(&[0x0F, 0x0F, 0x90], 0).collect::<Vec<_>>();
+        assert!(matches!(results[0], Err(DecodeError::InvalidOpCode(_))));
+        assert!(matches!(results[1], Err(DecodeError::InvalidOpCode(_))));
+        assert_eq!(2, results[2].as_ref().unwrap().offset);
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_decode!(&[0x00, 0xD8], "ADD AL, BL");
+        assert_decode!(&[0x03, 0x47, 0x04], "ADD AX, [BX + (0x04)]");
+        assert_decode!(&[0x29, 0x42, 0xFE], "SUB [BP + SI - (0x02)], AX");
+        assert_decode!(&[0x3C, 0x7F], "CMP AL, 0x7F");
+        assert_decode!(&[0x0D, 0x00, 0xFF], "OR AX, 0xFF00");
+        assert_decode!(&[0x80, 0x36, 0x00, 0x20, 0x01], "XOR BYTE [0x2000], 0x01");
+        assert_decode!(&[0x83, 0xEB, 0xFF], "SUB BX, - (0x01)");
+        assert_decode!(&[0x81, 0xC1, 0x34, 0x12], "ADD CX, 0x1234");
+        assert_decode!(&[0x48], "DEC AX");
+        assert_decode!(&[0xFE, 0x07], "INC BYTE [BX]");
+        assert_decode!(&[0xF7, 0xE3], "MUL BX");
+        assert_decode!(&[0xF6, 0xC4, 0x80], "TEST AH, 0x80");
+        assert_decode!(&[0x6B, 0xC3, 0xFE], "IMUL AX, BX, - (0x02)");
+        assert_decode!(&[0xD1, 0xE0], "SHL AX, 0x01");
+        assert_decode!(&[0xD2, 0x0F], "ROR BYTE [BX], CL");
+        assert_decode!(&[0xC1, 0xF8, 0x04], "SAR AX, 0x04");
+        assert_decode!(&[0xD4, 0x0A], "AAM");
+        assert_decode!(&[0xD5, 0x10], "AAD 0x10");
+    }
+
+    #[test]
+    fn data_transfer() {
+        assert_decode!(&[0xB0, 0x41], "MOV AL, 0x41");
+        assert_decode!(&[0xBF, 0x00, 0x80], "MOV DI, 0x8000");
+        assert_decode!(&[0xA1, 0x00, 0x20], "MOV AX, [0x2000]");
+        assert_decode!(&[0x2E, 0xA2, 0x00, 0x20], "MOV [CS:0x2000], AL");
+        assert_decode!(&[0x8E, 0xD8], "MOV DS, AX");
+        assert_decode!(&[0x8C, 0x06, 0x00, 0x20], "MOV [0x2000], ES");
+        assert_decode!(
+            &[0xC7, 0x06, 0x00, 0x20, 0x34, 0x12],
+            "MOV WORD [0x2000], 0x1234"
+        );
+        assert_decode!(&[0x8D, 0x40, 0x02], "LEA AX, [BX + SI + (0x02)]");
+        assert_decode!(&[0xC4, 0x1E, 0x00, 0x20], "LES BX, [0x2000]");
+        assert_decode!(&[0x93], "XCHG AX, BX");
+        assert_decode!(&[0x1E], "PUSH DS");
+        assert_decode!(&[0xFF, 0x37], "PUSH WORD [BX]");
+        assert_decode!(&[0x6A, 0x80], "PUSH - (0x80)");
+        assert_decode!(&[0xE4, 0x60], "IN AL, 0x60");
+        assert_decode!(&[0xEF], "OUT DX, AX");
+        assert_decode!(&[0x3E, 0xD7], "XLATB WORD [DS:BX]");
+    }
+
+    #[test]
+    fn control_transfer() {
+        // Relative targets are converted to absolute addresses.
+        assert_decode!(&[0x74, 0xFE], "JE 0x100");
+        assert_decode!(&[0xEB, 0x10], "JMP 0x112");
+        assert_decode!(&[0xE8, 0xFD, 0xFF], "CALL 0x100");
+        assert_decode!(&[0xE2, 0xFE], "LOOP 0x100");
+        assert_decode!(&[0xEA, 0x00, 0x00, 0xFF, 0xFF], "JMP 0xFFFF:0x00");
+        assert_decode!(&[0xFF, 0xE3], "JMP BX");
+        assert_decode!(&[0xFF, 0x1F], "CALL DWORD [BX]");
+        assert_decode!(&[0xC2, 0x04, 0x00], "RET 0x04");
+        assert_decode!(&[0xCD, 0x21], "INT 0x21");
+        assert_decode!(&[0xC8, 0x08, 0x00, 0x00], "ENTER 0x08, 0x00");
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_decode!(&[0xF3], "REP");
+        assert_decode!(&[0xF0], "LOCK");
+        assert_decode!(&[0x26, 0x8B, 0x07], "MOV AX, [ES:BX]");
+        assert_decode!(&[0x64, 0x88, 0x07], "MOV [FS:BX], AL");
+        assert_decode!(&[0x26, 0xA4], "ES MOVSB");
+        assert_decode!(&[0xAB], "STOSW");
+        assert_decode!(&[0x66, 0xA5], "MOVSD");
+        assert_decode!(&[0x66, 0x98], "CWDE");
+        assert_decode!(&[0x67, 0xAC], "LODS BYTE [ESI]");
+        assert_decode!(&[0x67, 0xA6], "CMPS BYTE [ESI], BYTE [EDI]");
+    }
+
+    #[test]
+    fn system() {
+        assert_decode!(&[0x0F, 0x01, 0x16, 0x00, 0x20], "LGDT [0x2000]");
+        assert_decode!(&[0x0F, 0x00, 0xD8], "LTR AX");
+        assert_decode!(&[0x0F, 0x02, 0xC3], "LAR AX, BX");
+        assert_decode!(&[0x0F, 0x06], "CLTS");
+        assert_decode!(&[0x63, 0xD8], "ARPL AX, BX");
+        assert_decode!(&[0x0F, 0xA0], "PUSH FS");
+        assert_decode!(&[0x0F, 0xA9], "POP GS");
+    }
+
+    #[test]
+    fn fpu() {
+        assert_decode!(&[0xD9, 0x07], "FLD DWORD [BX]");
+        assert_decode!(&[0xDD, 0x1F], "FSTP QWORD [BX]");
+        assert_decode!(&[0xDB, 0x2F], "FLD TWORD [BX]");
+        assert_decode!(&[0xDE, 0x07], "FIADD WORD [BX]");
+        assert_decode!(&[0xD8, 0xC1], "FADD ST0, ST1");
+        assert_decode!(&[0xDC, 0xE9], "FSUB ST1, ST0");
+        assert_decode!(&[0xDE, 0xF9], "FDIVP ST1, ST0");
+        assert_decode!(&[0xD8, 0xD2], "FCOM ST2");
+        assert_decode!(&[0xDE, 0xD9], "FCOMPP");
+        assert_decode!(&[0xD9, 0xC9], "FXCH ST1");
+        assert_decode!(&[0xD9, 0xE8], "FLD1");
+        assert_decode!(&[0xD9, 0xFA], "FSQRT");
+        assert_decode!(&[0xDD, 0xC3], "FFREE ST3");
+        assert_decode!(&[0xD9, 0x3F], "FNSTCW [BX]");
+        assert_decode!(&[0xDF, 0xE0], "FNSTSW AX");
+
+        // A WAIT is merged with control instructions that have a waiting form.
+        assert_decode!(&[0x9B, 0xDB, 0xE3], "FINIT");
+        assert_decode!(&[0x9B, 0xDF, 0xE0], "FSTSW AX");
+        assert_decode!(&[0x9B, 0x26, 0xD9, 0x3F], "FSTCW [ES:BX]");
+        assert_decode!(&[0x9B], "WAIT");
+
+        let decoded = decode(&[0x9B, 0xD9, 0xE8], 0).unwrap();
+        assert_eq!(Operation::WAIT, decoded[0].instruction.operation);
+        assert_eq!(Operation::FLD1, decoded[1].instruction.operation);
+
+        // Unknown coprocessor instructions.
+        assert_decode!(&[0xD9, 0xD8], "ESC 0x0B, AX");
+    }
+
+    #[test]
+    fn v20() {
+        assert_decode!(&[0x0F, 0x10, 0xC0], "TEST1 AL, CL");
+        assert_decode!(&[0x0F, 0x1D, 0x07, 0x0F], "SET1 WORD [BX], 0x0F");
+        assert_decode!(&[0x0F, 0x28, 0xC3], "ROL4 BL");
+        assert_decode!(&[0x0F, 0x31, 0xC1], "INS CL, AL");
+        assert_decode!(&[0x0F, 0x3B, 0xC1, 0x04], "EXT CL, 0x04");
+        assert_decode!(&[0x0F, 0x20], "ADD4S");
+        assert_decode!(&[0x0F, 0xFF, 0x10], "BRKEM 0x10");
+    }
+
+    #[test]
+    fn bits32() {
+        use ast::Bits::Bits32;
+
+        assert_decode!(&[0x8B, 0x04, 0x0B], Bits32, "MOV EAX, [EBX + ECX]");
+        assert_decode!(
+            &[0x89, 0x44, 0x8B, 0x08],
+            Bits32,
+            "MOV [EBX + ECX * 4 + (0x08)], EAX"
+        );
+        assert_decode!(&[0xA1, 0x00, 0x20, 0x00, 0x00], Bits32, "MOV EAX, [0x2000]");
+        assert_decode!(&[0x66, 0x8B, 0xC3], Bits32, "MOV AX, BX");
+        assert_decode!(
+            &[0xB8, 0x78, 0x56, 0x34, 0x12],
+            Bits32,
+            "MOV EAX, 0x12345678"
+        );
+        assert_decode!(&[0xE9, 0xFB, 0xFF, 0xFF, 0xFF], Bits32, "JMP 0x100");
+
+        // 32-bit operands and addresses in 16-bit code.
+        assert_decode!(&[0x66, 0x40], "INC EAX");
+        assert_decode!(&[0x67, 0x66, 0x8B, 0x04, 0x0B], "MOV EAX, [EBX + ECX]");
+    }
+}
@@ -2,6 +2,9 @@ use crate::ast::Span;
 
 const TAB_SIZE: usize = 4;
 
+const COLOR_RESET: &str = "\x1b[0m";
+const COLOR_GUTTER: &str = "\x1b[1;34m";
+
 #[repr(u8)]
 pub enum DiagnosticKind {
     Info,
@@ -9,16 +12,87 @@ pub enum DiagnosticKind {
     Error,
 }
 
-struct Diagnostic {
+impl DiagnosticKind {
+    /// The severity as it is written in JSON output.
+    fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Info => "info",
+            DiagnosticKind::Warning => "warning",
+            DiagnosticKind::Error => "error",
+        }
+    }
+
+    /// The SARIF result level.
+    fn sarif_level(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Info => "note",
+            DiagnosticKind::Warning => "warning",
+            DiagnosticKind::Error => "error",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Info => "INFO",
+            DiagnosticKind::Warning => "WARNING",
+            DiagnosticKind::Error => "ERROR",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Info => "\x1b[1;36m",
+            DiagnosticKind::Warning => "\x1b[1;33m",
+            DiagnosticKind::Error => "\x1b[1;31m",
+        }
+    }
+}
+
+pub struct Diagnostic {
     kind: DiagnosticKind,
+    code: Option<String>,
     message: String,
     span: Span,
+    labels: Vec<(Span, String)>,
+    notes: Vec<String>,
+    help: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Add a secondary span with a message, e.g. where a label was defined first.
+    pub fn label(&mut self, span: Span, message: impl ToString) -> &mut Self {
+        self.labels.push((span, message.to_string()));
+        self
+    }
+
+    /// Add a note, printed below the source.
+    pub fn note(&mut self, message: impl ToString) -> &mut Self {
+        self.notes.push(message.to_string());
+        self
+    }
+
+    /// Add a suggestion of how to fix the problem, printed below the source.
+    pub fn help(&mut self, message: impl ToString) -> &mut Self {
+        self.help.push(message.to_string());
+        self
+    }
+}
+
+/// A part of a span that is on a single line.  Spans that cross lines are split into one
+/// annotation per line and the message is shown on the last one.
+struct Annotation<'a> {
+    line: usize,
+    start: usize,
+    end: usize,
+    primary: bool,
+    message: Option<&'a str>,
 }
 
 #[derive(Default)]
 pub struct Diagnostics<'a> {
     source: &'a str,
     path: String,
+    color: bool,
 
     diags: Vec<Diagnostic>,
 }
@@ -28,28 +102,57 @@ impl<'a> Diagnostics<'a> {
         Self {
             source,
             path,
+            color: false,
             diags: vec![],
         }
     }
 
-    pub fn diag(&mut self, kind: DiagnosticKind, message: impl ToString, span: Span) {
+    /// Use ANSI escape codes to color the human readable output.
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    pub fn diag(
+        &mut self,
+        kind: DiagnosticKind,
+        message: impl ToString,
+        span: Span,
+    ) -> &mut Diagnostic {
         self.diags.push(Diagnostic {
             kind,
+            code: None,
             message: message.to_string(),
             span,
+            labels: vec![],
+            notes: vec![],
+            help: vec![],
         });
+        self.diags.last_mut().unwrap()
+    }
+
+    /// Add a diagnostic with an error code, which is included in the machine readable output.
+    pub fn diag_with_code(
+        &mut self,
+        kind: DiagnosticKind,
+        code: impl ToString,
+        message: impl ToString,
+        span: Span,
+    ) -> &mut Diagnostic {
+        let diag = self.diag(kind, message, span);
+        diag.code = Some(code.to_string());
+        diag
     }
 
-    pub fn info(&mut self, message: impl ToString, span: Span) {
-        self.diag(DiagnosticKind::Info, message, span);
+    pub fn info(&mut self, message: impl ToString, span: Span) -> &mut Diagnostic {
+        self.diag(DiagnosticKind::Info, message, span)
     }
 
-    pub fn warn(&mut self, message: impl ToString, span: Span) {
-        self.diag(DiagnosticKind::Warning, message, span);
+    pub fn warn(&mut self, message: impl ToString, span: Span) -> &mut Diagnostic {
+        self.diag(DiagnosticKind::Warning, message, span)
     }
 
-    pub fn error(&mut self, message: impl ToString, span: Span) {
-        self.diag(DiagnosticKind::Error, message, span);
+    pub fn error(&mut self, message: impl ToString, span: Span) -> &mut Diagnostic {
+        self.diag(DiagnosticKind::Error, message, span)
     }
 
     #[inline]
@@ -59,66 +162,331 @@ impl<'a> Diagnostics<'a> {
 
     pub fn print<W: std::io::Write>(&self, output: &mut W) -> Result<(), std::io::Error> {
         for diag in &self.diags {
-            let message = match diag.kind {
-                DiagnosticKind::Info => format!("INFO: {}", &diag.message),
-                DiagnosticKind::Warning => format!("WARNING: {}", &diag.message),
-                DiagnosticKind::Error => format!("ERROR: {}", &diag.message),
-            };
-
-            self.print_source_line(output, &diag.span, message.as_str())?;
+            self.print_diagnostic(output, diag)?;
         }
 
         Ok(())
     }
 
-    fn print_source_line<W: std::io::Write>(
+    fn paint(&self, color: &'static str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, COLOR_RESET)
+        } else {
+            text.to_owned()
+        }
+    }
+
+    fn print_diagnostic<W: std::io::Write>(
         &self,
         output: &mut W,
-        span: &Span,
-        message: &str,
+        diag: &Diagnostic,
     ) -> Result<(), std::io::Error> {
-        let prev_new_line = if let Some(found) = self.source[..span.start].rfind('\n') {
-            found + 1
-        } else {
-            0
+        let (line, column) = self.line_and_column(diag.span.start);
+        let title = match &diag.code {
+            Some(code) => format!("{}[{}]:", diag.kind.title(), code),
+            None => format!("{}:", diag.kind.title()),
         };
+        writeln!(
+            output,
+            "{}:{}:{}: {} {}",
+            self.path,
+            line,
+            column,
+            self.paint(diag.kind.color(), &title),
+            diag.message
+        )?;
 
-        let next_new_line = if let Some(found) = self.source[span.start..].find('\n') {
-            span.start + found
-        } else {
-            self.source.len()
-        };
+        let mut annotations = self.annotations(&diag.span, true, None);
+        for (span, message) in &diag.labels {
+            annotations.extend(self.annotations(span, false, Some(message.as_str())));
+        }
+        annotations.sort_by_key(|annotation| (annotation.line, annotation.start));
+
+        let last_line = annotations.iter().map(|a| a.line).max().unwrap_or(0);
+        let width = (last_line + 1).to_string().len();
+        let gutter = |text: &str| self.paint(COLOR_GUTTER, &format!("{:>width$} |", text));
 
-        let fragment = &self.source[prev_new_line..next_new_line];
+        writeln!(output, "{}", gutter(""))?;
 
-        let line = self.source[0..span.start].matches('\n').count() + 1;
-        let column = span.start - prev_new_line;
+        let mut previous_line = None;
+        for annotation in &annotations {
+            if previous_line != Some(annotation.line) {
+                if matches!(previous_line, Some(previous) if annotation.line > previous + 1) {
+                    writeln!(output, "{}", self.paint(COLOR_GUTTER, "..."))?;
+                }
+                let text = self.line_text(annotation.line);
+                writeln!(
+                    output,
+                    "{} {}",
+                    gutter(&(annotation.line + 1).to_string()),
+                    expand_tabs(text, TAB_SIZE)
+                )?;
+                previous_line = Some(annotation.line);
+            }
 
-        // Adjust for tabs in the current line.
-        let highlight_start = fragment[..column].chars().fold(0, |pos, c| {
-            if c == '\t' {
-                pos + TAB_SIZE - pos % TAB_SIZE
+            let text = self.line_text(annotation.line);
+            let start = display_column(text, annotation.start);
+            let end = display_column(text, annotation.end).max(start + 1);
+            let (mark, color) = if annotation.primary {
+                ("^", diag.kind.color())
             } else {
-                pos + 1
+                ("-", COLOR_GUTTER)
+            };
+            let marks = mark.repeat(end - start);
+            let marks = match annotation.message {
+                Some(message) => format!("{} {}", marks, message),
+                None => marks,
+            };
+            writeln!(
+                output,
+                "{} {}{}",
+                gutter(""),
+                " ".repeat(start),
+                self.paint(color, &marks)
+            )?;
+        }
+
+        let equals = self.paint(COLOR_GUTTER, &format!("{:>width$} =", ""));
+        for note in &diag.notes {
+            writeln!(output, "{} note: {}", equals, note)?;
+        }
+        for help in &diag.help {
+            writeln!(output, "{} help: {}", equals, help)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write all diagnostics as a single JSON object with a "diagnostics" array.  Lines and
+    /// columns start at 1, columns count characters and the end of a range is exclusive.
+    pub fn print_json<W: std::io::Write>(&self, output: &mut W) -> Result<(), std::io::Error> {
+        write!(output, "{{\"diagnostics\":[")?;
+
+        for (index, diag) in self.diags.iter().enumerate() {
+            if index > 0 {
+                write!(output, ",")?;
             }
-        });
 
-        writeln!(output, "{}:{}:{}: {}", self.path, line, column + 1, message)?;
+            let labels = diag
+                .labels
+                .iter()
+                .map(|(span, message)| {
+                    format!(
+                        "{{\"message\":{},{}}}",
+                        json_string(message),
+                        self.json_location(span)
+                    )
+                })
+                .collect::<Vec<_>>();
 
-        writeln!(output, "{}", expand_tabs(fragment, TAB_SIZE))?;
-        for _ in 0..highlight_start {
-            write!(output, " ")?;
+            write!(
+                output,
+                "{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"file\":{},{},\
+                 \"labels\":[{}],\"notes\":[{}],\"help\":[{}]}}",
+                diag.kind.as_str(),
+                diag.code
+                    .as_ref()
+                    .map_or_else(|| "null".to_owned(), |code| json_string(code)),
+                json_string(&diag.message),
+                json_string(&self.path),
+                self.json_location(&diag.span),
+                labels.join(","),
+                json_strings(&diag.notes),
+                json_strings(&diag.help),
+            )?;
         }
-        let end = if span.start == span.end {
-            span.end + 1
-        } else {
-            span.end
-        };
-        for _ in span.start..end {
-            write!(output, "^")?;
+
+        writeln!(output, "]}}")
+    }
+
+    /// Write all diagnostics as a SARIF 2.1.0 log with a single run.
+    pub fn print_sarif<W: std::io::Write>(&self, output: &mut W) -> Result<(), std::io::Error> {
+        write!(
+            output,
+            "{{\"$schema\":\"https://json.schemastore.org/sarif-2.1.0.json\",\"version\":\"2.1.0\",\
+             \"runs\":[{{\"tool\":{{\"driver\":{{\"name\":\"{}\",\"version\":\"{}\"}}}},\
+             \"columnKind\":\"unicodeCodePoints\",\"results\":[",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        )?;
+
+        for (index, diag) in self.diags.iter().enumerate() {
+            if index > 0 {
+                write!(output, ",")?;
+            }
+
+            write!(output, "{{")?;
+            if let Some(code) = &diag.code {
+                write!(output, "\"ruleId\":{},", json_string(code))?;
+            }
+            write!(
+                output,
+                "\"level\":\"{}\",\"message\":{{\"text\":{}}},\"locations\":[{{{}}}]",
+                diag.kind.sarif_level(),
+                json_string(&diag.message),
+                self.sarif_location(&diag.span),
+            )?;
+            if !diag.labels.is_empty() {
+                let related = diag
+                    .labels
+                    .iter()
+                    .enumerate()
+                    .map(|(id, (span, message))| {
+                        format!(
+                            "{{\"id\":{},\"message\":{{\"text\":{}}},{}}}",
+                            id,
+                            json_string(message),
+                            self.sarif_location(span)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                write!(output, ",\"relatedLocations\":[{}]", related.join(","))?;
+            }
+            write!(output, "}}")?;
         }
-        writeln!(output)
+
+        writeln!(output, "]}}]}}")
+    }
+
+    /// The "span" and "range" members of a JSON location.
+    fn json_location(&self, span: &Span) -> String {
+        let (start_line, start_column) = self.line_and_column(span.start);
+        let (end_line, end_column) = self.line_and_column(span.end);
+
+        format!(
+            "\"span\":{{\"start\":{},\"end\":{}}},\
+             \"range\":{{\"start\":{{\"line\":{},\"column\":{}}},\
+             \"end\":{{\"line\":{},\"column\":{}}}}}",
+            span.start, span.end, start_line, start_column, end_line, end_column,
+        )
     }
+
+    /// The "physicalLocation" member of a SARIF location.
+    fn sarif_location(&self, span: &Span) -> String {
+        let (start_line, start_column) = self.line_and_column(span.start);
+        let (end_line, end_column) = self.line_and_column(span.end);
+
+        format!(
+            "\"physicalLocation\":{{\"artifactLocation\":{{\"uri\":{}}},\
+             \"region\":{{\"startLine\":{},\"startColumn\":{},\"endLine\":{},\"endColumn\":{},\
+             \"byteOffset\":{},\"byteLength\":{}}}}}",
+            json_string(&self.path),
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+            span.start,
+            span.end - span.start,
+        )
+    }
+
+    /// The line and column of a byte offset into the source, both starting at 1.  Columns count
+    /// characters, not bytes.
+    fn line_and_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line_start = self.source[..offset]
+            .rfind('\n')
+            .map_or(0, |found| found + 1);
+
+        let line = self.source[..offset].matches('\n').count() + 1;
+        let column = self.source[line_start..offset].chars().count() + 1;
+
+        (line, column)
+    }
+
+    /// The text of a line, starting at 0, without the line ending.
+    fn line_text(&self, line: usize) -> &str {
+        self.source
+            .split('\n')
+            .nth(line)
+            .map_or("", |text| text.trim_end_matches('\r'))
+    }
+
+    /// Split a span into one annotation for each line it covers.
+    fn annotations<'d>(
+        &self,
+        span: &Span,
+        primary: bool,
+        message: Option<&'d str>,
+    ) -> Vec<Annotation<'d>> {
+        let start = span.start.min(self.source.len());
+        let end = span.end.clamp(start, self.source.len());
+
+        let first_line = self.source[..start].matches('\n').count();
+        let mut line_start = self.source[..start]
+            .rfind('\n')
+            .map_or(0, |found| found + 1);
+
+        let mut result = vec![];
+        let mut line = first_line;
+        loop {
+            let line_end = self.source[line_start..]
+                .find('\n')
+                .map_or(self.source.len(), |found| line_start + found);
+
+            result.push(Annotation {
+                line,
+                start: start.max(line_start) - line_start,
+                end: end.min(line_end) - line_start,
+                primary,
+                message: None,
+            });
+
+            if end <= line_end || line_end == self.source.len() {
+                break;
+            }
+
+            line += 1;
+            line_start = line_end + 1;
+        }
+
+        if let Some(last) = result.last_mut() {
+            last.message = message;
+        }
+
+        result
+    }
+}
+
+/// Quote and escape a string for JSON output.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+/// The column on screen of a byte offset into a line, after expanding tabs.
+fn display_column(line: &str, offset: usize) -> usize {
+    line[..offset.min(line.len())].chars().fold(0, |pos, c| {
+        if c == '\t' {
+            pos + TAB_SIZE - pos % TAB_SIZE
+        } else {
+            pos + 1
+        }
+    })
+}
+
+/// Quote and escape a list of strings, separated by commas.
+fn json_strings(strings: &[String]) -> String {
+    strings
+        .iter()
+        .map(|s| json_string(s))
+        .collect::<Vec<_>>()
+        .join(",")
 }
 
 fn expand_tabs(s: &str, tab_size: usize) -> String {
@@ -134,7 +502,7 @@ fn expand_tabs(s: &str, tab_size: usize) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::diagnostics::Diagnostics;
+    use crate::diagnostics::{DiagnosticKind, Diagnostics};
 
     macro_rules! assert_print_output {
         ($diags:expr, $expected:literal) => {{
@@ -160,7 +528,205 @@ mod tests {
 
         assert_print_output!(
             diags,
-            "mem:1:6: INFO: This is an info\nThis is the source\n     ^^\nmem:1:9: WARNING: This is a warning\nThis is the source\n        ^^^\nmem:1:13: ERROR: This is an error\nThis is the source\n            ^^^^^^\n"
+            "mem:1:6: INFO: This is an info\n  |\n1 | This is the source\n  |      ^^\nmem:1:9: WARNING: This is a warning\n  |\n1 | This is the source\n  |         ^^^\nmem:1:13: ERROR: This is an error\n  |\n1 | This is the source\n  |             ^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn code() {
+        const SOURCE: &str = "mov ax, bl";
+        let mut diags = Diagnostics::new(SOURCE, "mem".to_owned());
+
+        diags.diag_with_code(
+            DiagnosticKind::Error,
+            "E0203",
+            "Operand sizes does not match.",
+            8..10,
+        );
+
+        assert_print_output!(
+            diags,
+            "mem:1:9: ERROR[E0203]: Operand sizes does not match.\n  |\n1 | mov ax, bl\n  |         ^^\n"
+        );
+    }
+
+    #[test]
+    fn labels_notes_and_help() {
+        const SOURCE: &str = "start:\n    nop\nstart:\n    hlt";
+        let mut diags = Diagnostics::new(SOURCE, "mem".to_owned());
+
+        diags
+            .error("Label \"start\" is defined more than once", 15..20)
+            .label(0..5, "first defined here")
+            .note("labels are global")
+            .help("rename one of the labels");
+
+        assert_print_output!(
+            diags,
+            "mem:3:1: ERROR: Label \"start\" is defined more than once\n  |\n1 | start:\n  | ----- first defined here\n...\n3 | start:\n  | ^^^^^\n  = note: labels are global\n  = help: rename one of the labels\n"
+        );
+    }
+
+    #[test]
+    fn multi_line_span() {
+        const SOURCE: &str = "mov ax,\n\tbx\nhlt";
+        let mut diags = Diagnostics::new(SOURCE, "mem".to_owned());
+
+        diags
+            .error("Expected one operand", 4..11)
+            .label(4..6, "destination");
+
+        assert_print_output!(
+            diags,
+            "mem:1:5: ERROR: Expected one operand\n  |\n1 | mov ax,\n  |     ^^^\n  |     -- destination\n2 |     bx\n  | ^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn gutter_width() {
+        const SOURCE: &str = "nop\nnop\nnop\nnop\nnop\nnop\nnop\nnop\nnop\nnop dx";
+        let mut diags = Diagnostics::new(SOURCE, "mem".to_owned());
+
+        diags.warn("Unexpected operand", 40..42);
+
+        assert_print_output!(
+            diags,
+            "mem:10:5: WARNING: Unexpected operand\n   |\n10 | nop dx\n   |     ^^\n"
         );
     }
+
+    #[test]
+    fn color() {
+        const SOURCE: &str = "hlt";
+        let mut diags = Diagnostics::new(SOURCE, "mem".to_owned());
+        diags.set_color(true);
+
+        diags.error("Halted", 0..3).label(0..3, "here");
+
+        assert_print_output!(
+            diags,
+            "mem:1:1: \x1b[1;31mERROR:\x1b[0m Halted\n\x1b[1;34m  |\x1b[0m\n\x1b[1;34m1 |\x1b[0m hlt\n\x1b[1;34m  |\x1b[0m \x1b[1;31m^^^\x1b[0m\n\x1b[1;34m  |\x1b[0m \x1b[1;34m--- here\x1b[0m\n"
+        );
+    }
+
+    macro_rules! assert_output {
+        ($diags:expr, $print:ident, $expected:expr) => {{
+            let mut out = Vec::new();
+            $diags.$print(&mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), $expected)
+        }};
+    }
+
+    #[test]
+    fn json() {
+        const SOURCE: &str = "This is the source";
+        let mut diags = Diagnostics::new(SOURCE, "mem".to_owned());
+
+        assert_output!(diags, print_json, "{\"diagnostics\":[]}\n");
+
+        diags.info("This is an info", 5..7);
+        diags.diag_with_code(DiagnosticKind::Error, "E0001", "An \"error\"", 12..18);
+
+        assert_output!(
+            diags,
+            print_json,
+            concat!(
+                "{\"diagnostics\":[",
+                "{\"severity\":\"info\",\"code\":null,\"message\":\"This is an info\",",
+                "\"file\":\"mem\",\"span\":{\"start\":5,\"end\":7},",
+                "\"range\":{\"start\":{\"line\":1,\"column\":6},\"end\":{\"line\":1,\"column\":8}},\"labels\":[],\"notes\":[],\"help\":[]},",
+                "{\"severity\":\"error\",\"code\":\"E0001\",\"message\":\"An \\\"error\\\"\",",
+                "\"file\":\"mem\",\"span\":{\"start\":12,\"end\":18},",
+                "\"range\":{\"start\":{\"line\":1,\"column\":13},\"end\":{\"line\":1,\"column\":19}},\"labels\":[],\"notes\":[],\"help\":[]}",
+                "]}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn json_ranges() {
+        // Columns count characters and ranges can cover multiple lines.
+        const SOURCE: &str = "first\n\u{e9}t\u{e9}\nthird";
+        let mut diags = Diagnostics::new(SOURCE, "C:\\src\\main.asm".to_owned());
+
+        diags.warn("Tab\there", 8..13);
+
+        assert_output!(
+            diags,
+            print_json,
+            concat!(
+                "{\"diagnostics\":[",
+                "{\"severity\":\"warning\",\"code\":null,\"message\":\"Tab\\there\",",
+                "\"file\":\"C:\\\\src\\\\main.asm\",\"span\":{\"start\":8,\"end\":13},",
+                "\"range\":{\"start\":{\"line\":2,\"column\":2},\"end\":{\"line\":3,\"column\":2}},\"labels\":[],\"notes\":[],\"help\":[]}",
+                "]}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn sarif() {
+        const SOURCE: &str = "mov ax,\nhlt";
+        let mut diags = Diagnostics::new(SOURCE, "main.asm".to_owned());
+
+        diags.diag_with_code(DiagnosticKind::Error, "E0002", "Expected an operand", 7..7);
+        diags.info("Note", 8..11);
+
+        assert_output!(
+            diags,
+            print_sarif,
+            concat!(
+                "{\"$schema\":\"https://json.schemastore.org/sarif-2.1.0.json\",\"version\":\"2.1.0\",",
+                "\"runs\":[{\"tool\":{\"driver\":{\"name\":\"mrc_compiler\",\"version\":\"",
+                env!("CARGO_PKG_VERSION"),
+                "\"}},\"columnKind\":\"unicodeCodePoints\",\"results\":[",
+                "{\"ruleId\":\"E0002\",\"level\":\"error\",\"message\":{\"text\":\"Expected an operand\"},",
+                "\"locations\":[{\"physicalLocation\":{\"artifactLocation\":{\"uri\":\"main.asm\"},",
+                "\"region\":{\"startLine\":1,\"startColumn\":8,\"endLine\":1,\"endColumn\":8,",
+                "\"byteOffset\":7,\"byteLength\":0}}}]},",
+                "{\"level\":\"note\",\"message\":{\"text\":\"Note\"},",
+                "\"locations\":[{\"physicalLocation\":{\"artifactLocation\":{\"uri\":\"main.asm\"},",
+                "\"region\":{\"startLine\":2,\"startColumn\":1,\"endLine\":2,\"endColumn\":4,",
+                "\"byteOffset\":8,\"byteLength\":3}}}]}",
+                "]}]}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn machine_readable_labels() {
+        const SOURCE: &str = "a:\na:";
+        let mut diags = Diagnostics::new(SOURCE, "mem".to_owned());
+
+        diags
+            .error("Duplicate", 3..4)
+            .label(0..1, "first")
+            .note("a note")
+            .help("a \"help\"");
+
+        assert_output!(
+            diags,
+            print_json,
+            concat!(
+                "{\"diagnostics\":[",
+                "{\"severity\":\"error\",\"code\":null,\"message\":\"Duplicate\",",
+                "\"file\":\"mem\",\"span\":{\"start\":3,\"end\":4},",
+                "\"range\":{\"start\":{\"line\":2,\"column\":1},\"end\":{\"line\":2,\"column\":2}},",
+                "\"labels\":[{\"message\":\"first\",\"span\":{\"start\":0,\"end\":1},",
+                "\"range\":{\"start\":{\"line\":1,\"column\":1},\"end\":{\"line\":1,\"column\":2}}}],",
+                "\"notes\":[\"a note\"],\"help\":[\"a \\\"help\\\"\"]}",
+                "]}\n"
+            )
+        );
+
+        let mut out = Vec::new();
+        diags.print_sarif(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(concat!(
+            "\"relatedLocations\":[{\"id\":0,\"message\":{\"text\":\"first\"},",
+            "\"physicalLocation\":{\"artifactLocation\":{\"uri\":\"mem\"},",
+            "\"region\":{\"startLine\":1,\"startColumn\":1,\"endLine\":1,\"endColumn\":2,",
+            "\"byteOffset\":0,\"byteLength\":1}}}]"
+        )));
+    }
 }
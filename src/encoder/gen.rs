@@ -1,8 +1,7 @@
 use super::{
-    require_value_is_byte, require_value_is_signed_byte, ByteEmitter, EncodeError, InstructionData,
-    OperandData, OperandSize,
+    emit_sib_and_displacement, require_value_is_byte, require_value_is_signed_byte, ByteEmitter,
+    EncodeError, InstructionData, OperandData, OperandSize,
 };
-use crate::ast;
 use crate::encoder::{emit_segment_prefix, require_value_is_word};
 
 type FirstOperand = u8;
@@ -11,6 +10,7 @@ type OpCode = u8;
 
 pub const FIRST_OPER_DST: FirstOperand = 0;
 pub const FIRST_OPER_SRC: FirstOperand = 1;
+pub const FIRST_OPER_THIRD: FirstOperand = 2;
 
 pub enum Code {
     /// Emits the given byte.
@@ -20,8 +20,12 @@ pub enum Code {
     ImmByte(FirstOperand),
     ImmWord(FirstOperand),
 
-    /// Emites an unsigned byte/word from the [disp] value of the specified operand.
-    DispWord(FirstOperand),
+    /// Emits a signed byte from the [imm] value of the specified operand.
+    SignedImmByte(FirstOperand),
+
+    /// Emits an unsigned word/dword from the [disp] value of the specified operand, depending on
+    /// its address size.
+    Disp(FirstOperand),
 
     /// Emits a relative offset between the given operand's [imm] value and the current offset +
     /// the size of the instruction in the 2nd value.
@@ -33,8 +37,20 @@ pub enum Code {
     /// Emits a mod reg r/m encoded byte according to the given values and the [reg] value
     /// statically set.
     ModRM(FirstOperand, OpCode, u8),
+
+    /// Emits a mod reg r/m encoded byte with the r/m from the first operand and the [reg] value
+    /// from the register in the last operand.
+    RegModRM(FirstOperand, OpCode, FirstOperand),
+
+    /// Same as [ModRegRM] and [ModRM], but with the 0x0F prefix before the op code, used by the
+    /// instructions added on the 80286.
+    ExtModRegRM(FirstOperand, OpCode),
+    ExtModRM(FirstOperand, OpCode, u8),
 }
 
+/// The op code prefix for instructions with a 2 byte op code.
+const EXTENDED_OP_CODE: u8 = 0x0F;
+
 pub fn emit_codes(
     emitter: &mut impl ByteEmitter,
     insn: &InstructionData,
@@ -53,6 +69,20 @@ pub fn emit_codes(
                 emitter.emit(value);
             }
 
+            Code::SignedImmByte(first_operand) => {
+                let oper = &insn.opers[*first_operand as usize];
+                let value = require_value_is_signed_byte(oper.imm, &oper.span)?;
+                emitter.emit(value as u8);
+            }
+
+            // Instructions with a 32-bit operand size have 32-bit immediate values.
+            Code::ImmWord(first_operand) if insn.imm32 => {
+                let oper = &insn.opers[*first_operand as usize];
+                for byte in oper.imm.to_le_bytes() {
+                    emitter.emit(byte);
+                }
+            }
+
             Code::ImmWord(first_operand) => {
                 let oper = &insn.opers[*first_operand as usize];
                 let value = require_value_is_word(oper.imm, &oper.span)?;
@@ -61,12 +91,18 @@ pub fn emit_codes(
                 }
             }
 
-            Code::DispWord(first_operand) => {
+            Code::Disp(first_operand) => {
                 let oper = &insn.opers[*first_operand as usize];
-                debug_assert_eq!(OperandSize::Word, oper.displacement_size);
-                let value = require_value_is_word(oper.displacement, &oper.span)?;
-                for byte in value.to_le_bytes() {
-                    emitter.emit(byte);
+                if oper.displacement_size == OperandSize::DWord {
+                    for byte in oper.displacement.to_le_bytes() {
+                        emitter.emit(byte);
+                    }
+                } else {
+                    debug_assert_eq!(OperandSize::Word, oper.displacement_size);
+                    let value = require_value_is_word(oper.displacement, &oper.span)?;
+                    for byte in value.to_le_bytes() {
+                        emitter.emit(byte);
+                    }
                 }
             }
 
@@ -92,18 +128,29 @@ pub fn emit_codes(
             }
 
             Code::ModRegRM(first_operand, op_code) => {
-                let (reg, rm) = if *first_operand == 0 {
-                    (insn.opers[1].rm, &insn.opers[0])
-                } else {
-                    (insn.opers[0].rm, &insn.opers[1])
-                };
-
-                emit_mod_reg_rm(*op_code, rm, reg, emitter);
+                let (reg, rm) = mod_reg_rm_operands(insn, *first_operand);
+                emit_mod_reg_rm(&[*op_code], rm, reg, emitter);
             }
 
             Code::ModRM(first_operand, op_code, reg) => {
                 let rm = &insn.opers[*first_operand as usize];
-                emit_mod_reg_rm(*op_code, rm, *reg, emitter);
+                emit_mod_reg_rm(&[*op_code], rm, *reg, emitter);
+            }
+
+            Code::RegModRM(first_operand, op_code, reg_operand) => {
+                let rm = &insn.opers[*first_operand as usize];
+                let reg = insn.opers[*reg_operand as usize].rm;
+                emit_mod_reg_rm(&[*op_code], rm, reg, emitter);
+            }
+
+            Code::ExtModRegRM(first_operand, op_code) => {
+                let (reg, rm) = mod_reg_rm_operands(insn, *first_operand);
+                emit_mod_reg_rm(&[EXTENDED_OP_CODE, *op_code], rm, reg, emitter);
+            }
+
+            Code::ExtModRM(first_operand, op_code, reg) => {
+                let rm = &insn.opers[*first_operand as usize];
+                emit_mod_reg_rm(&[EXTENDED_OP_CODE, *op_code], rm, *reg, emitter);
             }
         }
     }
@@ -111,34 +158,25 @@ pub fn emit_codes(
     Ok(())
 }
 
-fn emit_mod_reg_rm(op_code: u8, rm: &OperandData, reg: u8, emitter: &mut impl ByteEmitter) {
-    // Any indirect address referencing BP uses SS as the default segment, all others use DS.
-    emit_segment_prefix(
-        rm.segment_prefix,
-        if rm.rm == 2 || rm.rm == 3 || (rm.mode > 0 && rm.rm == 6) {
-            ast::Segment::SS
-        } else {
-            ast::Segment::DS
-        },
-        emitter,
-    );
+/// Returns the [reg] value and the r/m operand for a [Code::ModRegRM].  The r/m operand is the
+/// [first_operand] and the register is the other one.
+fn mod_reg_rm_operands(insn: &InstructionData, first_operand: FirstOperand) -> (u8, &OperandData) {
+    if first_operand == 0 {
+        (insn.opers[1].rm, &insn.opers[0])
+    } else {
+        (insn.opers[0].rm, &insn.opers[1])
+    }
+}
+
+fn emit_mod_reg_rm(op_codes: &[u8], rm: &OperandData, reg: u8, emitter: &mut impl ByteEmitter) {
+    emit_segment_prefix(rm.segment_prefix, rm.default_segment(), emitter);
 
     let modrm = (rm.mode << 6) + (reg << 3) + rm.rm;
 
-    emitter.emit(op_code);
+    for op_code in op_codes {
+        emitter.emit(*op_code);
+    }
     emitter.emit(modrm);
 
-    match rm.displacement_size {
-        OperandSize::Byte => {
-            emitter.emit(rm.displacement as i8 as u8);
-        }
-
-        OperandSize::Word => {
-            for byte in (rm.displacement as i16 as u16).to_le_bytes() {
-                emitter.emit(byte);
-            }
-        }
-
-        _ => {}
-    }
+    emit_sib_and_displacement(rm, emitter);
 }
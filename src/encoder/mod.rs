@@ -1,8 +1,10 @@
 mod gen;
+#[cfg(test)]
+mod round_trip;
 
 use super::ast;
-use crate::operations::Operation;
-use gen::{emit_codes, Code, FIRST_OPER_DST, FIRST_OPER_SRC};
+use crate::operations::{Cpu, Operation};
+use gen::{emit_codes, Code, FIRST_OPER_DST, FIRST_OPER_SRC, FIRST_OPER_THIRD};
 use std::fmt::{Display, Formatter};
 
 const EMIT_DEFAULT_SEGMENTS: bool = true;
@@ -61,6 +63,7 @@ pub enum EncodeError {
     InvalidOperandSize(ast::Span),
     ImmediateOutOfRange(ast::Span, i32, i32, i32),
     RelativeJumpOutOfRange(ast::Span, OperandSize, bool, i32),
+    InvalidSegmentOverride(ast::Span),
 }
 
 impl EncodeError {
@@ -71,7 +74,22 @@ impl EncodeError {
             | EncodeError::OperandSizesDoNotMatch(span)
             | EncodeError::InvalidOperandSize(span)
             | EncodeError::ImmediateOutOfRange(span, _, _, _)
-            | EncodeError::RelativeJumpOutOfRange(span, _, _, _) => span,
+            | EncodeError::RelativeJumpOutOfRange(span, _, _, _)
+            | EncodeError::InvalidSegmentOverride(span) => span,
+        }
+    }
+
+    /// A stable identifier for the kind of error, which can be looked up with
+    /// [crate::explain::explain].
+    pub fn code(&self) -> &'static str {
+        match self {
+            EncodeError::InvalidOperands(_) => "E0201",
+            EncodeError::OperandSizeNotSpecified(_) => "E0202",
+            EncodeError::OperandSizesDoNotMatch(_) => "E0203",
+            EncodeError::InvalidOperandSize(_) => "E0204",
+            EncodeError::ImmediateOutOfRange(..) => "E0205",
+            EncodeError::RelativeJumpOutOfRange(..) => "E0206",
+            EncodeError::InvalidSegmentOverride(_) => "E0207",
         }
     }
 }
@@ -103,21 +121,69 @@ impl Display for EncodeError {
                         OperandSize::Unspecified => "value with unspecified size",
                         OperandSize::Byte => "byte",
                         OperandSize::Word => "word",
+                        OperandSize::DWord => "dword",
+                        OperandSize::QWord => "qword",
+                        OperandSize::TWord => "tword",
                     }
                 )
             }
+
+            EncodeError::InvalidSegmentOverride(_) => {
+                write!(f, "The segment of this operand can not be overridden.")
+            }
         }
     }
 }
 
+/// The segment override prefix for the encoding of a segment register operand.
+fn segment_prefix_for_register(encoding: u8) -> u8 {
+    use ast::Segment::*;
+
+    [ES, CS, SS, DS, FS, GS]
+        .into_iter()
+        .find(|segment| segment.encoding() == encoding)
+        .map_or(0, segment_prefix_for)
+}
+
 const fn segment_prefix_for(seg: ast::Segment) -> u8 {
-    0x26 + ((seg as u8) << 3)
+    match seg {
+        ast::Segment::ES => 0x26,
+        ast::Segment::CS => 0x2E,
+        ast::Segment::SS => 0x36,
+        ast::Segment::DS => 0x3E,
+        ast::Segment::FS => 0x64,
+        ast::Segment::GS => 0x65,
+    }
 }
 
 pub fn encode(
     insn: &InstructionData,
     offset: u16,
     emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    if insn.wait_prefix() {
+        emitter.emit(0x9B);
+    }
+
+    if insn.address_size_prefix() {
+        emitter.emit(0x67);
+    }
+
+    if insn.operand_size_prefix() {
+        emitter.emit(0x66);
+    }
+
+    if insn.operand_size() == OperandSize::DWord {
+        encode_operation(&insn.with_word_operands(), offset, emitter)
+    } else {
+        encode_operation(insn, offset, emitter)
+    }
+}
+
+fn encode_operation(
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
     use crate::operations::Operation::*;
 
@@ -134,6 +200,7 @@ pub fn encode(
         NOT => encode_group_not_neg_mul_imul_div_idiv(0x02, insn, offset, emitter),
         NEG => encode_group_not_neg_mul_imul_div_idiv(0x03, insn, offset, emitter),
         MUL => encode_group_not_neg_mul_imul_div_idiv(0x04, insn, offset, emitter),
+        IMUL if insn.num_opers > 1 => encode_group_imul(0x00, insn, offset, emitter),
         IMUL => encode_group_not_neg_mul_imul_div_idiv(0x05, insn, offset, emitter),
         DIV => encode_group_not_neg_mul_imul_div_idiv(0x06, insn, offset, emitter),
         IDIV => encode_group_not_neg_mul_imul_div_idiv(0x07, insn, offset, emitter),
@@ -155,8 +222,9 @@ pub fn encode(
         //
         LEA => encode_group_lea(0x00, insn, offset, emitter),
 
-        LES => encode_group_les_lds(0xC4, insn, offset, emitter),
-        LDS => encode_group_les_lds(0xC5, insn, offset, emitter),
+        LES => encode_group_les_lds_bound(0xC4, insn, offset, emitter),
+        LDS => encode_group_les_lds_bound(0xC5, insn, offset, emitter),
+        BOUND => encode_group_les_lds_bound(0x62, insn, offset, emitter),
 
         ROL => encode_group_rol_ror_rcl_rcr_shl_sal_shr_sar(0x00, insn, offset, emitter),
         ROR => encode_group_rol_ror_rcl_rcr_shl_sal_shr_sar(0x01, insn, offset, emitter),
@@ -198,11 +266,12 @@ pub fn encode(
         AAA => encode_group_no_operands(0x37, insn, offset, emitter),
         AAS => encode_group_no_operands(0x3F, insn, offset, emitter),
         NOP => encode_group_no_operands(0x90, insn, offset, emitter),
-        CBW => encode_group_no_operands(0x98, insn, offset, emitter),
-        CWD => encode_group_no_operands(0x99, insn, offset, emitter),
+        CBW | CWDE => encode_group_no_operands(0x98, insn, offset, emitter),
+        CWD | CDQ => encode_group_no_operands(0x99, insn, offset, emitter),
         INT3 => encode_group_no_operands(0xCC, insn, offset, emitter),
+        INT1 => encode_group_no_operands(0xF1, insn, offset, emitter),
         INTO => encode_group_no_operands(0xCE, insn, offset, emitter),
-        IRET => encode_group_no_operands(0xCF, insn, offset, emitter),
+        IRET | IRETD => encode_group_no_operands(0xCF, insn, offset, emitter),
         SALC => encode_group_no_operands(0xD6, insn, offset, emitter),
         HLT => encode_group_no_operands(0xF4, insn, offset, emitter),
         CMC => encode_group_no_operands(0xF5, insn, offset, emitter),
@@ -212,21 +281,36 @@ pub fn encode(
         STI => encode_group_no_operands(0xFB, insn, offset, emitter),
         CLD => encode_group_no_operands(0xFC, insn, offset, emitter),
         STD => encode_group_no_operands(0xFD, insn, offset, emitter),
-        PUSHF => encode_group_no_operands(0x9C, insn, offset, emitter),
-        POPF => encode_group_no_operands(0x9D, insn, offset, emitter),
+        PUSHF | PUSHFD => encode_group_no_operands(0x9C, insn, offset, emitter),
+        POPF | POPFD => encode_group_no_operands(0x9D, insn, offset, emitter),
         SAHF => encode_group_no_operands(0x9E, insn, offset, emitter),
         LAHF => encode_group_no_operands(0x9F, insn, offset, emitter),
-        MOVSB => encode_group_no_operands(0xA4, insn, offset, emitter),
-        CMPSB => encode_group_no_operands(0xA6, insn, offset, emitter),
-        STOSB => encode_group_no_operands(0xAA, insn, offset, emitter),
-        LODSB => encode_group_no_operands(0xAC, insn, offset, emitter),
-        SCASB => encode_group_no_operands(0xAE, insn, offset, emitter),
+        MOVSB => encode_group_string_source(0xA4, insn, offset, emitter),
+        CMPSB => encode_group_string_source(0xA6, insn, offset, emitter),
+        STOSB => encode_group_string_destination(0xAA, insn, offset, emitter),
+        LODSB => encode_group_string_source(0xAC, insn, offset, emitter),
+        SCASB => encode_group_string_destination(0xAE, insn, offset, emitter),
         // XLATB => encode_group_no_operands(0xD7, insn, offset, emitter),
-        MOVSW => encode_group_no_operands(0xA5, insn, offset, emitter),
-        CMPSW => encode_group_no_operands(0xA7, insn, offset, emitter),
-        STOSW => encode_group_no_operands(0xAB, insn, offset, emitter),
-        LODSW => encode_group_no_operands(0xAD, insn, offset, emitter),
-        SCASW => encode_group_no_operands(0xAF, insn, offset, emitter),
+        MOVSW | MOVSD => encode_group_string_source(0xA5, insn, offset, emitter),
+        CMPSW | CMPSD => encode_group_string_source(0xA7, insn, offset, emitter),
+        STOSW | STOSD => encode_group_string_destination(0xAB, insn, offset, emitter),
+        LODSW | LODSD => encode_group_string_source(0xAD, insn, offset, emitter),
+        SCASW | SCASD => encode_group_string_destination(0xAF, insn, offset, emitter),
+        PUSHA | PUSHAD => encode_group_no_operands(0x60, insn, offset, emitter),
+        POPA | POPAD => encode_group_no_operands(0x61, insn, offset, emitter),
+        INSB => encode_group_string_destination(0x6C, insn, offset, emitter),
+        INSW | INSD => encode_group_string_destination(0x6D, insn, offset, emitter),
+        OUTSB => encode_group_string_source(0x6E, insn, offset, emitter),
+        OUTSW | OUTSD => encode_group_string_source(0x6F, insn, offset, emitter),
+
+        MOVS => encode_group_movs_cmps(0xA4, insn, offset, emitter),
+        CMPS => encode_group_movs_cmps(0xA6, insn, offset, emitter),
+        STOS => encode_group_lods_stos_scas(0xAA, insn, offset, emitter),
+        LODS => encode_group_lods_stos_scas(0xAC, insn, offset, emitter),
+        SCAS => encode_group_lods_stos_scas(0xAE, insn, offset, emitter),
+        OUTS => encode_group_outs(0x6E, insn, offset, emitter),
+        LEAVE => encode_group_no_operands(0xC9, insn, offset, emitter),
+        WAIT => encode_group_no_operands(0x9B, insn, offset, emitter),
 
         LOCK => encode_group_prefixes(0xF0, insn, offset, emitter),
         REPNE => encode_group_prefixes(0xF2, insn, offset, emitter),
@@ -234,6 +318,8 @@ pub fn encode(
 
         INT => encode_group_int(0x00, insn, offset, emitter),
 
+        ENTER => encode_group_enter(0x00, insn, offset, emitter),
+
         AAM => encode_group_aam(0x00, insn, offset, emitter),
 
         AAD => encode_group_aad(0x00, insn, offset, emitter),
@@ -244,7 +330,159 @@ pub fn encode(
 
         OUT => encode_group_out(0x00, insn, offset, emitter),
 
-        op => todo!("{:?}", op),
+        SLDT => encode_group_sldt_str_lldt_ltr_verr_verw(0x00, insn, offset, emitter),
+        STR => encode_group_sldt_str_lldt_ltr_verr_verw(0x01, insn, offset, emitter),
+        LLDT => encode_group_sldt_str_lldt_ltr_verr_verw(0x02, insn, offset, emitter),
+        LTR => encode_group_sldt_str_lldt_ltr_verr_verw(0x03, insn, offset, emitter),
+        VERR => encode_group_sldt_str_lldt_ltr_verr_verw(0x04, insn, offset, emitter),
+        VERW => encode_group_sldt_str_lldt_ltr_verr_verw(0x05, insn, offset, emitter),
+
+        SGDT => encode_group_sgdt_sidt_lgdt_lidt(0x00, insn, offset, emitter),
+        SIDT => encode_group_sgdt_sidt_lgdt_lidt(0x01, insn, offset, emitter),
+        LGDT => encode_group_sgdt_sidt_lgdt_lidt(0x02, insn, offset, emitter),
+        LIDT => encode_group_sgdt_sidt_lgdt_lidt(0x03, insn, offset, emitter),
+
+        SMSW => encode_group_smsw_lmsw(0x04, insn, offset, emitter),
+        LMSW => encode_group_smsw_lmsw(0x06, insn, offset, emitter),
+
+        LAR => encode_group_lar_lsl(0x02, insn, offset, emitter),
+        LSL => encode_group_lar_lsl(0x03, insn, offset, emitter),
+
+        ARPL => encode_group_arpl(0x63, insn, offset, emitter),
+
+        CLTS => encode_group_clts(0x00, insn, offset, emitter),
+
+        ESC => encode_group_esc(0xD8, insn, offset, emitter),
+
+        FADD => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x00, insn, offset, emitter)
+        }
+        FMUL => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x01, insn, offset, emitter)
+        }
+        FCOM => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x02, insn, offset, emitter)
+        }
+        FCOMP => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x03, insn, offset, emitter)
+        }
+        FSUB => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x04, insn, offset, emitter)
+        }
+        FSUBR => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x05, insn, offset, emitter)
+        }
+        FDIV => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x06, insn, offset, emitter)
+        }
+        FDIVR => {
+            encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(0x07, insn, offset, emitter)
+        }
+
+        // The [reg] values for the reversed variants are swapped when popping.
+        FADDP => encode_group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp(0x00, insn, offset, emitter),
+        FMULP => encode_group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp(0x01, insn, offset, emitter),
+        FSUBRP => encode_group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp(0x04, insn, offset, emitter),
+        FSUBP => encode_group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp(0x05, insn, offset, emitter),
+        FDIVRP => encode_group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp(0x06, insn, offset, emitter),
+        FDIVP => encode_group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp(0x07, insn, offset, emitter),
+
+        FIADD => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x00, insn, offset, emitter,
+        ),
+        FIMUL => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x01, insn, offset, emitter,
+        ),
+        FICOM => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x02, insn, offset, emitter,
+        ),
+        FICOMP => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x03, insn, offset, emitter,
+        ),
+        FISUB => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x04, insn, offset, emitter,
+        ),
+        FISUBR => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x05, insn, offset, emitter,
+        ),
+        FIDIV => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x06, insn, offset, emitter,
+        ),
+        FIDIVR => encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+            0x07, insn, offset, emitter,
+        ),
+
+        FLD => encode_group_fld_fst_fstp(0x00, insn, offset, emitter),
+        FST => encode_group_fld_fst_fstp(0x02, insn, offset, emitter),
+        FSTP => encode_group_fld_fst_fstp(0x03, insn, offset, emitter),
+
+        FILD => encode_group_fild_fist_fistp(0x00, insn, offset, emitter),
+        FIST => encode_group_fild_fist_fistp(0x02, insn, offset, emitter),
+        FISTP => encode_group_fild_fist_fistp(0x03, insn, offset, emitter),
+
+        FBLD => encode_group_fbld_fbstp(0x04, insn, offset, emitter),
+        FBSTP => encode_group_fbld_fbstp(0x06, insn, offset, emitter),
+
+        FXCH => encode_group_fxch(0x00, insn, offset, emitter),
+        FFREE => encode_group_ffree(0x00, insn, offset, emitter),
+
+        FCOMPP => encode_group_fcompp(0x00, insn, offset, emitter),
+
+        FNOP => encode_group_fpu_no_operands(0xD0, insn, offset, emitter),
+        FCHS => encode_group_fpu_no_operands(0xE0, insn, offset, emitter),
+        FABS => encode_group_fpu_no_operands(0xE1, insn, offset, emitter),
+        FTST => encode_group_fpu_no_operands(0xE4, insn, offset, emitter),
+        FXAM => encode_group_fpu_no_operands(0xE5, insn, offset, emitter),
+        FLD1 => encode_group_fpu_no_operands(0xE8, insn, offset, emitter),
+        FLDL2T => encode_group_fpu_no_operands(0xE9, insn, offset, emitter),
+        FLDL2E => encode_group_fpu_no_operands(0xEA, insn, offset, emitter),
+        FLDPI => encode_group_fpu_no_operands(0xEB, insn, offset, emitter),
+        FLDLG2 => encode_group_fpu_no_operands(0xEC, insn, offset, emitter),
+        FLDLN2 => encode_group_fpu_no_operands(0xED, insn, offset, emitter),
+        FLDZ => encode_group_fpu_no_operands(0xEE, insn, offset, emitter),
+        F2XM1 => encode_group_fpu_no_operands(0xF0, insn, offset, emitter),
+        FYL2X => encode_group_fpu_no_operands(0xF1, insn, offset, emitter),
+        FPTAN => encode_group_fpu_no_operands(0xF2, insn, offset, emitter),
+        FPATAN => encode_group_fpu_no_operands(0xF3, insn, offset, emitter),
+        FXTRACT => encode_group_fpu_no_operands(0xF4, insn, offset, emitter),
+        FDECSTP => encode_group_fpu_no_operands(0xF6, insn, offset, emitter),
+        FINCSTP => encode_group_fpu_no_operands(0xF7, insn, offset, emitter),
+        FPREM => encode_group_fpu_no_operands(0xF8, insn, offset, emitter),
+        FYL2XP1 => encode_group_fpu_no_operands(0xF9, insn, offset, emitter),
+        FSQRT => encode_group_fpu_no_operands(0xFA, insn, offset, emitter),
+        FRNDINT => encode_group_fpu_no_operands(0xFC, insn, offset, emitter),
+        FSCALE => encode_group_fpu_no_operands(0xFD, insn, offset, emitter),
+
+        FENI | FNENI => encode_group_finit_feni_fdisi_fclex(0xE0, insn, offset, emitter),
+        FDISI | FNDISI => encode_group_finit_feni_fdisi_fclex(0xE1, insn, offset, emitter),
+        FCLEX | FNCLEX => encode_group_finit_feni_fdisi_fclex(0xE2, insn, offset, emitter),
+        FINIT | FNINIT => encode_group_finit_feni_fdisi_fclex(0xE3, insn, offset, emitter),
+
+        FLDENV => encode_group_fldenv_fldcw_fstenv_fstcw(0x04, insn, offset, emitter),
+        FLDCW => encode_group_fldenv_fldcw_fstenv_fstcw(0x05, insn, offset, emitter),
+        FSTENV | FNSTENV => encode_group_fldenv_fldcw_fstenv_fstcw(0x06, insn, offset, emitter),
+        FSTCW | FNSTCW => encode_group_fldenv_fldcw_fstenv_fstcw(0x07, insn, offset, emitter),
+
+        FRSTOR => encode_group_frstor_fsave_fstsw(0x04, insn, offset, emitter),
+        FSAVE | FNSAVE => encode_group_frstor_fsave_fstsw(0x06, insn, offset, emitter),
+        FSTSW | FNSTSW => encode_group_frstor_fsave_fstsw(0x07, insn, offset, emitter),
+
+        TEST1 => encode_group_test1_clr1_set1_not1(0x10, insn, offset, emitter),
+        CLR1 => encode_group_test1_clr1_set1_not1(0x12, insn, offset, emitter),
+        SET1 => encode_group_test1_clr1_set1_not1(0x14, insn, offset, emitter),
+        NOT1 => encode_group_test1_clr1_set1_not1(0x16, insn, offset, emitter),
+
+        INS => encode_group_ins_ext(0x31, insn, offset, emitter),
+        EXT => encode_group_ins_ext(0x33, insn, offset, emitter),
+
+        ROL4 => encode_group_rol4_ror4(0x28, insn, offset, emitter),
+        ROR4 => encode_group_rol4_ror4(0x2A, insn, offset, emitter),
+
+        ADD4S => encode_group_add4s_sub4s_cmp4s(0x20, insn, offset, emitter),
+        SUB4S => encode_group_add4s_sub4s_cmp4s(0x22, insn, offset, emitter),
+        CMP4S => encode_group_add4s_sub4s_cmp4s(0x26, insn, offset, emitter),
+
+        BRKEM => encode_group_brkem(0xFF, insn, offset, emitter),
     }
 }
 
@@ -267,7 +505,7 @@ fn encode_group_add_or_adc_sbb_and_sub_xor_cmp(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
     let size = common_operand_size(dst, src, &insn.opers_span)?;
 
     match (dst.kind, src.kind) {
@@ -297,8 +535,8 @@ fn encode_group_add_or_adc_sbb_and_sub_xor_cmp(
                             insn,
                             offset,
                             &[
-                                Code::ModRM(0, 0x83, base >> 3),
-                                Code::ImmByte(FIRST_OPER_SRC),
+                                Code::ModRM(FIRST_OPER_DST, 0x83, base >> 3),
+                                Code::SignedImmByte(FIRST_OPER_SRC),
                             ],
                         )
                     } else if dst.kind == OperandKind::Reg && dst.rm == 0 {
@@ -360,7 +598,7 @@ fn encode_group_not_neg_mul_imul_div_idiv(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     if dst.size.is_unspecified() {
         return Err(EncodeError::OperandSizeNotSpecified(
@@ -387,13 +625,63 @@ fn encode_group_not_neg_mul_imul_div_idiv(
     }
 }
 
+fn encode_group_imul(
+    _base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, third] = &insn.opers;
+
+    // "imul reg, imm" is a short form of "imul reg, reg, imm".
+    let (rm, imm, imm_operand) = if insn.num_opers == 2 {
+        (FIRST_OPER_DST, src, FIRST_OPER_SRC)
+    } else {
+        (FIRST_OPER_SRC, third, FIRST_OPER_THIRD)
+    };
+    let rm_oper = &insn.opers[rm as usize];
+
+    if dst.kind != OperandKind::Reg
+        || dst.size != OperandSize::Word
+        || !matches!(rm_oper.kind, OperandKind::Reg | OperandKind::Mem)
+        || rm_oper.size == OperandSize::Byte
+        || imm.kind != OperandKind::Imm
+    {
+        return Err(EncodeError::InvalidOperands(insn.opers_span.clone()));
+    }
+
+    if value_is_signed_byte(imm.imm) {
+        // reg16, r/m16, simm8
+        emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[
+                Code::RegModRM(rm, 0x6B, FIRST_OPER_DST),
+                Code::SignedImmByte(imm_operand),
+            ],
+        )
+    } else {
+        // reg16, r/m16, imm16
+        emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[
+                Code::RegModRM(rm, 0x69, FIRST_OPER_DST),
+                Code::ImmWord(imm_operand),
+            ],
+        )
+    }
+}
+
 fn encode_group_mov(
     _base: u8,
     insn: &InstructionData,
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
     let size = common_operand_size(dst, src, &insn.opers_span)?;
 
     match (dst.kind, src.kind) {
@@ -406,7 +694,7 @@ fn encode_group_mov(
                 emitter,
                 insn,
                 offset,
-                &[Code::Byte(0xA2), Code::DispWord(FIRST_OPER_DST)],
+                &[Code::Byte(0xA2), Code::Disp(FIRST_OPER_DST)],
             )
         }
 
@@ -419,7 +707,7 @@ fn encode_group_mov(
                 emitter,
                 insn,
                 offset,
-                &[Code::Byte(0xA3), Code::DispWord(FIRST_OPER_DST)],
+                &[Code::Byte(0xA3), Code::Disp(FIRST_OPER_DST)],
             )
         }
 
@@ -434,7 +722,7 @@ fn encode_group_mov(
                 Ok(())
             }
 
-            OperandSize::Unspecified => unreachable!(),
+            _ => unreachable!(),
         },
 
         (OperandKind::Reg, OperandKind::Mem) => {
@@ -443,24 +731,18 @@ fn encode_group_mov(
                 // al, mem
                 emit_segment_prefix(src.segment_prefix, ast::Segment::DS, emitter);
 
-                match size {
-                    OperandSize::Byte => {
-                        emitter.emit(0xA0);
-                    }
-
-                    OperandSize::Word => {
-                        emitter.emit(0xA1);
-                    }
-
-                    OperandSize::Unspecified => unreachable!(),
-                }
-
-                let value = require_value_is_word(src.displacement, &src.span)?;
-                for byte in value.to_le_bytes() {
-                    emitter.emit(byte);
-                }
-
-                Ok(())
+                let op_code = match size {
+                    OperandSize::Byte => 0xA0,
+                    OperandSize::Word => 0xA1,
+                    _ => unreachable!(),
+                };
+
+                emit_codes(
+                    emitter,
+                    insn,
+                    offset,
+                    &[Code::Byte(op_code), Code::Disp(FIRST_OPER_SRC)],
+                )
             } else {
                 match size {
                     OperandSize::Byte => {
@@ -473,7 +755,7 @@ fn encode_group_mov(
                         Ok(())
                     }
 
-                    OperandSize::Unspecified => unreachable!(),
+                    _ => unreachable!(),
                 }
             }
         }
@@ -489,11 +771,16 @@ fn encode_group_mov(
                     }
 
                     OperandSize::Word => {
-                        emit_mod_reg_rm(0xC7, dst, 0, OperandSize::Word, src.imm, emitter);
+                        let imm_size = if insn.imm32 {
+                            OperandSize::DWord
+                        } else {
+                            OperandSize::Word
+                        };
+                        emit_mod_reg_rm(0xC7, dst, 0, imm_size, src.imm, emitter);
                         Ok(())
                     }
 
-                    OperandSize::Unspecified => unreachable!(),
+                    _ => unreachable!(),
                 },
 
                 OperandKind::Reg => match size {
@@ -504,16 +791,14 @@ fn encode_group_mov(
                         Ok(())
                     }
 
-                    OperandSize::Word => {
-                        let value = require_value_is_word(src.imm, &src.span)?;
-                        emitter.emit(0xB8 + dst.rm);
-                        for byte in value.to_le_bytes() {
-                            emitter.emit(byte);
-                        }
-                        Ok(())
-                    }
+                    OperandSize::Word => emit_codes(
+                        emitter,
+                        insn,
+                        offset,
+                        &[Code::Byte(0xB8 + dst.rm), Code::ImmWord(FIRST_OPER_SRC)],
+                    ),
 
-                    OperandSize::Unspecified => unreachable!(),
+                    _ => unreachable!(),
                 },
                 _ => unreachable!(),
             }
@@ -540,18 +825,26 @@ fn common_operand_size(
     src: &OperandData,
     opers_span: &ast::Span,
 ) -> Result<OperandSize, EncodeError> {
-    if dst.size.is_unspecified() && src.size.is_unspecified() {
+    // The size of an immediate value is only used by instructions that have no other operand to
+    // take the size from, e.g. "push dword 5".
+    let src_size = if src.kind == OperandKind::Imm {
+        OperandSize::Unspecified
+    } else {
+        src.size
+    };
+
+    if dst.size.is_unspecified() && src_size.is_unspecified() {
         return Err(EncodeError::OperandSizeNotSpecified(opers_span.clone()));
     }
 
-    if dst.size.is_specified() && src.size.is_specified() && dst.size != src.size {
+    if dst.size.is_specified() && src_size.is_specified() && dst.size != src_size {
         return Err(EncodeError::OperandSizesDoNotMatch(opers_span.clone()));
     }
 
     let size = if dst.size.is_specified() {
         dst.size
     } else {
-        src.size
+        src_size
     };
 
     Ok(size)
@@ -560,42 +853,89 @@ fn common_operand_size(
 fn encode_group_test(
     _base: u8,
     insn: &InstructionData,
-    _offset: u16,
+    offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
     let size = common_operand_size(dst, src, &insn.opers_span)?;
 
     match (dst.kind, src.kind) {
-        (OperandKind::Reg | OperandKind::Mem, OperandKind::Reg) => {
-            todo!()
+        (OperandKind::Reg | OperandKind::Mem, OperandKind::Reg) if size == OperandSize::Byte => {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRegRM(FIRST_OPER_DST, 0x84)],
+            )
         }
 
-        (OperandKind::Reg, OperandKind::Mem) => {
-            todo!()
+        (OperandKind::Reg | OperandKind::Mem, OperandKind::Reg) if size == OperandSize::Word => {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRegRM(FIRST_OPER_DST, 0x85)],
+            )
         }
 
-        (OperandKind::Reg | OperandKind::Mem, OperandKind::Imm) => {
-            match size {
-                OperandSize::Byte => {
-                    if dst.kind == OperandKind::Reg && dst.rm == 0 {
-                        let value = require_value_is_byte(dst.imm, &dst.span)?;
-                        emitter.emit(0xA8);
-                        emitter.emit(value);
-                        Ok(())
-                    } else {
-                        // x86.store_instruction@dest, (0F6h),(0),byte,@src.imm
-                        todo!()
-                    }
-                }
+        // The operands of test can be in any order, there is no separate reg, mem form.
+        (OperandKind::Reg, OperandKind::Mem) if size == OperandSize::Byte => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRegRM(FIRST_OPER_SRC, 0x84)],
+        ),
 
-                OperandSize::Word => {
-                    todo!()
-                }
+        (OperandKind::Reg, OperandKind::Mem) if size == OperandSize::Word => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRegRM(FIRST_OPER_SRC, 0x85)],
+        ),
 
-                OperandSize::Unspecified => unreachable!(),
+        (OperandKind::Reg | OperandKind::Mem, OperandKind::Imm) => match size {
+            OperandSize::Byte if dst.kind == OperandKind::Reg && dst.rm == 0 => {
+                // AL, imm
+                emit_codes(
+                    emitter,
+                    insn,
+                    offset,
+                    &[Code::Byte(0xA8), Code::ImmByte(FIRST_OPER_SRC)],
+                )
             }
-        }
+
+            OperandSize::Byte => emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[
+                    Code::ModRM(FIRST_OPER_DST, 0xF6, 0),
+                    Code::ImmByte(FIRST_OPER_SRC),
+                ],
+            ),
+
+            OperandSize::Word if dst.kind == OperandKind::Reg && dst.rm == 0 => {
+                // AX, imm
+                emit_codes(
+                    emitter,
+                    insn,
+                    offset,
+                    &[Code::Byte(0xA9), Code::ImmWord(FIRST_OPER_SRC)],
+                )
+            }
+
+            OperandSize::Word => emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[
+                    Code::ModRM(FIRST_OPER_DST, 0xF7, 0),
+                    Code::ImmWord(FIRST_OPER_SRC),
+                ],
+            ),
+
+            _ => Err(EncodeError::InvalidOperandSize(insn.opers_span.clone())),
+        },
 
         _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
     }
@@ -607,12 +947,12 @@ fn encode_group_xchg(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
     let size = common_operand_size(dst, src, &insn.opers_span)?;
 
     match (dst.kind, src.kind) {
         (OperandKind::Reg, OperandKind::Reg)
-            if size == OperandSize::Word && dst.rm == 0 || src.rm == 0 =>
+            if size == OperandSize::Word && (dst.rm == 0 || src.rm == 0) =>
         {
             emit_codes(emitter, insn, offset, &[Code::Byte(0x90 + dst.rm + src.rm)])
         }
@@ -663,7 +1003,7 @@ fn encode_group_inc_dec(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     if dst.size.is_unspecified() {
         return Err(EncodeError::InvalidOperandSize(dst.span.clone()));
@@ -708,19 +1048,48 @@ fn encode_group_push(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     match dst.kind {
-        OperandKind::Mem if dst.size == OperandSize::Word => {
-            todo!()
-        }
+        OperandKind::Mem if dst.size == OperandSize::Word => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xFF, 6)],
+        ),
 
         OperandKind::Reg if dst.size == OperandSize::Word => {
             emit_codes(emitter, insn, offset, &[Code::Byte(0x50 + dst.rm)])
         }
 
+        // FS and GS were added on the 80386 with a 2 byte op code.
+        OperandKind::Seg if dst.rm >= ast::Segment::FS.encoding() => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::Byte(0x0F), Code::Byte(0b10_000_000 + (dst.rm << 3))],
+        ),
+
         OperandKind::Seg => emit_codes(emitter, insn, offset, &[Code::Byte(0b110 + (dst.rm << 3))]),
 
+        // simm8, "push byte" always uses this form.
+        OperandKind::Imm if dst.size == OperandSize::Byte || value_is_signed_byte(dst.imm) => {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::Byte(0x6A), Code::SignedImmByte(FIRST_OPER_DST)],
+            )
+        }
+
+        // imm16
+        OperandKind::Imm => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::Byte(0x68), Code::ImmWord(FIRST_OPER_DST)],
+        ),
+
         _ => Err(EncodeError::InvalidOperands(dst.span.clone())),
     }
 }
@@ -731,21 +1100,31 @@ fn encode_group_pop(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     if dst.size != OperandSize::Word {
         return Err(EncodeError::InvalidOperandSize(dst.span.clone()));
     }
 
     match dst.kind {
-        OperandKind::Mem if dst.size == OperandSize::Word => {
-            todo!()
-        }
+        OperandKind::Mem if dst.size == OperandSize::Word => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0x8F, 0)],
+        ),
 
         OperandKind::Reg if dst.size == OperandSize::Word => {
             emit_codes(emitter, insn, offset, &[Code::Byte(0x58 + dst.rm)])
         }
 
+        OperandKind::Seg if dst.rm >= ast::Segment::FS.encoding() => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::Byte(0x0F), Code::Byte(0b10_000_001 + (dst.rm << 3))],
+        ),
+
         OperandKind::Seg => emit_codes(emitter, insn, offset, &[Code::Byte(0b111 + (dst.rm << 3))]),
 
         _ => Err(EncodeError::InvalidOperandSize(dst.span.clone())),
@@ -758,32 +1137,54 @@ fn encode_group_ret_retn_retf(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    if insn.num_opers == 0 {
-        emit_codes(emitter, insn, offset, &[Code::Byte(base + 1)])
-    } else {
-        // let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
+
+    match dst.kind {
+        _ if insn.num_opers == 0 => emit_codes(emitter, insn, offset, &[Code::Byte(base + 1)]),
+
+        // The number of bytes to pop from the stack after returning.
+        OperandKind::Imm => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::Byte(base), Code::ImmWord(FIRST_OPER_DST)],
+        ),
 
-        // Other ret types.
-        todo!()
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
     }
 }
 
 fn encode_group_lea(
     _base: u8,
-    _insn: &InstructionData,
-    _offset: u16,
-    _emitter: &mut impl ByteEmitter,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    todo!()
+    let [dst, src, _] = &insn.opers;
+
+    match (dst.kind, src.kind) {
+        (OperandKind::Reg, OperandKind::Mem) if dst.size == OperandSize::Word => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRegRM(FIRST_OPER_SRC, 0x8D)],
+        ),
+
+        (OperandKind::Reg, OperandKind::Mem) => {
+            Err(EncodeError::InvalidOperandSize(dst.span.clone()))
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
 }
 
-fn encode_group_les_lds(
+fn encode_group_les_lds_bound(
     base: u8,
     insn: &InstructionData,
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
 
     match (dst.kind, src.kind) {
         (OperandKind::Reg, OperandKind::Mem) if dst.size == OperandSize::Word => emit_codes(
@@ -800,10 +1201,10 @@ fn encode_group_les_lds(
 fn encode_group_rol_ror_rcl_rcr_shl_sal_shr_sar(
     base: u8,
     insn: &InstructionData,
-    _offset: u16,
+    offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
 
     if dst.size.is_unspecified() {
         return Err(EncodeError::OperandSizeNotSpecified(dst.span.clone()));
@@ -824,7 +1225,7 @@ fn encode_group_rol_ror_rcl_rcr_shl_sal_shr_sar(
                     Ok(())
                 }
 
-                OperandSize::Unspecified => unreachable!(),
+                _ => unreachable!(),
             }
         }
 
@@ -839,7 +1240,32 @@ fn encode_group_rol_ror_rcl_rcr_shl_sal_shr_sar(
                 Ok(())
             }
 
-            OperandSize::Unspecified => unreachable!(),
+            _ => unreachable!(),
+        },
+
+        // Shifting by an immediate count other than 1 is only available on the 80186 and later.
+        (OperandKind::Reg | OperandKind::Mem, OperandKind::Imm) => match dst.size {
+            OperandSize::Byte => emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[
+                    Code::ModRM(FIRST_OPER_DST, 0xC0, base),
+                    Code::ImmByte(FIRST_OPER_SRC),
+                ],
+            ),
+
+            OperandSize::Word => emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[
+                    Code::ModRM(FIRST_OPER_DST, 0xC1, base),
+                    Code::ImmByte(FIRST_OPER_SRC),
+                ],
+            ),
+
+            _ => unreachable!(),
         },
 
         _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
@@ -852,9 +1278,16 @@ fn encode_group_call(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     match dst.kind {
+        OperandKind::Imm
+            if dst.jmp_kind.unwrap_or(JumpKind::Near) == JumpKind::Near
+                && insn.bits == ast::Bits::Bits32 =>
+        {
+            emit_near_rel32(0xE8, dst, offset, emitter)
+        }
+
         OperandKind::Imm if dst.jmp_kind.unwrap_or(JumpKind::Near) == JumpKind::Near => {
             let value = require_value_is_word(dst.imm, &dst.span)?;
             let jmp_dst = value.wrapping_sub(offset + 3);
@@ -865,17 +1298,12 @@ fn encode_group_call(
             Ok(())
         }
 
-        OperandKind::Mem if dst.jmp_kind.unwrap_or(JumpKind::Near) == JumpKind::Far => {
-            todo!()
-            // call_direct_far:
-            //     check	@dest.jump_type & @dest.jump_type <> 'far'
-            // jyes	invalid_operand
-            // check	@dest.size and not 4
-            // jyes	invalid_operand
-            // asmcmd	=db 9Ah
-            // asmcmd	=dw =@dest.=offset,=@dest.=segment
-            // exit
-        }
+        OperandKind::Mem if dst.jmp_kind.unwrap_or(JumpKind::Near) == JumpKind::Far => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xFF, 0x03)],
+        ),
 
         OperandKind::Mem | OperandKind::Reg => emit_codes(
             emitter,
@@ -888,22 +1316,49 @@ fn encode_group_call(
     }
 }
 
+/// In 32-bit code, near jumps and calls have a 32-bit offset relative to the next instruction.
+fn emit_near_rel32(
+    op_code: u8,
+    dst: &OperandData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let rel = dst.imm.wrapping_sub(offset as i32 + 5);
+    emitter.emit(op_code);
+    for byte in rel.to_le_bytes() {
+        emitter.emit(byte);
+    }
+    Ok(())
+}
+
 fn encode_group_jmp(
     _base: u8,
     insn: &InstructionData,
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     match dst.kind {
+        OperandKind::Imm
+            if dst.jmp_kind.unwrap_or(JumpKind::Near) == JumpKind::Near
+                && insn.bits == ast::Bits::Bits32 =>
+        {
+            emit_near_rel32(0xE9, dst, offset, emitter)?;
+        }
+
         OperandKind::Imm => {
             // The value in the dst.imm field is an absolute address within the same segment.
             let value = require_value_is_word(dst.imm, &dst.span)?;
 
             match dst.jmp_kind {
                 Some(JumpKind::Short) => {
-                    todo!()
+                    emit_codes(
+                        emitter,
+                        insn,
+                        offset,
+                        &[Code::Byte(0xEB), Code::RelByte(FIRST_OPER_DST, 2)],
+                    )?;
                 }
 
                 None | Some(JumpKind::Near) => {
@@ -951,7 +1406,7 @@ fn encode_group_jcc(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     match dst.kind {
         OperandKind::Imm if dst.jmp_kind.unwrap_or(JumpKind::Short) == JumpKind::Short => {
@@ -973,7 +1428,7 @@ fn encode_group_loopc(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    let [dst, ..] = &insn.opers;
 
     match dst.kind {
         OperandKind::Imm
@@ -1002,22 +1457,215 @@ fn encode_group_no_operands(
     emit_codes(emitter, insn, offset, &[Code::Byte(base)])
 }
 
-fn encode_group_prefixes(
+/// String instructions without operands, where the memory addressed by SI can be in another segment
+/// using a segment override prefix.
+fn encode_group_string_source(
     base: u8,
     insn: &InstructionData,
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    emit_codes(emitter, insn, offset, &[Code::Byte(base)])
-}
+    let [dst, ..] = &insn.opers;
 
-fn encode_group_int(
-    _base: u8,
-    insn: &InstructionData,
-    offset: u16,
-    emitter: &mut impl ByteEmitter,
+    match dst.kind {
+        _ if insn.num_opers == 0 => emit_codes(emitter, insn, offset, &[Code::Byte(base)]),
+
+        OperandKind::Seg if insn.num_opers == 1 => {
+            emit_segment_prefix(
+                segment_prefix_for_register(dst.rm),
+                ast::Segment::DS,
+                emitter,
+            );
+            emit_codes(emitter, insn, offset, &[Code::Byte(base)])
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+/// String instructions without operands, that only address memory at ES:DI, which can not be
+/// overridden.
+fn encode_group_string_destination(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    match dst.kind {
+        _ if insn.num_opers == 0 => emit_codes(emitter, insn, offset, &[Code::Byte(base)]),
+
+        OperandKind::Seg if insn.num_opers == 1 => {
+            Err(EncodeError::InvalidSegmentOverride(dst.span.clone()))
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_movs_cmps(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    if insn.num_opers != 2 {
+        return Err(EncodeError::InvalidOperands(insn.opers_span.clone()));
+    }
+
+    // movs copies from [si] to [di], but cmps compares [si] with [di].
+    let (source, destination) = if base == 0xA4 { (src, dst) } else { (dst, src) };
+
+    emit_string_instruction(base, insn, Some(source), Some(destination), offset, emitter)
+}
+
+fn encode_group_lods_stos_scas(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    match insn.num_opers {
+        // lods m
+        1 if base == 0xAC => emit_string_instruction(base, insn, Some(dst), None, offset, emitter),
+
+        // stos m, scas m
+        1 => emit_string_instruction(base, insn, None, Some(dst), offset, emitter),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_outs(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    match dst.kind {
+        // dx, m
+        OperandKind::Reg
+            if insn.num_opers == 2
+                && dst.size == OperandSize::Word
+                && dst.rm == ast::WordRegister::Dx.encoding() =>
+        {
+            emit_string_instruction(base, insn, Some(src), None, offset, emitter)
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+/// Is the operand the memory addressed by a string instruction, i.e. "[si]" or "[esi]" for the
+/// source and "[di]" or "[edi]" for the destination?
+fn is_string_address(
+    oper: &OperandData,
+    index16: ast::IndirectEncoding,
+    index32: ast::DWordRegister,
+) -> bool {
+    oper.kind == OperandKind::Mem
+        && oper.mode == 0b00
+        && oper.sib.is_none()
+        && match oper.address_size {
+            OperandSize::Word => oper.rm == index16.encoding(),
+            OperandSize::DWord => oper.rm == index32.encoding(),
+            _ => false,
+        }
+}
+
+/// Encode a string instruction with explicit operands.  The operands only specify the size and
+/// the segment of the source, which can be overridden.  The destination is always in ES.
+fn emit_string_instruction(
+    base: u8,
+    insn: &InstructionData,
+    source: Option<&OperandData>,
+    destination: Option<&OperandData>,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    if let Some(source) = source {
+        if !is_string_address(source, ast::IndirectEncoding::Si, ast::DWordRegister::Esi) {
+            return Err(EncodeError::InvalidOperands(source.span.clone()));
+        }
+    }
+
+    if let Some(destination) = destination {
+        if !is_string_address(
+            destination,
+            ast::IndirectEncoding::Di,
+            ast::DWordRegister::Edi,
+        ) {
+            return Err(EncodeError::InvalidOperands(destination.span.clone()));
+        }
+        if destination.segment_prefix != 0
+            && destination.segment_prefix != segment_prefix_for(ast::Segment::ES)
+        {
+            return Err(EncodeError::InvalidSegmentOverride(
+                destination.span.clone(),
+            ));
+        }
+    }
+
+    // The address size prefix applies to both addresses.
+    if let (Some(source), Some(destination)) = (source, destination) {
+        if source.address_size != destination.address_size {
+            return Err(EncodeError::InvalidOperands(insn.opers_span.clone()));
+        }
+    }
+
+    let mut sizes = [source, destination]
+        .into_iter()
+        .flatten()
+        .map(|oper| oper.size)
+        .filter(|size| size.is_specified());
+    let size = match (sizes.next(), sizes.next()) {
+        (None, _) => {
+            return Err(EncodeError::OperandSizeNotSpecified(
+                insn.opers_span.clone(),
+            ))
+        }
+        (Some(first), Some(second)) if first != second => {
+            return Err(EncodeError::OperandSizesDoNotMatch(insn.opers_span.clone()))
+        }
+        (Some(size), _) => size,
+    };
+
+    let op_code = match size {
+        OperandSize::Byte => base,
+        OperandSize::Word => base + 1,
+        _ => return Err(EncodeError::InvalidOperandSize(insn.opers_span.clone())),
+    };
+
+    if let Some(source) = source {
+        emit_segment_prefix(source.segment_prefix, ast::Segment::DS, emitter);
+    }
+
+    emit_codes(emitter, insn, offset, &[Code::Byte(op_code)])
+}
+
+fn encode_group_prefixes(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
+    emit_codes(emitter, insn, offset, &[Code::Byte(base)])
+}
+
+fn encode_group_int(
+    _base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
 
     match dst.kind {
         OperandKind::Imm => emit_codes(
@@ -1031,13 +1679,38 @@ fn encode_group_int(
     }
 }
 
+fn encode_group_enter(
+    _base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    match (dst.kind, src.kind) {
+        // imm16, imm8
+        (OperandKind::Imm, OperandKind::Imm) if insn.num_opers == 2 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[
+                Code::Byte(0xC8),
+                Code::ImmWord(FIRST_OPER_DST),
+                Code::ImmByte(FIRST_OPER_SRC),
+            ],
+        ),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
 fn encode_group_aam(
     _base: u8,
     insn: &InstructionData,
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    emit_codes(emitter, insn, offset, &[Code::Byte(0xD4), Code::Byte(0xA0)])
+    emit_codes(emitter, insn, offset, &[Code::Byte(0xD4), Code::Byte(0x0A)])
 }
 
 fn encode_group_aad(
@@ -1046,7 +1719,7 @@ fn encode_group_aad(
     offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    emit_codes(emitter, insn, offset, &[Code::Byte(0xD5), Code::Byte(0xA0)])
+    emit_codes(emitter, insn, offset, &[Code::Byte(0xD5), Code::Byte(0x0A)])
 }
 
 fn encode_group_xlat(
@@ -1055,13 +1728,25 @@ fn encode_group_xlat(
     _offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, _] = &insn.opers;
-
-    if dst.size != OperandSize::Word {
-        return Err(EncodeError::InvalidOperandSize(dst.span.clone()));
-    }
+    let [dst, ..] = &insn.opers;
 
     match dst.kind {
+        _ if insn.num_opers == 0 => emitter.emit(0xD7),
+
+        // The table is read from DS:BX, which can be in another segment, e.g. "cs xlatb".
+        OperandKind::Seg if insn.num_opers == 1 => {
+            emit_segment_prefix(
+                segment_prefix_for_register(dst.rm),
+                ast::Segment::DS,
+                emitter,
+            );
+            emitter.emit(0xD7);
+        }
+
+        OperandKind::Mem if dst.size != OperandSize::Word => {
+            return Err(EncodeError::InvalidOperandSize(dst.span.clone()));
+        }
+
         OperandKind::Mem if dst.mode == 0 && dst.rm == 7 => {
             emit_segment_prefix(dst.segment_prefix, ast::Segment::DS, emitter);
             emitter.emit(0xD7);
@@ -1079,7 +1764,7 @@ fn encode_group_in(
     _offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
 
     match (dst.kind, src.kind) {
         (OperandKind::Reg, OperandKind::Reg)
@@ -1095,7 +1780,7 @@ fn encode_group_in(
                 // ax, dx
                 OperandSize::Word => emitter.emit(0xED),
 
-                OperandSize::Unspecified => unreachable!(),
+                _ => unreachable!(),
             }
         }
 
@@ -1113,7 +1798,7 @@ fn encode_group_in(
                     emitter.emit(src.imm as u8);
                 }
 
-                OperandSize::Unspecified => unreachable!(),
+                _ => unreachable!(),
             }
         }
 
@@ -1129,7 +1814,7 @@ fn encode_group_out(
     _offset: u16,
     emitter: &mut impl ByteEmitter,
 ) -> Result<(), EncodeError> {
-    let [dst, src] = &insn.opers;
+    let [dst, src, _] = &insn.opers;
 
     match (dst.kind, src.kind) {
         (OperandKind::Reg, OperandKind::Reg)
@@ -1173,93 +1858,897 @@ fn encode_group_out(
     Ok(())
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum OperandKind {
-    Imm,
-    Reg,
-    Seg,
-    Mem,
+/// Returns true if the operand is a word register or a memory operand that is not explicitly sized
+/// as something other than a word.
+fn is_reg_mem_word(oper: &OperandData) -> bool {
+    match oper.kind {
+        OperandKind::Reg => oper.size == OperandSize::Word,
+        OperandKind::Mem => oper.size != OperandSize::Byte,
+        _ => false,
+    }
 }
 
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum OperandSize {
-    Unspecified = 0,
-    Byte = 1,
-    Word = 2,
-}
+fn encode_group_sldt_str_lldt_ltr_verr_verw(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
 
-impl OperandSize {
-    fn is_specified(&self) -> bool {
-        match self {
-            OperandSize::Unspecified => false,
-            _ => true,
-        }
-    }
+    match dst.kind {
+        // r/m16
+        _ if insn.num_opers == 1 && is_reg_mem_word(dst) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ExtModRM(FIRST_OPER_DST, 0x00, base)],
+        ),
 
-    fn is_unspecified(&self) -> bool {
-        match self {
-            OperandSize::Unspecified => true,
-            _ => false,
-        }
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
     }
 }
 
-#[allow(dead_code)]
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum JumpKind {
-    Far,
-    Near,
-    Short,
-}
-
-#[derive(Debug)]
-pub struct OperandData {
-    /// The span of the operand in the AST.
-    pub span: ast::Span,
+fn encode_group_sgdt_sidt_lgdt_lidt(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
 
-    /// The auto-detected size of the operand.
-    pub size: OperandSize,
+    match dst.kind {
+        // The operand points to a 6 byte pseudo descriptor, so only memory is allowed.
+        OperandKind::Mem if insn.num_opers == 1 && dst.size.is_unspecified() => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ExtModRM(FIRST_OPER_DST, 0x01, base)],
+        ),
 
-    /// What kind of operand?  Immediate? Register? Address?
-    pub kind: OperandKind,
+        OperandKind::Mem if insn.num_opers == 1 => {
+            Err(EncodeError::InvalidOperandSize(dst.span.clone()))
+        }
 
-    /// Encoding for a segment prefix.  0 means NO prefix.
-    pub segment_prefix: u8,
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
 
-    /// An immediate value on the operand.
-    pub imm: i32,
+fn encode_group_smsw_lmsw(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
 
-    /// Indirect displacement.
-    pub displacement: i32,
+    match dst.kind {
+        // r/m16
+        _ if insn.num_opers == 1 && is_reg_mem_word(dst) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ExtModRM(FIRST_OPER_DST, 0x01, base)],
+        ),
 
-    /// Indirect displacement size.  This is relative to the [mode] field.
-    pub displacement_size: OperandSize,
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
 
-    /// The kind of jump requested in the AST if any.
-    pub jmp_kind: Option<JumpKind>,
+fn encode_group_lar_lsl(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
 
-    /// The mode used for mod reg r/m encoding.
-    pub mode: u8,
+    match dst.kind {
+        // reg16, r/m16
+        OperandKind::Reg
+            if insn.num_opers == 2 && dst.size == OperandSize::Word && is_reg_mem_word(src) =>
+        {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ExtModRegRM(FIRST_OPER_SRC, base)],
+            )
+        }
 
-    /// The encoding for the r/m field in the mod reg r/m encoding.
-    pub rm: u8,
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
 }
 
-impl OperandData {
-    pub fn empty(span: ast::Span) -> Self {
-        Self {
-            span,
-            size: OperandSize::Unspecified,
-            kind: OperandKind::Imm,
-            segment_prefix: 0,
-            imm: 0,
-            displacement: 0,
-            displacement_size: OperandSize::Unspecified,
+fn encode_group_arpl(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    match src.kind {
+        // r/m16, reg16
+        OperandKind::Reg
+            if insn.num_opers == 2 && src.size == OperandSize::Word && is_reg_mem_word(dst) =>
+        {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRegRM(FIRST_OPER_DST, base)],
+            )
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_clts(
+    _base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    emit_codes(emitter, insn, offset, &[Code::Byte(0x0F), Code::Byte(0x06)])
+}
+
+fn encode_group_esc(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    match (dst.kind, src.kind) {
+        // imm6, r/m
+        (OperandKind::Imm, OperandKind::Reg | OperandKind::Mem) if insn.num_opers == 2 => {
+            if !(0..=0x3F).contains(&dst.imm) {
+                return Err(EncodeError::ImmediateOutOfRange(
+                    dst.span.clone(),
+                    dst.imm,
+                    0,
+                    0x3F,
+                ));
+            }
+
+            // The top 3 bits of the external op code go into the op code, the bottom 3 bits into
+            // the [reg] field.
+            let external = dst.imm as u8;
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRM(
+                    FIRST_OPER_SRC,
+                    base | (external >> 3),
+                    external & 0x07,
+                )],
+            )
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+/// Returns an error for a memory operand with a size that the 8087 instruction does not support.
+fn fpu_memory_size_error(oper: &OperandData) -> EncodeError {
+    if oper.size.is_unspecified() {
+        EncodeError::OperandSizeNotSpecified(oper.span.clone())
+    } else {
+        EncodeError::InvalidOperandSize(oper.span.clone())
+    }
+}
+
+/// Returns true if the operand is ST0, the top of the 8087 register stack.
+fn is_st0(oper: &OperandData) -> bool {
+    oper.kind == OperandKind::FpuReg && oper.rm == 0
+}
+
+fn encode_group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    let is_compare = base == 0x02 || base == 0x03;
+
+    match (dst.kind, src.kind) {
+        // Compare ST0 with ST1.
+        _ if insn.num_opers == 0 && is_compare => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::Byte(0xD8), Code::Byte(0xC1 | (base << 3))],
+        ),
+
+        // m32
+        (OperandKind::Mem, _) if insn.num_opers == 1 && dst.size == OperandSize::DWord => {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRM(FIRST_OPER_DST, 0xD8, base)],
+            )
+        }
+
+        // m64
+        (OperandKind::Mem, _) if insn.num_opers == 1 && dst.size == OperandSize::QWord => {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRM(FIRST_OPER_DST, 0xDC, base)],
+            )
+        }
+
+        (OperandKind::Mem, _) if insn.num_opers == 1 => Err(fpu_memory_size_error(dst)),
+
+        // st(i), the destination is ST0
+        (OperandKind::FpuReg, _) if insn.num_opers == 1 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xD8, base)],
+        ),
+
+        // st0, st(i)
+        (OperandKind::FpuReg, OperandKind::FpuReg) if is_st0(dst) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_SRC, 0xD8, base)],
+        ),
+
+        // st(i), st0
+        // The [reg] values for the reversed subtract and divide are swapped in this form.
+        (OperandKind::FpuReg, OperandKind::FpuReg) if is_st0(src) && !is_compare => {
+            let reg = if base >= 0x04 { base ^ 0x01 } else { base };
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRM(FIRST_OPER_DST, 0xDC, reg)],
+            )
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    match (dst.kind, src.kind) {
+        // Same as "st1, st0".
+        _ if insn.num_opers == 0 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::Byte(0xDE), Code::Byte(0xC1 | (base << 3))],
+        ),
+
+        // st(i)
+        (OperandKind::FpuReg, _) if insn.num_opers == 1 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDE, base)],
+        ),
+
+        // st(i), st0
+        (OperandKind::FpuReg, OperandKind::FpuReg) if is_st0(src) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDE, base)],
+        ),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    match dst.kind {
+        // m16
+        OperandKind::Mem if insn.num_opers == 1 && dst.size == OperandSize::Word => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDE, base)],
+        ),
+
+        // m32
+        OperandKind::Mem if insn.num_opers == 1 && dst.size == OperandSize::DWord => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDA, base)],
+        ),
+
+        OperandKind::Mem if insn.num_opers == 1 => Err(fpu_memory_size_error(dst)),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_fld_fst_fstp(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    if insn.num_opers != 1 {
+        return Err(EncodeError::InvalidOperands(insn.opers_span.clone()));
+    }
+
+    match (dst.kind, dst.size) {
+        // m32
+        (OperandKind::Mem, OperandSize::DWord) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xD9, base)],
+        ),
+
+        // m64
+        (OperandKind::Mem, OperandSize::QWord) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDD, base)],
+        ),
+
+        // m80, only loading and storing with pop.
+        (OperandKind::Mem, OperandSize::TWord) if base != 0x02 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(
+                FIRST_OPER_DST,
+                0xDB,
+                if base == 0x00 { 0x05 } else { 0x07 },
+            )],
+        ),
+
+        (OperandKind::Mem, _) => Err(fpu_memory_size_error(dst)),
+
+        // st(i)
+        (OperandKind::FpuReg, _) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(
+                FIRST_OPER_DST,
+                if base == 0x00 { 0xD9 } else { 0xDD },
+                base,
+            )],
+        ),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_fild_fist_fistp(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    if insn.num_opers != 1 {
+        return Err(EncodeError::InvalidOperands(insn.opers_span.clone()));
+    }
+
+    match (dst.kind, dst.size) {
+        // m16
+        (OperandKind::Mem, OperandSize::Word) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDF, base)],
+        ),
+
+        // m32
+        (OperandKind::Mem, OperandSize::DWord) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDB, base)],
+        ),
+
+        // m64, only loading and storing with pop.
+        (OperandKind::Mem, OperandSize::QWord) if base != 0x02 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(
+                FIRST_OPER_DST,
+                0xDF,
+                if base == 0x00 { 0x05 } else { 0x07 },
+            )],
+        ),
+
+        (OperandKind::Mem, _) => Err(fpu_memory_size_error(dst)),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_fbld_fbstp(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    match (dst.kind, dst.size) {
+        // m80, the size is implied.
+        (OperandKind::Mem, OperandSize::Unspecified | OperandSize::TWord)
+            if insn.num_opers == 1 =>
+        {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ModRM(FIRST_OPER_DST, 0xDF, base)],
+            )
+        }
+
+        (OperandKind::Mem, _) if insn.num_opers == 1 => {
+            Err(EncodeError::InvalidOperandSize(dst.span.clone()))
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_fxch(
+    _base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    match (dst.kind, src.kind) {
+        // Same as "st1".
+        _ if insn.num_opers == 0 => {
+            emit_codes(emitter, insn, offset, &[Code::Byte(0xD9), Code::Byte(0xC9)])
+        }
+
+        // st(i)
+        (OperandKind::FpuReg, _) if insn.num_opers == 1 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xD9, 0x01)],
+        ),
+
+        // st0, st(i)
+        (OperandKind::FpuReg, OperandKind::FpuReg) if is_st0(dst) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_SRC, 0xD9, 0x01)],
+        ),
+
+        // st(i), st0
+        (OperandKind::FpuReg, OperandKind::FpuReg) if is_st0(src) => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xD9, 0x01)],
+        ),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_ffree(
+    _base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    match dst.kind {
+        // st(i)
+        OperandKind::FpuReg if insn.num_opers == 1 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDD, 0x00)],
+        ),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_fcompp(
+    _base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    emit_codes(emitter, insn, offset, &[Code::Byte(0xDE), Code::Byte(0xD9)])
+}
+
+fn encode_group_fpu_no_operands(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    emit_codes(emitter, insn, offset, &[Code::Byte(0xD9), Code::Byte(base)])
+}
+
+fn encode_group_finit_feni_fdisi_fclex(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    emit_codes(emitter, insn, offset, &[Code::Byte(0xDB), Code::Byte(base)])
+}
+
+fn encode_group_fldenv_fldcw_fstenv_fstcw(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    // The environment is 14 bytes, so it has no size, the control word is a word.
+    let size_is_valid = match base {
+        0x05 | 0x07 => matches!(dst.size, OperandSize::Unspecified | OperandSize::Word),
+        _ => dst.size.is_unspecified(),
+    };
+
+    match dst.kind {
+        OperandKind::Mem if insn.num_opers == 1 && size_is_valid => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xD9, base)],
+        ),
+
+        OperandKind::Mem if insn.num_opers == 1 => {
+            Err(EncodeError::InvalidOperandSize(dst.span.clone()))
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_frstor_fsave_fstsw(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    // The state is 94 bytes, so it has no size, the status word is a word.
+    let size_is_valid = match base {
+        0x07 => matches!(dst.size, OperandSize::Unspecified | OperandSize::Word),
+        _ => dst.size.is_unspecified(),
+    };
+
+    match dst.kind {
+        // ax, added on the 80287.
+        OperandKind::Reg
+            if insn.num_opers == 1
+                && base == 0x07
+                && dst.size == OperandSize::Word
+                && dst.rm == ast::WordRegister::Ax.encoding() =>
+        {
+            emit_codes(emitter, insn, offset, &[Code::Byte(0xDF), Code::Byte(0xE0)])
+        }
+
+        OperandKind::Mem if insn.num_opers == 1 && size_is_valid => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ModRM(FIRST_OPER_DST, 0xDD, base)],
+        ),
+
+        OperandKind::Mem if insn.num_opers == 1 => {
+            Err(EncodeError::InvalidOperandSize(dst.span.clone()))
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+/// Make sure the immediate value of a NEC V20 bit instruction is in range, e.g. a bit number
+/// within a byte or word.
+fn require_bit_number(oper: &OperandData, max: i32) -> Result<(), EncodeError> {
+    if (0..=max).contains(&oper.imm) {
+        Ok(())
+    } else {
+        Err(EncodeError::ImmediateOutOfRange(
+            oper.span.clone(),
+            oper.imm,
+            0,
+            max,
+        ))
+    }
+}
+
+fn encode_group_test1_clr1_set1_not1(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    if insn.num_opers != 2 || !matches!(dst.kind, OperandKind::Reg | OperandKind::Mem) {
+        return Err(EncodeError::InvalidOperands(insn.opers_span.clone()));
+    }
+
+    // The byte and word forms differ in the lowest bit of the op code.
+    let (op_code, max_bit) = match dst.size {
+        OperandSize::Byte => (base, 7),
+        OperandSize::Word => (base + 1, 15),
+        OperandSize::Unspecified => {
+            return Err(EncodeError::OperandSizeNotSpecified(dst.span.clone()))
+        }
+        _ => return Err(EncodeError::InvalidOperandSize(dst.span.clone())),
+    };
+
+    match src.kind {
+        // r/m, cl
+        OperandKind::Reg if src.size == OperandSize::Byte && src.rm == 1 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ExtModRM(FIRST_OPER_DST, op_code, 0)],
+        ),
+
+        // r/m, imm3/imm4
+        OperandKind::Imm => {
+            require_bit_number(src, max_bit)?;
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[
+                    Code::ExtModRM(FIRST_OPER_DST, op_code + 8, 0),
+                    Code::ImmByte(FIRST_OPER_SRC),
+                ],
+            )
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_ins_ext(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, src, _] = &insn.opers;
+
+    if insn.num_opers != 2 || dst.kind != OperandKind::Reg || dst.size != OperandSize::Byte {
+        return Err(EncodeError::InvalidOperands(insn.opers_span.clone()));
+    }
+
+    match src.kind {
+        // reg8, reg8
+        OperandKind::Reg if src.size == OperandSize::Byte => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[Code::ExtModRegRM(FIRST_OPER_DST, base)],
+        ),
+
+        // reg8, imm4
+        OperandKind::Imm => {
+            require_bit_number(src, 15)?;
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[
+                    Code::ExtModRM(FIRST_OPER_DST, base + 8, 0),
+                    Code::ImmByte(FIRST_OPER_SRC),
+                ],
+            )
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_rol4_ror4(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    match dst.kind {
+        // r/m8, memory is always a byte.
+        OperandKind::Reg | OperandKind::Mem
+            if insn.num_opers == 1
+                && matches!(dst.size, OperandSize::Byte | OperandSize::Unspecified) =>
+        {
+            emit_codes(
+                emitter,
+                insn,
+                offset,
+                &[Code::ExtModRM(FIRST_OPER_DST, base, 0)],
+            )
+        }
+
+        OperandKind::Reg | OperandKind::Mem if insn.num_opers == 1 => {
+            Err(EncodeError::InvalidOperandSize(dst.span.clone()))
+        }
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+fn encode_group_add4s_sub4s_cmp4s(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    emit_codes(emitter, insn, offset, &[Code::Byte(0x0F), Code::Byte(base)])
+}
+
+fn encode_group_brkem(
+    base: u8,
+    insn: &InstructionData,
+    offset: u16,
+    emitter: &mut impl ByteEmitter,
+) -> Result<(), EncodeError> {
+    let [dst, ..] = &insn.opers;
+
+    match dst.kind {
+        // imm8
+        OperandKind::Imm if insn.num_opers == 1 => emit_codes(
+            emitter,
+            insn,
+            offset,
+            &[
+                Code::Byte(0x0F),
+                Code::Byte(base),
+                Code::ImmByte(FIRST_OPER_DST),
+            ],
+        ),
+
+        _ => Err(EncodeError::InvalidOperands(insn.opers_span.clone())),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperandKind {
+    Imm,
+    Reg,
+    Seg,
+    Mem,
+    FpuReg,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperandSize {
+    Unspecified = 0,
+    Byte = 1,
+    Word = 2,
+    DWord = 4,
+    QWord = 8,
+    TWord = 10,
+}
+
+impl OperandSize {
+    fn is_specified(&self) -> bool {
+        match self {
+            OperandSize::Unspecified => false,
+            _ => true,
+        }
+    }
+
+    fn is_unspecified(&self) -> bool {
+        match self {
+            OperandSize::Unspecified => true,
+            _ => false,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JumpKind {
+    Far,
+    Near,
+    Short,
+}
+
+#[derive(Clone, Debug)]
+pub struct OperandData {
+    /// The span of the operand in the AST.
+    pub span: ast::Span,
+
+    /// The auto-detected size of the operand.
+    pub size: OperandSize,
+
+    /// What kind of operand?  Immediate? Register? Address?
+    pub kind: OperandKind,
+
+    /// Encoding for a segment prefix.  0 means NO prefix.
+    pub segment_prefix: u8,
+
+    /// An immediate value on the operand.
+    pub imm: i32,
+
+    /// Indirect displacement.
+    pub displacement: i32,
+
+    /// Indirect displacement size.  This is relative to the [mode] field.
+    pub displacement_size: OperandSize,
+
+    /// The kind of jump requested in the AST if any.
+    pub jmp_kind: Option<JumpKind>,
+
+    /// The mode used for mod reg r/m encoding.
+    pub mode: u8,
+
+    /// The encoding for the r/m field in the mod reg r/m encoding.
+    pub rm: u8,
+
+    /// The SIB byte that follows the mod reg r/m byte for some 32-bit addresses.
+    pub sib: Option<u8>,
+
+    /// The size of the address of memory operands, [OperandSize::Word] or [OperandSize::DWord].
+    pub address_size: OperandSize,
+}
+
+impl OperandData {
+    pub fn empty(span: ast::Span) -> Self {
+        Self {
+            span,
+            size: OperandSize::Unspecified,
+            kind: OperandKind::Imm,
+            segment_prefix: 0,
+            imm: 0,
+            displacement: 0,
+            displacement_size: OperandSize::Unspecified,
             jmp_kind: None,
             mode: 0,
             rm: 0,
+            sib: None,
+            address_size: OperandSize::Unspecified,
         }
     }
 
@@ -1275,6 +2764,8 @@ impl OperandData {
             jmp_kind: None,
             mode: 0,
             rm: 0,
+            sib: None,
+            address_size: OperandSize::Unspecified,
         }
     }
 
@@ -1290,6 +2781,25 @@ impl OperandData {
             jmp_kind: None,
             mode: 0b11,
             rm: encoding,
+            sib: None,
+            address_size: OperandSize::Unspecified,
+        }
+    }
+
+    pub fn fpu_register(span: ast::Span, index: u8) -> Self {
+        Self {
+            span,
+            size: OperandSize::Unspecified,
+            kind: OperandKind::FpuReg,
+            segment_prefix: 0,
+            imm: 0,
+            displacement: 0,
+            displacement_size: OperandSize::Unspecified,
+            jmp_kind: None,
+            mode: 0b11,
+            rm: index,
+            sib: None,
+            address_size: OperandSize::Unspecified,
         }
     }
 
@@ -1305,6 +2815,8 @@ impl OperandData {
             jmp_kind: None,
             mode: 0b11,
             rm: encoding,
+            sib: None,
+            address_size: OperandSize::Unspecified,
         }
     }
 
@@ -1316,11 +2828,7 @@ impl OperandData {
     ) -> Self {
         let size = operand_size_from_data_size(data_size);
 
-        let segment_prefix = if let Some(sp) = seg_override {
-            0x26 + (sp.encoding() << 3)
-        } else {
-            0
-        };
+        let segment_prefix = seg_override.map_or(0, segment_prefix_for);
 
         OperandData {
             span: span.clone(),
@@ -1333,6 +2841,8 @@ impl OperandData {
             jmp_kind: None,
             mode: 0b00,
             rm: 0b110,
+            sib: None,
+            address_size: OperandSize::Word,
         }
     }
 
@@ -1345,19 +2855,18 @@ impl OperandData {
     ) -> Self {
         let size = operand_size_from_data_size(data_size);
 
-        let segment_prefix = if let Some(seg) = seg_override {
-            0x26 + (seg.encoding() << 3)
-        } else {
-            0
-        };
+        let segment_prefix = seg_override.map_or(0, segment_prefix_for);
 
-        let (mode, displacement, displacement_size) = if displacement == 0 {
-            (0b00_u8, 0_i16, OperandSize::Unspecified)
-        } else if value_is_signed_byte(displacement as i32) {
-            (0b01, displacement, OperandSize::Byte)
-        } else {
-            (0b10, displacement, OperandSize::Word)
-        };
+        // [bp] without a displacement has the encoding of a direct address, so it always gets a
+        // displacement byte.
+        let (mode, displacement, displacement_size) =
+            if displacement == 0 && addr_mode != ast::IndirectEncoding::Bp.encoding() {
+                (0b00_u8, 0_i16, OperandSize::Unspecified)
+            } else if value_is_signed_byte(displacement as i32) {
+                (0b01, displacement, OperandSize::Byte)
+            } else {
+                (0b10, displacement, OperandSize::Word)
+            };
 
         OperandData {
             span: span.clone(),
@@ -1370,6 +2879,66 @@ impl OperandData {
             jmp_kind: None,
             mode,
             rm: addr_mode,
+            sib: None,
+            address_size: OperandSize::Word,
+        }
+    }
+
+    /// A memory operand with a 32-bit address.  The [base] and [index] are register encodings,
+    /// the index with its scale.  Without a base or an index, this is a direct address.
+    pub fn indirect32(
+        span: ast::Span,
+        base: Option<u8>,
+        index: Option<(u8, u8)>,
+        displacement: i32,
+        data_size: &Option<ast::DataSize>,
+        seg_override: &Option<ast::Segment>,
+    ) -> Self {
+        const ESP: u8 = 0b100;
+        const EBP: u8 = 0b101;
+
+        let size = operand_size_from_data_size(data_size);
+
+        let segment_prefix = seg_override.map_or(0, segment_prefix_for);
+
+        // Without a base, the address always has a 32-bit displacement.  EBP as a base always has
+        // a displacement, because that encoding is used for no base.
+        let (mode, displacement_size) = match base {
+            None => (0b00_u8, OperandSize::DWord),
+            Some(base) if displacement == 0 && base != EBP => (0b00, OperandSize::Unspecified),
+            Some(_) if value_is_signed_byte(displacement) => (0b01, OperandSize::Byte),
+            Some(_) => (0b10, OperandSize::DWord),
+        };
+
+        // An index or ESP as a base requires a SIB byte.
+        let (rm, sib) = match (base, index) {
+            (None, None) => (EBP, None),
+            (Some(base), None) if base != ESP => (base, None),
+            (base, index) => {
+                let (index, scale) = index.unwrap_or((ESP, 1));
+                let scale = match scale {
+                    1 => 0b00,
+                    2 => 0b01,
+                    4 => 0b10,
+                    _ => 0b11,
+                };
+                (ESP, Some((scale << 6) | (index << 3) | base.unwrap_or(EBP)))
+            }
+        };
+
+        OperandData {
+            span,
+            size,
+            kind: OperandKind::Mem,
+            segment_prefix,
+            imm: 0,
+            displacement,
+            displacement_size,
+            jmp_kind: None,
+            mode,
+            rm,
+            sib,
+            address_size: OperandSize::DWord,
         }
     }
 
@@ -1385,11 +2954,64 @@ impl OperandData {
             jmp_kind: Some(JumpKind::Far),
             mode: 0,
             rm: 0,
+            sib: None,
+            address_size: OperandSize::Unspecified,
         }
     }
 
     pub fn is_direct(&self) -> bool {
-        self.kind == OperandKind::Mem && self.mode == 0b00 && self.rm == 0b110
+        let direct_rm = if self.address_size == OperandSize::DWord {
+            0b101
+        } else {
+            0b110
+        };
+        self.kind == OperandKind::Mem
+            && self.mode == 0b00
+            && self.rm == direct_rm
+            && self.sib.is_none()
+    }
+
+    /// Any address referencing BP, EBP or ESP uses SS as the default segment, all others use DS.
+    fn default_segment(&self) -> ast::Segment {
+        let uses_stack = if self.address_size == OperandSize::DWord {
+            let base = self.sib.map_or(self.rm, |sib| sib & 0b111);
+            base == 0b100 || (base == 0b101 && self.mode > 0)
+        } else {
+            self.rm == 2 || self.rm == 3 || (self.mode > 0 && self.rm == 6)
+        };
+
+        if uses_stack {
+            ast::Segment::SS
+        } else {
+            ast::Segment::DS
+        }
+    }
+}
+
+/// Emits the SIB byte and displacement that follow the mod reg r/m byte of a memory operand.
+fn emit_sib_and_displacement(rm: &OperandData, emitter: &mut impl ByteEmitter) {
+    if let Some(sib) = rm.sib {
+        emitter.emit(sib);
+    }
+
+    match rm.displacement_size {
+        OperandSize::Byte => {
+            emitter.emit(rm.displacement as i8 as u8);
+        }
+
+        OperandSize::Word => {
+            for byte in (rm.displacement as i16 as u16).to_le_bytes() {
+                emitter.emit(byte);
+            }
+        }
+
+        OperandSize::DWord => {
+            for byte in rm.displacement.to_le_bytes() {
+                emitter.emit(byte);
+            }
+        }
+
+        _ => {}
     }
 }
 
@@ -1398,18 +3020,31 @@ pub fn operand_size_from_data_size(data_size: &Option<ast::DataSize>) -> Operand
         match data_size {
             ast::DataSize::Byte => OperandSize::Byte,
             ast::DataSize::Word => OperandSize::Word,
+            ast::DataSize::DWord => OperandSize::DWord,
+            ast::DataSize::QWord => OperandSize::QWord,
+            ast::DataSize::TWord => OperandSize::TWord,
         }
     } else {
         OperandSize::Unspecified
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct InstructionData {
     pub operation: Operation,
     pub num_opers: u8,
-    pub opers: [OperandData; 2],
+    pub opers: [OperandData; 3],
     pub opers_span: ast::Span,
+
+    /// Emit a WAIT before every 8087 instruction.  The 8087 does not synchronize with the CPU by
+    /// itself, later coprocessors do.
+    pub fpu_wait: bool,
+
+    /// The default operand and address size.  Others are selected with a prefix.
+    pub bits: ast::Bits,
+
+    /// Immediate values are 32-bit, because the operand size is 32-bit.
+    imm32: bool,
 }
 
 impl InstructionData {
@@ -1420,8 +3055,12 @@ impl InstructionData {
             opers: [
                 OperandData::empty(span.clone()),
                 OperandData::empty(span.clone()),
+                OperandData::empty(span.clone()),
             ],
             opers_span: span,
+            fpu_wait: false,
+            bits: ast::Bits::Bits16,
+            imm32: false,
         }
     }
 
@@ -1429,8 +3068,15 @@ impl InstructionData {
         Self {
             operation: op,
             num_opers: 1,
-            opers: [dst, OperandData::empty(span.clone())],
+            opers: [
+                dst,
+                OperandData::empty(span.clone()),
+                OperandData::empty(span.clone()),
+            ],
             opers_span: span,
+            fpu_wait: false,
+            bits: ast::Bits::Bits16,
+            imm32: false,
         }
     }
 
@@ -1438,8 +3084,152 @@ impl InstructionData {
         Self {
             operation: op,
             num_opers: 2,
-            opers: [dst, src],
+            opers: [dst, src, OperandData::empty(span.clone())],
+            opers_span: span,
+            fpu_wait: false,
+            bits: ast::Bits::Bits16,
+            imm32: false,
+        }
+    }
+
+    pub fn dst_src_and_third(
+        span: ast::Span,
+        op: Operation,
+        dst: OperandData,
+        src: OperandData,
+        third: OperandData,
+    ) -> Self {
+        Self {
+            operation: op,
+            num_opers: 3,
+            opers: [dst, src, third],
             opers_span: span,
+            fpu_wait: false,
+            bits: ast::Bits::Bits16,
+            imm32: false,
+        }
+    }
+
+    /// The oldest CPU that supports the instruction.  Some forms of 8086 instructions were only
+    /// added on later CPUs, e.g. "push imm" on the 80186.
+    pub fn minimum_cpu(&self) -> Cpu {
+        use Operation::*;
+
+        let [dst, src, _] = &self.opers;
+
+        // Anything 32-bit requires a 80386.
+        let uses_fs_or_gs = self.opers.iter().any(|oper| {
+            (oper.kind == OperandKind::Seg && oper.rm >= ast::Segment::FS.encoding())
+                || oper.segment_prefix == segment_prefix_for(ast::Segment::FS)
+                || oper.segment_prefix == segment_prefix_for(ast::Segment::GS)
+        });
+        if self.bits == ast::Bits::Bits32
+            || self.operand_size() == OperandSize::DWord
+            || self.address_size_prefix()
+            || uses_fs_or_gs
+        {
+            return Cpu::I386;
+        }
+
+        match self.operation {
+            PUSH if dst.kind == OperandKind::Imm => Cpu::I186,
+            IMUL if self.num_opers > 1 => Cpu::I186,
+            ROL | ROR | RCL | RCR | SHL | SHR | SAR
+                if src.kind == OperandKind::Imm && src.imm != 1 =>
+            {
+                Cpu::I186
+            }
+            FSTSW | FNSTSW if dst.kind == OperandKind::Reg => Cpu::I286,
+            operation => operation.minimum_cpu(),
+        }
+    }
+
+    /// The size of the operands, which decides if an operand size prefix is needed.  Operations on
+    /// bytes and those that have no size, return [OperandSize::Byte] and
+    /// [OperandSize::Unspecified].
+    fn operand_size(&self) -> OperandSize {
+        use Operation::*;
+
+        let [dst, src, _] = &self.opers;
+
+        match self.operation {
+            CWDE | CDQ | MOVSD | CMPSD | STOSD | LODSD | SCASD | INSD | OUTSD | PUSHAD | POPAD
+            | PUSHFD | POPFD | IRETD => OperandSize::DWord,
+
+            CBW | CWD | MOVSW | CMPSW | STOSW | LODSW | SCASW | INSW | OUTSW | PUSHA | POPA
+            | PUSHF | POPF | IRET => OperandSize::Word,
+
+            // The size of the string or the accumulator, not the port.
+            OUT | OUTS => src.size,
+
+            // Pushing an immediate value pushes the default operand size, unless the value has a
+            // size.  Bytes are sign extended to the default size.
+            PUSH if dst.kind == OperandKind::Imm => match dst.size {
+                OperandSize::Word | OperandSize::DWord => dst.size,
+                _ => self.default_size(),
+            },
+
+            // Segment registers are always moved as words.
+            _ if dst.kind == OperandKind::Seg || src.kind == OperandKind::Seg => {
+                OperandSize::Unspecified
+            }
+
+            operation if operation.is_fpu() => OperandSize::Unspecified,
+
+            _ => [dst, src]
+                .into_iter()
+                .take(self.num_opers as usize)
+                .filter(|oper| matches!(oper.kind, OperandKind::Reg | OperandKind::Mem))
+                .map(|oper| oper.size)
+                .find(|size| size.is_specified())
+                .unwrap_or(OperandSize::Unspecified),
+        }
+    }
+
+    /// The operand and address size used when there is no prefix.
+    fn default_size(&self) -> OperandSize {
+        match self.bits {
+            ast::Bits::Bits16 => OperandSize::Word,
+            ast::Bits::Bits32 => OperandSize::DWord,
+        }
+    }
+
+    /// Does the operand size differ from the default, requiring a prefix?
+    fn operand_size_prefix(&self) -> bool {
+        let size = self.operand_size();
+        matches!(size, OperandSize::Word | OperandSize::DWord) && size != self.default_size()
+    }
+
+    /// Does a memory operand use an address size different from the default, requiring a prefix?
+    fn address_size_prefix(&self) -> bool {
+        self.opers
+            .iter()
+            .take(self.num_opers as usize)
+            .any(|oper| oper.kind == OperandKind::Mem && oper.address_size != self.default_size())
+    }
+
+    /// The instruction with 32-bit operands encoded as the 16-bit forms with 32-bit immediate
+    /// values.  The operand size prefix selects the 32-bit operation.
+    fn with_word_operands(&self) -> Self {
+        let mut insn = self.clone();
+        for oper in insn.opers.iter_mut() {
+            if oper.size == OperandSize::DWord {
+                oper.size = OperandSize::Word;
+            }
+        }
+        insn.imm32 = true;
+        insn
+    }
+
+    /// The 8087 control instructions have a variant that waits for the FPU and one that does not
+    /// (the FN... variants).  All other 8087 instructions wait if [fpu_wait] is set.
+    fn wait_prefix(&self) -> bool {
+        use Operation::*;
+
+        match self.operation {
+            FINIT | FENI | FDISI | FCLEX | FSTCW | FSTSW | FSTENV | FSAVE => true,
+            FNINIT | FNENI | FNDISI | FNCLEX | FNSTCW | FNSTSW | FNSTENV | FNSAVE => false,
+            operation => self.fpu_wait && operation.is_fpu(),
         }
     }
 }
@@ -1452,35 +3242,14 @@ fn emit_mod_reg_rm(
     immediate: i32,
     emitter: &mut impl ByteEmitter,
 ) {
-    // Any indirect address referencing BP uses SS as the default segment, all others use DS.
-    emit_segment_prefix(
-        rm.segment_prefix,
-        if rm.rm == 2 || rm.rm == 3 || (rm.mode > 0 && rm.rm == 6) {
-            ast::Segment::SS
-        } else {
-            ast::Segment::DS
-        },
-        emitter,
-    );
+    emit_segment_prefix(rm.segment_prefix, rm.default_segment(), emitter);
 
     let modrm = (rm.mode << 6) + (reg << 3) + rm.rm;
 
     emitter.emit(op_code);
     emitter.emit(modrm);
 
-    match rm.displacement_size {
-        OperandSize::Byte => {
-            emitter.emit(rm.displacement as i8 as u8);
-        }
-
-        OperandSize::Word => {
-            for byte in (rm.displacement as i16 as u16).to_le_bytes() {
-                emitter.emit(byte);
-            }
-        }
-
-        _ => {}
-    }
+    emit_sib_and_displacement(rm, emitter);
 
     match imm_size {
         OperandSize::Byte => emitter.emit(immediate as u8),
@@ -1489,6 +3258,11 @@ fn emit_mod_reg_rm(
                 emitter.emit(byte);
             }
         }
+        OperandSize::DWord => {
+            for byte in immediate.to_le_bytes() {
+                emitter.emit(byte);
+            }
+        }
         _ => {}
     }
 }
@@ -1556,6 +3330,10 @@ mod tests {
         ($operation:expr, $dst:expr, $src:expr $(,)?) => {{
             InstructionData::dst_and_src(0..0, $operation, $dst, $src)
         }};
+
+        ($operation:expr, $dst:expr, $src:expr, $third:expr $(,)?) => {{
+            InstructionData::dst_src_and_third(0..0, $operation, $dst, $src, $third)
+        }};
     }
 
     macro_rules! imm {
@@ -1616,6 +3394,22 @@ mod tests {
         (di) => {{
             OperandData::register(0..0, ast::WordRegister::Di.encoding(), OperandSize::Word)
         }};
+
+        (eax) => {{
+            OperandData::register(0..0, ast::DWordRegister::Eax.encoding(), OperandSize::DWord)
+        }};
+        (ebx) => {{
+            OperandData::register(0..0, ast::DWordRegister::Ebx.encoding(), OperandSize::DWord)
+        }};
+        (ecx) => {{
+            OperandData::register(0..0, ast::DWordRegister::Ecx.encoding(), OperandSize::DWord)
+        }};
+    }
+
+    macro_rules! st {
+        ($index:expr) => {{
+            OperandData::fpu_register(0..0, $index)
+        }};
     }
 
     macro_rules! seg {
@@ -1631,6 +3425,12 @@ mod tests {
         (ds) => {{
             OperandData::segment(0..0, ast::Segment::DS.encoding())
         }};
+        (fs) => {{
+            OperandData::segment(0..0, ast::Segment::FS.encoding())
+        }};
+        (gs) => {{
+            OperandData::segment(0..0, ast::Segment::GS.encoding())
+        }};
     }
 
     macro_rules! direct {
@@ -1665,6 +3465,14 @@ mod tests {
         }};
     }
 
+    macro_rules! assert_encode_err {
+        ($insn:expr) => {{
+            let mut bytes = vec![];
+            let instruction = $insn;
+            assert!(encode(&instruction, 0x100, &mut bytes).is_err());
+        }};
+    }
+
     #[test]
     fn group_add_or_adc_sbb_and_sub_xor_cmp() {
         assert_encode!(
@@ -1718,6 +3526,56 @@ mod tests {
             insn!(Operation::ADD, reg!(bx), imm!(0x100)),
             "add bx, 0x100"
         );
+
+        // Negative values that fit in a signed byte are sign extended.
+        assert_encode!(
+            &[0x83, 0xC3, 0xFF],
+            insn!(Operation::ADD, reg!(bx), imm!(-1)),
+            "add bx, -1"
+        );
+        assert_encode!(
+            &[0x83, 0xE0, 0xF0],
+            insn!(Operation::AND, reg!(ax), imm!(-16)),
+            "and ax, -16"
+        );
+        assert_encode!(
+            &[0x83, 0xEC, 0x80],
+            insn!(Operation::SUB, reg!(sp), imm!(-128)),
+            "sub sp, -128"
+        );
+        assert_encode!(
+            &[0x83, 0x3E, 0x00, 0x20, 0xFB],
+            insn!(
+                Operation::CMP,
+                direct!(0x2000, Some(ast::DataSize::Word), None),
+                imm!(-5)
+            ),
+            "cmp word [0x2000], -5"
+        );
+        assert_encode!(
+            &[0x81, 0xEC, 0x80, 0x00],
+            insn!(Operation::SUB, reg!(sp), imm!(0x80)),
+            "sub sp, 0x80"
+        );
+        assert_encode!(
+            &[0x66, 0x83, 0xC3, 0xFF],
+            insn!(Operation::ADD, reg!(ebx), imm!(-1)),
+            "add ebx, -1"
+        );
+        assert_encode!(
+            &[0x66, 0x83, 0xE0, 0xF0],
+            insn!(Operation::AND, reg!(eax), imm!(-16)),
+            "and eax, -16"
+        );
+        assert_encode!(
+            &[0x66, 0x83, 0x06, 0x00, 0x20, 0xFF],
+            insn!(
+                Operation::ADD,
+                direct!(0x2000, Some(ast::DataSize::DWord), None),
+                imm!(-1)
+            ),
+            "add dword [0x2000], -1"
+        );
     }
 
     #[test]
@@ -1761,6 +3619,16 @@ mod tests {
             "mov [es:di], al"
         );
 
+        assert_encode!(
+            &[0x8B, 0x46, 0x00],
+            insn!(
+                Operation::MOV,
+                reg!(ax),
+                OperandData::indirect(0..0, ast::IndirectEncoding::Bp.encoding(), 0, &None, &None,)
+            ),
+            "mov ax, [bp]"
+        );
+
         // mov al, 0x20
         assert_encode!(&[0xB0, 0x20], insn!(Operation::MOV, reg!(al), imm!(0x20)));
 
@@ -1917,6 +3785,12 @@ mod tests {
         // nop
         assert_encode!(&[0x90], insn!(Operation::XCHG, reg!(ax), reg!(ax)));
 
+        // xchg bl, al
+        assert_encode!(
+            &[0x86, 0b11_011_000],
+            insn!(Operation::XCHG, reg!(bl), reg!(al))
+        );
+
         // xchg bl, dl
         assert_encode!(
             &[0x86, 0b11_011_010],
@@ -1976,6 +3850,48 @@ mod tests {
         assert_encode!(&[0x1E], insn!(Operation::PUSH, seg!(ds)));
         // push ss
         assert_encode!(&[0x16], insn!(Operation::PUSH, seg!(ss)));
+        // push 0x10
+        assert_encode!(&[0x6A, 0x10], insn!(Operation::PUSH, imm!(0x10)));
+        // push -2
+        assert_encode!(&[0x6A, 0xFE], insn!(Operation::PUSH, imm!(-2)));
+        // push 0x1234
+        assert_encode!(&[0x68, 0x34, 0x12], insn!(Operation::PUSH, imm!(0x1234)));
+
+        let sized = |value, size| {
+            let mut operand = imm!(value);
+            operand.size = size;
+            operand
+        };
+        // push byte 0x10
+        assert_encode!(
+            &[0x6A, 0x10],
+            insn!(Operation::PUSH, sized(0x10, OperandSize::Byte))
+        );
+        // push byte 0x80
+        assert_encode_err!(insn!(Operation::PUSH, sized(0x80, OperandSize::Byte)));
+        // push word 0x10
+        assert_encode!(
+            &[0x6A, 0x10],
+            insn!(Operation::PUSH, sized(0x10, OperandSize::Word))
+        );
+        // push dword 5
+        assert_encode!(
+            &[0x66, 0x6A, 0x05],
+            insn!(Operation::PUSH, sized(5, OperandSize::DWord))
+        );
+        // push dword 0x12345678
+        assert_encode!(
+            &[0x66, 0x68, 0x78, 0x56, 0x34, 0x12],
+            insn!(Operation::PUSH, sized(0x12345678, OperandSize::DWord))
+        );
+        // push word [0x2000]
+        assert_encode!(
+            &[0xFF, 0b00_110_110, 0x00, 0x20],
+            insn!(
+                Operation::PUSH,
+                direct!(0x2000, Some(ast::DataSize::Word), None)
+            )
+        );
     }
 
     #[test]
@@ -1988,6 +3904,14 @@ mod tests {
         assert_encode!(&[0x1F], insn!(Operation::POP, seg!(ds)));
         // push ss
         assert_encode!(&[0x17], insn!(Operation::POP, seg!(ss)));
+        // pop word [0x2000]
+        assert_encode!(
+            &[0x8F, 0b00_000_110, 0x00, 0x20],
+            insn!(
+                Operation::POP,
+                direct!(0x2000, Some(ast::DataSize::Word), None)
+            )
+        );
     }
 
     #[test]
@@ -2004,10 +3928,91 @@ mod tests {
             &[0xFF, 0x26, 0x00, 0x20],
             insn!(Operation::JMP, direct!(0x2000))
         );
+
+        // jmp short 0x110
+        let mut target = imm!(0x110);
+        target.jmp_kind = Some(JumpKind::Short);
+        assert_encode!(&[0xEB, 0x0E], insn!(Operation::JMP, target));
+        // jmp short 0x200
+        let mut target = imm!(0x200);
+        target.jmp_kind = Some(JumpKind::Short);
+        assert_encode_err!(insn!(Operation::JMP, target));
+    }
+
+    #[test]
+    fn group_call() {
+        // call far [0x2000]
+        let mut target = direct!(0x2000);
+        target.jmp_kind = Some(JumpKind::Far);
+        assert_encode!(
+            &[0xFF, 0b00_011_110, 0x00, 0x20],
+            insn!(Operation::CALL, target)
+        );
+    }
+
+    #[test]
+    fn group_ret() {
+        // ret
+        assert_encode!(&[0xC3], insn!(Operation::RET));
+        // ret 4
+        assert_encode!(&[0xC2, 0x04, 0x00], insn!(Operation::RET, imm!(4)));
+    }
+
+    #[test]
+    fn group_lea() {
+        // lea dx, [0x2000]
+        assert_encode!(
+            &[0x8D, 0b00_010_110, 0x00, 0x20],
+            insn!(Operation::LEA, reg!(dx), direct!(0x2000))
+        );
+        // lea dl, [0x2000]
+        assert_encode_err!(insn!(Operation::LEA, reg!(dl), direct!(0x2000)));
+        // lea dx, bx
+        assert_encode_err!(insn!(Operation::LEA, reg!(dx), reg!(bx)));
+    }
+
+    #[test]
+    fn group_imul() {
+        // imul ax, bx, 10
+        assert_encode!(
+            &[0x6B, 0b11_000_011, 0x0A],
+            insn!(Operation::IMUL, reg!(ax), reg!(bx), imm!(10))
+        );
+        // imul dx, [0x2000], -3
+        assert_encode!(
+            &[0x6B, 0b00_010_110, 0x00, 0x20, 0xFD],
+            insn!(Operation::IMUL, reg!(dx), direct!(0x2000), imm!(-3))
+        );
+        // imul cx, si, 0x1234
+        assert_encode!(
+            &[0x69, 0b11_001_110, 0x34, 0x12],
+            insn!(Operation::IMUL, reg!(cx), reg!(si), imm!(0x1234))
+        );
+        // imul cx, 10
+        assert_encode!(
+            &[0x6B, 0b11_001_001, 0x0A],
+            insn!(Operation::IMUL, reg!(cx), imm!(10))
+        );
+        // imul bl
+        assert_encode!(&[0xF6, 0b11_101_011], insn!(Operation::IMUL, reg!(bl)));
+    }
+
+    #[test]
+    fn group_enter() {
+        // enter 0x10, 0
+        assert_encode!(
+            &[0xC8, 0x10, 0x00, 0x00],
+            insn!(Operation::ENTER, imm!(0x10), imm!(0))
+        );
+        // enter 0x1234, 2
+        assert_encode!(
+            &[0xC8, 0x34, 0x12, 0x02],
+            insn!(Operation::ENTER, imm!(0x1234), imm!(2))
+        );
     }
 
     #[test]
-    fn group_les_lds() {
+    fn group_les_lds_bound() {
         // lds dx, [0x2000]
         assert_encode!(
             &[0xC5, 0b00_010_110, 0x00, 0x20],
@@ -2029,6 +4034,140 @@ mod tests {
             &[0x2E, 0xC4, 0b00_010_110, 0x00, 0x20],
             insn!(Operation::LES, reg!(dx), direct!(cs:0x2000))
         );
+
+        // bound dx, [0x2000]
+        assert_encode!(
+            &[0x62, 0b00_010_110, 0x00, 0x20],
+            insn!(Operation::BOUND, reg!(dx), direct!(0x2000))
+        );
+    }
+
+    #[test]
+    fn group_sldt_str_lldt_ltr_verr_verw() {
+        // sldt ax
+        assert_encode!(
+            &[0x0F, 0x00, 0b11_000_000],
+            insn!(Operation::SLDT, reg!(ax))
+        );
+        // str [0x2000]
+        assert_encode!(
+            &[0x0F, 0x00, 0b00_001_110, 0x00, 0x20],
+            insn!(Operation::STR, direct!(0x2000))
+        );
+        // lldt word [0x2000]
+        assert_encode!(
+            &[0x0F, 0x00, 0b00_010_110, 0x00, 0x20],
+            insn!(
+                Operation::LLDT,
+                direct!(0x2000, Some(ast::DataSize::Word), None)
+            )
+        );
+        // ltr dx
+        assert_encode!(&[0x0F, 0x00, 0b11_011_010], insn!(Operation::LTR, reg!(dx)));
+        // verr cs:[0x2000]
+        assert_encode!(
+            &[0x2E, 0x0F, 0x00, 0b00_100_110, 0x00, 0x20],
+            insn!(Operation::VERR, direct!(cs:0x2000)),
+            "segment prefix before the 0x0F prefix"
+        );
+        // verw bx
+        assert_encode!(
+            &[0x0F, 0x00, 0b11_101_011],
+            insn!(Operation::VERW, reg!(bx))
+        );
+
+        // sldt al
+        assert_encode_err!(insn!(Operation::SLDT, reg!(al)));
+        // str byte [0x2000]
+        assert_encode_err!(insn!(
+            Operation::STR,
+            direct!(0x2000, Some(ast::DataSize::Byte), None)
+        ));
+    }
+
+    #[test]
+    fn group_sgdt_sidt_lgdt_lidt() {
+        // sgdt [0x2000]
+        assert_encode!(
+            &[0x0F, 0x01, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::SGDT, direct!(0x2000))
+        );
+        // sidt [0x2000]
+        assert_encode!(
+            &[0x0F, 0x01, 0b00_001_110, 0x00, 0x20],
+            insn!(Operation::SIDT, direct!(0x2000))
+        );
+        // lgdt [0x2000]
+        assert_encode!(
+            &[0x0F, 0x01, 0b00_010_110, 0x00, 0x20],
+            insn!(Operation::LGDT, direct!(0x2000))
+        );
+        // lidt es:[0x2000]
+        assert_encode!(
+            &[0x26, 0x0F, 0x01, 0b00_011_110, 0x00, 0x20],
+            insn!(Operation::LIDT, direct!(es:0x2000))
+        );
+
+        // lgdt ax
+        assert_encode_err!(insn!(Operation::LGDT, reg!(ax)));
+        // lidt word [0x2000]
+        assert_encode_err!(insn!(
+            Operation::LIDT,
+            direct!(0x2000, Some(ast::DataSize::Word), None)
+        ));
+    }
+
+    #[test]
+    fn group_smsw_lmsw() {
+        // smsw ax
+        assert_encode!(
+            &[0x0F, 0x01, 0b11_100_000],
+            insn!(Operation::SMSW, reg!(ax))
+        );
+        // lmsw [0x2000]
+        assert_encode!(
+            &[0x0F, 0x01, 0b00_110_110, 0x00, 0x20],
+            insn!(Operation::LMSW, direct!(0x2000))
+        );
+
+        // lmsw al
+        assert_encode_err!(insn!(Operation::LMSW, reg!(al)));
+    }
+
+    #[test]
+    fn group_lar_lsl() {
+        // lar ax, bx
+        assert_encode!(
+            &[0x0F, 0x02, 0b11_000_011],
+            insn!(Operation::LAR, reg!(ax), reg!(bx))
+        );
+        // lsl dx, [0x2000]
+        assert_encode!(
+            &[0x0F, 0x03, 0b00_010_110, 0x00, 0x20],
+            insn!(Operation::LSL, reg!(dx), direct!(0x2000))
+        );
+
+        // lar al, bx
+        assert_encode_err!(insn!(Operation::LAR, reg!(al), reg!(bx)));
+        // lsl [0x2000], dx
+        assert_encode_err!(insn!(Operation::LSL, direct!(0x2000), reg!(dx)));
+    }
+
+    #[test]
+    fn group_arpl() {
+        // arpl ax, bx
+        assert_encode!(
+            &[0x63, 0b11_011_000],
+            insn!(Operation::ARPL, reg!(ax), reg!(bx))
+        );
+        // arpl [0x2000], dx
+        assert_encode!(
+            &[0x63, 0b00_010_110, 0x00, 0x20],
+            insn!(Operation::ARPL, direct!(0x2000), reg!(dx))
+        );
+
+        // arpl dx, [0x2000]
+        assert_encode_err!(insn!(Operation::ARPL, reg!(dx), direct!(0x2000)));
     }
 
     #[test]
@@ -2056,6 +4195,28 @@ mod tests {
             &[0xD1, 0b11_100_000],
             insn!(Operation::SHL, reg!(ax), imm!(0x01))
         );
+
+        // shl al, 4
+        assert_encode!(
+            &[0xC0, 0b11_100_000, 0x04],
+            insn!(Operation::SHL, reg!(al), imm!(0x04))
+        );
+
+        // sar bx, 3
+        assert_encode!(
+            &[0xC1, 0b11_111_011, 0x03],
+            insn!(Operation::SAR, reg!(bx), imm!(0x03))
+        );
+
+        // rol word [0x2000], 2
+        assert_encode!(
+            &[0xC1, 0b00_000_110, 0x00, 0x20, 0x02],
+            insn!(
+                Operation::ROL,
+                OperandData::direct(0..0, 0x2000, &Some(ast::DataSize::Word), &None),
+                imm!(0x02)
+            )
+        );
     }
 
     #[test]
@@ -2078,88 +4239,455 @@ mod tests {
         assert_encode!(&[0x7F, 0xFE], insn!(Operation::JNLE, imm!(0x100)));
     }
 
-    #[test]
-    fn group_loopc() {
-        assert_encode!(&[0xE0, 0xFE], insn!(Operation::LOOPNZ, imm!(0x100)));
-        assert_encode!(&[0xE1, 0xFE], insn!(Operation::LOOPZ, imm!(0x100)));
-        assert_encode!(&[0xE2, 0xFE], insn!(Operation::LOOP, imm!(0x100)));
-        assert_encode!(&[0xE3, 0xFE], insn!(Operation::JCXZ, imm!(0x100)));
+    #[test]
+    fn group_loopc() {
+        assert_encode!(&[0xE0, 0xFE], insn!(Operation::LOOPNZ, imm!(0x100)));
+        assert_encode!(&[0xE1, 0xFE], insn!(Operation::LOOPZ, imm!(0x100)));
+        assert_encode!(&[0xE2, 0xFE], insn!(Operation::LOOP, imm!(0x100)));
+        assert_encode!(&[0xE3, 0xFE], insn!(Operation::JCXZ, imm!(0x100)));
+    }
+
+    #[test]
+    fn group_no_operands() {
+        let tests: &[(Operation, u8)] = &[
+            (Operation::DAA, 0x27),
+            (Operation::DAS, 0x2F),
+            (Operation::AAA, 0x37),
+            (Operation::AAS, 0x3F),
+            (Operation::NOP, 0x90),
+            (Operation::CBW, 0x98),
+            (Operation::CWD, 0x99),
+            (Operation::INT3, 0xCC),
+            (Operation::INT1, 0xF1),
+            (Operation::INTO, 0xCE),
+            (Operation::IRET, 0xCF),
+            (Operation::SALC, 0xD6),
+            (Operation::HLT, 0xF4),
+            (Operation::CMC, 0xF5),
+            (Operation::CLC, 0xF8),
+            (Operation::STC, 0xF9),
+            (Operation::CLI, 0xFA),
+            (Operation::STI, 0xFB),
+            (Operation::CLD, 0xFC),
+            (Operation::STD, 0xFD),
+            (Operation::PUSHF, 0x9C),
+            (Operation::POPF, 0x9D),
+            (Operation::SAHF, 0x9E),
+            (Operation::LAHF, 0x9F),
+            (Operation::MOVSB, 0xA4),
+            (Operation::CMPSB, 0xA6),
+            (Operation::STOSB, 0xAA),
+            (Operation::LODSB, 0xAC),
+            (Operation::SCASB, 0xAE),
+            // (Operation::XLATB, 0xD7),
+            (Operation::MOVSW, 0xA5),
+            (Operation::CMPSW, 0xA7),
+            (Operation::STOSW, 0xAB),
+            (Operation::LODSW, 0xAD),
+            (Operation::SCASW, 0xAF),
+            (Operation::PUSHA, 0x60),
+            (Operation::POPA, 0x61),
+            (Operation::INSB, 0x6C),
+            (Operation::INSW, 0x6D),
+            (Operation::OUTSB, 0x6E),
+            (Operation::OUTSW, 0x6F),
+            (Operation::LEAVE, 0xC9),
+        ];
+
+        for (op, op_code) in tests.iter() {
+            assert_encode!(&[*op_code], insn!(*op));
+        }
+    }
+
+    #[test]
+    fn group_prefixes() {
+        // lock
+        assert_encode!(&[0xF0], insn!(Operation::LOCK));
+        // repne
+        assert_encode!(&[0xF2], insn!(Operation::REPNE));
+        // rep
+        assert_encode!(&[0xF3], insn!(Operation::REP));
+    }
+
+    #[test]
+    fn group_int() {
+        // int 0x21
+        assert_encode!(&[0xCD, 0x21], insn!(Operation::INT, imm!(0x21)));
+    }
+
+    #[test]
+    fn group_aam() {
+        // aam
+        assert_encode!(&[0xD4, 0x0A], insn!(Operation::AAM));
+    }
+
+    #[test]
+    fn group_aad() {
+        // aad
+        assert_encode!(&[0xD5, 0x0A], insn!(Operation::AAD));
+    }
+
+    #[test]
+    fn group_clts() {
+        // clts
+        assert_encode!(&[0x0F, 0x06], insn!(Operation::CLTS));
+    }
+
+    macro_rules! mem {
+        ($data_size:ident) => {{
+            direct!(0x2000, Some(ast::DataSize::$data_size), None)
+        }};
+    }
+
+    #[test]
+    fn group_esc() {
+        // esc 0x3F, [0x2000]
+        assert_encode!(
+            &[0xDF, 0b00_111_110, 0x00, 0x20],
+            insn!(Operation::ESC, imm!(0x3F), direct!(0x2000))
+        );
+        // esc 0x0A, bx
+        assert_encode!(
+            &[0xD9, 0b11_010_011],
+            insn!(Operation::ESC, imm!(0x0A), reg!(bx))
+        );
+
+        // esc 0x40, [0x2000]
+        assert_encode_err!(insn!(Operation::ESC, imm!(0x40), direct!(0x2000)));
+    }
+
+    #[test]
+    fn group_fadd_fmul_fcom_fcomp_fsub_fsubr_fdiv_fdivr() {
+        // fadd dword [0x2000]
+        assert_encode!(
+            &[0xD8, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::FADD, mem!(DWord))
+        );
+        // fmul qword [0x2000]
+        assert_encode!(
+            &[0xDC, 0b00_001_110, 0x00, 0x20],
+            insn!(Operation::FMUL, mem!(QWord))
+        );
+        // fcom st2
+        assert_encode!(&[0xD8, 0xD2], insn!(Operation::FCOM, st!(2)));
+        // fcomp
+        assert_encode!(&[0xD8, 0xD9], insn!(Operation::FCOMP));
+        // fsub st0, st3
+        assert_encode!(&[0xD8, 0xE3], insn!(Operation::FSUB, st!(0), st!(3)));
+        // fsub st3, st0
+        assert_encode!(&[0xDC, 0xEB], insn!(Operation::FSUB, st!(3), st!(0)));
+        // fsubr st3, st0
+        assert_encode!(&[0xDC, 0xE3], insn!(Operation::FSUBR, st!(3), st!(0)));
+        // fdiv st1, st0
+        assert_encode!(&[0xDC, 0xF9], insn!(Operation::FDIV, st!(1), st!(0)));
+        // fdivr st0, st1
+        assert_encode!(&[0xD8, 0xF9], insn!(Operation::FDIVR, st!(0), st!(1)));
+
+        // fadd [0x2000]
+        assert_encode_err!(insn!(Operation::FADD, direct!(0x2000)));
+        // fadd word [0x2000]
+        assert_encode_err!(insn!(Operation::FADD, mem!(Word)));
+        // fadd st1, st2
+        assert_encode_err!(insn!(Operation::FADD, st!(1), st!(2)));
+        // fcom st1, st0
+        assert_encode_err!(insn!(Operation::FCOM, st!(1), st!(0)));
+        // fadd
+        assert_encode_err!(insn!(Operation::FADD));
+    }
+
+    #[test]
+    fn group_faddp_fmulp_fsubp_fsubrp_fdivp_fdivrp() {
+        // faddp
+        assert_encode!(&[0xDE, 0xC1], insn!(Operation::FADDP));
+        // fmulp st2
+        assert_encode!(&[0xDE, 0xCA], insn!(Operation::FMULP, st!(2)));
+        // fsubp st1, st0
+        assert_encode!(&[0xDE, 0xE9], insn!(Operation::FSUBP, st!(1), st!(0)));
+        // fsubrp st1, st0
+        assert_encode!(&[0xDE, 0xE1], insn!(Operation::FSUBRP, st!(1), st!(0)));
+        // fdivp st1, st0
+        assert_encode!(&[0xDE, 0xF9], insn!(Operation::FDIVP, st!(1), st!(0)));
+        // fdivrp
+        assert_encode!(&[0xDE, 0xF1], insn!(Operation::FDIVRP));
+
+        // faddp st0, st1
+        assert_encode_err!(insn!(Operation::FADDP, st!(0), st!(1)));
+    }
+
+    #[test]
+    fn group_fiadd_fimul_ficom_ficomp_fisub_fisubr_fidiv_fidivr() {
+        // fiadd word [0x2000]
+        assert_encode!(
+            &[0xDE, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::FIADD, mem!(Word))
+        );
+        // fidiv dword [0x2000]
+        assert_encode!(
+            &[0xDA, 0b00_110_110, 0x00, 0x20],
+            insn!(Operation::FIDIV, mem!(DWord))
+        );
+        // ficomp word [bx]
+        assert_encode!(
+            &[0xDE, 0b00_011_111],
+            insn!(
+                Operation::FICOMP,
+                OperandData::indirect(0..0, 0b111, 0, &Some(ast::DataSize::Word), &None)
+            )
+        );
+
+        // fimul qword [0x2000]
+        assert_encode_err!(insn!(Operation::FIMUL, mem!(QWord)));
+    }
+
+    #[test]
+    fn group_fld_fst_fstp() {
+        // fld dword [0x2000]
+        assert_encode!(
+            &[0xD9, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::FLD, mem!(DWord))
+        );
+        // fld qword [0x2000]
+        assert_encode!(
+            &[0xDD, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::FLD, mem!(QWord))
+        );
+        // fld tword [0x2000]
+        assert_encode!(
+            &[0xDB, 0b00_101_110, 0x00, 0x20],
+            insn!(Operation::FLD, mem!(TWord))
+        );
+        // fld st1
+        assert_encode!(&[0xD9, 0xC1], insn!(Operation::FLD, st!(1)));
+        // fst dword [0x2000]
+        assert_encode!(
+            &[0xD9, 0b00_010_110, 0x00, 0x20],
+            insn!(Operation::FST, mem!(DWord))
+        );
+        // fst st4
+        assert_encode!(&[0xDD, 0xD4], insn!(Operation::FST, st!(4)));
+        // fstp qword [0x2000]
+        assert_encode!(
+            &[0xDD, 0b00_011_110, 0x00, 0x20],
+            insn!(Operation::FSTP, mem!(QWord))
+        );
+        // fstp tword [0x2000]
+        assert_encode!(
+            &[0xDB, 0b00_111_110, 0x00, 0x20],
+            insn!(Operation::FSTP, mem!(TWord))
+        );
+        // fstp st1
+        assert_encode!(&[0xDD, 0xD9], insn!(Operation::FSTP, st!(1)));
+        // fld dword es:[0x2000]
+        assert_encode!(
+            &[0x26, 0xD9, 0b00_000_110, 0x00, 0x20],
+            insn!(
+                Operation::FLD,
+                direct!(0x2000, Some(ast::DataSize::DWord), Some(ast::Segment::ES))
+            )
+        );
+
+        // fst tword [0x2000]
+        assert_encode_err!(insn!(Operation::FST, mem!(TWord)));
+        // fld [0x2000]
+        assert_encode_err!(insn!(Operation::FLD, direct!(0x2000)));
+        // fld ax
+        assert_encode_err!(insn!(Operation::FLD, reg!(ax)));
+    }
+
+    #[test]
+    fn group_fild_fist_fistp() {
+        // fild word [0x2000]
+        assert_encode!(
+            &[0xDF, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::FILD, mem!(Word))
+        );
+        // fild dword [0x2000]
+        assert_encode!(
+            &[0xDB, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::FILD, mem!(DWord))
+        );
+        // fild qword [0x2000]
+        assert_encode!(
+            &[0xDF, 0b00_101_110, 0x00, 0x20],
+            insn!(Operation::FILD, mem!(QWord))
+        );
+        // fist word [0x2000]
+        assert_encode!(
+            &[0xDF, 0b00_010_110, 0x00, 0x20],
+            insn!(Operation::FIST, mem!(Word))
+        );
+        // fistp qword [0x2000]
+        assert_encode!(
+            &[0xDF, 0b00_111_110, 0x00, 0x20],
+            insn!(Operation::FISTP, mem!(QWord))
+        );
+
+        // fist qword [0x2000]
+        assert_encode_err!(insn!(Operation::FIST, mem!(QWord)));
     }
 
     #[test]
-    fn group_no_operands() {
-        let tests: &[(Operation, u8)] = &[
-            (Operation::DAA, 0x27),
-            (Operation::DAS, 0x2F),
-            (Operation::AAA, 0x37),
-            (Operation::AAS, 0x3F),
-            (Operation::NOP, 0x90),
-            (Operation::CBW, 0x98),
-            (Operation::CWD, 0x99),
-            (Operation::INT3, 0xCC),
-            (Operation::INTO, 0xCE),
-            (Operation::IRET, 0xCF),
-            (Operation::SALC, 0xD6),
-            (Operation::HLT, 0xF4),
-            (Operation::CMC, 0xF5),
-            (Operation::CLC, 0xF8),
-            (Operation::STC, 0xF9),
-            (Operation::CLI, 0xFA),
-            (Operation::STI, 0xFB),
-            (Operation::CLD, 0xFC),
-            (Operation::STD, 0xFD),
-            (Operation::PUSHF, 0x9C),
-            (Operation::POPF, 0x9D),
-            (Operation::SAHF, 0x9E),
-            (Operation::LAHF, 0x9F),
-            (Operation::MOVSB, 0xA4),
-            (Operation::CMPSB, 0xA6),
-            (Operation::STOSB, 0xAA),
-            (Operation::LODSB, 0xAC),
-            (Operation::SCASB, 0xAE),
-            // (Operation::XLATB, 0xD7),
-            (Operation::MOVSW, 0xA5),
-            (Operation::CMPSW, 0xA7),
-            (Operation::STOSW, 0xAB),
-            (Operation::LODSW, 0xAD),
-            (Operation::SCASW, 0xAF),
-        ];
+    fn group_fbld_fbstp() {
+        // fbld [0x2000]
+        assert_encode!(
+            &[0xDF, 0b00_100_110, 0x00, 0x20],
+            insn!(Operation::FBLD, direct!(0x2000))
+        );
+        // fbstp tword [0x2000]
+        assert_encode!(
+            &[0xDF, 0b00_110_110, 0x00, 0x20],
+            insn!(Operation::FBSTP, mem!(TWord))
+        );
 
-        for (op, op_code) in tests.iter() {
-            assert_encode!(&[*op_code], insn!(*op));
-        }
+        // fbld qword [0x2000]
+        assert_encode_err!(insn!(Operation::FBLD, mem!(QWord)));
     }
 
     #[test]
-    fn group_prefixes() {
-        // lock
-        assert_encode!(&[0xF0], insn!(Operation::LOCK));
-        // repne
-        assert_encode!(&[0xF2], insn!(Operation::REPNE));
-        // rep
-        assert_encode!(&[0xF3], insn!(Operation::REP));
+    fn group_fxch_ffree() {
+        // fxch
+        assert_encode!(&[0xD9, 0xC9], insn!(Operation::FXCH));
+        // fxch st3
+        assert_encode!(&[0xD9, 0xCB], insn!(Operation::FXCH, st!(3)));
+        // fxch st0, st3
+        assert_encode!(&[0xD9, 0xCB], insn!(Operation::FXCH, st!(0), st!(3)));
+        // fxch st3, st0
+        assert_encode!(&[0xD9, 0xCB], insn!(Operation::FXCH, st!(3), st!(0)));
+        // ffree st5
+        assert_encode!(&[0xDD, 0xC5], insn!(Operation::FFREE, st!(5)));
+
+        // fxch st1, st2
+        assert_encode_err!(insn!(Operation::FXCH, st!(1), st!(2)));
+        // ffree [0x2000]
+        assert_encode_err!(insn!(Operation::FFREE, direct!(0x2000)));
     }
 
     #[test]
-    fn group_int() {
-        // int 0x21
-        assert_encode!(&[0xCD, 0x21], insn!(Operation::INT, imm!(0x21)));
+    fn group_fpu_no_operands() {
+        let tests = [
+            (Operation::FNOP, 0xD9, 0xD0),
+            (Operation::FCHS, 0xD9, 0xE0),
+            (Operation::FABS, 0xD9, 0xE1),
+            (Operation::FTST, 0xD9, 0xE4),
+            (Operation::FXAM, 0xD9, 0xE5),
+            (Operation::FLD1, 0xD9, 0xE8),
+            (Operation::FLDL2T, 0xD9, 0xE9),
+            (Operation::FLDL2E, 0xD9, 0xEA),
+            (Operation::FLDPI, 0xD9, 0xEB),
+            (Operation::FLDLG2, 0xD9, 0xEC),
+            (Operation::FLDLN2, 0xD9, 0xED),
+            (Operation::FLDZ, 0xD9, 0xEE),
+            (Operation::F2XM1, 0xD9, 0xF0),
+            (Operation::FYL2X, 0xD9, 0xF1),
+            (Operation::FPTAN, 0xD9, 0xF2),
+            (Operation::FPATAN, 0xD9, 0xF3),
+            (Operation::FXTRACT, 0xD9, 0xF4),
+            (Operation::FDECSTP, 0xD9, 0xF6),
+            (Operation::FINCSTP, 0xD9, 0xF7),
+            (Operation::FPREM, 0xD9, 0xF8),
+            (Operation::FYL2XP1, 0xD9, 0xF9),
+            (Operation::FSQRT, 0xD9, 0xFA),
+            (Operation::FRNDINT, 0xD9, 0xFC),
+            (Operation::FSCALE, 0xD9, 0xFD),
+            (Operation::FCOMPP, 0xDE, 0xD9),
+            (Operation::FNENI, 0xDB, 0xE0),
+            (Operation::FNDISI, 0xDB, 0xE1),
+            (Operation::FNCLEX, 0xDB, 0xE2),
+            (Operation::FNINIT, 0xDB, 0xE3),
+        ];
+
+        for (op, first, second) in tests.iter() {
+            assert_encode!(&[*first, *second], insn!(*op));
+        }
     }
 
     #[test]
-    fn group_aam() {
-        // aam
-        assert_encode!(&[0xD4, 0xA0], insn!(Operation::AAM));
+    fn group_fpu_control() {
+        // fldcw [0x2000]
+        assert_encode!(
+            &[0xD9, 0b00_101_110, 0x00, 0x20],
+            insn!(Operation::FLDCW, direct!(0x2000))
+        );
+        // fnstcw word [0x2000]
+        assert_encode!(
+            &[0xD9, 0b00_111_110, 0x00, 0x20],
+            insn!(Operation::FNSTCW, mem!(Word))
+        );
+        // fldenv [0x2000]
+        assert_encode!(
+            &[0xD9, 0b00_100_110, 0x00, 0x20],
+            insn!(Operation::FLDENV, direct!(0x2000))
+        );
+        // fnstenv [0x2000]
+        assert_encode!(
+            &[0xD9, 0b00_110_110, 0x00, 0x20],
+            insn!(Operation::FNSTENV, direct!(0x2000))
+        );
+        // frstor [0x2000]
+        assert_encode!(
+            &[0xDD, 0b00_100_110, 0x00, 0x20],
+            insn!(Operation::FRSTOR, direct!(0x2000))
+        );
+        // fnsave [0x2000]
+        assert_encode!(
+            &[0xDD, 0b00_110_110, 0x00, 0x20],
+            insn!(Operation::FNSAVE, direct!(0x2000))
+        );
+        // fnstsw [0x2000]
+        assert_encode!(
+            &[0xDD, 0b00_111_110, 0x00, 0x20],
+            insn!(Operation::FNSTSW, direct!(0x2000))
+        );
+        // fnstsw ax
+        assert_encode!(&[0xDF, 0xE0], insn!(Operation::FNSTSW, reg!(ax)));
+
+        // fldcw dword [0x2000]
+        assert_encode_err!(insn!(Operation::FLDCW, mem!(DWord)));
+        // fnsave word [0x2000]
+        assert_encode_err!(insn!(Operation::FNSAVE, mem!(Word)));
+        // fnstsw bx
+        assert_encode_err!(insn!(Operation::FNSTSW, reg!(bx)));
     }
 
     #[test]
-    fn group_aad() {
-        // aad
-        assert_encode!(&[0xD5, 0xA0], insn!(Operation::AAD));
+    fn fpu_wait_prefix() {
+        // finit
+        assert_encode!(&[0x9B, 0xDB, 0xE3], insn!(Operation::FINIT));
+        // fstsw ax
+        assert_encode!(&[0x9B, 0xDF, 0xE0], insn!(Operation::FSTSW, reg!(ax)));
+        // fsave [0x2000]
+        assert_encode!(
+            &[0x9B, 0xDD, 0b00_110_110, 0x00, 0x20],
+            insn!(Operation::FSAVE, direct!(0x2000))
+        );
+
+        let mut insn = insn!(Operation::FLD, st!(1));
+        insn.fpu_wait = true;
+        assert_encode!(
+            &[0x9B, 0xD9, 0xC1],
+            insn,
+            "8087 waits before each instruction"
+        );
+
+        let mut insn = insn!(Operation::FNINIT);
+        insn.fpu_wait = true;
+        assert_encode!(&[0xDB, 0xE3], insn, "no wait variants never wait");
+
+        let mut insn = insn!(Operation::NOP);
+        insn.fpu_wait = true;
+        assert_encode!(&[0x90], insn, "only FPU instructions wait");
+
+        // wait
+        assert_encode!(&[0x9B], insn!(Operation::WAIT));
     }
 
     #[test]
     fn group_xlat() {
+        // xlatb
+        assert_encode!(&[0xD7], insn!(Operation::XLATB));
+        // cs xlatb
+        assert_encode!(&[0x2E, 0xD7], insn!(Operation::XLATB, seg!(cs)));
+
         // xlat word [bx]
         assert_encode!(
             &[0xD7],
@@ -2223,6 +4751,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn group_test() {
+        // test al, 0x12
+        assert_encode!(&[0xA8, 0x12], insn!(Operation::TEST, reg!(al), imm!(0x12)));
+        // test ax, 0x1234
+        assert_encode!(
+            &[0xA9, 0x34, 0x12],
+            insn!(Operation::TEST, reg!(ax), imm!(0x1234))
+        );
+        // test bl, 1
+        assert_encode!(
+            &[0xF6, 0b11_000_011, 0x01],
+            insn!(Operation::TEST, reg!(bl), imm!(1))
+        );
+        // test word [0x2000], 1
+        assert_encode!(
+            &[0xF7, 0b00_000_110, 0x00, 0x20, 0x01, 0x00],
+            insn!(Operation::TEST, mem!(Word), imm!(1))
+        );
+        // test ax, bx
+        assert_encode!(
+            &[0x85, 0b11_011_000],
+            insn!(Operation::TEST, reg!(ax), reg!(bx))
+        );
+        // test [0x2000], cl
+        assert_encode!(
+            &[0x84, 0b00_001_110, 0x00, 0x20],
+            insn!(Operation::TEST, direct!(0x2000), reg!(cl))
+        );
+        // test cl, [0x2000]
+        assert_encode!(
+            &[0x84, 0b00_001_110, 0x00, 0x20],
+            insn!(Operation::TEST, reg!(cl), direct!(0x2000))
+        );
+    }
+
     #[test]
     fn group_in() {
         // in al, 0x10
@@ -2247,5 +4811,364 @@ mod tests {
         assert_encode!(&[0xEE], insn!(Operation::OUT, reg!(dx), reg!(al)));
         // out dx, ax
         assert_encode!(&[0xEF], insn!(Operation::OUT, reg!(dx), reg!(ax)));
+        // out dx, eax
+        assert_encode!(&[0x66, 0xEF], insn!(Operation::OUT, reg!(dx), reg!(eax)));
+    }
+
+    macro_rules! indirect32 {
+        ($base:expr, $index:expr, $displacement:expr $(,)?) => {{
+            indirect32!($base, $index, $displacement, None)
+        }};
+
+        ($base:expr, $index:expr, $displacement:expr, $data_size:expr $(,)?) => {{
+            OperandData::indirect32(
+                0..0,
+                $base.map(|reg: ast::DWordRegister| reg.encoding()),
+                $index.map(|(reg, scale): (ast::DWordRegister, u8)| (reg.encoding(), scale)),
+                $displacement,
+                &$data_size,
+                &None,
+            )
+        }};
+    }
+
+    macro_rules! bits32 {
+        ($insn:expr) => {{
+            let mut insn = $insn;
+            insn.bits = ast::Bits::Bits32;
+            insn
+        }};
+    }
+
+    #[test]
+    fn operand_size_prefix() {
+        use ast::DWordRegister::*;
+
+        // mov eax, ebx
+        assert_encode!(
+            &[0x66, 0x89, 0xD8],
+            insn!(Operation::MOV, reg!(eax), reg!(ebx))
+        );
+        // add eax, 0x12345678
+        assert_encode!(
+            &[0x66, 0x05, 0x78, 0x56, 0x34, 0x12],
+            insn!(Operation::ADD, reg!(eax), imm!(0x12345678))
+        );
+        // mov eax, [0x2000]
+        assert_encode!(
+            &[0x66, 0xA1, 0x00, 0x20],
+            insn!(Operation::MOV, reg!(eax), direct!(0x2000))
+        );
+        // mov dword [eax], 1
+        assert_encode!(
+            &[0x67, 0x66, 0xC7, 0x00, 0x01, 0x00, 0x00, 0x00],
+            insn!(
+                Operation::MOV,
+                indirect32!(Some(Eax), None, 0, Some(ast::DataSize::DWord)),
+                imm!(1)
+            )
+        );
+
+        // pushad
+        assert_encode!(&[0x66, 0x60], insn!(Operation::PUSHAD));
+        // cwde
+        assert_encode!(&[0x66, 0x98], insn!(Operation::CWDE));
+        // movsd
+        assert_encode!(&[0x66, 0xA5], insn!(Operation::MOVSD));
+        // cbw
+        assert_encode!(&[0x98], insn!(Operation::CBW));
+
+        // push fs
+        assert_encode!(&[0x0F, 0xA0], insn!(Operation::PUSH, seg!(fs)));
+        // pop gs
+        assert_encode!(&[0x0F, 0xA9], insn!(Operation::POP, seg!(gs)));
+    }
+
+    #[test]
+    fn address_size_prefix() {
+        use ast::DWordRegister::*;
+
+        // mov ax, [eax]
+        assert_encode!(
+            &[0x67, 0x8B, 0x00],
+            insn!(Operation::MOV, reg!(ax), indirect32!(Some(Eax), None, 0))
+        );
+        // mov word [ebp], 1
+        assert_encode!(
+            &[0x67, 0xC7, 0x45, 0x00, 0x01, 0x00],
+            insn!(
+                Operation::MOV,
+                indirect32!(Some(Ebp), None, 0, Some(ast::DataSize::Word)),
+                imm!(1)
+            )
+        );
+        // mov [esp], ax
+        assert_encode!(
+            &[0x67, 0x89, 0x04, 0x24],
+            insn!(Operation::MOV, indirect32!(Some(Esp), None, 0), reg!(ax))
+        );
+        // mov eax, [ebx+esi*4+0x10]
+        assert_encode!(
+            &[0x67, 0x66, 0x8B, 0x44, 0xB3, 0x10],
+            insn!(
+                Operation::MOV,
+                reg!(eax),
+                indirect32!(Some(Ebx), Some((Esi, 4)), 0x10)
+            )
+        );
+        // mov ecx, [esi*2+0x100]
+        assert_encode!(
+            &[0x67, 0x66, 0x8B, 0x0C, 0x75, 0x00, 0x01, 0x00, 0x00],
+            insn!(
+                Operation::MOV,
+                reg!(ecx),
+                indirect32!(None, Some((Esi, 2)), 0x100)
+            )
+        );
+        // mov eax, [ebx+ecx]
+        assert_encode!(
+            &[0x67, 0x66, 0x8B, 0x04, 0x0B],
+            insn!(
+                Operation::MOV,
+                reg!(eax),
+                indirect32!(Some(Ebx), Some((Ecx, 1)), 0)
+            )
+        );
+    }
+
+    #[test]
+    fn bits32() {
+        // mov eax, ebx
+        assert_encode!(
+            &[0x89, 0xD8],
+            bits32!(insn!(Operation::MOV, reg!(eax), reg!(ebx)))
+        );
+        // mov ax, bx
+        assert_encode!(
+            &[0x66, 0x89, 0xD8],
+            bits32!(insn!(Operation::MOV, reg!(ax), reg!(bx)))
+        );
+        // push 0x1234
+        assert_encode!(
+            &[0x68, 0x34, 0x12, 0x00, 0x00],
+            bits32!(insn!(Operation::PUSH, imm!(0x1234)))
+        );
+        // push word 0x1234
+        let mut value = imm!(0x1234);
+        value.size = OperandSize::Word;
+        assert_encode!(
+            &[0x66, 0x68, 0x34, 0x12],
+            bits32!(insn!(Operation::PUSH, value))
+        );
+        // mov ecx, [0x2000]
+        assert_encode!(
+            &[0x8B, 0x0D, 0x00, 0x20, 0x00, 0x00],
+            bits32!(insn!(
+                Operation::MOV,
+                reg!(ecx),
+                indirect32!(None, None, 0x2000)
+            ))
+        );
+        // cmp ecx, -5
+        assert_encode!(
+            &[0x83, 0xF9, 0xFB],
+            bits32!(insn!(Operation::CMP, reg!(ecx), imm!(-5)))
+        );
+        // jmp 0x200
+        assert_encode!(
+            &[0xE9, 0xFB, 0x00, 0x00, 0x00],
+            bits32!(insn!(Operation::JMP, imm!(0x200)))
+        );
+        // call 0x100
+        assert_encode!(
+            &[0xE8, 0xFB, 0xFF, 0xFF, 0xFF],
+            bits32!(insn!(Operation::CALL, imm!(0x100)))
+        );
+        // iret
+        assert_encode!(&[0x66, 0xCF], bits32!(insn!(Operation::IRET)));
+        // iretd
+        assert_encode!(&[0xCF], bits32!(insn!(Operation::IRETD)));
+    }
+
+    #[test]
+    fn group_test1_clr1_set1_not1() {
+        // test1 al, cl
+        assert_encode!(
+            &[0x0F, 0x10, 0b11_000_000],
+            insn!(Operation::TEST1, reg!(al), reg!(cl))
+        );
+        // clr1 word [0x2000], cl
+        assert_encode!(
+            &[0x0F, 0x13, 0b00_000_110, 0x00, 0x20],
+            insn!(
+                Operation::CLR1,
+                direct!(0x2000, Some(ast::DataSize::Word), None),
+                reg!(cl)
+            )
+        );
+        // set1 bl, 7
+        assert_encode!(
+            &[0x0F, 0x1C, 0b11_000_011, 0x07],
+            insn!(Operation::SET1, reg!(bl), imm!(7))
+        );
+        // not1 dx, 15
+        assert_encode!(
+            &[0x0F, 0x1F, 0b11_000_010, 0x0F],
+            insn!(Operation::NOT1, reg!(dx), imm!(15))
+        );
+
+        assert_encode_err!(insn!(Operation::SET1, reg!(bl), imm!(8)));
+        assert_encode_err!(insn!(Operation::TEST1, direct!(0x2000), reg!(cl)));
+    }
+
+    #[test]
+    fn group_ins_ext() {
+        // ins al, cl
+        assert_encode!(
+            &[0x0F, 0x31, 0b11_001_000],
+            insn!(Operation::INS, reg!(al), reg!(cl))
+        );
+        // ext bl, 4
+        assert_encode!(
+            &[0x0F, 0x3B, 0b11_000_011, 0x04],
+            insn!(Operation::EXT, reg!(bl), imm!(4))
+        );
+
+        assert_encode_err!(insn!(Operation::EXT, reg!(ax), reg!(cl)));
+        assert_encode_err!(insn!(Operation::INS, reg!(al), imm!(16)));
+    }
+
+    #[test]
+    fn group_rol4_ror4() {
+        // rol4 al
+        assert_encode!(
+            &[0x0F, 0x28, 0b11_000_000],
+            insn!(Operation::ROL4, reg!(al))
+        );
+        // ror4 [0x2000]
+        assert_encode!(
+            &[0x0F, 0x2A, 0b00_000_110, 0x00, 0x20],
+            insn!(Operation::ROR4, direct!(0x2000))
+        );
+
+        assert_encode_err!(insn!(Operation::ROL4, reg!(ax)));
+    }
+
+    #[test]
+    fn nec_no_operands() {
+        assert_encode!(&[0x0F, 0x20], insn!(Operation::ADD4S));
+        assert_encode!(&[0x0F, 0x22], insn!(Operation::SUB4S));
+        assert_encode!(&[0x0F, 0x26], insn!(Operation::CMP4S));
+        // brkem 0x10
+        assert_encode!(&[0x0F, 0xFF, 0x10], insn!(Operation::BRKEM, imm!(0x10)));
+    }
+
+    macro_rules! string_addr {
+        ($index:ident, $data_size:expr, $segment_override:expr) => {{
+            OperandData::indirect(
+                0..0,
+                ast::IndirectEncoding::$index.encoding(),
+                0,
+                &$data_size,
+                &$segment_override,
+            )
+        }};
+    }
+
+    #[test]
+    fn string_segment_override() {
+        // es lodsb
+        assert_encode!(&[0x26, 0xAC], insn!(Operation::LODSB, seg!(es)));
+        // cs movsw
+        assert_encode!(&[0x2E, 0xA5], insn!(Operation::MOVSW, seg!(cs)));
+        // fs outsb
+        assert_encode!(&[0x64, 0x6E], insn!(Operation::OUTSB, seg!(fs)));
+
+        // The destination is always ES:DI.
+        assert_encode_err!(insn!(Operation::STOSB, seg!(cs)));
+        assert_encode_err!(insn!(Operation::SCASW, seg!(es)));
+        assert_encode_err!(insn!(Operation::MOVSB, reg!(al)));
+    }
+
+    #[test]
+    fn explicit_string_operands() {
+        // movs byte [di], [cs:si]
+        assert_encode!(
+            &[0x2E, 0xA4],
+            insn!(
+                Operation::MOVS,
+                string_addr!(Di, Some(ast::DataSize::Byte), None),
+                string_addr!(Si, None, Some(ast::Segment::CS))
+            )
+        );
+        // cmps word [ss:si], [es:di]
+        assert_encode!(
+            &[0x36, 0xA7],
+            insn!(
+                Operation::CMPS,
+                string_addr!(Si, Some(ast::DataSize::Word), Some(ast::Segment::SS)),
+                string_addr!(Di, None, Some(ast::Segment::ES))
+            )
+        );
+        // movs dword [di], [si]
+        assert_encode!(
+            &[0x66, 0xA5],
+            insn!(
+                Operation::MOVS,
+                string_addr!(Di, Some(ast::DataSize::DWord), None),
+                string_addr!(Si, None, None)
+            )
+        );
+        // lods byte [esi]
+        assert_encode!(
+            &[0x67, 0xAC],
+            insn!(
+                Operation::LODS,
+                OperandData::indirect32(
+                    0..0,
+                    Some(ast::DWordRegister::Esi.encoding()),
+                    None,
+                    0,
+                    &Some(ast::DataSize::Byte),
+                    &None
+                )
+            )
+        );
+        // stos word [di]
+        assert_encode!(
+            &[0xAB],
+            insn!(
+                Operation::STOS,
+                string_addr!(Di, Some(ast::DataSize::Word), None)
+            )
+        );
+        // outs dx, byte [es:si]
+        assert_encode!(
+            &[0x26, 0x6E],
+            insn!(
+                Operation::OUTS,
+                reg!(dx),
+                string_addr!(Si, Some(ast::DataSize::Byte), Some(ast::Segment::ES))
+            )
+        );
+
+        // stos byte [cs:di]
+        assert_encode_err!(insn!(
+            Operation::STOS,
+            string_addr!(Di, Some(ast::DataSize::Byte), Some(ast::Segment::CS))
+        ));
+        // scas [di]
+        assert_encode_err!(insn!(Operation::SCAS, string_addr!(Di, None, None)));
+        // lods byte [di]
+        assert_encode_err!(insn!(
+            Operation::LODS,
+            string_addr!(Di, Some(ast::DataSize::Byte), None)
+        ));
+        // movs byte [di], word [si]
+        assert_encode_err!(insn!(
+            Operation::MOVS,
+            string_addr!(Di, Some(ast::DataSize::Byte), None),
+            string_addr!(Si, Some(ast::DataSize::Word), None)
+        ));
     }
 }
@@ -0,0 +1,795 @@
+//! Round trip tests for the encoder and the decoder.  Random instructions are generated for every
+//! operation group, encoded and then decoded again, after which the decoded instruction must be
+//! the same as the one that was generated.
+
+use super::{encode, InstructionData, OperandData, OperandSize};
+use crate::ast;
+use crate::compiler::Compiler;
+use crate::decoder::Decoder;
+use crate::operations::Operation;
+
+const ORIGIN: u16 = 0x100;
+const ITERATIONS: usize = 100;
+const SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// Values around which encodings change, e.g. from a signed byte to a word.
+const BOUNDARIES: [i64; 14] = [
+    i32::MIN as i64,
+    -0x8000,
+    -0x81,
+    -0x80,
+    -1,
+    0,
+    0x7F,
+    0x80,
+    0xFF,
+    0x100,
+    0x7FFF,
+    0x8000,
+    0xFFFF,
+    i32::MAX as i64,
+];
+
+/// A xorshift generator, so every run tests the same instructions.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, count: u64) -> u64 {
+        self.next() % count
+    }
+
+    fn range(&mut self, min: i64, max: i64) -> i64 {
+        min + self.below((max - min + 1) as u64) as i64
+    }
+
+    /// A value in the range, which is one of the [BOUNDARIES] in it or one of its ends half of the
+    /// time.  A uniform range would hardly ever generate the values where encodings change.
+    fn value(&mut self, min: i64, max: i64) -> i64 {
+        if self.one_in(2) {
+            let boundaries = BOUNDARIES
+                .into_iter()
+                .chain([min, max])
+                .filter(|value| (min..=max).contains(value))
+                .collect::<Vec<_>>();
+            self.pick(&boundaries)
+        } else {
+            self.range(min, max)
+        }
+    }
+
+    /// Returns true once in every [count] calls on average.
+    fn one_in(&mut self, count: u64) -> bool {
+        self.below(count) == 0
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize].clone()
+    }
+}
+
+const SEGMENTS: [ast::Segment; 6] = [
+    ast::Segment::ES,
+    ast::Segment::CS,
+    ast::Segment::SS,
+    ast::Segment::DS,
+    ast::Segment::FS,
+    ast::Segment::GS,
+];
+
+struct Generator {
+    random: Random,
+    bits: ast::Bits,
+}
+
+impl Generator {
+    fn insn(&self, operation: Operation, operands: Vec<OperandData>) -> InstructionData {
+        let mut operands = operands.into_iter();
+        let mut insn = match (operands.next(), operands.next(), operands.next()) {
+            (None, _, _) => InstructionData::none(0..0, operation),
+            (Some(dst), None, _) => InstructionData::dst(0..0, operation, dst),
+            (Some(dst), Some(src), None) => InstructionData::dst_and_src(0..0, operation, dst, src),
+            (Some(dst), Some(src), Some(third)) => {
+                InstructionData::dst_src_and_third(0..0, operation, dst, src, third)
+            }
+        };
+        insn.bits = self.bits;
+        insn
+    }
+
+    /// The size of operands without a prefix.
+    fn default_size(&self) -> OperandSize {
+        match self.bits {
+            ast::Bits::Bits16 => OperandSize::Word,
+            ast::Bits::Bits32 => OperandSize::DWord,
+        }
+    }
+
+    fn size(&mut self, sizes: &[OperandSize]) -> OperandSize {
+        self.random.pick(sizes)
+    }
+
+    fn register(&mut self, size: OperandSize) -> OperandData {
+        OperandData::register(0..0, self.random.below(8) as u8, size)
+    }
+
+    fn segment_register(&mut self, segments: &[ast::Segment]) -> OperandData {
+        OperandData::segment(0..0, self.random.pick(segments).encoding())
+    }
+
+    fn fpu_register(&mut self) -> OperandData {
+        OperandData::fpu_register(0..0, self.random.below(8) as u8)
+    }
+
+    fn segment_override(&mut self) -> Option<ast::Segment> {
+        if self.random.one_in(3) {
+            Some(self.random.pick(&SEGMENTS))
+        } else {
+            None
+        }
+    }
+
+    /// A memory operand with a random address.  Both address sizes are generated in both modes.
+    fn memory(&mut self, size: OperandSize) -> OperandData {
+        let data_size = data_size(size);
+        let segment = self.segment_override();
+
+        let address32 = match self.bits {
+            ast::Bits::Bits16 => self.random.one_in(4),
+            ast::Bits::Bits32 => !self.random.one_in(4),
+        };
+
+        if address32 {
+            const ESP: u8 = 0b100;
+
+            let base = if self.random.one_in(4) {
+                None
+            } else {
+                Some(self.random.below(8) as u8)
+            };
+            let index = if self.random.one_in(2) {
+                None
+            } else {
+                let index = self.random.pick(&[0, 1, 2, 3, 5, 6, 7]);
+                debug_assert_ne!(ESP, index);
+                Some((index, self.random.pick(&[1, 2, 4, 8])))
+            };
+            let displacement = match self.random.below(3) {
+                0 => 0,
+                1 => self.random.value(-0x80, 0x7F),
+                _ => self.random.value(i32::MIN as i64, i32::MAX as i64),
+            } as i32;
+
+            OperandData::indirect32(0..0, base, index, displacement, &data_size, &segment)
+        } else if self.bits == ast::Bits::Bits16 && self.random.one_in(4) {
+            // In 32-bit code a direct address is always compiled with a 32-bit address.
+            let address = self.random.value(0, 0xFFFF) as i32;
+            OperandData::direct(0..0, address, &data_size, &segment)
+        } else {
+            let displacement = match self.random.below(3) {
+                0 => 0,
+                1 => self.random.value(-0x80, 0x7F),
+                _ => self.random.value(i16::MIN as i64, i16::MAX as i64),
+            } as i16;
+
+            OperandData::indirect(
+                0..0,
+                self.random.below(8) as u8,
+                displacement,
+                &data_size,
+                &segment,
+            )
+        }
+    }
+
+    /// A register or memory operand of the given size.  Memory operands only sometimes have an
+    /// explicit size if [sized] is false.
+    fn rm(&mut self, size: OperandSize, sized: bool) -> OperandData {
+        if self.random.one_in(2) {
+            self.register(size)
+        } else if sized || self.random.one_in(2) {
+            self.memory(size)
+        } else {
+            self.memory(OperandSize::Unspecified)
+        }
+    }
+
+    fn immediate(&mut self, min: i64, max: i64) -> OperandData {
+        OperandData::immediate(0..0, self.random.value(min, max) as i32)
+    }
+
+    /// An immediate value for an operand of the given size.  Word values can also be negative, to
+    /// get the sign extended forms.
+    fn immediate_for(&mut self, size: OperandSize) -> OperandData {
+        match size {
+            OperandSize::Byte => self.immediate(0, 0xFF),
+            OperandSize::Word => self.immediate(-0x80, 0xFFFF),
+            _ => self.immediate(i32::MIN as i64, i32::MAX as i64),
+        }
+    }
+
+    /// The target of a jump with a relative byte offset, from an instruction of 2 bytes.
+    fn short_target(&mut self) -> OperandData {
+        let next = ORIGIN as i64 + 2;
+        self.immediate(next - 0x80, next + 0x7F)
+    }
+}
+
+fn data_size(size: OperandSize) -> Option<ast::DataSize> {
+    match size {
+        OperandSize::Unspecified => None,
+        OperandSize::Byte => Some(ast::DataSize::Byte),
+        OperandSize::Word => Some(ast::DataSize::Word),
+        OperandSize::DWord => Some(ast::DataSize::DWord),
+        OperandSize::QWord => Some(ast::DataSize::QWord),
+        OperandSize::TWord => Some(ast::DataSize::TWord),
+    }
+}
+
+use OperandSize::{Byte, DWord, QWord, TWord, Unspecified, Word};
+
+type Generate = fn(&mut Generator, Operation) -> InstructionData;
+
+/// The operations of each group, how to generate them and whether they are also generated in
+/// 32-bit code.
+const GROUPS: &[(&[Operation], Generate, bool)] = {
+    use Operation::*;
+
+    &[
+        (&[ADD, OR, ADC, SBB, AND, SUB, XOR, CMP], generate_alu, true),
+        (&[MOV], generate_mov, true),
+        (&[XCHG], generate_xchg, true),
+        (&[TEST], generate_test, true),
+        (
+            &[INC, DEC, NOT, NEG, MUL, IMUL, DIV, IDIV],
+            generate_rm,
+            true,
+        ),
+        (&[IMUL], generate_imul, true),
+        (&[ROL, ROR, RCL, RCR, SHL, SHR, SAR], generate_shift, true),
+        (&[PUSH, POP], generate_push_pop, true),
+        (
+            &[
+                JO, JNO, JB, JNB, JE, JNE, JBE, JNBE, JS, JNS, JP, JNP, JL, JNL, JLE, JNLE, LOOPNZ,
+                LOOPZ, LOOP, JCXZ,
+            ],
+            generate_short_jump,
+            true,
+        ),
+        (&[CALL, JMP], generate_call_jmp, true),
+        (&[INT, ENTER, BRKEM, RET], generate_immediates, true),
+        (
+            &[
+                DAA, DAS, AAA, AAS, NOP, CBW, CWD, INT1, INT3, INTO, IRET, SALC, HLT, CMC, CLC,
+                STC, CLI, STI, CLD, STD, PUSHF, POPF, SAHF, LAHF, PUSHA, POPA, LEAVE, WAIT, LOCK,
+                REPNE, REP, RET, AAM, AAD, CLTS, CWDE, CDQ, IRETD, PUSHFD, POPFD, PUSHAD, POPAD,
+                ADD4S, SUB4S, CMP4S,
+            ],
+            generate_no_operands,
+            true,
+        ),
+        (
+            &[
+                MOVSB, MOVSW, MOVSD, CMPSB, CMPSW, CMPSD, LODSB, LODSW, LODSD, OUTSB, OUTSW, OUTSD,
+                STOSB, STOSW, STOSD, SCASB, SCASW, SCASD, INSB, INSW, INSD,
+            ],
+            generate_implicit_string,
+            true,
+        ),
+        (
+            &[MOVS, CMPS, LODS, STOS, SCAS, OUTS],
+            generate_explicit_string,
+            true,
+        ),
+        (&[IN, OUT], generate_in_out, true),
+        (&[XLATB], generate_xlatb, false),
+        (&[LES, LDS, BOUND, LEA], generate_reg_memory, false),
+        (
+            &[
+                SLDT, STR, LLDT, LTR, VERR, VERW, SGDT, SIDT, LGDT, LIDT, SMSW, LMSW, LAR, LSL,
+                ARPL,
+            ],
+            generate_system,
+            true,
+        ),
+        (&[ESC], generate_esc, true),
+        (
+            &[
+                FADD, FMUL, FCOM, FCOMP, FSUB, FSUBR, FDIV, FDIVR, FADDP, FMULP, FSUBP, FSUBRP,
+                FDIVP, FDIVRP, FIADD, FIMUL, FICOM, FICOMP, FISUB, FISUBR, FIDIV, FIDIVR,
+            ],
+            generate_fpu_arithmetic,
+            true,
+        ),
+        (
+            &[
+                FLD, FST, FSTP, FILD, FIST, FISTP, FBLD, FBSTP, FXCH, FFREE, FLDCW, FSTCW, FNSTCW,
+                FLDENV, FSTENV, FNSTENV, FRSTOR, FSAVE, FNSAVE, FSTSW, FNSTSW,
+            ],
+            generate_fpu_transfer,
+            true,
+        ),
+        (
+            &[
+                FCOMPP, FNOP, FCHS, FABS, FTST, FXAM, FLD1, FLDL2T, FLDL2E, FLDPI, FLDLG2, FLDLN2,
+                FLDZ, F2XM1, FYL2X, FPTAN, FPATAN, FXTRACT, FDECSTP, FINCSTP, FPREM, FYL2XP1,
+                FSQRT, FRNDINT, FSCALE, FINIT, FNINIT, FENI, FNENI, FDISI, FNDISI, FCLEX, FNCLEX,
+            ],
+            generate_no_operands,
+            true,
+        ),
+        (
+            &[TEST1, CLR1, SET1, NOT1, INS, EXT, ROL4, ROR4],
+            generate_v20,
+            false,
+        ),
+    ]
+};
+
+fn generate_alu(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Byte, Word, DWord]);
+
+    let operands = match g.random.below(3) {
+        0 => vec![g.rm(size, false), g.register(size)],
+        1 => vec![g.register(size), g.memory(size)],
+        _ => vec![g.rm(size, true), g.immediate_for(size)],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_mov(g: &mut Generator, operation: Operation) -> InstructionData {
+    use ast::Segment::{DS, ES, FS, GS, SS};
+
+    let size = g.size(&[Byte, Word, DWord]);
+
+    let operands = match g.random.below(5) {
+        0 => vec![g.rm(size, false), g.register(size)],
+        1 => vec![g.register(size), g.memory(size)],
+        2 => vec![g.rm(size, true), g.immediate_for(size)],
+        3 => vec![g.segment_register(&[ES, SS, DS, FS, GS]), g.rm(Word, false)],
+        _ => vec![g.rm(Word, false), g.segment_register(&SEGMENTS)],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_xchg(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Byte, Word, DWord]);
+
+    let operands = match g.random.below(3) {
+        0 => loop {
+            let (dst, src) = (g.register(size), g.register(size));
+            // "xchg ax, ax" is a NOP.
+            if size == Byte || dst.rm != 0 || src.rm != 0 {
+                break vec![dst, src];
+            }
+        },
+        1 => vec![g.register(size), g.memory(size)],
+        _ => vec![g.memory(size), g.register(size)],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_test(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Byte, Word, DWord]);
+
+    let operands = if g.random.one_in(2) {
+        vec![g.rm(size, false), g.register(size)]
+    } else {
+        let value = match size {
+            Byte => g.immediate(0, 0xFF),
+            Word => g.immediate(0, 0xFFFF),
+            _ => g.immediate_for(size),
+        };
+        vec![g.rm(size, true), value]
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_rm(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Byte, Word, DWord]);
+    let operands = vec![g.rm(size, true)];
+    g.insn(operation, operands)
+}
+
+fn generate_imul(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Word, DWord]);
+    let operands = vec![g.register(size), g.rm(size, false), g.immediate_for(size)];
+    g.insn(operation, operands)
+}
+
+fn generate_shift(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Byte, Word, DWord]);
+    let dst = g.rm(size, true);
+    let count = match g.random.below(3) {
+        0 => OperandData::immediate(0..0, 1),
+        1 => OperandData::register(0..0, ast::ByteRegister::Cl.encoding(), Byte),
+        _ => g.immediate(0, 0xFF),
+    };
+    g.insn(operation, vec![dst, count])
+}
+
+fn generate_push_pop(g: &mut Generator, operation: Operation) -> InstructionData {
+    use ast::Segment::{DS, ES, FS, GS, SS};
+
+    let operand = match g.random.below(4) {
+        0 => {
+            let size = g.size(&[Word, DWord]);
+            g.register(size)
+        }
+        1 => {
+            let size = g.size(&[Word, DWord]);
+            g.memory(size)
+        }
+        2 if operation == Operation::PUSH => g.segment_register(&SEGMENTS),
+        2 => g.segment_register(&[ES, SS, DS, FS, GS]),
+        _ if operation == Operation::PUSH && g.bits == ast::Bits::Bits16 => {
+            g.immediate(-0x80, 0xFFFF)
+        }
+        _ => g.register(Word),
+    };
+    g.insn(operation, vec![operand])
+}
+
+fn generate_short_jump(g: &mut Generator, operation: Operation) -> InstructionData {
+    let target = g.short_target();
+    g.insn(operation, vec![target])
+}
+
+fn generate_call_jmp(g: &mut Generator, operation: Operation) -> InstructionData {
+    let operand = match g.random.below(3) {
+        0 => g.immediate(0, 0xFFFF),
+        1 if operation == Operation::JMP && g.bits == ast::Bits::Bits16 => {
+            let offset = g.random.range(0, 0xFFFF) as i32;
+            let segment = g.random.range(0, 0xFFFF) as i32;
+            OperandData::far(0..0, offset, segment)
+        }
+        _ => {
+            let size = g.default_size();
+            g.rm(size, false)
+        }
+    };
+    g.insn(operation, vec![operand])
+}
+
+fn generate_immediates(g: &mut Generator, operation: Operation) -> InstructionData {
+    let operands = match operation {
+        Operation::ENTER => vec![g.immediate(0, 0xFFFF), g.immediate(0, 0xFF)],
+        Operation::RET => vec![g.immediate(0, 0xFFFF)],
+        _ => vec![g.immediate(0, 0xFF)],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_no_operands(g: &mut Generator, operation: Operation) -> InstructionData {
+    g.insn(operation, vec![])
+}
+
+fn generate_implicit_string(g: &mut Generator, operation: Operation) -> InstructionData {
+    use Operation::*;
+
+    let has_source = matches!(
+        operation,
+        MOVSB
+            | MOVSW
+            | MOVSD
+            | CMPSB
+            | CMPSW
+            | CMPSD
+            | LODSB
+            | LODSW
+            | LODSD
+            | OUTSB
+            | OUTSW
+            | OUTSD
+    );
+
+    let operands = if has_source && g.random.one_in(2) {
+        vec![g.segment_register(&SEGMENTS)]
+    } else {
+        vec![]
+    };
+    g.insn(operation, operands)
+}
+
+/// Explicit operands are only needed for the address size that is not the default.
+fn generate_explicit_string(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Byte, Word, DWord]);
+    let data_size = data_size(size);
+    let segment = g.segment_override();
+
+    let (source, destination) = match g.bits {
+        ast::Bits::Bits16 => (
+            OperandData::indirect32(0..0, Some(6), None, 0, &data_size, &segment),
+            OperandData::indirect32(0..0, Some(7), None, 0, &data_size, &None),
+        ),
+        ast::Bits::Bits32 => (
+            OperandData::indirect(0..0, 4, 0, &data_size, &segment),
+            OperandData::indirect(0..0, 5, 0, &data_size, &None),
+        ),
+    };
+
+    let operands = match operation {
+        Operation::MOVS => vec![destination, source],
+        Operation::CMPS => vec![source, destination],
+        Operation::LODS => vec![source],
+        Operation::OUTS => vec![
+            OperandData::register(0..0, ast::WordRegister::Dx.encoding(), Word),
+            source,
+        ],
+        _ => vec![destination],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_in_out(g: &mut Generator, operation: Operation) -> InstructionData {
+    let size = g.size(&[Byte, Word, DWord]);
+    let accumulator = OperandData::register(0..0, 0, size);
+    let port = if g.random.one_in(2) {
+        OperandData::register(0..0, ast::WordRegister::Dx.encoding(), Word)
+    } else {
+        g.immediate(0, 0xFF)
+    };
+
+    let operands = match operation {
+        Operation::IN => vec![accumulator, port],
+        _ => vec![port, accumulator],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_xlatb(g: &mut Generator, operation: Operation) -> InstructionData {
+    let segment = g.segment_override();
+    let table = OperandData::indirect(
+        0..0,
+        ast::IndirectEncoding::Bx.encoding(),
+        0,
+        &Some(ast::DataSize::Word),
+        &segment,
+    );
+    g.insn(operation, vec![table])
+}
+
+fn generate_reg_memory(g: &mut Generator, operation: Operation) -> InstructionData {
+    let operands = vec![g.register(Word), g.memory(Unspecified)];
+    g.insn(operation, operands)
+}
+
+fn generate_system(g: &mut Generator, operation: Operation) -> InstructionData {
+    use Operation::*;
+
+    let operands = match operation {
+        SGDT | SIDT | LGDT | LIDT => vec![g.memory(Unspecified)],
+        LAR | LSL => {
+            let size = g.default_size();
+            vec![g.register(size), g.rm(Word, false)]
+        }
+        ARPL => vec![g.rm(Word, false), g.register(Word)],
+        _ => vec![g.rm(Word, false)],
+    };
+    g.insn(operation, operands)
+}
+
+/// Only external op codes that are not used by the 8087 are decoded as ESC.
+fn generate_esc(g: &mut Generator, operation: Operation) -> InstructionData {
+    let (external, rm) = if g.random.one_in(2) {
+        (
+            g.random.pick(&[0x09, 0x19, 0x1C, 0x1E, 0x29, 0x2D]),
+            g.memory(Unspecified),
+        )
+    } else {
+        (g.random.pick(&[0x0B, 0x39]), g.register(Word))
+    };
+    let operands = vec![OperandData::immediate(0..0, external), rm];
+    g.insn(operation, operands)
+}
+
+fn generate_fpu_arithmetic(g: &mut Generator, operation: Operation) -> InstructionData {
+    use Operation::*;
+
+    let st0 = OperandData::fpu_register(0..0, 0);
+
+    let operands = match operation {
+        FADDP | FMULP | FSUBP | FSUBRP | FDIVP | FDIVRP => vec![g.fpu_register(), st0],
+        FIADD | FIMUL | FICOM | FICOMP | FISUB | FISUBR | FIDIV | FIDIVR => {
+            let size = g.size(&[Word, DWord]);
+            vec![g.memory(size)]
+        }
+        _ if g.random.one_in(2) => {
+            let size = g.size(&[DWord, QWord]);
+            vec![g.memory(size)]
+        }
+        FCOM | FCOMP => vec![g.fpu_register()],
+        _ if g.random.one_in(2) => vec![st0, g.fpu_register()],
+        _ => vec![g.fpu_register(), st0],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_fpu_transfer(g: &mut Generator, operation: Operation) -> InstructionData {
+    use Operation::*;
+
+    let operands = match operation {
+        FLD | FSTP if g.random.one_in(2) => {
+            let size = g.size(&[DWord, QWord, TWord]);
+            vec![g.memory(size)]
+        }
+        FST if g.random.one_in(2) => {
+            let size = g.size(&[DWord, QWord]);
+            vec![g.memory(size)]
+        }
+        FLD | FST | FSTP | FXCH | FFREE => vec![g.fpu_register()],
+        FILD | FISTP => {
+            let size = g.size(&[Word, DWord, QWord]);
+            vec![g.memory(size)]
+        }
+        FIST => {
+            let size = g.size(&[Word, DWord]);
+            vec![g.memory(size)]
+        }
+        FBLD | FBSTP => vec![g.memory(TWord)],
+        FSTSW | FNSTSW if g.random.one_in(2) => vec![OperandData::register(0..0, 0, Word)],
+        _ => vec![g.memory(Unspecified)],
+    };
+    g.insn(operation, operands)
+}
+
+fn generate_v20(g: &mut Generator, operation: Operation) -> InstructionData {
+    use Operation::*;
+
+    let operands = match operation {
+        INS | EXT if g.random.one_in(2) => vec![g.register(Byte), g.register(Byte)],
+        INS | EXT => vec![g.register(Byte), g.immediate(0, 15)],
+        ROL4 | ROR4 => vec![g.rm(Byte, false)],
+        _ => {
+            let size = g.size(&[Byte, Word]);
+            let bit = if g.random.one_in(2) {
+                OperandData::register(0..0, ast::ByteRegister::Cl.encoding(), Byte)
+            } else if size == Byte {
+                g.immediate(0, 7)
+            } else {
+                g.immediate(0, 15)
+            };
+            vec![g.rm(size, true), bit]
+        }
+    };
+    g.insn(operation, operands)
+}
+
+/// Operands are equivalent if they encode the same way.  The size is only compared if both
+/// operands have one, because the decoder only adds sizes to memory operands when no other
+/// operand determines it.  Immediate values are compared in the operand size of the instruction,
+/// because the decoder does not know whether a value was written as signed or unsigned.
+fn is_equivalent_operand(expected: &OperandData, actual: &OperandData, size: OperandSize) -> bool {
+    let truncate = |value: i32| match size {
+        Byte => value as u8 as i32,
+        Word => value as u16 as i32,
+        _ => value,
+    };
+
+    expected.kind == actual.kind
+        && (expected.size == actual.size
+            || expected.size == Unspecified
+            || actual.size == Unspecified)
+        && expected.segment_prefix == actual.segment_prefix
+        && truncate(expected.imm) == truncate(actual.imm)
+        && expected.displacement == actual.displacement
+        && expected.displacement_size == actual.displacement_size
+        && expected.jmp_kind == actual.jmp_kind
+        && expected.mode == actual.mode
+        && expected.rm == actual.rm
+        && expected.sib == actual.sib
+        && expected.address_size == actual.address_size
+}
+
+fn is_equivalent(expected: &InstructionData, actual: &InstructionData) -> bool {
+    let count = expected.num_opers as usize;
+    let size = expected.operand_size();
+    let same_operands = |order: [usize; 3]| {
+        (0..count).all(|i| is_equivalent_operand(&expected.opers[i], &actual.opers[order[i]], size))
+    };
+
+    expected.operation == actual.operation
+        && expected.num_opers == actual.num_opers
+        && (same_operands([0, 1, 2])
+            // The operands of xchg can be in any order.
+            || (expected.operation == Operation::XCHG && same_operands([1, 0, 2])))
+}
+
+fn describe(insn: &InstructionData) -> String {
+    let operands = insn.opers[..insn.num_opers as usize]
+        .iter()
+        .map(|oper| {
+            format!(
+                "{:?} {:?} rm={} mode={} sib={:?} imm={:#X} disp={:#X} seg={:#04X}",
+                oper.kind,
+                oper.size,
+                oper.rm,
+                oper.mode,
+                oper.sib,
+                oper.imm,
+                oper.displacement,
+                oper.segment_prefix,
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "{:?} {:?} [{}]",
+        insn.bits,
+        insn.operation,
+        operands.join(", ")
+    )
+}
+
+fn assert_round_trip(expected: &InstructionData) {
+    let mut bytes = vec![];
+    if let Err(err) = encode(expected, ORIGIN, &mut bytes) {
+        panic!("{} does not encode: {}", describe(expected), err);
+    }
+
+    let mut decoder = Decoder::new(&bytes, ORIGIN);
+    decoder.set_bits(expected.bits);
+    let decoded = match decoder.next() {
+        Some(Ok(decoded)) => decoded,
+        Some(Err(err)) => panic!(
+            "{} encoded as {:02X?} does not decode: {}",
+            describe(expected),
+            bytes,
+            err
+        ),
+        None => panic!("{} does not encode to any bytes", describe(expected)),
+    };
+    assert_eq!(
+        bytes.len(),
+        decoded.size as usize,
+        "{} encoded as {:02X?} decoded as \"{}\"",
+        describe(expected),
+        bytes,
+        decoded.instruction
+    );
+
+    let actual = Compiler::default()
+        .build_instruction_data(&decoded.instruction, expected.minimum_cpu(), expected.bits)
+        .unwrap_or_else(|err| {
+            panic!(
+                "\"{}\" decoded from {:02X?} does not compile: {:?}",
+                decoded.instruction, bytes, err
+            )
+        });
+
+    assert!(
+        is_equivalent(expected, &actual),
+        "{} encoded as {:02X?} decoded as \"{}\", which is {}",
+        describe(expected),
+        bytes,
+        decoded.instruction,
+        describe(&actual)
+    );
+}
+
+#[test]
+fn round_trip() {
+    for bits in [ast::Bits::Bits16, ast::Bits::Bits32] {
+        let mut generator = Generator {
+            random: Random(SEED),
+            bits,
+        };
+
+        for (operations, generate, in_bits32) in GROUPS {
+            if bits == ast::Bits::Bits32 && !in_bits32 {
+                continue;
+            }
+
+            for operation in operations.iter() {
+                for _ in 0..ITERATIONS {
+                    assert_round_trip(&generate(&mut generator, *operation));
+                }
+            }
+        }
+    }
+}
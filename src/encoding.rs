@@ -0,0 +1,43 @@
+//! Conversion of source text to the bytes emitted in the output.  Source files are UTF-8, but
+//! the target machine expects text in code page 437, the character set of the original IBM PC.
+
+/// The characters for bytes 0x80 to 0xFF in code page 437.  Bytes below 0x80 are the same as
+/// ASCII.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}', //
+];
+
+/// Returns the code page 437 byte for the given character, or [None] if the character can not be
+/// represented.
+pub fn cp437_from_char(c: char) -> Option<u8> {
+    if c.is_ascii() {
+        Some(c as u8)
+    } else {
+        CP437_HIGH
+            .iter()
+            .position(|&high| high == c)
+            .map(|index| 0x80 + index as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cp437() {
+        assert_eq!(cp437_from_char('A'), Some(0x41));
+        assert_eq!(cp437_from_char('\n'), Some(0x0A));
+        assert_eq!(cp437_from_char('Ç'), Some(0x80));
+        assert_eq!(cp437_from_char('═'), Some(0xCD));
+        assert_eq!(cp437_from_char('\u{A0}'), Some(0xFF));
+        assert_eq!(cp437_from_char('€'), None);
+    }
+}
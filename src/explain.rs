@@ -0,0 +1,879 @@
+//! Long-form explanations for the error codes reported by the parser, compiler and encoder.
+//!
+//! Every error has a stable code (see [crate::CompileError::code]) that is included in the
+//! diagnostics.  The explanation for a code describes the error, shows source that causes it and
+//! how to fix it.
+
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "E0001",
+        r#"A specific token was expected, but something else was found.
+
+Erroneous code example:
+
+```asm
+mov ax bx
+```
+
+The parser expected the token named in the message, in this case the comma that separates the
+operands of an instruction:
+
+```asm
+mov ax, bx
+```
+"#,
+    ),
+    (
+        "E0002",
+        r#"A line does not start with a label, an instruction or a directive.
+
+Erroneous code example:
+
+```asm
+42
+```
+
+Each line can start with a label, followed by an instruction, a data definition or a directive:
+
+```asm
+answer: db 42
+```
+"#,
+    ),
+    (
+        "E0003",
+        r#"An instruction that takes two operands only has one.
+
+This error is not reported at the moment, a missing operand is reported as E0004.
+
+Erroneous code example:
+
+```asm
+mov ax,
+```
+
+Add the second operand after the comma:
+
+```asm
+mov ax, 1
+```
+"#,
+    ),
+    (
+        "E0004",
+        r#"An operand was expected, but something else was found.
+
+Erroneous code example:
+
+```asm
+push ,
+```
+
+Operands can be registers, segments, memory references or expressions:
+
+```asm
+push ax
+```
+"#,
+    ),
+    (
+        "E0005",
+        r#"An operator that only takes two values was used in front of a single value.
+
+Erroneous code example:
+
+```asm
+mov ax, * 2
+```
+
+The prefix operators are `-`, `+`, `~` and `!`.  Other operators need a value on both sides:
+
+```asm
+mov ax, 3 * 2
+```
+"#,
+    ),
+    (
+        "E0006",
+        r#"A data definition does not contain any values.
+
+Erroneous code example:
+
+```asm
+db
+```
+
+Add at least one value, string or character constant.  Use `resb` and friends to reserve space
+without initializing it:
+
+```asm
+db 0
+```
+"#,
+    ),
+    (
+        "E0007",
+        r#"A memory reference does not contain an address or a segment override.
+
+Erroneous code example:
+
+```asm
+mov ax, [,]
+```
+
+A memory reference contains an optional segment override, registers and a displacement:
+
+```asm
+mov ax, [cs:0x10]
+```
+"#,
+    ),
+    (
+        "E0008",
+        r#"The registers of a memory reference can not be encoded.
+
+Erroneous code example:
+
+```asm
+mov ax, [cx]
+```
+
+16-bit addresses can only be formed with BX+SI, BX+DI, BP+SI, BP+DI, SI, DI, BX or BP, optionally
+followed by a displacement:
+
+```asm
+mov ax, [bx]
+```
+"#,
+    ),
+    (
+        "E0009",
+        r#"A string literal is not closed before the end of the line.
+
+Erroneous code example:
+
+```asm
+db 'abc
+```
+
+Close the string with the same quote that it was opened with:
+
+```asm
+db 'abc'
+```
+"#,
+    ),
+    (
+        "E0010",
+        r#"A backquoted string contains an escape sequence that is not known.
+
+Erroneous code example:
+
+```asm
+db `\q`
+```
+
+Backquoted strings support the escapes `\'`, `\"`, `` \` ``, `\\`, `\?`, `\a`, `\b`, `\t`, `\n`,
+`\v`, `\f`, `\r`, `\e`, octal escapes like `\101` and hex escapes like `\x41`:
+
+```asm
+db `\x41`
+```
+"#,
+    ),
+    (
+        "E0011",
+        r#"A string contains a character that does not exist in code page 437.
+
+Erroneous code example:
+
+```asm
+db '€'
+```
+
+Strings are stored in code page 437, the character set of the IBM PC.  Use a character that it
+contains, or write the byte value directly:
+
+```asm
+db 0xEE
+```
+"#,
+    ),
+    (
+        "E0012",
+        r#"A character constant used as a number has more than 8 characters.
+
+Erroneous code example:
+
+```asm
+mov ax, 'abcdefghi'
+```
+
+Character constants are stored in a 64-bit value, so they can contain at most 8 characters:
+
+```asm
+mov ax, 'ab'
+```
+"#,
+    ),
+    (
+        "E0013",
+        r#"A number literal contains digits that are not valid for its base.
+
+Erroneous code example:
+
+```asm
+mov ax, 0b102
+```
+
+Binary numbers only contain the digits 0 and 1, octal numbers 0 to 7 and hexadecimal numbers 0 to 9
+and A to F.  A hexadecimal number with a trailing `h` has to start with a digit:
+
+```asm
+mov ax, 0b101
+```
+"#,
+    ),
+    (
+        "E0014",
+        r#"A number literal does not fit into 64 bits.
+
+Erroneous code example:
+
+```asm
+mov ax, 0x1234567890abcdef0
+```
+
+All expressions are calculated with 64-bit values, so literals must fit into 64 bits:
+
+```asm
+mov ax, 0x1234
+```
+"#,
+    ),
+    (
+        "E0015",
+        r#"A segment prefix is used on an instruction without exactly one memory operand.
+
+Erroneous code example:
+
+```asm
+es nop
+```
+
+Write the segment override inside the memory operand, or use the prefix on an instruction with a
+single memory operand:
+
+```asm
+es inc word [bx]
+```
+"#,
+    ),
+    (
+        "E0016",
+        r#"A line starts with a name that is not an instruction or directive, followed by operands.
+
+Erroneous code example:
+
+```asm
+mvo ax, 1
+```
+
+A name that is not an instruction is a label, which can only be followed by a colon or an
+instruction.  When the name is a misspelled instruction, the message suggests the closest match:
+
+```asm
+mov ax, 1
+```
+"#,
+    ),
+    (
+        "E0017",
+        r#"A keyword of a structure block is used outside of the block.
+
+Erroneous code example:
+
+```asm
+buffer resb 16
+```
+
+`resb`, `resw`, `resd`, `resq`, `rest` and `endstruc` are only valid inside `struc`, and `at` and
+`iend` only inside `istruc`.  Reserve space in a structure, or define data with `times`:
+
+```asm
+buffer: times 16 db 0
+```
+"#,
+    ),
+    (
+        "E0101",
+        r#"The operands are not valid for the instruction.
+
+This error is not reported at the moment, invalid operands are reported as E0201.
+
+Erroneous code example:
+
+```asm
+mov 1, ax
+```
+
+Check which combinations of registers, memory and immediate values the instruction supports:
+
+```asm
+mov ax, 1
+```
+"#,
+    ),
+    (
+        "E0102",
+        r#"A label or structure that is used is never defined.
+
+Erroneous code example:
+
+```asm
+p: istruc missing
+iend
+```
+
+Define the structure with `struc` and `endstruc` before creating an instance of it:
+
+```asm
+struc missing
+    .x: resw 1
+endstruc
+p: istruc missing
+iend
+```
+"#,
+    ),
+    (
+        "E0103",
+        r#"The value of a constant depends on something that is not known while assembling.
+
+This error is not reported at the moment.
+
+Erroneous code example:
+
+```asm
+size equ [bx]
+```
+
+Constants can only contain numbers, other constants and labels:
+
+```asm
+size equ 10
+```
+"#,
+    ),
+    (
+        "E0104",
+        r#"The value of a constant refers to a label, where a plain number is required.
+
+This error is not reported at the moment.
+
+Erroneous code example:
+
+```asm
+start:
+count equ start
+```
+
+Use a number or another constant:
+
+```asm
+count equ 10
+```
+"#,
+    ),
+    (
+        "E0105",
+        r#"A constant is declared with `equ`, but does not have a name.
+
+Erroneous code example:
+
+```asm
+equ 10
+```
+
+Put a label in front of `equ`, which becomes the name of the constant:
+
+```asm
+ten equ 10
+```
+"#,
+    ),
+    (
+        "E0106",
+        r#"A value does not fit into the space that is available for it.
+
+Erroneous code example:
+
+```asm
+db 0x100
+```
+
+Use a smaller value or a larger data size:
+
+```asm
+dw 0x100
+```
+"#,
+    ),
+    (
+        "E0107",
+        r#"A label that is used is never defined.
+
+Erroneous code example:
+
+```asm
+jmp missing
+```
+
+Define the label, or check the spelling.  Local labels starting with a `.` are only visible after
+the global label they belong to:
+
+```asm
+jmp missing
+missing:
+```
+"#,
+    ),
+    (
+        "E0108",
+        r#"The size of a data operand can not be determined.
+
+This error is not reported at the moment, operand sizes are checked by the encoder and reported as
+E0202 or E0204.
+
+Erroneous code example:
+
+```asm
+inc [bx]
+```
+
+Add the size to the memory operand:
+
+```asm
+inc word [bx]
+```
+"#,
+    ),
+    (
+        "E0109",
+        r#"An expression divides by zero.
+
+Erroneous code example:
+
+```asm
+mov ax, 10 / 0
+```
+
+This applies to `/`, `//`, `%` and `%%`.  Check the value of the divisor:
+
+```asm
+mov ax, 10 / 2
+```
+"#,
+    ),
+    (
+        "E0110",
+        r#"The segment of a label is requested, but the base segment of the output is not known.
+
+Erroneous code example:
+
+```asm
+mov ax, seg start
+start:
+```
+
+`seg` can only be resolved when the output is loaded at a known segment.  Without one, load the
+segment from a register instead:
+
+```asm
+mov ax, cs
+start:
+```
+"#,
+    ),
+    (
+        "E0111",
+        r#"`seg` or `wrt` is used on an expression that does not contain a label.
+
+Erroneous code example:
+
+```asm
+mov ax, seg 10
+```
+
+Only labels have a segment, plain numbers do not:
+
+```asm
+mov ax, 10
+```
+"#,
+    ),
+    (
+        "E0112",
+        r#"`sizeof` or `lengthof` is used on something that is not a label of a data definition.
+
+Erroneous code example:
+
+```asm
+mov ax, sizeof start
+start:
+    nop
+```
+
+Put the label on a data definition:
+
+```asm
+mov ax, sizeof message
+message: db 'Hello'
+```
+"#,
+    ),
+    (
+        "E0113",
+        r#"A field of a structure instance overlaps the previous field.
+
+Erroneous code example:
+
+```asm
+struc point
+    .x: resw 1
+    .y: resw 1
+endstruc
+p: istruc point
+    at point.y, dw 1
+    at point.x, dw 2
+iend
+```
+
+The fields of an `istruc` must be given in the order of the structure and their data must fit
+before the next field:
+
+```asm
+struc point
+    .x: resw 1
+    .y: resw 1
+endstruc
+p: istruc point
+    at point.x, dw 2
+    at point.y, dw 1
+iend
+```
+"#,
+    ),
+    (
+        "E0114",
+        r#"The data of a structure instance is larger than the structure.
+
+Erroneous code example:
+
+```asm
+struc point
+    .x: resw 1
+endstruc
+p: istruc point
+    at point.x, dw 1, 2
+iend
+```
+
+Only put as much data in a field as the structure reserves for it:
+
+```asm
+struc point
+    .x: resw 1
+endstruc
+p: istruc point
+    at point.x, dw 1
+iend
+```
+"#,
+    ),
+    (
+        "E0115",
+        r#"An alignment is not a power of two.
+
+Erroneous code example:
+
+```asm
+align 3
+```
+
+`align` and `alignb` pad the output to a multiple of a power of two:
+
+```asm
+align 4
+```
+"#,
+    ),
+    (
+        "E0116",
+        r#"An instruction is used that the selected CPU does not support.
+
+Erroneous code example:
+
+```asm
+pusha
+```
+
+The default CPU is the 8086.  Select a CPU that supports the instruction with the `cpu` directive:
+
+```asm
+cpu 186
+pusha
+```
+"#,
+    ),
+    (
+        "E0117",
+        r#"The offsets of labels keep changing, because a short jump only fits while it takes no space.
+
+Erroneous code example:
+
+```asm
+jcxz target
+times 128 nop
+target:
+```
+
+A jump that is out of range takes no space while the offsets are worked out, which brings its
+target in range, after which it takes space again.  Move the jump closer to its target:
+
+```asm
+jcxz target
+times 127 nop
+target:
+```
+"#,
+    ),
+    (
+        "E0118",
+        r#"A structure, or a repeated structure instance, is larger than 65535 bytes.
+
+Erroneous code example:
+
+```asm
+struc buffer
+    .data: resw 0x8000
+endstruc
+```
+
+Structures and their instances have to fit in a segment.  Reserve less space:
+
+```asm
+struc buffer
+    .data: resw 0x4000
+endstruc
+```
+"#,
+    ),
+    (
+        "E0201",
+        r#"The instruction can not be encoded with this combination of operands.
+
+Erroneous code example:
+
+```asm
+mov cs, ax
+```
+
+Check which combinations of registers, memory and immediate values the instruction supports.  The
+CS register can not be loaded with `mov`, use a far jump instead:
+
+```asm
+mov ds, ax
+```
+"#,
+    ),
+    (
+        "E0202",
+        r#"The size of an operand can not be determined from the instruction.
+
+Erroneous code example:
+
+```asm
+mov [bx], 1
+```
+
+When no register operand tells the size, add it to the memory operand:
+
+```asm
+mov word [bx], 1
+```
+"#,
+    ),
+    (
+        "E0203",
+        r#"The operands of an instruction have different sizes.
+
+Erroneous code example:
+
+```asm
+mov ax, bl
+```
+
+Use operands of the same size:
+
+```asm
+mov ax, bx
+```
+"#,
+    ),
+    (
+        "E0204",
+        r#"An operand has a size that the instruction does not support.
+
+Erroneous code example:
+
+```asm
+mov ax, qword [bx]
+```
+
+`qword` and `tword` operands can only be used with FPU instructions:
+
+```asm
+mov ax, word [bx]
+```
+"#,
+    ),
+    (
+        "E0205",
+        r#"An immediate value does not fit into the operand.
+
+Erroneous code example:
+
+```asm
+int 0x100
+```
+
+Use a value in the range of the operand, for example 0 to 255 for a byte:
+
+```asm
+int 0x21
+```
+"#,
+    ),
+    (
+        "E0206",
+        r#"The target of a relative jump is too far away.
+
+Erroneous code example:
+
+```asm
+target:
+times 200 nop
+jcxz target
+```
+
+Instructions like `jcxz` and `loop` only have a signed byte offset, which reaches 128 bytes back and
+127 bytes forward.  Move the target closer, or jump over a near jump:
+
+```asm
+target:
+times 200 nop
+jcxz near_target
+jmp done
+near_target:
+jmp target
+done:
+```
+"#,
+    ),
+    (
+        "E0207",
+        r#"The segment of an operand can not be overridden.
+
+Erroneous code example:
+
+```asm
+ds stosb
+```
+
+String instructions always write to ES:DI, only the source segment of `movs`, `cmps`, `lods` and
+`outs` can be overridden:
+
+```asm
+ds lodsb
+```
+"#,
+    ),
+];
+
+/// The long-form explanation for an error code, like "E0012".
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+/// All error codes that have an explanation, in order.
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Codes for errors that are not reported by the compiler at the moment, so the examples can
+    /// not be checked.
+    const UNVERIFIED: &[&str] = &["E0003", "E0101", "E0103", "E0104", "E0108"];
+
+    fn examples(explanation: &str) -> Vec<String> {
+        explanation
+            .split("```asm\n")
+            .skip(1)
+            .map(|block| block.split("```").next().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn lookup() {
+        assert!(explain("E0012")
+            .unwrap()
+            .starts_with("A character constant"));
+        assert_eq!(explain("e0012"), explain("E0012"));
+        assert_eq!(explain("E9999"), None);
+        assert_eq!(codes().count(), EXPLANATIONS.len());
+    }
+
+    #[test]
+    fn every_code_is_explained() {
+        let expected = (1..=17)
+            .chain(101..=118)
+            .chain(201..=207)
+            .map(|number| format!("E{:04}", number))
+            .collect::<Vec<_>>();
+        assert_eq!(codes().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn codes_are_sorted_and_unique() {
+        let codes = codes().collect::<Vec<_>>();
+        assert!(codes.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn examples_report_their_code() {
+        for (code, explanation) in EXPLANATIONS {
+            let examples = examples(explanation);
+            assert_eq!(examples.len(), 2, "{} should have two examples", code);
+
+            if UNVERIFIED.contains(code) {
+                continue;
+            }
+
+            match crate::compile(&examples[0]) {
+                Err(err) => assert_eq!(err.code(), *code, "{}", err),
+                Ok(_) => panic!("the example for {} compiles", code),
+            }
+
+            if let Err(err) = crate::compile(&examples[1]) {
+                panic!("the fixed example for {} does not compile: {}", code, err);
+            }
+        }
+    }
+}
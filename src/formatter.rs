@@ -0,0 +1,593 @@
+//! Format source code into columns: labels at the start of the line, then the mnemonic, the
+//! operands and the comment.  Keywords and registers are written in lower case and numbers with a
+//! base prefix, e.g. "0FFh" becomes "0xFF".
+//!
+//! The formatter works on the tokens of each line in the [SyntaxTree], so comments and the
+//! spelling of labels and strings are kept.  The lines of the AST are only used to find out which
+//! identifiers are labels.  To make sure formatting never changes the meaning of the source, the
+//! lines of the original and the formatted source are compared, using their
+//! [Display](std::fmt::Display) implementations.
+
+use crate::ast;
+use crate::lexer::{LiteralKind, PunctuationKind, Token};
+use crate::operations::Operation;
+use crate::parser::{ParserError, DIRECTIVES};
+use crate::syntax::SyntaxTree;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The columns that the parts of a line are aligned to.  If a part does not fit before the column,
+/// it is separated from the part before it by a single space.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub mnemonic_column: usize,
+    pub operand_column: usize,
+    pub comment_column: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            mnemonic_column: 8,
+            operand_column: 16,
+            comment_column: 40,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    /// The source has to parse before it can be formatted.
+    ParserError(ParserError),
+    /// The formatted line does not mean the same as the original line.  This is a bug in the
+    /// formatter, the span is the line in the original source.
+    ChangedMeaning(ast::Span),
+}
+
+impl FormatError {
+    pub fn span(&self) -> &ast::Span {
+        match self {
+            FormatError::ParserError(err) => err.span(),
+            FormatError::ChangedMeaning(span) => span,
+        }
+    }
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::ParserError(err) => write!(f, "{}", err),
+            FormatError::ChangedMeaning(_) => {
+                write!(f, "Formatting would change the meaning of the line.")
+            }
+        }
+    }
+}
+
+/// Keywords besides instructions, directives, registers and data sizes.
+const KEYWORDS: &[&str] = &[
+    "at", "endstruc", "iend", "resb", "resw", "resd", "resq", "rest", "st", "seg", "wrt", "low",
+    "high", "sizeof", "lengthof",
+];
+
+/// Keywords that are written directly in front of an opening parenthesis, e.g. "st(1)".
+const FUNCTIONS: &[&str] = &["st", "seg", "low", "high", "sizeof", "lengthof"];
+
+/// Prefixes that are written in the mnemonic column together with the instruction they apply to.
+const PREFIXES: &[&str] = &["rep", "repe", "repz", "repne", "repnz", "lock"];
+
+/// Format the source.  Returns an error if the source does not parse.
+pub fn format(source: &str, options: &Options) -> Result<String, FormatError> {
+    let mut tree = SyntaxTree::parse(source);
+    if !tree.errors.is_empty() {
+        return Err(FormatError::ParserError(tree.errors.swap_remove(0)));
+    }
+    let labels = Labels::new(tree.lines());
+
+    let mut result = String::new();
+    let mut blank = false;
+
+    for line in split_lines(&tree) {
+        // Consecutive blank lines are reduced to one and blank lines at the start and end of the
+        // source are removed.
+        if line.pieces.is_empty() {
+            blank = !result.is_empty();
+            continue;
+        }
+        if blank {
+            result.push('\n');
+            blank = false;
+        }
+
+        result += &labels.format_line(&line, options);
+        result.push('\n');
+    }
+
+    let formatted = SyntaxTree::parse(&result);
+    let changed = tree
+        .lines()
+        .map(Some)
+        .chain(std::iter::once(None))
+        .zip(formatted.lines().map(Some).chain(std::iter::repeat(None)))
+        .find(|(original, formatted)| {
+            original.map(ToString::to_string) != formatted.map(ToString::to_string)
+        });
+    match changed {
+        Some((Some(line), _)) => Err(FormatError::ChangedMeaning(line.span().clone())),
+        Some((None, _)) => Err(FormatError::ChangedMeaning(source.len()..source.len())),
+        None => Ok(result),
+    }
+}
+
+/// Returns true if formatting would not change the source.
+pub fn is_formatted(source: &str, options: &Options) -> Result<bool, FormatError> {
+    Ok(format(source, options)? == source)
+}
+
+/// A token with its position and text in the source.
+struct Piece<'a> {
+    token: Token,
+    start: usize,
+    text: &'a str,
+}
+
+struct SourceLine<'a> {
+    start: usize,
+    /// The tokens on the line, without whitespace.
+    pieces: Vec<Piece<'a>>,
+}
+
+fn split_lines<'a>(tree: &SyntaxTree<'a>) -> Vec<SourceLine<'a>> {
+    let mut lines = vec![SourceLine {
+        start: 0,
+        pieces: vec![],
+    }];
+
+    for token in tree.tokens() {
+        match token.token {
+            Token::Whitespace(_) => {}
+            Token::NewLine(_) => lines.push(SourceLine {
+                start: token.span.end,
+                pieces: vec![],
+            }),
+            _ => lines.last_mut().unwrap().pieces.push(Piece {
+                token: token.token.clone(),
+                start: token.span.start,
+                text: tree.text(&token.span),
+            }),
+        }
+    }
+
+    lines
+}
+
+/// The labels defined in the source.
+struct Labels<'a> {
+    /// Where each label that starts a line is defined.
+    starts: HashSet<usize>,
+    /// The names of all labels and structures, which are never changed to lower case even if they
+    /// are spelled like a keyword.
+    names: HashSet<&'a str>,
+}
+
+impl<'a> Labels<'a> {
+    fn new(lines: impl Iterator<Item = &'a ast::Line>) -> Self {
+        let mut labels = Self {
+            starts: HashSet::new(),
+            names: HashSet::new(),
+        };
+
+        for line in lines {
+            match line {
+                ast::Line::Label(label) => labels.define(label),
+                ast::Line::Struct(_, name, fields) => {
+                    labels.names.insert(&name.1);
+                    for ast::StructField(_, label, ..) in fields {
+                        if let Some(label) = label {
+                            labels.define(label);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        labels
+    }
+
+    fn define(&mut self, label: &'a ast::Label) {
+        self.starts.insert(label.0.start);
+        self.names.insert(&label.1);
+    }
+
+    fn format_line(&self, line: &SourceLine, options: &Options) -> String {
+        let (code, comment) = match line.pieces.split_last() {
+            Some((last, code)) if matches!(last.token, Token::Comment(_)) => (code, Some(last)),
+            _ => (&line.pieces[..], None),
+        };
+
+        let mut result = String::new();
+
+        // A comment on its own line stays at the start of the line or is indented like an
+        // instruction.
+        if code.is_empty() {
+            if let Some(comment) = comment {
+                if comment.start != line.start {
+                    pad(&mut result, options.mnemonic_column);
+                }
+                result += comment.text.trim_end();
+            }
+            return result;
+        }
+
+        let mut index = 0;
+
+        while index < code.len() && self.starts.contains(&code[index].start) {
+            if index > 0 {
+                result.push(' ');
+            }
+            result += code[index].text;
+            index += 1;
+
+            if let Some(Token::Punctuation(_, PunctuationKind::Colon)) =
+                code.get(index).map(|piece| &piece.token)
+            {
+                result.push(':');
+                index += 1;
+            }
+        }
+
+        if index < code.len() {
+            let mut mnemonic = self.text(&code[index]).into_owned();
+            let mut last = self.text(&code[index]);
+            index += 1;
+
+            // Prefixes can follow each other, e.g. "es rep movsb".
+            while index < code.len()
+                && (PREFIXES.contains(&last.as_ref()) || ast::Segment::from_str(&last).is_ok())
+                && matches!(code[index].token, Token::Identifier(_))
+                && Operation::from_str(code[index].text).is_ok()
+            {
+                last = self.text(&code[index]);
+                mnemonic.push(' ');
+                mnemonic += &last;
+                index += 1;
+            }
+
+            pad(&mut result, options.mnemonic_column);
+            result += &mnemonic;
+
+            if index < code.len() {
+                pad(&mut result, options.operand_column);
+                result += &self.operands(&code[index..]);
+            }
+        }
+
+        if let Some(comment) = comment {
+            pad(&mut result, options.comment_column);
+            result += comment.text.trim_end();
+        }
+
+        result
+    }
+
+    /// Join the operand tokens with a space after each comma and around binary operators.
+    fn operands(&self, pieces: &[Piece]) -> String {
+        let mut result = String::new();
+        let mut previous: Option<(&Piece, bool)> = None;
+
+        for piece in pieces {
+            let unary = is_operator(&piece.token)
+                && previous.is_none_or(|(previous, _)| self.is_operand_expected_after(previous));
+
+            if let Some((previous, previous_unary)) = previous {
+                if needs_space(previous, previous_unary, piece, unary) {
+                    result.push(' ');
+                }
+            }
+
+            result += &self.text(piece);
+            previous = Some((piece, unary));
+        }
+
+        result
+    }
+
+    /// Returns true if an operator after the piece is a prefix operator, e.g. the "-" in "+ -1" or
+    /// "db -1".
+    fn is_operand_expected_after(&self, piece: &Piece) -> bool {
+        match piece.token {
+            Token::Punctuation(_, PunctuationKind::CloseBracket)
+            | Token::Punctuation(_, PunctuationKind::CloseParenthesis) => false,
+            Token::Punctuation(..) => true,
+            Token::Identifier(_) => {
+                self.is_keyword(piece.text)
+                    && ast::Register::from_str(piece.text).is_err()
+                    && ast::Segment::from_str(piece.text).is_err()
+                    && !piece.text.eq_ignore_ascii_case("st")
+            }
+            _ => false,
+        }
+    }
+
+    fn is_keyword(&self, identifier: &str) -> bool {
+        if self.names.contains(identifier) {
+            return false;
+        }
+
+        let lower = identifier.to_lowercase();
+        Operation::from_str(identifier).is_ok()
+            || ast::Register::from_str(identifier).is_ok()
+            || ast::Segment::from_str(identifier).is_ok()
+            || ast::DataSize::from_str(identifier).is_ok()
+            || DIRECTIVES.contains(&lower.as_str())
+            || KEYWORDS.contains(&lower.as_str())
+    }
+
+    /// The normalized text of a token.
+    fn text<'p>(&self, piece: &Piece<'p>) -> Cow<'p, str> {
+        match piece.token {
+            Token::Identifier(_) if self.is_keyword(piece.text) => {
+                Cow::Owned(piece.text.to_lowercase())
+            }
+            Token::Literal(_, LiteralKind::Number(value)) => {
+                normalize_number(piece.text, value).map_or(Cow::Borrowed(piece.text), Cow::Owned)
+            }
+            _ => Cow::Borrowed(piece.text),
+        }
+    }
+}
+
+fn is_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Punctuation(_, kind) if !matches!(
+            kind,
+            PunctuationKind::Colon
+                | PunctuationKind::Comma
+                | PunctuationKind::Dot
+                | PunctuationKind::OpenBracket
+                | PunctuationKind::CloseBracket
+                | PunctuationKind::OpenParenthesis
+                | PunctuationKind::CloseParenthesis
+        )
+    )
+}
+
+fn needs_space(previous: &Piece, previous_unary: bool, next: &Piece, next_unary: bool) -> bool {
+    use PunctuationKind::*;
+
+    match (&previous.token, &next.token) {
+        (_, Token::Punctuation(_, Comma | CloseBracket | CloseParenthesis | Colon)) => false,
+        (Token::Punctuation(_, OpenBracket | OpenParenthesis | Colon), _) => false,
+        (Token::Punctuation(_, Comma), _) => true,
+        _ if previous_unary => false,
+        _ if is_operator(&next.token) && !next_unary => true,
+        _ if is_operator(&previous.token) => true,
+        (Token::Identifier(_), Token::Punctuation(_, OpenParenthesis)) => !FUNCTIONS
+            .iter()
+            .any(|function| previous.text.eq_ignore_ascii_case(function)),
+        _ => true,
+    }
+}
+
+/// Add spaces up to the column, or a single space if the text is already past it.
+fn pad(text: &mut String, column: usize) {
+    let width = text.chars().count();
+    if width < column {
+        text.extend(std::iter::repeat_n(' ', column - width));
+    } else if width > 0 {
+        text.push(' ');
+    }
+}
+
+/// Write a number with a base prefix, in upper case if it is hexadecimal.  Decimal numbers have no
+/// prefix.  Returns [None] if the spelling of the number is not recognized.
+fn normalize_number(text: &str, value: i64) -> Option<String> {
+    let radix_of = |c: char| match c.to_ascii_lowercase() {
+        'b' | 'y' => Some(2),
+        'o' | 'q' => Some(8),
+        'd' | 't' => Some(10),
+        'h' | 'x' => Some(16),
+        _ => None,
+    };
+
+    // The spellings the lexer accepts, in the order it tries them.  The digits of a number with a
+    // suffix start with a "0" if the first digit is a letter, which is not needed with a prefix.
+    let mut candidates = vec![];
+    if let Some(digits) = text.strip_prefix('$') {
+        candidates.push((digits, 16, false));
+    }
+    if let (Some("0"), Some(radix)) = (text.get(..1), text[1..].chars().next().and_then(radix_of)) {
+        candidates.push((&text[2..], radix, false));
+    }
+    if let Some(radix) = text.chars().last().and_then(radix_of) {
+        candidates.push((&text[..text.len() - 1], radix, true));
+    }
+    candidates.push((text, 10, false));
+
+    let (digits, radix, suffix) = candidates.into_iter().find(|(digits, radix, _)| {
+        u64::from_str_radix(&digits.replace('_', ""), *radix).ok() == Some(value as u64)
+    })?;
+
+    Some(match radix {
+        2 => format!("0b{}", digits),
+        8 => format!("0o{}", digits),
+        16 => {
+            let digits = match digits.strip_prefix('0') {
+                Some(rest) if suffix && rest.starts_with(|c: char| c.is_ascii_alphabetic()) => rest,
+                _ => digits,
+            };
+            format!("0x{}", digits.to_uppercase())
+        }
+        _ => digits.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_default(source: &str) -> String {
+        format(source, &Options::default()).unwrap()
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(
+            format_default("start:\n  mov ax,bx ; copy\nlabel: nop\n\n\n\n; done\n\n"),
+            concat!(
+                "start:\n",
+                "        mov     ax, bx                  ; copy\n",
+                "label:  nop\n",
+                "\n",
+                "; done\n",
+            )
+        );
+
+        assert_eq!(
+            format_default("\n\n  ; indented\r\nvery_long_label: nop\nten equ 10\n"),
+            concat!(
+                "        ; indented\n",
+                "very_long_label: nop\n",
+                "ten     equ     10\n",
+            )
+        );
+
+        assert_eq!(format_default(""), "");
+        assert_eq!(format_default("\n \n"), "");
+    }
+
+    #[test]
+    fn options() {
+        let options = Options {
+            mnemonic_column: 4,
+            operand_column: 10,
+            comment_column: 0,
+        };
+        assert_eq!(
+            format("a: mov ax, 1 ; one\n", &options).unwrap(),
+            "a:  mov   ax, 1 ; one\n"
+        );
+    }
+
+    #[test]
+    fn case_and_numbers() {
+        assert_eq!(
+            format_default("MOV AX, Word [ES:BX]\nLoop Start\nStart: DB 0FFh, $1f, 0b101, 0Q17, 12d, 0x0a, 1_000\n"),
+            concat!(
+                "        mov     ax, word [es:bx]\n",
+                "        loop    Start\n",
+                "Start:  db      0xFF, 0x1F, 0b101, 0o17, 12, 0x0A, 1_000\n",
+            )
+        );
+
+        // Labels that are spelled like keywords keep their case.
+        assert_eq!(format_default("Word: dw Word\n"), "Word:   dw      Word\n");
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            format_default("mov ax,[bx+si-2]\nmov ax,-(1+2)*3\ndw -1,~0\nfadd st0,st ( 1 )\n"),
+            concat!(
+                "        mov     ax, [bx + si - 2]\n",
+                "        mov     ax, -(1 + 2) * 3\n",
+                "        dw      -1, ~0\n",
+                "        fadd    st0, st(1)\n",
+            )
+        );
+
+        assert_eq!(
+            format_default("a: jmp 0x10 : 0x20\nmov ax,seg a+HIGH ( a )\nmov al,es : [a]\n"),
+            concat!(
+                "a:      jmp     0x10:0x20\n",
+                "        mov     ax, seg a + high(a)\n",
+                "        mov     al, es:[a]\n",
+            )
+        );
+    }
+
+    #[test]
+    fn prefixes_and_blocks() {
+        const SOURCE: &str = concat!(
+            "rep movsb\n",
+            "es lodsb\n",
+            "ES  REP MOVSB\n",
+            "times 2 db 'a;b'\n",
+            "struc point\n",
+            ".x: resw 1\n",
+            ".y: RESW 1\n",
+            "endstruc\n",
+            "p: istruc point\n",
+            "at point.y,dw 1\n",
+            "iend\n",
+        );
+        assert_eq!(
+            format_default(SOURCE),
+            concat!(
+                "        rep movsb\n",
+                "        es lodsb\n",
+                "        es rep movsb\n",
+                "        times   2 db 'a;b'\n",
+                "        struc   point\n",
+                ".x:     resw    1\n",
+                ".y:     resw    1\n",
+                "        endstruc\n",
+                "p:      istruc  point\n",
+                "        at      point.y, dw 1\n",
+                "        iend\n",
+            )
+        );
+    }
+
+    #[test]
+    fn same_output() {
+        for source in [
+            include_str!("../tests/calljmp.asm"),
+            include_str!("../tests/ea.asm"),
+            include_str!("../tests/each.asm"),
+            include_str!("../tests/group1.asm"),
+            include_str!("../tests/imul.asm"),
+            include_str!("../tests/incdec.asm"),
+        ] {
+            let formatted = format_default(source);
+            assert_eq!(
+                crate::compile(&formatted).map_err(|err| err.code()),
+                crate::compile(source).map_err(|err| err.code())
+            );
+            assert_eq!(format_default(&formatted), formatted);
+        }
+    }
+
+    #[test]
+    fn check() {
+        let options = Options::default();
+        assert!(is_formatted("        nop\n", &options).unwrap());
+        assert!(!is_formatted("nop\n", &options).unwrap());
+        assert!(!is_formatted("        nop", &options).unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        let err = format("mov ax,\n", &Options::default()).unwrap_err();
+        assert!(matches!(err, FormatError::ParserError(_)));
+        assert_eq!(err.span(), &(7..8));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(normalize_number("0ffh", 255).as_deref(), Some("0xFF"));
+        assert_eq!(normalize_number("0100h", 256).as_deref(), Some("0x0100"));
+        assert_eq!(normalize_number("0b1h", 0xB1).as_deref(), Some("0xB1"));
+        assert_eq!(normalize_number("0x0a", 10).as_deref(), Some("0x0A"));
+        assert_eq!(normalize_number("$ff", 255).as_deref(), Some("0xFF"));
+        assert_eq!(normalize_number("0y11", 3).as_deref(), Some("0b11"));
+        assert_eq!(normalize_number("17q", 15).as_deref(), Some("0o17"));
+        assert_eq!(normalize_number("0d10", 10).as_deref(), Some("10"));
+        assert_eq!(normalize_number("10t", 10).as_deref(), Some("10"));
+        assert_eq!(normalize_number("42", 42).as_deref(), Some("42"));
+    }
+}
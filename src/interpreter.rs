@@ -0,0 +1,1742 @@
+//! An interpreter for 8086 machine code, so tests can check what assembled code does and not only
+//! which bytes it assembles to.  Instructions are decoded with the [Decoder] and executed one at a
+//! time until `HLT`.
+
+use crate::ast;
+use crate::decoder::Decoder;
+use crate::operations::Operation;
+use std::fmt::Display;
+
+/// The size of the address space of the 8086.
+pub const MEMORY_SIZE: usize = 0x10_0000;
+
+/// The segment [run] loads programs at.
+pub const LOAD_SEGMENT: u16 = 0x1000;
+
+/// The 8086 does not execute instructions longer than this, even with redundant prefixes.
+const MAX_INSTRUCTION_SIZE: u16 = 15;
+
+/// Bit 1 of the flags register is always set.
+const FLAGS_RESERVED: u16 = 0x0002;
+
+/// The flags that can be changed by `POPF` and `IRET`.
+const FLAGS_WRITABLE: u16 = 0x0FD5;
+
+const AX: usize = 0;
+const CX: usize = 1;
+const DX: usize = 2;
+const BX: usize = 3;
+const SP: usize = 4;
+const BP: usize = 5;
+const SI: usize = 6;
+const DI: usize = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flag {
+    Carry,
+    Parity,
+    AuxiliaryCarry,
+    Zero,
+    Sign,
+    Trap,
+    Interrupt,
+    Direction,
+    Overflow,
+}
+
+impl Flag {
+    /// The bit of the flag in the flags register.
+    pub fn mask(&self) -> u16 {
+        match self {
+            Flag::Carry => 1 << 0,
+            Flag::Parity => 1 << 2,
+            Flag::AuxiliaryCarry => 1 << 4,
+            Flag::Zero => 1 << 6,
+            Flag::Sign => 1 << 7,
+            Flag::Trap => 1 << 8,
+            Flag::Interrupt => 1 << 9,
+            Flag::Direction => 1 << 10,
+            Flag::Overflow => 1 << 11,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecuteError {
+    /// The bytes at CS:IP are not an instruction.
+    InvalidInstruction(u16, u16),
+    /// The instruction at CS:IP is not supported by the interpreter, e.g. 32-bit or FPU code.
+    UnsupportedInstruction(u16, u16, String),
+    /// The machine did not halt within the given number of steps.
+    StepLimitReached(usize),
+}
+
+impl Display for ExecuteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecuteError::InvalidInstruction(segment, offset) => {
+                write!(f, "Invalid instruction at {:04X}:{:04X}", segment, offset)
+            }
+            ExecuteError::UnsupportedInstruction(segment, offset, instruction) => write!(
+                f,
+                "Unsupported instruction at {:04X}:{:04X}: {}",
+                segment, offset, instruction
+            ),
+            ExecuteError::StepLimitReached(steps) => {
+                write!(f, "Did not halt within {} steps", steps)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    CompileError(Box<crate::CompileError>),
+    ExecuteError(ExecuteError),
+}
+
+/// Returned by the execution of an instruction that the interpreter can not execute.  It is turned
+/// into an [ExecuteError] with the address and text of the instruction.
+struct Unsupported;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Size {
+    Byte,
+    Word,
+}
+
+impl Size {
+    fn mask(self) -> u32 {
+        match self {
+            Size::Byte => 0xFF,
+            Size::Word => 0xFFFF,
+        }
+    }
+
+    fn sign(self) -> u32 {
+        match self {
+            Size::Byte => 0x80,
+            Size::Word => 0x8000,
+        }
+    }
+
+    fn bytes(self) -> u16 {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+        }
+    }
+
+    fn sign_extend(self, value: u32) -> i32 {
+        match self {
+            Size::Byte => value as u8 as i8 as i32,
+            Size::Word => value as u16 as i16 as i32,
+        }
+    }
+}
+
+/// The string instruction prefixes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Repeat {
+    WhileEqual,
+    WhileNotEqual,
+}
+
+/// The state of an 8086 with 1MB of memory and 64K of I/O ports.
+pub struct Machine {
+    memory: Vec<u8>,
+    ports: Vec<u8>,
+    registers: [u16; 8],
+    segments: [u16; 6],
+    ip: u16,
+    flags: u16,
+    halted: bool,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; MEMORY_SIZE],
+            ports: vec![0; 0x10000],
+            registers: [0; 8],
+            segments: [0; 6],
+            ip: 0,
+            flags: FLAGS_RESERVED,
+            halted: false,
+        }
+    }
+
+    /// Copy [code] to the start of [segment] and point all the segment registers at it.  Execution
+    /// starts at the first byte and the stack starts at the top of the segment.
+    pub fn load(&mut self, segment: u16, code: &[u8]) {
+        for (offset, byte) in code.iter().enumerate() {
+            self.memory[(linear_address(segment, 0) + offset) % MEMORY_SIZE] = *byte;
+        }
+
+        self.segments = [segment; 6];
+        self.registers[SP] = 0;
+        self.ip = 0;
+        self.halted = false;
+    }
+
+    /// Execute instructions until `HLT`.  Returns the number of instructions that were executed.
+    pub fn run(&mut self, step_limit: usize) -> Result<usize, ExecuteError> {
+        let mut steps = 0;
+        while !self.halted {
+            if steps == step_limit {
+                return Err(ExecuteError::StepLimitReached(step_limit));
+            }
+            self.step()?;
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Execute the instruction at CS:IP, including its prefixes.  A repeated string instruction is
+    /// executed until it is done.  Does nothing once the machine halted.
+    pub fn step(&mut self) -> Result<(), ExecuteError> {
+        if self.halted {
+            return Ok(());
+        }
+
+        let (cs, ip) = (self.segment(ast::Segment::CS), self.ip);
+        let bytes = (0..MAX_INSTRUCTION_SIZE)
+            .map(|i| self.read_byte(cs, ip.wrapping_add(i)))
+            .collect::<Vec<_>>();
+
+        let mut decoder = Decoder::new(&bytes, ip);
+        let mut repeat = None;
+        let instruction = loop {
+            let decoded = match decoder.next() {
+                Some(Ok(decoded)) => decoded,
+                _ => return Err(ExecuteError::InvalidInstruction(cs, ip)),
+            };
+            self.ip = decoded.offset.wrapping_add(decoded.size);
+
+            match decoded.instruction.operation {
+                Operation::REP => repeat = Some(Repeat::WhileEqual),
+                Operation::REPNE => repeat = Some(Repeat::WhileNotEqual),
+                Operation::LOCK => {}
+                _ => break decoded.instruction,
+            }
+        };
+
+        self.execute(&instruction, repeat).map_err(|_| {
+            self.ip = ip;
+            ExecuteError::UnsupportedInstruction(cs, ip, instruction.to_string())
+        })
+    }
+
+    #[inline]
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn register(&self, register: ast::WordRegister) -> u16 {
+        self.registers[register.encoding() as usize]
+    }
+
+    pub fn set_register(&mut self, register: ast::WordRegister, value: u16) {
+        self.registers[register.encoding() as usize] = value;
+    }
+
+    pub fn byte_register(&self, register: ast::ByteRegister) -> u8 {
+        self.get_register(Size::Byte, register.encoding()) as u8
+    }
+
+    pub fn set_byte_register(&mut self, register: ast::ByteRegister, value: u8) {
+        self.set_register_value(Size::Byte, register.encoding(), value as u16);
+    }
+
+    pub fn segment(&self, segment: ast::Segment) -> u16 {
+        self.segments[segment.encoding() as usize]
+    }
+
+    pub fn set_segment(&mut self, segment: ast::Segment, value: u16) {
+        self.segments[segment.encoding() as usize] = value;
+    }
+
+    #[inline]
+    pub fn ip(&self) -> u16 {
+        self.ip
+    }
+
+    pub fn set_ip(&mut self, ip: u16) {
+        self.ip = ip;
+    }
+
+    #[inline]
+    pub fn flags(&self) -> u16 {
+        self.flags
+    }
+
+    pub fn flag(&self, flag: Flag) -> bool {
+        self.flags & flag.mask() != 0
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        if value {
+            self.flags |= flag.mask();
+        } else {
+            self.flags &= !flag.mask();
+        }
+    }
+
+    /// All of memory, indexed by linear address.
+    #[inline]
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn read_byte(&self, segment: u16, offset: u16) -> u8 {
+        self.memory[linear_address(segment, offset)]
+    }
+
+    pub fn write_byte(&mut self, segment: u16, offset: u16, value: u8) {
+        self.memory[linear_address(segment, offset)] = value;
+    }
+
+    /// Words that cross the end of a segment wrap around to its start.
+    pub fn read_word(&self, segment: u16, offset: u16) -> u16 {
+        u16::from_le_bytes([
+            self.read_byte(segment, offset),
+            self.read_byte(segment, offset.wrapping_add(1)),
+        ])
+    }
+
+    pub fn write_word(&mut self, segment: u16, offset: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.write_byte(segment, offset, low);
+        self.write_byte(segment, offset.wrapping_add(1), high);
+    }
+
+    pub fn port(&self, port: u16) -> u8 {
+        self.ports[port as usize]
+    }
+
+    pub fn set_port(&mut self, port: u16, value: u8) {
+        self.ports[port as usize] = value;
+    }
+}
+
+/// Instruction execution.
+impl Machine {
+    fn execute(
+        &mut self,
+        instruction: &ast::Instruction,
+        repeat: Option<Repeat>,
+    ) -> Result<(), Unsupported> {
+        use Operation::*;
+
+        let operands = operands(&instruction.operands);
+
+        match (instruction.operation, operands.as_slice()) {
+            (MOV, [dst, src]) => {
+                let size = common_size(&operands)?;
+                let value = self.read(src, size)?;
+                self.write(dst, size, value)
+            }
+
+            (XCHG, [dst, src]) => {
+                let size = common_size(&operands)?;
+                let (left, right) = (self.read(dst, size)?, self.read(src, size)?);
+                self.write(dst, size, right)?;
+                self.write(src, size, left)
+            }
+
+            (LEA, [dst, src]) => {
+                let (_, offset) = self.effective_address(src)?;
+                self.write(dst, Size::Word, offset)
+            }
+
+            (LDS | LES, [dst, src]) => {
+                let (segment, offset) = self.effective_address(src)?;
+                let value = self.read_word(segment, offset);
+                let pointer_segment = self.read_word(segment, offset.wrapping_add(2));
+                self.write(dst, Size::Word, value)?;
+                let target = if instruction.operation == LDS {
+                    ast::Segment::DS
+                } else {
+                    ast::Segment::ES
+                };
+                self.set_segment(target, pointer_segment);
+                Ok(())
+            }
+
+            (XLATB, [table]) => {
+                let (segment, _) = self.effective_address(table)?;
+                let offset = self.registers[BX].wrapping_add(self.get_register(Size::Byte, 0));
+                let value = self.read_byte(segment, offset);
+                self.set_register_value(Size::Byte, 0, value as u16);
+                Ok(())
+            }
+
+            (LAHF, []) => {
+                self.set_register_value(Size::Byte, 4, self.flags & 0xFF);
+                Ok(())
+            }
+
+            (SAHF, []) => {
+                let ah = self.get_register(Size::Byte, 4);
+                self.flags = (self.flags & 0xFF00) | (ah & FLAGS_WRITABLE & 0xFF) | FLAGS_RESERVED;
+                Ok(())
+            }
+
+            // The 8086 pushes the value of SP after it was decremented, later CPUs push the value
+            // from before.
+            (PUSH, [ast::Operand::Register(_, ast::Register::Word(ast::WordRegister::Sp))]) => {
+                self.push(self.registers[SP].wrapping_sub(2));
+                Ok(())
+            }
+
+            (PUSH, [src]) => {
+                // Only to reject 32-bit operands.
+                common_size(&operands)?;
+                let value = self.read(src, Size::Word)?;
+                self.push(value);
+                Ok(())
+            }
+
+            (POP, [dst]) => {
+                common_size(&operands)?;
+                let value = self.pop();
+                self.write(dst, Size::Word, value)
+            }
+
+            (PUSHF, []) => {
+                self.push(self.flags);
+                Ok(())
+            }
+
+            (POPF, []) => {
+                let flags = self.pop();
+                self.set_flags(flags);
+                Ok(())
+            }
+
+            (PUSHA, []) => {
+                let sp = self.registers[SP];
+                for register in [AX, CX, DX, BX] {
+                    self.push(self.registers[register]);
+                }
+                self.push(sp);
+                for register in [BP, SI, DI] {
+                    self.push(self.registers[register]);
+                }
+                Ok(())
+            }
+
+            (POPA, []) => {
+                for register in [DI, SI, BP] {
+                    self.registers[register] = self.pop();
+                }
+                // The stored SP is skipped.
+                self.pop();
+                for register in [BX, DX, CX, AX] {
+                    self.registers[register] = self.pop();
+                }
+                Ok(())
+            }
+
+            (ENTER, [size, level]) => {
+                let size = self.read(size, Size::Word)?;
+                let level = self.read(level, Size::Byte)? % 32;
+
+                self.push(self.registers[BP]);
+                let frame = self.registers[SP];
+                if level > 0 {
+                    for _ in 1..level {
+                        self.registers[BP] = self.registers[BP].wrapping_sub(2);
+                        let value =
+                            self.read_word(self.segment(ast::Segment::SS), self.registers[BP]);
+                        self.push(value);
+                    }
+                    self.push(frame);
+                }
+                self.registers[BP] = frame;
+                self.registers[SP] = self.registers[SP].wrapping_sub(size);
+                Ok(())
+            }
+
+            (LEAVE, []) => {
+                self.registers[SP] = self.registers[BP];
+                self.registers[BP] = self.pop();
+                Ok(())
+            }
+
+            (IN, [dst, port]) => {
+                let size = common_size(&[dst])?;
+                let port = self.read(port, Size::Word)?;
+                let value = match size {
+                    Size::Byte => self.port(port) as u16,
+                    Size::Word => {
+                        u16::from_le_bytes([self.port(port), self.port(port.wrapping_add(1))])
+                    }
+                };
+                self.write(dst, size, value)
+            }
+
+            (OUT, [port, src]) => {
+                let size = common_size(&[src])?;
+                let port = self.read(port, Size::Word)?;
+                let [low, high] = self.read(src, size)?.to_le_bytes();
+                self.set_port(port, low);
+                if size == Size::Word {
+                    self.set_port(port.wrapping_add(1), high);
+                }
+                Ok(())
+            }
+
+            (ADD | OR | ADC | SBB | AND | SUB | XOR | CMP | TEST, [dst, src]) => {
+                let size = common_size(&operands)?;
+                let (left, right) = (self.read(dst, size)?, self.read(src, size)?);
+                let result = self.arithmetic(instruction.operation, size, left, right);
+                if matches!(instruction.operation, CMP | TEST) {
+                    Ok(())
+                } else {
+                    self.write(dst, size, result)
+                }
+            }
+
+            (INC | DEC, [dst]) => {
+                let size = common_size(&operands)?;
+                let value = self.read(dst, size)?;
+                // The carry flag is not changed.
+                let carry = self.flag(Flag::Carry);
+                let result = if instruction.operation == INC {
+                    self.add(size, value as u32, 1, 0)
+                } else {
+                    self.subtract(size, value as u32, 1, 0)
+                };
+                self.set_flag(Flag::Carry, carry);
+                self.write(dst, size, result)
+            }
+
+            (NEG, [dst]) => {
+                let size = common_size(&operands)?;
+                let value = self.read(dst, size)?;
+                let result = self.subtract(size, 0, value as u32, 0);
+                self.write(dst, size, result)
+            }
+
+            (NOT, [dst]) => {
+                let size = common_size(&operands)?;
+                let value = self.read(dst, size)?;
+                self.write(dst, size, !value)
+            }
+
+            (MUL | IMUL, [src]) => {
+                let size = common_size(&operands)?;
+                let value = self.read(src, size)? as u32;
+                self.multiply(instruction.operation == IMUL, size, value);
+                Ok(())
+            }
+
+            (IMUL, [dst, src, third]) => {
+                let left = Size::Word.sign_extend(self.read(src, Size::Word)? as u32);
+                let right = Size::Word.sign_extend(self.read(third, Size::Word)? as u32);
+                let result = left * right;
+                let overflow = result != result as i16 as i32;
+                self.set_flag(Flag::Carry, overflow);
+                self.set_flag(Flag::Overflow, overflow);
+                self.write(dst, Size::Word, result as u16)
+            }
+
+            (DIV | IDIV, [src]) => {
+                let size = common_size(&operands)?;
+                let value = self.read(src, size)? as u32;
+                self.divide(instruction.operation == IDIV, size, value);
+                Ok(())
+            }
+
+            (ROL | ROR | RCL | RCR | SHL | SHR | SAR, [dst, count]) => {
+                let size = common_size(&[dst])?;
+                let value = self.read(dst, size)?;
+                let count = self.read(count, Size::Byte)?;
+                let result = self.shift(instruction.operation, size, value as u32, count as u32);
+                self.write(dst, size, result)
+            }
+
+            (CBW, []) => {
+                self.registers[AX] = self.registers[AX] as u8 as i8 as i16 as u16;
+                Ok(())
+            }
+
+            (CWD, []) => {
+                self.registers[DX] = if self.registers[AX] & 0x8000 != 0 {
+                    0xFFFF
+                } else {
+                    0x0000
+                };
+                Ok(())
+            }
+
+            (DAA | DAS | AAA | AAS, []) => {
+                self.adjust(instruction.operation);
+                Ok(())
+            }
+
+            (AAM | AAD, []) => {
+                self.adjust_with_base(instruction.operation, 10);
+                Ok(())
+            }
+
+            (AAM | AAD, [base]) => {
+                let base = self.read(base, Size::Byte)?;
+                self.adjust_with_base(instruction.operation, base as u8);
+                Ok(())
+            }
+
+            (MOVSB | MOVSW | CMPSB | CMPSW | SCASB | SCASW | LODSB | LODSW | STOSB | STOSW, _)
+            | (INSB | INSW | OUTSB | OUTSW, _) => {
+                let segment = match operands.as_slice() {
+                    [] => ast::Segment::DS,
+                    [ast::Operand::Segment(_, segment)] => *segment,
+                    _ => return Err(Unsupported),
+                };
+                self.string(instruction.operation, segment, repeat);
+                Ok(())
+            }
+
+            (JMP, [target]) => {
+                let (segment, offset) = self.jump_target(target)?;
+                if let Some(segment) = segment {
+                    self.set_segment(ast::Segment::CS, segment);
+                }
+                self.ip = offset;
+                Ok(())
+            }
+
+            (CALL, [target]) => {
+                let (segment, offset) = self.jump_target(target)?;
+                if let Some(segment) = segment {
+                    self.push(self.segment(ast::Segment::CS));
+                    self.set_segment(ast::Segment::CS, segment);
+                }
+                self.push(self.ip);
+                self.ip = offset;
+                Ok(())
+            }
+
+            (RET, []) => {
+                self.ip = self.pop();
+                Ok(())
+            }
+
+            (RET, [size]) => {
+                let size = self.read(size, Size::Word)?;
+                self.ip = self.pop();
+                self.registers[SP] = self.registers[SP].wrapping_add(size);
+                Ok(())
+            }
+
+            (
+                JO | JNO | JB | JNB | JE | JNE | JBE | JNBE | JS | JNS | JP | JNP | JL | JNL | JLE
+                | JNLE,
+                [target],
+            ) => {
+                let target = self.read(target, Size::Word)?;
+                if self.condition(instruction.operation) {
+                    self.ip = target;
+                }
+                Ok(())
+            }
+
+            (LOOP | LOOPZ | LOOPNZ, [target]) => {
+                let target = self.read(target, Size::Word)?;
+                self.registers[CX] = self.registers[CX].wrapping_sub(1);
+                let jump = self.registers[CX] != 0
+                    && match instruction.operation {
+                        LOOPZ => self.flag(Flag::Zero),
+                        LOOPNZ => !self.flag(Flag::Zero),
+                        _ => true,
+                    };
+                if jump {
+                    self.ip = target;
+                }
+                Ok(())
+            }
+
+            (JCXZ, [target]) => {
+                let target = self.read(target, Size::Word)?;
+                if self.registers[CX] == 0 {
+                    self.ip = target;
+                }
+                Ok(())
+            }
+
+            (INT, [number]) => {
+                let number = self.read(number, Size::Byte)?;
+                self.interrupt(number as u8);
+                Ok(())
+            }
+
+            (INT1, []) => {
+                self.interrupt(1);
+                Ok(())
+            }
+
+            (INT3, []) => {
+                self.interrupt(3);
+                Ok(())
+            }
+
+            (INTO, []) => {
+                if self.flag(Flag::Overflow) {
+                    self.interrupt(4);
+                }
+                Ok(())
+            }
+
+            (IRET, []) => {
+                self.ip = self.pop();
+                let cs = self.pop();
+                self.set_segment(ast::Segment::CS, cs);
+                let flags = self.pop();
+                self.set_flags(flags);
+                Ok(())
+            }
+
+            (CLC | STC | CMC | CLD | STD | CLI | STI, []) => {
+                match instruction.operation {
+                    CLC => self.set_flag(Flag::Carry, false),
+                    STC => self.set_flag(Flag::Carry, true),
+                    CMC => self.set_flag(Flag::Carry, !self.flag(Flag::Carry)),
+                    CLD => self.set_flag(Flag::Direction, false),
+                    STD => self.set_flag(Flag::Direction, true),
+                    CLI => self.set_flag(Flag::Interrupt, false),
+                    _ => self.set_flag(Flag::Interrupt, true),
+                }
+                Ok(())
+            }
+
+            (SALC, []) => {
+                let value = if self.flag(Flag::Carry) { 0xFF } else { 0x00 };
+                self.set_register_value(Size::Byte, 0, value);
+                Ok(())
+            }
+
+            (HLT, []) => {
+                self.halted = true;
+                Ok(())
+            }
+
+            // There is no coprocessor to escape to.
+            (NOP | WAIT | ESC, _) => Ok(()),
+
+            _ => Err(Unsupported),
+        }
+    }
+
+    fn get_register(&self, size: Size, encoding: u8) -> u16 {
+        match size {
+            Size::Byte => {
+                let word = self.registers[(encoding & 0b11) as usize];
+                if encoding & 0b100 == 0 {
+                    word & 0xFF
+                } else {
+                    word >> 8
+                }
+            }
+            Size::Word => self.registers[encoding as usize],
+        }
+    }
+
+    fn set_register_value(&mut self, size: Size, encoding: u8, value: u16) {
+        match size {
+            Size::Byte => {
+                let word = &mut self.registers[(encoding & 0b11) as usize];
+                if encoding & 0b100 == 0 {
+                    *word = (*word & 0xFF00) | (value & 0xFF);
+                } else {
+                    *word = (*word & 0x00FF) | (value << 8);
+                }
+            }
+            Size::Word => self.registers[encoding as usize] = value,
+        }
+    }
+
+    fn set_flags(&mut self, flags: u16) {
+        self.flags = (flags & FLAGS_WRITABLE) | FLAGS_RESERVED;
+    }
+
+    /// The segment and offset of a memory operand.
+    fn effective_address(&self, operand: &ast::Operand) -> Result<(u16, u16), Unsupported> {
+        match operand {
+            ast::Operand::Direct(_, address, _, segment) => Ok((
+                self.segment(segment.unwrap_or(ast::Segment::DS)),
+                constant(address)? as u16,
+            )),
+
+            ast::Operand::Indirect(_, encoding, displacement, _, segment) => {
+                use ast::IndirectEncoding::*;
+
+                let r = &self.registers;
+                let (base, default_segment) = match encoding {
+                    BxSi => (r[BX].wrapping_add(r[SI]), ast::Segment::DS),
+                    BxDi => (r[BX].wrapping_add(r[DI]), ast::Segment::DS),
+                    BpSi => (r[BP].wrapping_add(r[SI]), ast::Segment::SS),
+                    BpDi => (r[BP].wrapping_add(r[DI]), ast::Segment::SS),
+                    Si => (r[SI], ast::Segment::DS),
+                    Di => (r[DI], ast::Segment::DS),
+                    Bp => (r[BP], ast::Segment::SS),
+                    Bx => (r[BX], ast::Segment::DS),
+                };
+                let displacement = match displacement {
+                    Some(displacement) => constant(displacement)?,
+                    None => 0,
+                };
+
+                Ok((
+                    self.segment(segment.unwrap_or(default_segment)),
+                    base.wrapping_add(displacement as u16),
+                ))
+            }
+
+            _ => Err(Unsupported),
+        }
+    }
+
+    fn read(&self, operand: &ast::Operand, size: Size) -> Result<u16, Unsupported> {
+        match operand {
+            ast::Operand::Immediate(_, value, _) => {
+                Ok((constant(value)? as u32 & size.mask()) as u16)
+            }
+
+            ast::Operand::Register(_, ast::Register::Byte(register)) => {
+                Ok(self.get_register(Size::Byte, register.encoding()))
+            }
+
+            ast::Operand::Register(_, ast::Register::Word(register)) => {
+                Ok(self.get_register(Size::Word, register.encoding()))
+            }
+
+            ast::Operand::Segment(_, segment) => Ok(self.segment(*segment)),
+
+            ast::Operand::Direct(..) | ast::Operand::Indirect(..) => {
+                let (segment, offset) = self.effective_address(operand)?;
+                Ok(match size {
+                    Size::Byte => self.read_byte(segment, offset) as u16,
+                    Size::Word => self.read_word(segment, offset),
+                })
+            }
+
+            _ => Err(Unsupported),
+        }
+    }
+
+    fn write(&mut self, operand: &ast::Operand, size: Size, value: u16) -> Result<(), Unsupported> {
+        match operand {
+            ast::Operand::Register(_, ast::Register::Byte(register)) => {
+                self.set_register_value(Size::Byte, register.encoding(), value);
+                Ok(())
+            }
+
+            ast::Operand::Register(_, ast::Register::Word(register)) => {
+                self.set_register_value(Size::Word, register.encoding(), value);
+                Ok(())
+            }
+
+            ast::Operand::Segment(_, segment) => {
+                self.set_segment(*segment, value);
+                Ok(())
+            }
+
+            ast::Operand::Direct(..) | ast::Operand::Indirect(..) => {
+                let (segment, offset) = self.effective_address(operand)?;
+                match size {
+                    Size::Byte => self.write_byte(segment, offset, value as u8),
+                    Size::Word => self.write_word(segment, offset, value),
+                }
+                Ok(())
+            }
+
+            _ => Err(Unsupported),
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        self.registers[SP] = self.registers[SP].wrapping_sub(2);
+        self.write_word(self.segment(ast::Segment::SS), self.registers[SP], value);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let value = self.read_word(self.segment(ast::Segment::SS), self.registers[SP]);
+        self.registers[SP] = self.registers[SP].wrapping_add(2);
+        value
+    }
+
+    /// The segment, if the jump is far, and offset a jump or call transfers control to.
+    fn jump_target(&self, operand: &ast::Operand) -> Result<(Option<u16>, u16), Unsupported> {
+        match operand {
+            ast::Operand::Far(_, offset, segment) => {
+                Ok((Some(constant(segment)? as u16), constant(offset)? as u16))
+            }
+
+            // A double word in memory is a far pointer.
+            ast::Operand::Direct(_, _, Some(ast::DataSize::DWord), _)
+            | ast::Operand::Indirect(_, _, _, Some(ast::DataSize::DWord), _) => {
+                let (segment, offset) = self.effective_address(operand)?;
+                Ok((
+                    Some(self.read_word(segment, offset.wrapping_add(2))),
+                    self.read_word(segment, offset),
+                ))
+            }
+
+            operand => Ok((None, self.read(operand, Size::Word)?)),
+        }
+    }
+
+    fn interrupt(&mut self, number: u8) {
+        self.push(self.flags);
+        self.set_flag(Flag::Interrupt, false);
+        self.set_flag(Flag::Trap, false);
+        self.push(self.segment(ast::Segment::CS));
+        self.push(self.ip);
+
+        let vector = number as u16 * 4;
+        self.ip = self.read_word(0, vector);
+        let cs = self.read_word(0, vector + 2);
+        self.set_segment(ast::Segment::CS, cs);
+    }
+
+    fn condition(&self, operation: Operation) -> bool {
+        use Operation::*;
+
+        let carry = self.flag(Flag::Carry);
+        let zero = self.flag(Flag::Zero);
+        let less = self.flag(Flag::Sign) != self.flag(Flag::Overflow);
+
+        match operation {
+            JO => self.flag(Flag::Overflow),
+            JNO => !self.flag(Flag::Overflow),
+            JB => carry,
+            JNB => !carry,
+            JE => zero,
+            JNE => !zero,
+            JBE => carry || zero,
+            JNBE => !(carry || zero),
+            JS => self.flag(Flag::Sign),
+            JNS => !self.flag(Flag::Sign),
+            JP => self.flag(Flag::Parity),
+            JNP => !self.flag(Flag::Parity),
+            JL => less,
+            JNL => !less,
+            JLE => zero || less,
+            JNLE => !(zero || less),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Sets the zero, sign and parity flags from the result of an operation.
+    fn set_result_flags(&mut self, size: Size, result: u32) {
+        self.set_flag(Flag::Zero, result & size.mask() == 0);
+        self.set_flag(Flag::Sign, result & size.sign() != 0);
+        self.set_flag(Flag::Parity, (result as u8).count_ones().is_multiple_of(2));
+    }
+
+    fn add(&mut self, size: Size, left: u32, right: u32, carry: u32) -> u16 {
+        let result = left + right + carry;
+        self.set_flag(Flag::Carry, result > size.mask());
+        self.set_flag(Flag::AuxiliaryCarry, (left ^ right ^ result) & 0x10 != 0);
+        self.set_flag(
+            Flag::Overflow,
+            (result ^ left) & (result ^ right) & size.sign() != 0,
+        );
+        self.set_result_flags(size, result);
+        (result & size.mask()) as u16
+    }
+
+    fn subtract(&mut self, size: Size, left: u32, right: u32, borrow: u32) -> u16 {
+        let result = left.wrapping_sub(right).wrapping_sub(borrow);
+        self.set_flag(Flag::Carry, right + borrow > left);
+        self.set_flag(Flag::AuxiliaryCarry, (left ^ right ^ result) & 0x10 != 0);
+        self.set_flag(
+            Flag::Overflow,
+            (left ^ right) & (left ^ result) & size.sign() != 0,
+        );
+        self.set_result_flags(size, result);
+        (result & size.mask()) as u16
+    }
+
+    fn logic(&mut self, size: Size, result: u32) -> u16 {
+        self.set_flag(Flag::Carry, false);
+        self.set_flag(Flag::Overflow, false);
+        self.set_flag(Flag::AuxiliaryCarry, false);
+        self.set_result_flags(size, result);
+        result as u16
+    }
+
+    fn arithmetic(&mut self, operation: Operation, size: Size, left: u16, right: u16) -> u16 {
+        use Operation::*;
+
+        let (left, right) = (left as u32, right as u32);
+        let carry = self.flag(Flag::Carry) as u32;
+
+        match operation {
+            ADD => self.add(size, left, right, 0),
+            ADC => self.add(size, left, right, carry),
+            SUB | CMP => self.subtract(size, left, right, 0),
+            SBB => self.subtract(size, left, right, carry),
+            AND | TEST => self.logic(size, left & right),
+            OR => self.logic(size, left | right),
+            XOR => self.logic(size, left ^ right),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Multiplies the accumulator with [value] into AX for bytes and DX:AX for words.
+    fn multiply(&mut self, signed: bool, size: Size, value: u32) {
+        let accumulator = self.get_register(size, 0) as u32;
+
+        let (result, overflow) = if signed {
+            let result = size.sign_extend(accumulator) * size.sign_extend(value);
+            let overflow = result != size.sign_extend(result as u32);
+            (result as u32, overflow)
+        } else {
+            let result = accumulator * value;
+            (result, result > size.mask())
+        };
+
+        match size {
+            Size::Byte => self.registers[AX] = result as u16,
+            Size::Word => {
+                self.registers[AX] = result as u16;
+                self.registers[DX] = (result >> 16) as u16;
+            }
+        }
+        self.set_flag(Flag::Carry, overflow);
+        self.set_flag(Flag::Overflow, overflow);
+    }
+
+    /// Divides AX for bytes and DX:AX for words by [value].  Division by zero, or a quotient that
+    /// does not fit, raises interrupt 0.
+    fn divide(&mut self, signed: bool, size: Size, value: u32) {
+        let dividend = match size {
+            Size::Byte => self.registers[AX] as u32,
+            Size::Word => (self.registers[DX] as u32) << 16 | self.registers[AX] as u32,
+        };
+
+        if value == 0 {
+            self.interrupt(0);
+            return;
+        }
+
+        let (quotient, remainder) = if signed {
+            let dividend = match size {
+                Size::Byte => dividend as u16 as i16 as i64,
+                Size::Word => dividend as i32 as i64,
+            };
+            let divisor = size.sign_extend(value) as i64;
+            let quotient = dividend / divisor;
+            let limit = size.sign() as i64;
+            if quotient < -limit || quotient >= limit {
+                self.interrupt(0);
+                return;
+            }
+            (quotient as u32, (dividend % divisor) as u32)
+        } else {
+            let quotient = dividend / value;
+            if quotient > size.mask() {
+                self.interrupt(0);
+                return;
+            }
+            (quotient, dividend % value)
+        };
+
+        match size {
+            Size::Byte => {
+                self.set_register_value(Size::Byte, 0, quotient as u16);
+                self.set_register_value(Size::Byte, 4, remainder as u16);
+            }
+            Size::Word => {
+                self.registers[AX] = quotient as u16;
+                self.registers[DX] = remainder as u16;
+            }
+        }
+    }
+
+    fn shift(&mut self, operation: Operation, size: Size, value: u32, count: u32) -> u16 {
+        use Operation::*;
+
+        if count == 0 {
+            return value as u16;
+        }
+
+        let (mask, sign) = (size.mask(), size.sign());
+        let mut result = value;
+        let mut carry = self.flag(Flag::Carry);
+
+        for _ in 0..count {
+            let (shifted, out) = match operation {
+                ROL => (
+                    (result << 1) | (result & sign != 0) as u32,
+                    result & sign != 0,
+                ),
+                ROR => (
+                    (result >> 1) | if result & 1 != 0 { sign } else { 0 },
+                    result & 1 != 0,
+                ),
+                RCL => ((result << 1) | carry as u32, result & sign != 0),
+                RCR => (
+                    (result >> 1) | if carry { sign } else { 0 },
+                    result & 1 != 0,
+                ),
+                SHL => (result << 1, result & sign != 0),
+                SHR => (result >> 1, result & 1 != 0),
+                SAR => ((result >> 1) | (result & sign), result & 1 != 0),
+                _ => unreachable!(),
+            };
+            result = shifted & mask;
+            carry = out;
+        }
+
+        let overflow = match operation {
+            ROL | RCL | SHL => (result & sign != 0) != carry,
+            ROR | RCR => (result & sign != 0) != (result & (sign >> 1) != 0),
+            SHR => value & sign != 0,
+            _ => false,
+        };
+        self.set_flag(Flag::Carry, carry);
+        self.set_flag(Flag::Overflow, overflow);
+
+        // Rotates only change the carry and overflow flags.
+        if matches!(operation, SHL | SHR | SAR) {
+            self.set_result_flags(size, result);
+        }
+
+        result as u16
+    }
+
+    /// The decimal and ASCII adjustments of AL after an addition or subtraction.
+    fn adjust(&mut self, operation: Operation) {
+        use Operation::*;
+
+        let al = self.get_register(Size::Byte, 0) as u8;
+        let carry = self.flag(Flag::Carry);
+        let adjust_low = al & 0x0F > 9 || self.flag(Flag::AuxiliaryCarry);
+
+        match operation {
+            DAA | DAS => {
+                let mut result = al;
+                let mut new_carry = false;
+                if adjust_low {
+                    let (value, overflow) = if operation == DAA {
+                        al.overflowing_add(0x06)
+                    } else {
+                        al.overflowing_sub(0x06)
+                    };
+                    result = value;
+                    new_carry = carry || overflow;
+                }
+                if al > 0x99 || carry {
+                    result = if operation == DAA {
+                        result.wrapping_add(0x60)
+                    } else {
+                        result.wrapping_sub(0x60)
+                    };
+                    new_carry = true;
+                }
+                self.set_register_value(Size::Byte, 0, result as u16);
+                self.set_flag(Flag::AuxiliaryCarry, adjust_low);
+                self.set_flag(Flag::Carry, new_carry);
+                self.set_result_flags(Size::Byte, result as u32);
+            }
+
+            _ => {
+                let ah = self.get_register(Size::Byte, 4) as u8;
+                let (al, ah) = match (adjust_low, operation) {
+                    (false, _) => (al, ah),
+                    (true, AAA) => (al.wrapping_add(6), ah.wrapping_add(1)),
+                    (true, _) => (al.wrapping_sub(6), ah.wrapping_sub(1)),
+                };
+                self.set_register_value(Size::Byte, 0, (al & 0x0F) as u16);
+                self.set_register_value(Size::Byte, 4, ah as u16);
+                self.set_flag(Flag::AuxiliaryCarry, adjust_low);
+                self.set_flag(Flag::Carry, adjust_low);
+            }
+        }
+    }
+
+    /// The ASCII adjustments of AX after a multiplication or before a division.
+    fn adjust_with_base(&mut self, operation: Operation, base: u8) {
+        let al = self.get_register(Size::Byte, 0) as u8;
+        let ah = self.get_register(Size::Byte, 4) as u8;
+
+        let (al, ah) = if operation == Operation::AAM {
+            if base == 0 {
+                self.interrupt(0);
+                return;
+            }
+            (al % base, al / base)
+        } else {
+            (al.wrapping_add(ah.wrapping_mul(base)), 0)
+        };
+
+        self.registers[AX] = u16::from_le_bytes([al, ah]);
+        self.set_result_flags(Size::Byte, al as u32);
+    }
+
+    /// Execute a string instruction, repeating it CX times with a prefix.  The compare and scan
+    /// instructions also stop when the zero flag does not match the prefix.
+    fn string(&mut self, operation: Operation, segment: ast::Segment, repeat: Option<Repeat>) {
+        let Some(repeat) = repeat else {
+            self.string_once(operation, segment);
+            return;
+        };
+
+        let compares = matches!(
+            operation,
+            Operation::CMPSB | Operation::CMPSW | Operation::SCASB | Operation::SCASW
+        );
+
+        while self.registers[CX] != 0 {
+            self.string_once(operation, segment);
+            self.registers[CX] = self.registers[CX].wrapping_sub(1);
+
+            if compares && self.flag(Flag::Zero) != (repeat == Repeat::WhileEqual) {
+                break;
+            }
+        }
+    }
+
+    fn string_once(&mut self, operation: Operation, segment: ast::Segment) {
+        use Operation::*;
+
+        let size = match operation {
+            MOVSB | CMPSB | SCASB | LODSB | STOSB | INSB | OUTSB => Size::Byte,
+            _ => Size::Word,
+        };
+        let step = if self.flag(Flag::Direction) {
+            size.bytes().wrapping_neg()
+        } else {
+            size.bytes()
+        };
+
+        let source = (self.segment(segment), self.registers[SI]);
+        let destination = (self.segment(ast::Segment::ES), self.registers[DI]);
+        let read = |machine: &Self, (segment, offset): (u16, u16)| match size {
+            Size::Byte => machine.read_byte(segment, offset) as u16,
+            Size::Word => machine.read_word(segment, offset),
+        };
+
+        let (uses_source, uses_destination) = match operation {
+            MOVSB | MOVSW => {
+                let value = read(self, source);
+                self.write_sized(size, destination, value);
+                (true, true)
+            }
+            CMPSB | CMPSW => {
+                let (left, right) = (read(self, source), read(self, destination));
+                self.subtract(size, left as u32, right as u32, 0);
+                (true, true)
+            }
+            SCASB | SCASW => {
+                let (left, right) = (self.get_register(size, 0), read(self, destination));
+                self.subtract(size, left as u32, right as u32, 0);
+                (false, true)
+            }
+            LODSB | LODSW => {
+                let value = read(self, source);
+                self.set_register_value(size, 0, value);
+                (true, false)
+            }
+            STOSB | STOSW => {
+                self.write_sized(size, destination, self.get_register(size, 0));
+                (false, true)
+            }
+            INSB | INSW => {
+                let port = self.registers[DX];
+                let value = match size {
+                    Size::Byte => self.port(port) as u16,
+                    Size::Word => {
+                        u16::from_le_bytes([self.port(port), self.port(port.wrapping_add(1))])
+                    }
+                };
+                self.write_sized(size, destination, value);
+                (false, true)
+            }
+            _ => {
+                let port = self.registers[DX];
+                let [low, high] = read(self, source).to_le_bytes();
+                self.set_port(port, low);
+                if size == Size::Word {
+                    self.set_port(port.wrapping_add(1), high);
+                }
+                (true, false)
+            }
+        };
+
+        if uses_source {
+            self.registers[SI] = self.registers[SI].wrapping_add(step);
+        }
+        if uses_destination {
+            self.registers[DI] = self.registers[DI].wrapping_add(step);
+        }
+    }
+
+    fn write_sized(&mut self, size: Size, (segment, offset): (u16, u16), value: u16) {
+        match size {
+            Size::Byte => self.write_byte(segment, offset, value as u8),
+            Size::Word => self.write_word(segment, offset, value),
+        }
+    }
+}
+
+fn linear_address(segment: u16, offset: u16) -> usize {
+    (((segment as usize) << 4) + offset as usize) % MEMORY_SIZE
+}
+
+fn operands(operands: &ast::Operands) -> Vec<&ast::Operand> {
+    match operands {
+        ast::Operands::None(_) => vec![],
+        ast::Operands::Destination(_, dst) => vec![dst],
+        ast::Operands::DestinationAndSource(_, dst, src) => vec![dst, src],
+        ast::Operands::DestinationSourceAndThird(_, dst, src, third) => vec![dst, src, third],
+    }
+}
+
+/// The value of an expression produced by the decoder, which is a constant with an optional sign.
+fn constant(expression: &ast::Expression) -> Result<i64, Unsupported> {
+    match expression {
+        ast::Expression::Value(_, ast::Value::Constant(value)) => Ok(*value),
+        ast::Expression::PrefixOperator(_, ast::Operator::Add, value) => constant(value),
+        ast::Expression::PrefixOperator(_, ast::Operator::Subtract, value) => Ok(-constant(value)?),
+        _ => Err(Unsupported),
+    }
+}
+
+/// The size of an operand, if it has one.  32-bit and FPU operands are not supported.
+fn operand_size(operand: &ast::Operand) -> Result<Option<Size>, Unsupported> {
+    match operand {
+        ast::Operand::Register(_, ast::Register::Byte(_)) => Ok(Some(Size::Byte)),
+        ast::Operand::Register(_, ast::Register::Word(_)) | ast::Operand::Segment(..) => {
+            Ok(Some(Size::Word))
+        }
+        ast::Operand::Direct(_, _, data_size, _)
+        | ast::Operand::Indirect(_, _, _, data_size, _) => match data_size {
+            None => Ok(None),
+            Some(ast::DataSize::Byte) => Ok(Some(Size::Byte)),
+            Some(ast::DataSize::Word) => Ok(Some(Size::Word)),
+            Some(_) => Err(Unsupported),
+        },
+        ast::Operand::Immediate(..) => Ok(None),
+        _ => Err(Unsupported),
+    }
+}
+
+/// The size of the first operand that has one.
+fn common_size(operands: &[&ast::Operand]) -> Result<Size, Unsupported> {
+    for operand in operands {
+        if let Some(size) = operand_size(operand)? {
+            return Ok(size);
+        }
+    }
+    Ok(Size::Word)
+}
+
+/// Assemble [source], load it at [LOAD_SEGMENT] and run it until `HLT`.
+pub fn run(source: &str, step_limit: usize) -> Result<Machine, RunError> {
+    let mut compiler = crate::compiler::Compiler::default();
+    compiler.set_base_segment(LOAD_SEGMENT);
+    let code = crate::compile_with(compiler, source)
+        .map_err(|err| RunError::CompileError(Box::new(err)))?;
+
+    let mut machine = Machine::new();
+    machine.load(LOAD_SEGMENT, &code);
+    machine.run(step_limit).map_err(RunError::ExecuteError)?;
+
+    Ok(machine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::ByteRegister::*;
+    use ast::WordRegister::*;
+
+    const STEP_LIMIT: usize = 10_000;
+
+    fn execute(source: &str) -> Machine {
+        match run(source, STEP_LIMIT) {
+            Ok(machine) => machine,
+            Err(err) => panic!("{:?}", err),
+        }
+    }
+
+    macro_rules! assert_registers {
+        ($source:expr, $($register:expr => $value:expr),+ $(,)?) => {{
+            let machine = execute($source);
+            $(
+                assert_eq!(
+                    $value,
+                    machine.register($register),
+                    "{:?} after \"{}\"",
+                    $register,
+                    $source
+                );
+            )+
+            machine
+        }};
+    }
+
+    macro_rules! assert_flags {
+        ($machine:expr, $($flag:ident = $value:expr),+ $(,)?) => {{
+            $(
+                assert_eq!($value, $machine.flag(Flag::$flag), "{:?}", Flag::$flag);
+            )+
+        }};
+    }
+
+    #[test]
+    fn data_transfer() {
+        assert_registers!(
+            "mov ax, 0x1234\nmov bx, ax\nmov cl, bh\nxchg ax, cx\nhlt",
+            Ax => 0x0012,
+            Bx => 0x1234,
+            Cx => 0x1234,
+        );
+
+        assert_registers!(
+            "mov ax, [value]\nmov [copy], ax\nmov bx, copy\nmov dx, [bx]\nhlt\n\
+             value: dw 0xBEEF\ncopy: dw 0",
+            Ax => 0xBEEF,
+            Dx => 0xBEEF,
+        );
+
+        assert_registers!(
+            "mov bx, table\nmov al, 2\nxlatb word [bx]\nhlt\ntable: db 10, 20, 30",
+            Ax => 30,
+        );
+
+        // lea si, [bx + 1]
+        // hlt
+        let mut machine = Machine::new();
+        machine.load(LOAD_SEGMENT, &[0x8D, 0x77, 0x01, 0xF4]);
+        machine.set_register(Bx, 0x10);
+        machine.run(STEP_LIMIT).unwrap();
+        assert_eq!(0x11, machine.register(Si));
+
+        let machine = assert_registers!(
+            "les di, [pointer]\nlds si, [pointer]\nhlt\npointer: dw 0x1234, 0x5678",
+            Di => 0x1234,
+            Si => 0x1234,
+        );
+        assert_eq!(0x5678, machine.segment(ast::Segment::ES));
+        assert_eq!(0x5678, machine.segment(ast::Segment::DS));
+
+        // The loaded program can refer to its own segment.
+        let machine = assert_registers!("mov ax, seg start\nstart: hlt", Ax => LOAD_SEGMENT);
+        assert_eq!(LOAD_SEGMENT, machine.segment(ast::Segment::CS));
+    }
+
+    #[test]
+    fn stack() {
+        let machine = assert_registers!(
+            "mov ax, 1\nmov bx, 2\npush ax\npush bx\npop ax\npop bx\nhlt",
+            Ax => 2,
+            Bx => 1,
+            Sp => 0,
+        );
+        assert_eq!(1, machine.read_word(LOAD_SEGMENT, 0xFFFE));
+
+        // The 8086 pushes the decremented value of SP.
+        assert_registers!("push sp\npop ax\nhlt", Ax => 0xFFFE, Sp => 0);
+
+        assert_registers!(
+            "cpu 186\npush cs\npop ds\npush 0x1234\npop ax\nhlt",
+            Ax => 0x1234,
+        );
+
+        assert_registers!(
+            "cpu 186\nmov ax, 1\nmov cx, 2\npusha\nxor ax, ax\nmov cx, ax\npopa\nhlt",
+            Ax => 1,
+            Cx => 2,
+            Sp => 0,
+        );
+
+        assert_registers!(
+            "cpu 186\nenter 4, 0\nmov word [bp - 2], 0x55\nmov ax, [bp - 2]\nmov bx, sp\nleave\nhlt",
+            Ax => 0x55,
+            Bx => 0xFFFA,
+            Bp => 0,
+            Sp => 0,
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let machine = assert_registers!("mov ax, 5\nadd ax, 7\nsub ax, 2\nhlt", Ax => 10);
+        assert_flags!(machine, Carry = false, Zero = false, Sign = false);
+
+        let machine = assert_registers!("mov al, 0xFF\nadd al, 1\nhlt", Ax => 0);
+        assert_flags!(
+            machine,
+            Carry = true,
+            Zero = true,
+            AuxiliaryCarry = true,
+            Parity = true
+        );
+
+        let machine = assert_registers!("mov al, 0x7F\ninc al\nhlt", Ax => 0x80);
+        assert_flags!(machine, Overflow = true, Sign = true, Carry = false);
+
+        let machine = assert_registers!("mov ax, 1\ncmp ax, 2\nhlt", Ax => 1);
+        assert_flags!(machine, Carry = true, Sign = true, Zero = false);
+
+        // A 32-bit addition with carry.
+        assert_registers!(
+            "mov ax, 0xFFFF\nmov dx, 0x0001\nadd ax, 1\nadc dx, 0\nhlt",
+            Ax => 0,
+            Dx => 2,
+        );
+        assert_registers!(
+            "mov ax, 0\nmov dx, 2\nsub ax, 1\nsbb dx, 0\nhlt",
+            Ax => 0xFFFF,
+            Dx => 1,
+        );
+
+        let machine = assert_registers!("stc\nmov ax, 5\ndec ax\nneg ax\nhlt", Ax => 0xFFFC);
+        assert_flags!(machine, Carry = true, Sign = true);
+
+        assert_registers!("mov al, 0x80\ncbw\ncwd\nhlt", Ax => 0xFF80, Dx => 0xFFFF);
+    }
+
+    #[test]
+    fn logic() {
+        let machine = assert_registers!(
+            "mov ax, 0xF0F0\nand ax, 0xFF00\nor ax, 0x000F\nxor ax, 0x0101\nnot ax\nhlt",
+            Ax => 0x0EF1,
+        );
+        assert_flags!(machine, Carry = false, Overflow = false);
+
+        let machine = assert_registers!("mov al, 0x0F\ntest al, 0xF0\nhlt", Ax => 0x0F);
+        assert_flags!(machine, Zero = true);
+
+        assert_registers!("mov ax, 1\nmov cl, 4\nshl ax, cl\nshl ax, 1\nhlt", Ax => 0x20);
+        assert_registers!("cpu 186\nmov ax, 0x8000\nsar ax, 3\nhlt", Ax => 0xF000);
+        assert_registers!("cpu 186\nmov ax, 0x8000\nshr ax, 15\nhlt", Ax => 1);
+        assert_registers!("mov al, 0x81\nrol al, 1\nhlt", Ax => 0x03);
+        assert_registers!("mov al, 0x81\nror al, 1\nhlt", Ax => 0xC0);
+
+        let machine = assert_registers!("clc\nmov al, 0x80\nrcl al, 1\nhlt", Ax => 0);
+        assert_flags!(machine, Carry = true, Overflow = true);
+        assert_registers!("stc\nmov al, 0x02\nrcr al, 1\nhlt", Ax => 0x81);
+    }
+
+    #[test]
+    fn multiply_and_divide() {
+        let machine = assert_registers!("mov al, 16\nmov bl, 32\nmul bl\nhlt", Ax => 512);
+        assert_flags!(machine, Carry = true, Overflow = true);
+
+        assert_registers!(
+            "mov ax, 0x1000\nmov bx, 0x0100\nmul bx\nhlt",
+            Ax => 0x0000,
+            Dx => 0x0010,
+        );
+        assert_registers!("mov al, 0xFC\nmov bl, 3\nimul bl\nhlt", Ax => 0xFFF4);
+        assert_registers!("cpu 186\nmov bx, 0xFFF9\nimul ax, bx, 3\nhlt", Ax => 0xFFEB);
+
+        assert_registers!(
+            "mov dx, 0\nmov ax, 100\nmov bx, 7\ndiv bx\nhlt",
+            Ax => 14,
+            Dx => 2,
+        );
+        assert_registers!("mov ax, 0xFF9C\nmov bl, 7\nidiv bl\nhlt", Ax => 0xFEF2);
+    }
+
+    #[test]
+    fn decimal_adjust() {
+        assert_registers!("mov al, 0x38\nadd al, 0x45\ndaa\nhlt", Ax => 0x83);
+        assert_registers!("mov al, 0x83\nsub al, 0x38\ndas\nhlt", Ax => 0x45);
+        assert_registers!("mov ax, 0x0008\nadd al, 5\naaa\nhlt", Ax => 0x0103);
+        assert_registers!("mov ax, 0x0102\nsub al, 5\naas\nhlt", Ax => 0x0007);
+        assert_registers!("mov al, 9\nmov bl, 7\nmul bl\naam\nhlt", Ax => 0x0603);
+        assert_registers!("mov ax, 0x0603\naad\nhlt", Ax => 63);
+    }
+
+    #[test]
+    fn control_transfer() {
+        // The sum of 1 to 10.
+        assert_registers!(
+            "xor ax, ax\nmov cx, 10\nnext: add ax, cx\nloop next\nhlt",
+            Ax => 55,
+            Cx => 0,
+        );
+
+        assert_registers!(
+            "mov ax, 3\ncmp ax, 5\njl less\nmov bx, 1\nhlt\nless: mov bx, 2\nhlt",
+            Bx => 2,
+        );
+
+        assert_registers!(
+            "mov ax, 1\ncall double\ncall double\nhlt\ndouble: add ax, ax\nret",
+            Ax => 4,
+            Sp => 0,
+        );
+
+        assert_registers!(
+            "mov bx, target\njmp bx\nmov ax, 1\nhlt\ntarget: mov ax, 2\nhlt",
+            Ax => 2,
+        );
+
+        assert_registers!(
+            "cpu 186\npush 1\ncall function\npop bx\nhlt\nfunction: mov bp, sp\nmov ax, [bp + 2]\nret",
+            Ax => 1,
+            Sp => 0,
+        );
+
+        assert_registers!(
+            "xor cx, cx\njcxz done\nmov ax, 1\ndone: hlt",
+            Ax => 0,
+        );
+
+        // A far jump to the same code through another segment.
+        let machine = assert_registers!(
+            "jmp 0x0FFF:far_target + 0x10\nmov ax, 1\nhlt\nfar_target: mov ax, 2\nhlt",
+            Ax => 2,
+        );
+        assert_eq!(0x0FFF, machine.segment(ast::Segment::CS));
+    }
+
+    #[test]
+    fn strings() {
+        let machine = assert_registers!(
+            "mov si, source\nmov di, destination\nmov cx, 5\ncld\nrep movsb\nhlt\n\
+             source: db 'hello'\ndestination: db 0, 0, 0, 0, 0",
+            Cx => 0,
+        );
+        let destination = machine.register(Di) - 5;
+        let copied = (0..5)
+            .map(|i| machine.read_byte(LOAD_SEGMENT, destination + i))
+            .collect::<Vec<_>>();
+        assert_eq!(b"hello".to_vec(), copied);
+
+        // The length of a zero terminated string.
+        assert_registers!(
+            "mov di, text\nmov cx, 0xFFFF\nxor al, al\nrepne scasb\nnot cx\ndec cx\nhlt\n\
+             text: db 'abc', 0",
+            Cx => 3,
+        );
+
+        assert_registers!(
+            "mov si, left\nmov di, right\nmov cx, 4\nrep cmpsb\nhlt\n\
+             left: db 'abcd'\nright: db 'abxd'",
+            Cx => 1,
+        );
+
+        let machine = execute(
+            "mov di, buffer\nmov ax, 0x4141\nmov cx, 2\nrep stosw\n\
+             std\nmov si, buffer + 2\nlodsw\nhlt\nbuffer: dw 0, 0",
+        );
+        assert_eq!(0x4141, machine.register(Ax));
+        assert_eq!(machine.register(Di) - 4, machine.register(Si));
+    }
+
+    #[test]
+    fn interrupts() {
+        // Install a handler for interrupt 0x21 and call it.
+        let machine = assert_registers!(
+            "xor ax, ax\nmov es, ax\nmov word [es:0x84], handler\nmov [es:0x86], cs\n\
+             int 0x21\nhlt\nhandler: mov bx, 0x1234\niret",
+            Bx => 0x1234,
+            Sp => 0,
+        );
+        assert_eq!(LOAD_SEGMENT, machine.segment(ast::Segment::CS));
+
+        // Division by zero calls interrupt 0.
+        assert_registers!(
+            "xor ax, ax\nmov es, ax\nmov word [es:0], handler\nmov [es:2], cs\n\
+             mov bl, 0\ndiv bl\nhlt\nhandler: mov cx, 0xDEAD\niret",
+            Cx => 0xDEAD,
+        );
+    }
+
+    #[test]
+    fn flags() {
+        let machine = execute("stc\nstd\ncli\nhlt");
+        assert_flags!(machine, Carry = true, Direction = true, Interrupt = false);
+
+        assert_registers!("stc\npushf\npop ax\nhlt", Ax => 0x0003);
+        assert_registers!("mov ah, 0xFF\nsahf\nlahf\nhlt", Ax => 0xD700);
+        assert_registers!("stc\nsalc\nhlt", Ax => 0x00FF);
+
+        let machine = execute("mov ax, 0xFFFF\npush ax\npopf\nhlt");
+        assert_eq!(FLAGS_WRITABLE | FLAGS_RESERVED, machine.flags());
+    }
+
+    #[test]
+    fn ports() {
+        let machine =
+            execute("mov al, 0x42\nout 0x60, al\nmov dx, 0x3F8\nmov ax, 0x1234\nout dx, ax\nhlt");
+        assert_eq!(0x42, machine.port(0x60));
+        assert_eq!(0x34, machine.port(0x3F8));
+        assert_eq!(0x12, machine.port(0x3F9));
+
+        let mut machine = Machine::new();
+        machine.set_port(0x61, 0x99);
+        machine.load(LOAD_SEGMENT, &crate::compile("in al, 0x61\nhlt").unwrap());
+        assert_eq!(
+            Ok(2),
+            machine.run(STEP_LIMIT).map_err(|err| err.to_string())
+        );
+        assert_eq!(0x99, machine.byte_register(Al));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            run("start: jmp start", 100),
+            Err(RunError::ExecuteError(ExecuteError::StepLimitReached(100)))
+        ));
+
+        assert!(matches!(
+            run("nop\ndb 0x0F, 0x0B", 100),
+            Err(RunError::ExecuteError(ExecuteError::InvalidInstruction(
+                LOAD_SEGMENT,
+                1
+            )))
+        ));
+
+        match run("cpu 386\nmov ax, 1\nmov eax, 1\nhlt", 100) {
+            Err(RunError::ExecuteError(err)) => assert_eq!(
+                "Unsupported instruction at 1000:0003: MOV EAX, 0x01",
+                err.to_string()
+            ),
+            _ => panic!("32-bit registers are not supported"),
+        }
+
+        assert!(matches!(
+            run("mov ax, ", 100),
+            Err(RunError::CompileError(_))
+        ));
+    }
+
+    #[test]
+    fn step() {
+        let mut machine = Machine::new();
+        machine.load(
+            0x2000,
+            &crate::compile("mov ax, 1\nrep stosb\nhlt").unwrap(),
+        );
+
+        machine.step().unwrap();
+        assert_eq!(1, machine.register(Ax));
+        assert_eq!(3, machine.ip());
+
+        // The prefix and the instruction are one step.
+        machine.step().unwrap();
+        assert_eq!(5, machine.ip());
+
+        machine.step().unwrap();
+        assert!(machine.is_halted());
+        machine.step().unwrap();
+        assert_eq!(6, machine.ip());
+    }
+}
@@ -8,27 +8,35 @@ enum Base {
 }
 
 impl Base {
+    /// Returns the base for a prefix (after the "0") or suffix character.
     #[inline]
     fn try_from_char(c: char) -> Result<Self, ()> {
         Ok(match c {
-            'b' | 'B' => Self::Binary,
-            'o' | 'O' => Self::Octal,
-            'd' | 'D' => Self::Decimal,
-            'h' | 'H' => Self::Hexadecimal,
+            'b' | 'B' | 'y' | 'Y' => Self::Binary,
+            'o' | 'O' | 'q' | 'Q' => Self::Octal,
+            'd' | 'D' | 't' | 'T' => Self::Decimal,
+            'h' | 'H' | 'x' | 'X' => Self::Hexadecimal,
             _ => return Err(()),
         })
     }
 
+    /// Returns the lowest base that can represent all the digits in [s], ignoring `_` separators.
     fn detect_highest_for(s: &str) -> Self {
         let mut base = Self::Binary;
 
         for c in s.chars() {
-            if c > '1' {
-                base = Self::Octal;
-            } else if c > '8' {
-                base = Self::Decimal
-            } else if c > '9' {
-                base = Self::Hexadecimal
+            let required = if c > '9' {
+                Self::Hexadecimal
+            } else if c > '7' {
+                Self::Decimal
+            } else if c > '1' {
+                Self::Octal
+            } else {
+                Self::Binary
+            };
+
+            if c != '_' && required > base {
+                base = required;
             }
         }
 
@@ -38,23 +46,48 @@ impl Base {
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum LiteralKind {
-    Number(i32),
+    Number(i64),
+    /// A number that contains digits that are not valid for its base, e.g. "0b102".
+    InvalidNumber,
+    /// A number that does not fit into 64 bits.
+    NumberTooLarge,
     String(bool),
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PunctuationKind {
+    Ampersand,
+    Caret,
     CloseBracket,
     CloseParenthesis,
     Colon,
     Comma,
     Dot,
+    DoubleAmpersand,
+    DoubleCaret,
+    DoubleEqual,
+    DoubleForwardSlash,
+    DoubleGreaterThan,
+    DoubleLessThan,
+    DoublePercent,
+    DoublePipe,
+    Equal,
+    Exclamation,
+    ExclamationEqual,
     ForwardSlash,
+    GreaterThan,
+    GreaterThanEqual,
+    LessThan,
+    LessThanEqual,
+    LessThanGreaterThan,
     Minus,
     OpenBracket,
     OpenParenthesis,
+    Percent,
+    Pipe,
     Plus,
     Star,
+    Tilde,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -169,9 +202,16 @@ impl<'a> Lexer<'a> {
 
             c if is_decimal_digit(c) => self.number(c),
 
+            '$' if self.char_at(1).is_some_and(is_decimal_digit) => self.number_with_dollar(),
+
             c if is_identifier_first(c) => Token::Identifier(first_not_of!(self, is_identifier)),
 
-            '\'' => self.string_literal(),
+            // A dot directly followed by an identifier is a local label, e.g. ".loop".
+            '.' if self.char_at(1).is_some_and(is_identifier_first) => {
+                Token::Identifier(first_not_of!(self, is_identifier))
+            }
+
+            c @ ('\'' | '"' | '`') => self.string_literal(c),
 
             '\n' => Token::NewLine(1),
 
@@ -185,157 +225,133 @@ impl<'a> Lexer<'a> {
             '+' => Token::Punctuation(1, PunctuationKind::Plus),
             '-' => Token::Punctuation(1, PunctuationKind::Minus),
             '*' => Token::Punctuation(1, PunctuationKind::Star),
-            '/' => Token::Punctuation(1, PunctuationKind::ForwardSlash),
-
-            c => Token::Invalid(1, c),
+            '~' => Token::Punctuation(1, PunctuationKind::Tilde),
+
+            '/' => self.punctuation(
+                &[('/', PunctuationKind::DoubleForwardSlash)],
+                PunctuationKind::ForwardSlash,
+            ),
+            '%' => self.punctuation(
+                &[('%', PunctuationKind::DoublePercent)],
+                PunctuationKind::Percent,
+            ),
+            '&' => self.punctuation(
+                &[('&', PunctuationKind::DoubleAmpersand)],
+                PunctuationKind::Ampersand,
+            ),
+            '|' => self.punctuation(&[('|', PunctuationKind::DoublePipe)], PunctuationKind::Pipe),
+            '^' => self.punctuation(
+                &[('^', PunctuationKind::DoubleCaret)],
+                PunctuationKind::Caret,
+            ),
+            '=' => self.punctuation(
+                &[('=', PunctuationKind::DoubleEqual)],
+                PunctuationKind::Equal,
+            ),
+            '!' => self.punctuation(
+                &[('=', PunctuationKind::ExclamationEqual)],
+                PunctuationKind::Exclamation,
+            ),
+            '<' => self.punctuation(
+                &[
+                    ('<', PunctuationKind::DoubleLessThan),
+                    ('=', PunctuationKind::LessThanEqual),
+                    ('>', PunctuationKind::LessThanGreaterThan),
+                ],
+                PunctuationKind::LessThan,
+            ),
+            '>' => self.punctuation(
+                &[
+                    ('>', PunctuationKind::DoubleGreaterThan),
+                    ('=', PunctuationKind::GreaterThanEqual),
+                ],
+                PunctuationKind::GreaterThan,
+            ),
+
+            c => Token::Invalid(c.len_utf8(), c),
         }
     }
 
-    fn number(&mut self, first_char: char) -> Token {
-        debug_assert!(is_decimal_digit(first_char));
-
-        if first_char == '0' {
-            self.number_literal_with_prefix()
-        } else {
-            self.number_literal_with_suffix()
-        }
-    }
-
-    fn number_literal_with_prefix(&mut self) -> Token {
-        macro_rules! number_with_base {
-            ($prefix:expr, $is_digit:ident, $base:expr) => {{
-                debug_assert!(self.char_at(0).unwrap() == '0');
-                debug_assert!(self.char_at(1).unwrap() == $prefix);
-
-                let mut end = 1;
-
-                let found = first_not_of!(self, $is_digit, 2);
-
-                // If no additional characters were found, then we return the '0' character as the
-                // literal only.
-                if found == 0 {
-                    return Token::Literal(1, LiteralKind::Number(0));
+    /// Returns a 2 character punctuation if the second character matches one of the [pairs],
+    /// otherwise the single character punctuation [single].
+    fn punctuation(&self, pairs: &[(char, PunctuationKind)], single: PunctuationKind) -> Token {
+        if let Some(second) = self.char_at(1) {
+            for (c, kind) in pairs {
+                if *c == second {
+                    return Token::Punctuation(2, *kind);
                 }
-
-                // Consume the prefix.
-                end += 1;
-                let decode_pos = end;
-
-                // We have additional characters, so we can consume the prefix character and the found
-                // digits.
-                end += found;
-
-                // We can unwrap here, because we already made sure we only have valid characters.
-                #[allow(clippy::from_str_radix_10)]
-                let value = i32::from_str_radix(&self.source[decode_pos..end], $base).unwrap();
-                Token::Literal(end, LiteralKind::Number(value))
-            }};
-        }
-
-        if let Some(second_char) = self.char_at(1) {
-            // If we have a prefix at this point, then we can handle it immediately.
-            match second_char {
-                c @ 'b' => return number_with_base!(c, is_binary_digit, 2),
-                c @ 'o' => return number_with_base!(c, is_octal_digit, 8),
-                c @ 'd' => return number_with_base!(c, is_decimal_digit, 10),
-                c @ 'x' => return number_with_base!(c, is_hexadecimal_digit, 16),
-
-                // [second_char] is not a valid prefix, so fall through to handle other formats.
-                _ => self.number_literal_with_suffix(),
             }
-        } else {
-            // We reached the end of the source and we only have the '0' character.
-            Token::Literal(1, LiteralKind::Number(0))
         }
-    }
-
-    fn number_literal_with_suffix(&mut self) -> Token {
-        debug_assert!(is_decimal_digit(self.char_at(0).unwrap()));
 
-        // Consume as many of the highest base (hexadecimal) characters as we can.
-        let mut end = first_not_of!(self, is_hexadecimal_digit);
+        Token::Punctuation(1, single)
+    }
 
-        let mut s = &self.source[..end];
+    /// Consume a number literal.  The number can have a base prefix (`0x`, `0h`, `0d`, `0t`,
+    /// `0o`, `0q`, `0b`, `0y`) or suffix (`h`, `x`, `d`, `t`, `o`, `q`, `b`, `y`) and can contain
+    /// `_` separators.  Without a prefix or suffix the number is decimal.
+    fn number(&mut self, first_char: char) -> Token {
+        debug_assert!(is_decimal_digit(first_char));
 
-        // We can unwrap here, because we are guaranteed to have at least a single numeric
-        // character.
-        let suffix = s.chars().last().unwrap();
+        let end = first_not_of!(self, is_number);
+        let s = &self.source[..end];
 
-        let base = match Base::try_from_char(suffix) {
-            Ok(base) => {
-                // If we found a suffix included in the string already, we have to cut it off.
-                s = &s[..s.len() - 1];
+        // Try a prefix first, then a suffix, so that "0b1h" is hexadecimal.
+        let mut chars = s.chars();
+        if let (Some('0'), Some(prefix)) = (chars.next(), chars.next()) {
+            if let Ok(base) = Base::try_from_char(prefix) {
+                if let Some(kind) = number_with_base(&s[2..], base) {
+                    return Token::Literal(end, kind);
+                }
 
-                base
-            }
-            Err(_) => {
-                // Consume another character to see if there is a suffix that is not a valid
-                // hexadecimal character as well.
-                match self.char_at(end) {
-                    Some(c) => match Base::try_from_char(c) {
-                        Ok(base) => {
-                            // Consume the suffix.
-                            end += 1;
-
-                            base
-                        }
-                        Err(_) => Base::Decimal,
-                    },
-                    None => Base::Decimal,
+                // "0x", "0o" and "0b" are only written as prefixes, so without digits they are not
+                // a 0 with a suffix.
+                if s.len() == 2 && matches!(prefix.to_ascii_lowercase(), 'x' | 'o' | 'b') {
+                    return Token::Literal(end, LiteralKind::InvalidNumber);
                 }
             }
-        };
-
-        let highest_base = Base::detect_highest_for(s);
-
-        if highest_base > base {
-            todo!("The requested base in lower than the detected base")
         }
 
-        // We can unwrap here, because we already checked that all the characters are valid for the
-        // base.
-        let value = i32::from_str_radix(s, base as u32).unwrap();
+        let suffix = s.chars().last().unwrap();
+        let kind = match Base::try_from_char(suffix) {
+            Ok(base) => number_with_base(&s[..s.len() - 1], base),
+            Err(_) => number_with_base(s, Base::Decimal),
+        };
 
-        Token::Literal(end, LiteralKind::Number(value))
+        Token::Literal(end, kind.unwrap_or(LiteralKind::InvalidNumber))
     }
 
-    fn string_literal(&mut self) -> Token {
-        // Consume the opening character.
-        let mut end = 1;
+    /// Consume a hexadecimal number literal in the form "$0A".
+    fn number_with_dollar(&mut self) -> Token {
+        debug_assert_eq!(self.char_at(0), Some('$'));
 
-        let first_terminator = self.source[end..].find('\'');
-        let first_new_line = self.source[end..].find('\n');
-
-        match (first_terminator, first_new_line) {
-            (None, None) => {
-                end = self.source.len();
-                Token::Literal(end, LiteralKind::String(false))
-            }
-
-            (None, Some(new_line)) => {
-                // Consume until the '\n' character.
-                end += new_line;
-                Token::Literal(end, LiteralKind::String(false))
-            }
+        let end = 1 + first_not_of!(self, is_number, 1);
 
-            (Some(terminator), None) => {
-                // Consume the text and the terminator.
-                end += terminator + 1;
-                Token::Literal(end, LiteralKind::String(true))
-            }
-
-            (Some(terminator), Some(new_line)) => {
-                if new_line < terminator {
-                    end += new_line;
-
-                    Token::Literal(end, LiteralKind::String(false))
-                } else {
-                    end += terminator + 1;
+        Token::Literal(
+            end,
+            number_with_base(&self.source[1..end], Base::Hexadecimal)
+                .unwrap_or(LiteralKind::InvalidNumber),
+        )
+    }
 
-                    Token::Literal(end, LiteralKind::String(true))
+    /// Consume a string literal that starts and ends with the [quote] character.  In backquoted
+    /// strings a backslash escapes the next character, so "`\``" does not end the string.
+    fn string_literal(&mut self, quote: char) -> Token {
+        let mut chars = self.source.char_indices().skip(1);
+
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '\n' => return Token::Literal(index, LiteralKind::String(false)),
+                '\\' if quote == '`' => {
+                    if let Some((_, '\n')) | None = chars.next() {
+                        return Token::Literal(index + 1, LiteralKind::String(false));
+                    }
                 }
+                c if c == quote => return Token::Literal(index + 1, LiteralKind::String(true)),
+                _ => {}
             }
         }
+
+        Token::Literal(self.source.len(), LiteralKind::String(false))
     }
 
     // pub fn _source_line_current(&self, path: Option<&str>) -> String {
@@ -377,29 +393,48 @@ impl<'a> Lexer<'a> {
     // }
 }
 
-#[inline]
-fn is_identifier_first(c: char) -> bool {
-    ('a'..='z').contains(&c) | ('A'..='Z').contains(&c) || c == '_'
+/// Returns the literal for the digits in [s] in the given base.  Returns [None] if [s] contains no
+/// digits or digits that are not valid for the base.
+fn number_with_base(s: &str, base: Base) -> Option<LiteralKind> {
+    if !s.chars().any(is_hexadecimal_digit) || Base::detect_highest_for(s) > base {
+        return None;
+    }
+
+    let base = base as u64;
+    let mut value = 0_u64;
+
+    for c in s.chars().filter(|c| *c != '_') {
+        let digit = c.to_digit(16)? as u64;
+        value = match value
+            .checked_mul(base)
+            .and_then(|value| value.checked_add(digit))
+        {
+            Some(value) => value,
+            None => return Some(LiteralKind::NumberTooLarge),
+        };
+    }
+
+    Some(LiteralKind::Number(value as i64))
 }
 
 #[inline]
-fn is_identifier(c: char) -> bool {
-    is_identifier_first(c) || is_decimal_digit(c)
+fn is_number(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
 }
 
 #[inline]
-fn is_whitespace(c: char) -> bool {
-    c == ' ' || c == '\t' || c == '\r'
+fn is_identifier_first(c: char) -> bool {
+    ('a'..='z').contains(&c) | ('A'..='Z').contains(&c) || c == '_' || c == '@'
 }
 
 #[inline]
-fn is_binary_digit(c: char) -> bool {
-    c == '0' || c == '1'
+fn is_identifier(c: char) -> bool {
+    is_identifier_first(c) || is_decimal_digit(c) || c == '.'
 }
 
 #[inline]
-fn is_octal_digit(c: char) -> bool {
-    ('0'..='7').contains(&c)
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\r'
 }
 
 #[inline]
@@ -504,6 +539,27 @@ mod tests {
             Token::Literal(17, LiteralKind::String(false)),
             "'a string literal"
         );
+
+        assert_next_token!(
+            "\"double quoted\" test",
+            Token::Literal(15, LiteralKind::String(true)),
+            "\"double quoted\""
+        );
+        assert_next_token!(
+            "'it\\'s' test",
+            Token::Literal(5, LiteralKind::String(true)),
+            "'it\\'"
+        );
+        assert_next_token!(
+            "`it\\'s\\`` test",
+            Token::Literal(9, LiteralKind::String(true)),
+            "`it\\'s\\``"
+        );
+        assert_next_token!(
+            "`ends with escape\\",
+            Token::Literal(18, LiteralKind::String(false)),
+            "`ends with escape\\"
+        );
     }
 
     #[test]
@@ -546,6 +602,141 @@ mod tests {
         // hex
         assert_next_token!("0c8h", Token::Literal(4, LiteralKind::Number(200)), "0c8h");
         assert_next_token!("0xc8", Token::Literal(4, LiteralKind::Number(200)), "0xc8");
+        assert_next_token!("0Xc8", Token::Literal(4, LiteralKind::Number(200)), "0Xc8");
+        assert_next_token!("0hc8", Token::Literal(4, LiteralKind::Number(200)), "0hc8");
+        assert_next_token!("c8x", Token::Identifier(3), "c8x");
+        assert_next_token!("0c8x", Token::Literal(4, LiteralKind::Number(200)), "0c8x");
+        assert_next_token!("$0c8", Token::Literal(4, LiteralKind::Number(200)), "$0c8");
+        assert_next_token!(
+            "0BEEFh",
+            Token::Literal(6, LiteralKind::Number(0xBEEF)),
+            "0BEEFh"
+        );
+        assert_next_token!("0b1h", Token::Literal(4, LiteralKind::Number(0xB1)), "0b1h");
+
+        // other prefixes and suffixes
+        assert_next_token!("0y101", Token::Literal(5, LiteralKind::Number(5)), "0y101");
+        assert_next_token!("101y", Token::Literal(4, LiteralKind::Number(5)), "101y");
+        assert_next_token!("0q17", Token::Literal(4, LiteralKind::Number(15)), "0q17");
+        assert_next_token!("17q", Token::Literal(3, LiteralKind::Number(15)), "17q");
+        assert_next_token!("0t99", Token::Literal(4, LiteralKind::Number(99)), "0t99");
+        assert_next_token!("99t", Token::Literal(3, LiteralKind::Number(99)), "99t");
+
+        // separators
+        assert_next_token!(
+            "1_000_000",
+            Token::Literal(9, LiteralKind::Number(1_000_000)),
+            "1_000_000"
+        );
+        assert_next_token!(
+            "0xFFFF_FFFF",
+            Token::Literal(11, LiteralKind::Number(0xFFFF_FFFF)),
+            "0xFFFF_FFFF"
+        );
+        assert_next_token!(
+            "1010_0101b",
+            Token::Literal(10, LiteralKind::Number(0xA5)),
+            "1010_0101b"
+        );
+
+        // 64 bit
+        assert_next_token!(
+            "0xFFFFFFFFFFFFFFFF",
+            Token::Literal(18, LiteralKind::Number(-1)),
+            "0xFFFFFFFFFFFFFFFF"
+        );
+        assert_next_token!(
+            "0x10000000000000000",
+            Token::Literal(19, LiteralKind::NumberTooLarge),
+            "0x10000000000000000"
+        );
+
+        // invalid digits
+        assert_next_token!(
+            "0b102",
+            Token::Literal(5, LiteralKind::InvalidNumber),
+            "0b102"
+        );
+        assert_next_token!("19o", Token::Literal(3, LiteralKind::InvalidNumber), "19o");
+        assert_next_token!(
+            "12ab",
+            Token::Literal(4, LiteralKind::InvalidNumber),
+            "12ab"
+        );
+        assert_next_token!("0x_", Token::Literal(3, LiteralKind::InvalidNumber), "0x_");
+
+        // A prefix without digits.
+        assert_next_token!("0x", Token::Literal(2, LiteralKind::InvalidNumber), "0x");
+        assert_next_token!("0B\n", Token::Literal(2, LiteralKind::InvalidNumber), "0B");
+        assert_next_token!("0o", Token::Literal(2, LiteralKind::InvalidNumber), "0o");
+        // These are a 0 with a suffix.
+        assert_next_token!("0h", Token::Literal(2, LiteralKind::Number(0)), "0h");
+        assert_next_token!("0d", Token::Literal(2, LiteralKind::Number(0)), "0d");
+    }
+
+    #[test]
+    fn detect_highest_base() {
+        assert_eq!(Base::detect_highest_for("0101"), Base::Binary);
+        assert_eq!(Base::detect_highest_for("0127"), Base::Octal);
+        assert_eq!(Base::detect_highest_for("0189"), Base::Decimal);
+        assert_eq!(Base::detect_highest_for("01af"), Base::Hexadecimal);
+        assert_eq!(Base::detect_highest_for("1_1"), Base::Binary);
+    }
+
+    #[test]
+    fn punctuation() {
+        assert_next_token!(
+            "/ 2",
+            Token::Punctuation(1, PunctuationKind::ForwardSlash),
+            "/"
+        );
+        assert_next_token!(
+            "// 2",
+            Token::Punctuation(2, PunctuationKind::DoubleForwardSlash),
+            "//"
+        );
+        assert_next_token!(
+            "<<1",
+            Token::Punctuation(2, PunctuationKind::DoubleLessThan),
+            "<<"
+        );
+        assert_next_token!(
+            "<=1",
+            Token::Punctuation(2, PunctuationKind::LessThanEqual),
+            "<="
+        );
+        assert_next_token!(
+            "<>1",
+            Token::Punctuation(2, PunctuationKind::LessThanGreaterThan),
+            "<>"
+        );
+        assert_next_token!("< 1", Token::Punctuation(1, PunctuationKind::LessThan), "<");
+        assert_next_token!(
+            ">>1",
+            Token::Punctuation(2, PunctuationKind::DoubleGreaterThan),
+            ">>"
+        );
+        assert_next_token!(
+            "!=1",
+            Token::Punctuation(2, PunctuationKind::ExclamationEqual),
+            "!="
+        );
+        assert_next_token!(
+            "!1",
+            Token::Punctuation(1, PunctuationKind::Exclamation),
+            "!"
+        );
+        assert_next_token!(
+            "&&",
+            Token::Punctuation(2, PunctuationKind::DoubleAmpersand),
+            "&&"
+        );
+        assert_next_token!(
+            "%%",
+            Token::Punctuation(2, PunctuationKind::DoublePercent),
+            "%%"
+        );
+        assert_next_token!("~1", Token::Punctuation(1, PunctuationKind::Tilde), "~");
     }
 
     #[test]
@@ -557,6 +748,29 @@ mod tests {
         assert_next_token!("test123", Token::Identifier(7), "test123");
         assert_next_token!("test123\n", Token::Identifier(7), "test123");
 
-        assert_next_token!("1tst", Token::Literal(1, LiteralKind::Number(1)), "1");
+        assert_next_token!(
+            "1tst",
+            Token::Literal(4, LiteralKind::InvalidNumber),
+            "1tst"
+        );
+
+        // local labels
+        assert_next_token!(".loop", Token::Identifier(5), ".loop");
+        assert_next_token!(".loop:", Token::Identifier(5), ".loop");
+        assert_next_token!("func.loop", Token::Identifier(9), "func.loop");
+        assert_next_token!(". ", Token::Punctuation(1, PunctuationKind::Dot), ".");
+
+        // anonymous labels
+        assert_next_token!("@@:", Token::Identifier(2), "@@");
+        assert_next_token!("@f\n", Token::Identifier(2), "@f");
+        assert_next_token!("@b", Token::Identifier(2), "@b");
+    }
+
+    #[test]
+    fn invalid() {
+        assert_next_token!("?", Token::Invalid(1, '?'), "?");
+        // Characters that take more than one byte are taken as a whole.
+        assert_next_token!("é\n", Token::Invalid(2, 'é'), "é");
+        assert_next_token!("€x", Token::Invalid(3, '€'), "€");
     }
 }
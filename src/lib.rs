@@ -1,10 +1,18 @@
 pub mod ast;
 pub mod compiler;
+pub mod decoder;
 pub mod diagnostics;
 mod encoder;
+mod encoding;
+pub mod explain;
+pub mod formatter;
+pub mod interpreter;
 pub mod lexer;
+pub mod lsp;
 mod operations;
 pub mod parser;
+mod suggest;
+pub mod syntax;
 
 #[derive(Debug)]
 pub enum CompileError {
@@ -12,9 +20,43 @@ pub enum CompileError {
     CompileError(compiler::CompileError),
 }
 
+impl CompileError {
+    pub fn span(&self) -> &ast::Span {
+        match self {
+            CompileError::ParserError(err) => err.span(),
+            CompileError::CompileError(err) => err.span(),
+        }
+    }
+
+    /// A stable identifier for the kind of error, which can be looked up with
+    /// [explain::explain].
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompileError::ParserError(err) => err.code(),
+            CompileError::CompileError(err) => err.code(),
+        }
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::ParserError(err) => write!(f, "{}", err),
+            CompileError::CompileError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
 pub fn compile(source: &str) -> Result<Vec<u8>, CompileError> {
+    compile_with(compiler::Compiler::default(), source)
+}
+
+/// Compile [source] with a compiler that was already configured.
+pub(crate) fn compile_with(
+    mut compiler: compiler::Compiler,
+    source: &str,
+) -> Result<Vec<u8>, CompileError> {
     let mut parser = parser::Parser::new(source);
-    let mut compiler = compiler::Compiler::default();
 
     while let Some(line) = parser.parse_line().map_err(CompileError::ParserError)? {
         compiler
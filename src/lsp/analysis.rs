@@ -0,0 +1,416 @@
+//! Everything the language server knows about a document: the errors, where symbols are defined
+//! and referenced, and the offset and encoding of each line.
+
+use crate::ast;
+use crate::compiler::Compiler;
+use crate::parser::Parser;
+use crate::CompileError;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SymbolKind {
+    Label,
+    Constant,
+    Struct,
+    Field,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Symbol {
+    /// The qualified name, e.g. "func.loop" for a local label ".loop" after "func".
+    pub name: String,
+    pub span: ast::Span,
+    pub kind: SymbolKind,
+    /// The offset of a label, the value of a constant or the size of a structure, if it could be
+    /// resolved.
+    pub value: Option<i64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Reference {
+    /// The qualified name of the referenced symbol.
+    pub name: String,
+    pub span: ast::Span,
+}
+
+/// An instruction or data definition with its place in the output.
+#[derive(Debug, PartialEq)]
+pub struct Listing {
+    pub span: ast::Span,
+    pub offset: u16,
+    /// The encoded bytes, empty if the document did not compile.
+    pub bytes: Vec<u8>,
+    pub size: u16,
+}
+
+#[derive(Default)]
+pub struct Analysis {
+    pub errors: Vec<CompileError>,
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+    pub listing: Vec<Listing>,
+
+    /// The last global label, which local labels are qualified with.
+    scope: Option<String>,
+}
+
+impl Analysis {
+    /// Parse and compile the source.  Parsing continues on the next line after an error, so all
+    /// syntax errors are reported.  Errors from the compiler are only reported if the source
+    /// parsed without errors, because missing lines cause errors that are not really there.
+    pub fn new(source: &str) -> Self {
+        let mut analysis = Self::default();
+
+        let mut parser = Parser::new(source);
+        let mut compiler = Compiler::default();
+
+        loop {
+            match parser.parse_line() {
+                Ok(Some(line)) => {
+                    analysis.visit_line(&line);
+                    if let Err(err) = compiler.push_line(line) {
+                        analysis.errors.push(CompileError::CompileError(err));
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    analysis.errors.push(CompileError::ParserError(err));
+                    parser.skip_line();
+                }
+            }
+        }
+
+        let output = match compiler.compile() {
+            Ok(output) => output,
+            Err(err) => {
+                if analysis.errors.is_empty() {
+                    analysis.errors.push(CompileError::CompileError(err));
+                }
+                vec![]
+            }
+        };
+
+        for symbol in analysis.symbols.iter_mut() {
+            symbol.value = match symbol.kind {
+                SymbolKind::Label => compiler.label_offset(&symbol.name).map(i64::from),
+                SymbolKind::Struct => compiler.constant(&format!("{}_size", symbol.name)),
+                _ => compiler.constant(&symbol.name),
+            };
+        }
+
+        for (line, offset, size) in compiler.lines() {
+            if let ast::Line::Instruction(..) | ast::Line::Data(..) = line {
+                analysis.listing.push(Listing {
+                    span: line.span().clone(),
+                    offset,
+                    bytes: output
+                        .get(offset as usize..offset as usize + size as usize)
+                        .map_or_else(Vec::new, <[u8]>::to_vec),
+                    size,
+                });
+            }
+        }
+
+        analysis
+    }
+
+    /// The qualified name of the symbol defined or referenced at the offset in the source.
+    pub fn name_at(&self, offset: usize) -> Option<&str> {
+        let contains = |span: &ast::Span| span.start <= offset && offset <= span.end;
+
+        self.symbols
+            .iter()
+            .find(|symbol| contains(&symbol.span))
+            .map(|symbol| symbol.name.as_str())
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|reference| contains(&reference.span))
+                    .map(|reference| reference.name.as_str())
+            })
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    pub fn references_to<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Reference> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.name == name)
+    }
+
+    /// The instruction or data definition at the offset in the source.
+    pub fn listing_at(&self, offset: usize) -> Option<&Listing> {
+        self.listing
+            .iter()
+            .find(|listing| listing.span.start <= offset && offset <= listing.span.end)
+    }
+
+    fn qualified_name(&self, label: &ast::Label) -> String {
+        match &self.scope {
+            Some(global) if label.is_local() => format!("{}{}", global, label.1),
+            _ => label.1.clone(),
+        }
+    }
+
+    /// Anonymous labels and the references to them are not symbols.
+    fn is_anonymous(label: &ast::Label) -> bool {
+        label.is_anonymous() || matches!(label.1.to_lowercase().as_str(), "@b" | "@f" | "@r")
+    }
+
+    fn define(&mut self, name: String, span: &ast::Span, kind: SymbolKind) {
+        self.symbols.push(Symbol {
+            name,
+            span: span.clone(),
+            kind,
+            value: None,
+        });
+    }
+
+    fn visit_line(&mut self, line: &ast::Line) {
+        match line {
+            ast::Line::Label(label) => {
+                if Self::is_anonymous(label) {
+                    return;
+                }
+                let name = self.qualified_name(label);
+                if !label.is_local() {
+                    self.scope = Some(label.1.clone());
+                }
+                self.define(name, &label.0, SymbolKind::Label);
+            }
+
+            ast::Line::Constant(_, expr) => {
+                // The label on the line before is the name of the constant.
+                if let Some(symbol) = self.symbols.last_mut() {
+                    if symbol.kind == SymbolKind::Label {
+                        symbol.kind = SymbolKind::Constant;
+                    }
+                }
+                self.visit_expression(expr);
+            }
+
+            ast::Line::Instruction(insn) => match &insn.operands {
+                ast::Operands::None(_) => {}
+                ast::Operands::Destination(_, dst) => self.visit_operand(dst),
+                ast::Operands::DestinationAndSource(_, dst, src) => {
+                    self.visit_operand(dst);
+                    self.visit_operand(src);
+                }
+                ast::Operands::DestinationSourceAndThird(_, dst, src, third) => {
+                    self.visit_operand(dst);
+                    self.visit_operand(src);
+                    self.visit_operand(third);
+                }
+            },
+
+            ast::Line::Data(_, _, items) => {
+                for item in items {
+                    if let ast::DataItem::Expression(expr) = item {
+                        self.visit_expression(expr);
+                    }
+                }
+            }
+
+            ast::Line::Times(_, expr, line) => {
+                self.visit_expression(expr);
+                self.visit_line(line);
+            }
+
+            ast::Line::Struct(_, name, fields) => {
+                self.define(name.1.clone(), &name.0, SymbolKind::Struct);
+                for ast::StructField(_, label, _, count) in fields {
+                    if let Some(label) = label {
+                        let field_name = if label.is_local() {
+                            format!("{}{}", name.1, label.1)
+                        } else {
+                            label.1.clone()
+                        };
+                        self.define(field_name, &label.0, SymbolKind::Field);
+                    }
+                    self.visit_expression(count);
+                }
+            }
+
+            ast::Line::StructInstance(_, name, fields) => {
+                self.references.push(Reference {
+                    name: name.1.clone(),
+                    span: name.0.clone(),
+                });
+                for ast::StructInstanceField(_, field, line) in fields {
+                    self.visit_expression(field);
+                    self.visit_line(line);
+                }
+            }
+
+            ast::Line::Align(_, alignment, fill) => {
+                self.visit_expression(alignment);
+                if let Some(fill) = fill {
+                    self.visit_expression(fill);
+                }
+            }
+
+            ast::Line::Cpu(..) | ast::Line::Bits(..) => {}
+        }
+    }
+
+    fn visit_operand(&mut self, operand: &ast::Operand) {
+        match operand {
+            ast::Operand::Immediate(_, expr, _) | ast::Operand::Direct(_, expr, ..) => {
+                self.visit_expression(expr)
+            }
+            ast::Operand::Indirect(_, _, expr, ..) | ast::Operand::Indirect32(_, _, expr, ..) => {
+                if let Some(expr) = expr {
+                    self.visit_expression(expr);
+                }
+            }
+            ast::Operand::Far(_, offset, segment) => {
+                self.visit_expression(offset);
+                self.visit_expression(segment);
+            }
+            ast::Operand::Register(..) | ast::Operand::Segment(..) => {}
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &ast::Expression) {
+        for value in expr.iter_values() {
+            if let ast::Value::Label(label) = value {
+                if !Self::is_anonymous(label) {
+                    self.references.push(Reference {
+                        name: self.qualified_name(label),
+                        span: label.0.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_and_references() {
+        const SOURCE: &str = "ten equ 10\nstart:\n.loop: mov ax, ten\njmp .loop\njmp start\n";
+        let analysis = Analysis::new(SOURCE);
+
+        assert!(analysis.errors.is_empty());
+        assert_eq!(
+            analysis.symbols,
+            vec![
+                Symbol {
+                    name: "ten".to_owned(),
+                    span: 0..3,
+                    kind: SymbolKind::Constant,
+                    value: Some(10),
+                },
+                Symbol {
+                    name: "start".to_owned(),
+                    span: 11..16,
+                    kind: SymbolKind::Label,
+                    value: Some(0),
+                },
+                Symbol {
+                    name: "start.loop".to_owned(),
+                    span: 18..23,
+                    kind: SymbolKind::Label,
+                    value: Some(0),
+                },
+            ]
+        );
+        assert_eq!(
+            analysis.references,
+            vec![
+                Reference {
+                    name: "ten".to_owned(),
+                    span: 33..36,
+                },
+                Reference {
+                    name: "start.loop".to_owned(),
+                    span: 41..46,
+                },
+                Reference {
+                    name: "start".to_owned(),
+                    span: 51..56,
+                },
+            ]
+        );
+
+        assert_eq!(analysis.name_at(19), Some("start.loop"));
+        assert_eq!(analysis.name_at(46), Some("start.loop"));
+        assert_eq!(analysis.name_at(28), None);
+        assert_eq!(analysis.references_to("start.loop").count(), 1);
+        assert_eq!(analysis.definition("ten").unwrap().span, 0..3);
+    }
+
+    #[test]
+    fn listing() {
+        let analysis = Analysis::new("mov ax, 1\ndb 1, 2\nnop\n");
+
+        assert_eq!(
+            analysis.listing,
+            vec![
+                Listing {
+                    span: 0..9,
+                    offset: 0,
+                    bytes: vec![0xB8, 0x01, 0x00],
+                    size: 3,
+                },
+                Listing {
+                    span: 10..17,
+                    offset: 3,
+                    bytes: vec![0x01, 0x02],
+                    size: 2,
+                },
+                Listing {
+                    span: 18..21,
+                    offset: 5,
+                    bytes: vec![0x90],
+                    size: 1,
+                },
+            ]
+        );
+        assert_eq!(analysis.listing_at(19).unwrap().offset, 5);
+    }
+
+    #[test]
+    fn structures() {
+        let analysis =
+            Analysis::new("struc point\n.x: resw 1\n.y: resw 1\nendstruc\np: istruc point\nat point.y, dw 1\niend\n");
+
+        assert!(analysis.errors.is_empty());
+        let fields = analysis
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind, symbol.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("point", SymbolKind::Struct, Some(4)),
+                ("point.x", SymbolKind::Field, Some(0)),
+                ("point.y", SymbolKind::Field, Some(2)),
+                ("p", SymbolKind::Label, Some(0)),
+            ]
+        );
+        assert_eq!(analysis.references_to("point").count(), 1);
+        assert_eq!(analysis.references_to("point.y").count(), 1);
+    }
+
+    #[test]
+    fn errors() {
+        // Every line with a syntax error is reported, the compiler error is not.
+        let analysis = Analysis::new("mov ax,\nmvo ax, 1\njmp missing\n");
+        let codes = analysis
+            .errors
+            .iter()
+            .map(|err| err.code())
+            .collect::<Vec<_>>();
+        assert_eq!(codes, vec!["E0004", "E0016"]);
+
+        let analysis = Analysis::new("jmp missing\n");
+        assert_eq!(analysis.errors.len(), 1);
+        assert_eq!(analysis.errors[0].code(), "E0107");
+    }
+}
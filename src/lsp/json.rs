@@ -0,0 +1,412 @@
+//! A small JSON reader and writer, enough for the messages of the language server protocol.
+
+use crate::diagnostics::json_string;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members are kept in the order they were written.
+    Object(Vec<(String, Value)>),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum JsonError {
+    UnexpectedEndOfInput,
+    UnexpectedCharacter(usize, char),
+    InvalidNumber(usize),
+    InvalidEscapeSequence(usize),
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::UnexpectedEndOfInput => write!(f, "Unexpected end of input."),
+            JsonError::UnexpectedCharacter(offset, c) => {
+                write!(f, "Unexpected character '{}' at offset {}.", c, offset)
+            }
+            JsonError::InvalidNumber(offset) => write!(f, "Invalid number at offset {}.", offset),
+            JsonError::InvalidEscapeSequence(offset) => {
+                write!(f, "Invalid escape sequence at offset {}.", offset)
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Build an object from a list of members.
+    pub fn object(members: Vec<(&str, Value)>) -> Value {
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// The member of an object with the given name.  Returns [Value::Null] if this is not an
+    /// object or the member does not exist, so lookups can be chained.
+    pub fn get(&self, name: &str) -> &Value {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == name)
+                .map_or(&Value::Null, |(_, value)| value),
+            _ => &Value::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Self {
+        Value::Array(values)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Value::Number(_) => write!(f, "null"),
+            Value::String(s) => write!(f, "{}", json_string(s)),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", json_string(name), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Parse a complete JSON document.
+pub fn parse(source: &str) -> Result<Value, JsonError> {
+    let mut reader = Reader { source, pos: 0 };
+
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    match reader.peek() {
+        None => Ok(value),
+        Some(c) => Err(JsonError::UnexpectedCharacter(reader.pos, c)),
+    }
+}
+
+struct Reader<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Result<char, JsonError> {
+        let c = self.peek().ok_or(JsonError::UnexpectedEndOfInput)?;
+        self.pos += c.len_utf8();
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        let pos = self.pos;
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(JsonError::UnexpectedCharacter(pos, c)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, JsonError> {
+        for expected in keyword.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+
+        match self.peek().ok_or(JsonError::UnexpectedEndOfInput)? {
+            'n' => self.keyword("null", Value::Null),
+            't' => self.keyword("true", Value::Bool(true)),
+            'f' => self.keyword("false", Value::Bool(false)),
+            '"' => Ok(Value::String(self.string()?)),
+            '[' => self.array(),
+            '{' => self.object(),
+            '-' | '0'..='9' => self.number(),
+            c => Err(JsonError::UnexpectedCharacter(self.pos, c)),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError> {
+        self.expect('[')?;
+
+        let mut values = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+            let pos = self.pos;
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(Value::Array(values)),
+                c => return Err(JsonError::UnexpectedCharacter(pos, c)),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError> {
+        self.expect('{')?;
+
+        let mut members = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let name = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            members.push((name, self.value()?));
+
+            self.skip_whitespace();
+            let pos = self.pos;
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(Value::Object(members)),
+                c => return Err(JsonError::UnexpectedCharacter(pos, c)),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+
+        while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            self.pos += 1;
+        }
+
+        self.source[start..self.pos]
+            .parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| JsonError::InvalidNumber(start))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+
+        let mut result = String::new();
+
+        loop {
+            let pos = self.pos;
+            match self.next()? {
+                '"' => return Ok(result),
+                '\\' => match self.next()? {
+                    '"' => result.push('"'),
+                    '\\' => result.push('\\'),
+                    '/' => result.push('/'),
+                    'b' => result.push('\u{08}'),
+                    'f' => result.push('\u{0C}'),
+                    'n' => result.push('\n'),
+                    'r' => result.push('\r'),
+                    't' => result.push('\t'),
+                    'u' => {
+                        let first = self.hex4(pos)?;
+                        let c = if (0xD800..0xDC00).contains(&first) {
+                            // A surrogate pair.
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let second = self.hex4(pos)?;
+                            if !(0xDC00..0xE000).contains(&second) {
+                                return Err(JsonError::InvalidEscapeSequence(pos));
+                            }
+                            0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+                        } else {
+                            first
+                        };
+                        result
+                            .push(char::from_u32(c).ok_or(JsonError::InvalidEscapeSequence(pos))?);
+                    }
+                    _ => return Err(JsonError::InvalidEscapeSequence(pos)),
+                },
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self, escape: usize) -> Result<u32, JsonError> {
+        let digits = self
+            .source
+            .get(self.pos..self.pos + 4)
+            .ok_or(JsonError::UnexpectedEndOfInput)?;
+        let value = u32::from_str_radix(digits, 16)
+            .map_err(|_| JsonError::InvalidEscapeSequence(escape))?;
+        self.pos += 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse("null"), Ok(Value::Null));
+        assert_eq!(parse(" true "), Ok(Value::Bool(true)));
+        assert_eq!(parse("-12.5e1"), Ok(Value::Number(-125.0)));
+        assert_eq!(
+            parse(r#""a\"b\\c\né😀""#),
+            Ok(Value::String("a\"b\\c\n\u{e9}\u{1F600}".to_owned()))
+        );
+        assert_eq!(
+            parse(r#"{"a": [1, {}, []], "b": {"c": null}}"#),
+            Ok(Value::object(vec![
+                (
+                    "a",
+                    Value::Array(vec![
+                        Value::Number(1.0),
+                        Value::Object(vec![]),
+                        Value::Array(vec![])
+                    ])
+                ),
+                ("b", Value::object(vec![("c", Value::Null)])),
+            ]))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse(""), Err(JsonError::UnexpectedEndOfInput));
+        assert_eq!(parse("[1,"), Err(JsonError::UnexpectedEndOfInput));
+        assert_eq!(parse("[1 2]"), Err(JsonError::UnexpectedCharacter(3, '2')));
+        assert_eq!(parse("nul"), Err(JsonError::UnexpectedEndOfInput));
+        assert_eq!(parse("1 1"), Err(JsonError::UnexpectedCharacter(2, '1')));
+        assert_eq!(parse("1-"), Err(JsonError::InvalidNumber(0)));
+        assert_eq!(parse(r#""\x""#), Err(JsonError::InvalidEscapeSequence(1)));
+    }
+
+    #[test]
+    fn write_values() {
+        let value = Value::object(vec![
+            ("id", 1_u64.into()),
+            ("name", "a \"b\"".into()),
+            (
+                "list",
+                vec![Value::Null, true.into(), Value::Number(0.5)].into(),
+            ),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"id":1,"name":"a \"b\"","list":[null,true,0.5]}"#
+        );
+        assert_eq!(parse(&value.to_string()), Ok(value));
+    }
+
+    #[test]
+    fn lookup() {
+        let value = parse(r#"{"a": {"b": 3}, "c": "d"}"#).unwrap();
+        assert_eq!(value.get("a").get("b").as_u64(), Some(3));
+        assert_eq!(value.get("c").as_str(), Some("d"));
+        assert!(value.get("x").get("y").is_null());
+    }
+}
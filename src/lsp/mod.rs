@@ -0,0 +1,803 @@
+//! A language server for mrc assembly.  It speaks JSON-RPC over any reader and writer, usually
+//! stdin and stdout, and supports diagnostics, go to definition, find references, hover and
+//! completion.  Documents are always synchronized in full.
+
+mod analysis;
+pub mod json;
+
+use crate::ast;
+use crate::operations::Operation;
+use crate::parser::DIRECTIVES;
+use crate::suggest::REGISTERS;
+use analysis::{Analysis, SymbolKind};
+use json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// Completion item kinds.
+const KIND_FUNCTION: u64 = 3;
+const KIND_FIELD: u64 = 5;
+const KIND_VARIABLE: u64 = 6;
+const KIND_KEYWORD: u64 = 14;
+const KIND_CONSTANT: u64 = 21;
+const KIND_STRUCT: u64 = 22;
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit: bool,
+}
+
+impl Server {
+    /// Handle messages until the client sends "exit" or closes the input.
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<(), std::io::Error> {
+        while !self.exit {
+            let Some(content) = read_message(input)? else {
+                break;
+            };
+
+            match json::parse(&content) {
+                Ok(message) => self.handle(&message, output)?,
+                Err(err) => write_message(
+                    output,
+                    &error_response(Value::Null, PARSE_ERROR, &err.to_string()),
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// True if the client asked the server to shut down before it exited.
+    pub fn received_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    fn handle<W: Write>(&mut self, message: &Value, output: &mut W) -> Result<(), std::io::Error> {
+        // Responses to requests we never sent are ignored.
+        let Some(method) = message.get("method").as_str() else {
+            return Ok(());
+        };
+        let id = message.get("id").clone();
+        let params = message.get("params");
+
+        if self.shutdown && method != "exit" {
+            if !id.is_null() {
+                write_message(
+                    output,
+                    &error_response(id, INVALID_REQUEST, "The server is shutting down."),
+                )?;
+            }
+            return Ok(());
+        }
+
+        let result = match method {
+            "initialize" => initialize_result(),
+
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+
+            "exit" => {
+                self.exit = true;
+                return Ok(());
+            }
+
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                let uri = document.get("uri").as_str().unwrap_or_default();
+                let text = document.get("text").as_str().unwrap_or_default();
+                return self.update(uri, text.to_owned(), output);
+            }
+
+            "textDocument/didChange" => {
+                let uri = params.get("textDocument").get("uri");
+                let text = params
+                    .get("contentChanges")
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text").as_str());
+                return match (uri.as_str(), text) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_owned(), output),
+                    _ => Ok(()),
+                };
+            }
+
+            "textDocument/didClose" => {
+                let uri = params
+                    .get("textDocument")
+                    .get("uri")
+                    .as_str()
+                    .unwrap_or_default();
+                self.documents.remove(uri);
+                return write_message(output, &diagnostics_notification(uri, vec![]));
+            }
+
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+
+            _ => {
+                // Notifications we don't know, like "initialized", can be ignored.
+                if !id.is_null() {
+                    write_message(
+                        output,
+                        &error_response(
+                            id,
+                            METHOD_NOT_FOUND,
+                            &format!("Method \"{}\" not found.", method),
+                        ),
+                    )?;
+                }
+                return Ok(());
+            }
+        };
+
+        write_message(
+            output,
+            &Value::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                ("result", result),
+            ]),
+        )
+    }
+
+    /// Analyze the new text of a document and publish its diagnostics.
+    fn update<W: Write>(
+        &mut self,
+        uri: &str,
+        text: String,
+        output: &mut W,
+    ) -> Result<(), std::io::Error> {
+        let analysis = Analysis::new(&text);
+
+        let diagnostics = analysis
+            .errors
+            .iter()
+            .map(|err| {
+                Value::object(vec![
+                    ("range", range(&text, err.span())),
+                    ("severity", 1_u64.into()),
+                    ("code", err.code().into()),
+                    ("source", "mrc".into()),
+                    ("message", err.to_string().into()),
+                ])
+            })
+            .collect();
+
+        self.documents
+            .insert(uri.to_owned(), Document { text, analysis });
+
+        write_message(output, &diagnostics_notification(uri, diagnostics))
+    }
+
+    /// The document and the byte offset of the position in the parameters of a request.
+    fn document_and_offset<'p>(&self, params: &'p Value) -> Option<(&'p str, &Document, usize)> {
+        let uri = params.get("textDocument").get("uri").as_str()?;
+        let document = self.documents.get(uri)?;
+        let position = params.get("position");
+        let offset = offset_at(
+            &document.text,
+            position.get("line").as_u64()?,
+            position.get("character").as_u64()?,
+        );
+        Some((uri, document, offset))
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        self.document_and_offset(params)
+            .and_then(|(uri, document, offset)| {
+                let name = document.analysis.name_at(offset)?;
+                let symbol = document.analysis.definition(name)?;
+                Some(location(uri, &document.text, &symbol.span))
+            })
+            .unwrap_or(Value::Null)
+    }
+
+    fn references(&self, params: &Value) -> Value {
+        let Some((uri, document, offset)) = self.document_and_offset(params) else {
+            return Value::Null;
+        };
+        let Some(name) = document.analysis.name_at(offset) else {
+            return Value::Array(vec![]);
+        };
+
+        let mut locations = vec![];
+        if params
+            .get("context")
+            .get("includeDeclaration")
+            .as_bool()
+            .unwrap_or(false)
+        {
+            if let Some(symbol) = document.analysis.definition(name) {
+                locations.push(location(uri, &document.text, &symbol.span));
+            }
+        }
+        locations.extend(
+            document
+                .analysis
+                .references_to(name)
+                .map(|reference| location(uri, &document.text, &reference.span)),
+        );
+
+        Value::Array(locations)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((_, document, offset)) = self.document_and_offset(params) else {
+            return Value::Null;
+        };
+        let analysis = &document.analysis;
+
+        let (contents, span) = if let Some(symbol) = analysis
+            .name_at(offset)
+            .and_then(|name| analysis.definition(name))
+        {
+            let description = match (symbol.kind, symbol.value) {
+                (SymbolKind::Label, Some(offset)) => format!("label at offset {:#06X}", offset),
+                (SymbolKind::Constant, Some(value)) => {
+                    format!("constant = {} ({:#X})", value, value)
+                }
+                (SymbolKind::Struct, Some(size)) => format!("structure, {} bytes", size),
+                (SymbolKind::Field, Some(offset)) => {
+                    format!("structure field at offset {}", offset)
+                }
+                (SymbolKind::Label, None) => "label".to_owned(),
+                (SymbolKind::Constant, None) => "constant".to_owned(),
+                (SymbolKind::Struct, None) => "structure".to_owned(),
+                (SymbolKind::Field, None) => "structure field".to_owned(),
+            };
+            (
+                format!("```asm\n{}\n```\n{}", symbol.name, description),
+                symbol.span.clone(),
+            )
+        } else if let Some(listing) = analysis.listing_at(offset) {
+            let mut contents = format!(
+                "offset {:#06X}, {} byte{}",
+                listing.offset,
+                listing.size,
+                if listing.size == 1 { "" } else { "s" }
+            );
+            if !listing.bytes.is_empty() {
+                let bytes = listing
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<_>>();
+                contents.push_str(&format!("\n\n`{}`", bytes.join(" ")));
+            }
+            (contents, listing.span.clone())
+        } else {
+            return Value::Null;
+        };
+
+        Value::object(vec![
+            (
+                "contents",
+                Value::object(vec![
+                    ("kind", "markdown".into()),
+                    ("value", contents.into()),
+                ]),
+            ),
+            ("range", range(&document.text, &span)),
+        ])
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let item = |label: &str, kind: u64| {
+            Value::object(vec![("label", label.into()), ("kind", kind.into())])
+        };
+
+        let mut items = vec![];
+
+        if let Some((_, document, _)) = self.document_and_offset(params) {
+            for symbol in &document.analysis.symbols {
+                let kind = match symbol.kind {
+                    SymbolKind::Label => KIND_FUNCTION,
+                    SymbolKind::Constant => KIND_CONSTANT,
+                    SymbolKind::Struct => KIND_STRUCT,
+                    SymbolKind::Field => KIND_FIELD,
+                };
+                // Local labels are completed the way they are written.
+                items.push(item(&document.text[symbol.span.clone()], kind));
+            }
+        }
+
+        items.extend(Operation::mnemonics().map(|mnemonic| item(mnemonic, KIND_KEYWORD)));
+        items.extend(
+            DIRECTIVES
+                .iter()
+                .map(|directive| item(directive, KIND_KEYWORD)),
+        );
+        items.extend(
+            REGISTERS
+                .iter()
+                .map(|register| item(register, KIND_VARIABLE)),
+        );
+
+        Value::Array(items)
+    }
+}
+
+fn initialize_result() -> Value {
+    Value::object(vec![
+        (
+            "capabilities",
+            Value::object(vec![
+                // Documents are synchronized by sending the full text.
+                ("textDocumentSync", 1_u64.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("hoverProvider", true.into()),
+                ("completionProvider", Value::object(vec![])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Value::object(vec![
+                ("name", env!("CARGO_PKG_NAME").into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    Value::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Value::object(vec![
+                ("code", Value::Number(code as f64)),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+fn diagnostics_notification(uri: &str, diagnostics: Vec<Value>) -> Value {
+    Value::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Value::object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", diagnostics.into()),
+            ]),
+        ),
+    ])
+}
+
+/// Read a message framed with a "Content-Length" header.  Returns [None] at the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<String>, std::io::Error> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(content_length) = content_length else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Message without a Content-Length header.",
+        ));
+    };
+
+    let mut content = vec![0; content_length];
+    input.read_exact(&mut content)?;
+
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> Result<(), std::io::Error> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+/// The LSP position of a byte offset.  Characters are counted in UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |found| found + 1);
+
+    let line = text[..offset].matches('\n').count();
+    let character = text[line_start..offset]
+        .chars()
+        .map(char::len_utf16)
+        .sum::<usize>();
+
+    Value::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+/// The byte offset of an LSP position.  Positions past the end of a line are clamped to it.
+fn offset_at(text: &str, line: u64, character: u64) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(found) => line_start += found + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (index, c) in text[line_start..].char_indices() {
+        if c == '\n' || units >= character as usize {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+
+    text.len()
+}
+
+fn range(text: &str, span: &ast::Span) -> Value {
+    Value::object(vec![
+        ("start", position(text, span.start)),
+        ("end", position(text, span.end)),
+    ])
+}
+
+fn location(uri: &str, text: &str, span: &ast::Span) -> Value {
+    Value::object(vec![("uri", uri.into()), ("range", range(text, span))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send the messages to a new server and return everything it wrote, with the server.
+    fn exchange(messages: &[&str]) -> (Vec<Value>, Server) {
+        let mut input = vec![];
+        for message in messages {
+            write!(
+                input,
+                "Content-Length: {}\r\n\r\n{}",
+                message.len(),
+                message
+            )
+            .unwrap();
+        }
+
+        let mut server = Server::default();
+        let mut output = vec![];
+        server
+            .run(&mut std::io::Cursor::new(input), &mut output)
+            .unwrap();
+
+        let mut output = std::io::Cursor::new(output);
+        let mut responses = vec![];
+        while let Some(content) = read_message(&mut output).unwrap() {
+            responses.push(json::parse(&content).unwrap());
+        }
+
+        (responses, server)
+    }
+
+    fn open(text: &str) -> String {
+        Value::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/didOpen".into()),
+            (
+                "params",
+                Value::object(vec![(
+                    "textDocument",
+                    Value::object(vec![
+                        ("uri", "file:///main.asm".into()),
+                        ("languageId", "asm".into()),
+                        ("version", 1_u64.into()),
+                        ("text", text.into()),
+                    ]),
+                )]),
+            ),
+        ])
+        .to_string()
+    }
+
+    fn request(id: u64, method: &str, line: u64, character: u64) -> String {
+        format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{{"textDocument":{{"uri":"file:///main.asm"}},"position":{{"line":{},"character":{}}},"context":{{"includeDeclaration":true}}}}}}"#,
+            id, method, line, character
+        )
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        let position = |(line, character): (u64, u64)| {
+            Value::object(vec![("line", line.into()), ("character", character.into())])
+        };
+        Value::object(vec![("start", position(start)), ("end", position(end))])
+    }
+
+    #[test]
+    fn lifecycle() {
+        let (responses, server) = exchange(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":"two","method":"unknown/method"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"textDocument/hover"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"shutdown"}"#,
+        ]);
+
+        assert_eq!(responses.len(), 4);
+
+        let capabilities = responses[0].get("result").get("capabilities");
+        assert_eq!(responses[0].get("id").as_u64(), Some(1));
+        assert_eq!(capabilities.get("textDocumentSync").as_u64(), Some(1));
+        assert_eq!(capabilities.get("hoverProvider").as_bool(), Some(true));
+
+        assert_eq!(responses[1].get("id").as_str(), Some("two"));
+        assert_eq!(
+            responses[1].get("error").get("code"),
+            &Value::Number(METHOD_NOT_FOUND as f64)
+        );
+
+        assert_eq!(responses[2].get("id").as_u64(), Some(3));
+        assert!(responses[2].get("result").is_null());
+
+        // Requests after "shutdown" are rejected and nothing is read after "exit".
+        assert_eq!(
+            responses[3].get("error").get("code"),
+            &Value::Number(INVALID_REQUEST as f64)
+        );
+        assert!(server.received_shutdown());
+    }
+
+    #[test]
+    fn invalid_json() {
+        let (responses, _) = exchange(&["{\"id\":"]);
+        assert_eq!(
+            responses[0].get("error").get("code"),
+            &Value::Number(PARSE_ERROR as f64)
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let change = r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///main.asm","version":2},"contentChanges":[{"text":"mov ax, 1\n"}]}}"#;
+        let (responses, _) = exchange(&[&open("nop\n  mvo ax, 1\n"), change]);
+
+        let params = responses[0].get("params");
+        assert_eq!(
+            responses[0].get("method").as_str(),
+            Some("textDocument/publishDiagnostics")
+        );
+        assert_eq!(params.get("uri").as_str(), Some("file:///main.asm"));
+        assert_eq!(
+            params.get("diagnostics"),
+            &Value::Array(vec![Value::object(vec![
+                ("range", range((1, 2), (1, 5))),
+                ("severity", 1_u64.into()),
+                ("code", "E0016".into()),
+                ("source", "mrc".into()),
+                (
+                    "message",
+                    "Unknown instruction \"mvo\". Did you mean \"mov\"?".into()
+                ),
+            ])])
+        );
+
+        // Fixing the error clears the diagnostics.
+        assert_eq!(
+            responses[1].get("params").get("diagnostics"),
+            &Value::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn diagnostics_do_not_crash() {
+        let change = |version: u64, text: &str| {
+            Value::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("method", "textDocument/didChange".into()),
+                (
+                    "params",
+                    Value::object(vec![
+                        (
+                            "textDocument",
+                            Value::object(vec![
+                                ("uri", "file:///main.asm".into()),
+                                ("version", version.into()),
+                            ]),
+                        ),
+                        (
+                            "contentChanges",
+                            Value::Array(vec![Value::object(vec![("text", text.into())])]),
+                        ),
+                    ]),
+                ),
+            ])
+            .to_string()
+        };
+
+        let (responses, _) = exchange(&[
+            &open("test ax, bx\nlea ax, [bx]\npush word [bx]\n"),
+            &change(2, "times 3\n"),
+            &change(3, "target:\ntimes 200 nop\njcxz target\n"),
+            &change(4, "jcxz target\ntimes 200 nop\ntarget:\n"),
+            &change(5, "; déjà vu\nmov dx, é\nmov dx, 'é'\n"),
+        ]);
+
+        let codes = responses
+            .iter()
+            .map(|response| {
+                let diagnostics = response.get("params").get("diagnostics");
+                match diagnostics {
+                    Value::Array(diagnostics) => diagnostics
+                        .iter()
+                        .map(|diagnostic| diagnostic.get("code").as_str().unwrap())
+                        .collect::<Vec<_>>(),
+                    _ => panic!("no diagnostics"),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            vec![
+                vec![],
+                vec!["E0002"],
+                vec!["E0206"],
+                vec!["E0206"],
+                vec!["E0004"]
+            ]
+        );
+
+        // The range of the invalid character is counted in characters, not in bytes.
+        let range = responses[4]
+            .get("params")
+            .get("diagnostics")
+            .as_array()
+            .unwrap()[0]
+            .get("range");
+        assert_eq!(range.get("start").get("character").as_u64(), Some(8));
+        assert_eq!(range.get("end").get("character").as_u64(), Some(9));
+    }
+
+    #[test]
+    fn navigation() {
+        const SOURCE: &str = "start:\n    mov ax, count\n    jmp start\ncount equ 5\n";
+        let (responses, _) = exchange(&[
+            &open(SOURCE),
+            &request(1, "textDocument/definition", 2, 9),
+            &request(2, "textDocument/references", 0, 2),
+            &request(3, "textDocument/definition", 1, 5),
+        ]);
+
+        let location = |start, end| {
+            Value::object(vec![
+                ("uri", "file:///main.asm".into()),
+                ("range", range(start, end)),
+            ])
+        };
+
+        assert_eq!(responses[1].get("result"), &location((0, 0), (0, 5)));
+        assert_eq!(
+            responses[2].get("result"),
+            &Value::Array(vec![location((0, 0), (0, 5)), location((2, 8), (2, 13))])
+        );
+        // There is no symbol on the mnemonic.
+        assert!(responses[3].get("result").is_null());
+    }
+
+    #[test]
+    fn hover() {
+        const SOURCE: &str = "start:\n    mov ax, count\n    jmp start\ncount equ 5\n";
+        let (responses, _) = exchange(&[
+            &open(SOURCE),
+            &request(1, "textDocument/hover", 1, 14),
+            &request(2, "textDocument/hover", 2, 10),
+            &request(3, "textDocument/hover", 1, 5),
+            &request(4, "textDocument/hover", 3, 8),
+        ]);
+
+        let contents = |index: usize| {
+            responses[index]
+                .get("result")
+                .get("contents")
+                .get("value")
+                .as_str()
+                .map(str::to_owned)
+        };
+
+        assert_eq!(
+            contents(1).as_deref(),
+            Some("```asm\ncount\n```\nconstant = 5 (0x5)")
+        );
+        assert_eq!(
+            contents(2).as_deref(),
+            Some("```asm\nstart\n```\nlabel at offset 0x0000")
+        );
+        assert_eq!(
+            contents(3).as_deref(),
+            Some("offset 0x0000, 3 bytes\n\n`B8 05 00`")
+        );
+        assert_eq!(
+            responses[3].get("result").get("range"),
+            &range((1, 4), (1, 17))
+        );
+        assert!(responses[4].get("result").is_null());
+    }
+
+    #[test]
+    fn completion() {
+        let (responses, _) = exchange(&[
+            &open("first:\n.loop: jmp .loop\n"),
+            &request(1, "textDocument/completion", 1, 12),
+        ]);
+
+        let items = responses[1].get("result").as_array().unwrap();
+        let find = |label: &str| {
+            items
+                .iter()
+                .find(|item| item.get("label").as_str() == Some(label))
+                .and_then(|item| item.get("kind").as_u64())
+        };
+
+        assert_eq!(find("first"), Some(KIND_FUNCTION));
+        assert_eq!(find(".loop"), Some(KIND_FUNCTION));
+        assert_eq!(find("mov"), Some(KIND_KEYWORD));
+        assert_eq!(find("times"), Some(KIND_KEYWORD));
+        assert_eq!(find("bx"), Some(KIND_VARIABLE));
+        assert_eq!(find("first.loop"), None);
+    }
+
+    #[test]
+    fn positions() {
+        const TEXT: &str = "ab\n\u{e9}\u{1F600}x\n";
+
+        assert_eq!(
+            position(TEXT, 0),
+            Value::object(vec![("line", 0_u64.into()), ("character", 0_u64.into())])
+        );
+        // "é" is one UTF-16 code unit, the emoji two.
+        assert_eq!(
+            position(TEXT, 9),
+            Value::object(vec![("line", 1_u64.into()), ("character", 3_u64.into())])
+        );
+
+        assert_eq!(offset_at(TEXT, 0, 1), 1);
+        assert_eq!(offset_at(TEXT, 1, 3), 9);
+        assert_eq!(offset_at(TEXT, 0, 100), 2);
+        assert_eq!(offset_at(TEXT, 5, 0), TEXT.len());
+    }
+}
@@ -65,6 +65,11 @@ pub enum Operation {
     LODSW, // Load word to AX
     STOSB, // Store byte to AL
     STOSW, // Store word to AX
+    MOVS,  // Move string with explicit operands
+    CMPS,  // Compare string with explicit operands
+    SCAS,  // Scan string with explicit operands
+    LODS,  // Load string with explicit operands
+    STOS,  // Store string with explicit operands
 
     // Control transfer
     CALL,   // Call
@@ -113,6 +118,144 @@ pub enum Operation {
 
     // Undocumented
     SALC, // Set AL on carry
+
+    // 80186
+    PUSHA, // Push all general registers
+    POPA,  // Pop all general registers
+    ENTER, // Make stack frame for procedure parameters
+    LEAVE, // High level procedure exit
+    BOUND, // Check array index against bounds
+    INSB,  // Input byte from port DX to ES:DI
+    INSW,  // Input word from port DX to ES:DI
+    OUTSB, // Output byte from DS:SI to port DX
+    OUTSW, // Output word from DS:SI to port DX
+    OUTS,  // Output string to port DX with explicit operands
+
+    // 80286
+    LGDT, // Load global descriptor table register
+    SGDT, // Store global descriptor table register
+    LIDT, // Load interrupt descriptor table register
+    SIDT, // Store interrupt descriptor table register
+    LLDT, // Load local descriptor table register
+    SLDT, // Store local descriptor table register
+    LTR,  // Load task register
+    STR,  // Store task register
+    LMSW, // Load machine status word
+    SMSW, // Store machine status word
+    ARPL, // Adjust RPL field of selector
+    LAR,  // Load access rights byte
+    LSL,  // Load segment limit
+    VERR, // Verify a segment for reading
+    VERW, // Verify a segment for writing
+    CLTS, // Clear task switched flag
+
+    // 80386
+    CWDE,   // Convert word to dword
+    CDQ,    // Convert dword to qword
+    MOVSD,  // Move dword string
+    CMPSD,  // Compare dword string
+    STOSD,  // Store dword string
+    LODSD,  // Load dword string
+    SCASD,  // Scan dword string
+    INSD,   // Input dword from port DX to ES:DI
+    OUTSD,  // Output dword from DS:SI to port DX
+    PUSHAD, // Push all 32-bit registers
+    POPAD,  // Pop all 32-bit registers
+    PUSHFD, // Push 32-bit flags
+    POPFD,  // Pop 32-bit flags
+    IRETD,  // Interrupt return with 32-bit operand size
+
+    // 8087
+    FLD,     // Load real
+    FST,     // Store real
+    FSTP,    // Store real and pop
+    FILD,    // Load integer
+    FIST,    // Store integer
+    FISTP,   // Store integer and pop
+    FBLD,    // Load BCD
+    FBSTP,   // Store BCD and pop
+    FXCH,    // Exchange registers
+    FADD,    // Add real
+    FADDP,   // Add real and pop
+    FIADD,   // Add integer
+    FSUB,    // Subtract real
+    FSUBP,   // Subtract real and pop
+    FISUB,   // Subtract integer
+    FSUBR,   // Subtract real reversed
+    FSUBRP,  // Subtract real reversed and pop
+    FISUBR,  // Subtract integer reversed
+    FMUL,    // Multiply real
+    FMULP,   // Multiply real and pop
+    FIMUL,   // Multiply integer
+    FDIV,    // Divide real
+    FDIVP,   // Divide real and pop
+    FIDIV,   // Divide integer
+    FDIVR,   // Divide real reversed
+    FDIVRP,  // Divide real reversed and pop
+    FIDIVR,  // Divide integer reversed
+    FSQRT,   // Square root
+    FSCALE,  // Scale
+    FPREM,   // Partial remainder
+    FRNDINT, // Round to integer
+    FXTRACT, // Extract exponent and significand
+    FABS,    // Absolute value
+    FCHS,    // Change sign
+    FCOM,    // Compare real
+    FCOMP,   // Compare real and pop
+    FCOMPP,  // Compare real and pop twice
+    FICOM,   // Compare integer
+    FICOMP,  // Compare integer and pop
+    FTST,    // Test
+    FXAM,    // Examine
+    FPTAN,   // Partial tangent
+    FPATAN,  // Partial arctangent
+    F2XM1,   // 2^x - 1
+    FYL2X,   // y * log2(x)
+    FYL2XP1, // y * log2(x + 1)
+    FLDZ,    // Load +0.0
+    FLD1,    // Load +1.0
+    FLDPI,   // Load pi
+    FLDL2T,  // Load log2(10)
+    FLDL2E,  // Load log2(e)
+    FLDLG2,  // Load log10(2)
+    FLDLN2,  // Load ln(2)
+    FINIT,   // Initialize processor
+    FNINIT,  // Initialize processor (no wait)
+    FENI,    // Enable interrupts
+    FNENI,   // Enable interrupts (no wait)
+    FDISI,   // Disable interrupts
+    FNDISI,  // Disable interrupts (no wait)
+    FLDCW,   // Load control word
+    FSTCW,   // Store control word
+    FNSTCW,  // Store control word (no wait)
+    FSTSW,   // Store status word
+    FNSTSW,  // Store status word (no wait)
+    FCLEX,   // Clear exceptions
+    FNCLEX,  // Clear exceptions (no wait)
+    FSTENV,  // Store environment
+    FNSTENV, // Store environment (no wait)
+    FLDENV,  // Load environment
+    FSAVE,   // Save state
+    FNSAVE,  // Save state (no wait)
+    FRSTOR,  // Restore state
+    FINCSTP, // Increment stack pointer
+    FDECSTP, // Decrement stack pointer
+    FFREE,   // Free register
+    FNOP,    // No operation
+
+    // NEC V20
+    TEST1, // Test a bit
+    CLR1,  // Clear a bit
+    SET1,  // Set a bit
+    NOT1,  // Invert a bit
+    EXT,   // Extract a bit field
+    INS,   // Insert a bit field
+    ROL4,  // Rotate nibble left
+    ROR4,  // Rotate nibble right
+    ADD4S, // Add packed BCD strings
+    SUB4S, // Subtract packed BCD strings
+    CMP4S, // Compare packed BCD strings
+    BRKEM, // Break for emulation mode
 }
 
 impl std::fmt::Display for Operation {
@@ -180,6 +323,11 @@ impl std::fmt::Display for Operation {
                 LODSW => "lodsw",
                 STOSB => "stosb",
                 STOSW => "stosw",
+                MOVS => "movs",
+                CMPS => "cmps",
+                SCAS => "scas",
+                LODS => "lods",
+                STOS => "stos",
                 CALL => "call",
                 JMP => "jmp",
                 RET => "ret",
@@ -221,122 +369,637 @@ impl std::fmt::Display for Operation {
                 LOCK => "lock",
                 NOP => "nop",
                 SALC => "salc",
+                PUSHA => "pusha",
+                POPA => "popa",
+                ENTER => "enter",
+                LEAVE => "leave",
+                BOUND => "bound",
+                INSB => "insb",
+                INSW => "insw",
+                OUTSB => "outsb",
+                OUTSW => "outsw",
+                OUTS => "outs",
+                LGDT => "lgdt",
+                SGDT => "sgdt",
+                LIDT => "lidt",
+                SIDT => "sidt",
+                LLDT => "lldt",
+                SLDT => "sldt",
+                LTR => "ltr",
+                STR => "str",
+                LMSW => "lmsw",
+                SMSW => "smsw",
+                ARPL => "arpl",
+                LAR => "lar",
+                LSL => "lsl",
+                VERR => "verr",
+                VERW => "verw",
+                CLTS => "clts",
+                CWDE => "cwde",
+                CDQ => "cdq",
+                MOVSD => "movsd",
+                CMPSD => "cmpsd",
+                STOSD => "stosd",
+                LODSD => "lodsd",
+                SCASD => "scasd",
+                INSD => "insd",
+                OUTSD => "outsd",
+                PUSHAD => "pushad",
+                POPAD => "popad",
+                PUSHFD => "pushfd",
+                POPFD => "popfd",
+                IRETD => "iretd",
+                FLD => "fld",
+                FST => "fst",
+                FSTP => "fstp",
+                FILD => "fild",
+                FIST => "fist",
+                FISTP => "fistp",
+                FBLD => "fbld",
+                FBSTP => "fbstp",
+                FXCH => "fxch",
+                FADD => "fadd",
+                FADDP => "faddp",
+                FIADD => "fiadd",
+                FSUB => "fsub",
+                FSUBP => "fsubp",
+                FISUB => "fisub",
+                FSUBR => "fsubr",
+                FSUBRP => "fsubrp",
+                FISUBR => "fisubr",
+                FMUL => "fmul",
+                FMULP => "fmulp",
+                FIMUL => "fimul",
+                FDIV => "fdiv",
+                FDIVP => "fdivp",
+                FIDIV => "fidiv",
+                FDIVR => "fdivr",
+                FDIVRP => "fdivrp",
+                FIDIVR => "fidivr",
+                FSQRT => "fsqrt",
+                FSCALE => "fscale",
+                FPREM => "fprem",
+                FRNDINT => "frndint",
+                FXTRACT => "fxtract",
+                FABS => "fabs",
+                FCHS => "fchs",
+                FCOM => "fcom",
+                FCOMP => "fcomp",
+                FCOMPP => "fcompp",
+                FICOM => "ficom",
+                FICOMP => "ficomp",
+                FTST => "ftst",
+                FXAM => "fxam",
+                FPTAN => "fptan",
+                FPATAN => "fpatan",
+                F2XM1 => "f2xm1",
+                FYL2X => "fyl2x",
+                FYL2XP1 => "fyl2xp1",
+                FLDZ => "fldz",
+                FLD1 => "fld1",
+                FLDPI => "fldpi",
+                FLDL2T => "fldl2t",
+                FLDL2E => "fldl2e",
+                FLDLG2 => "fldlg2",
+                FLDLN2 => "fldln2",
+                FINIT => "finit",
+                FNINIT => "fninit",
+                FENI => "feni",
+                FNENI => "fneni",
+                FDISI => "fdisi",
+                FNDISI => "fndisi",
+                FLDCW => "fldcw",
+                FSTCW => "fstcw",
+                FNSTCW => "fnstcw",
+                FSTSW => "fstsw",
+                FNSTSW => "fnstsw",
+                FCLEX => "fclex",
+                FNCLEX => "fnclex",
+                FSTENV => "fstenv",
+                FNSTENV => "fnstenv",
+                FLDENV => "fldenv",
+                FSAVE => "fsave",
+                FNSAVE => "fnsave",
+                FRSTOR => "frstor",
+                FINCSTP => "fincstp",
+                FDECSTP => "fdecstp",
+                FFREE => "ffree",
+                FNOP => "fnop",
+                TEST1 => "test1",
+                CLR1 => "clr1",
+                SET1 => "set1",
+                NOT1 => "not1",
+                EXT => "ext",
+                INS => "ins",
+                ROL4 => "rol4",
+                ROR4 => "ror4",
+                ADD4S => "add4s",
+                SUB4S => "sub4s",
+                CMP4S => "cmp4s",
+                BRKEM => "brkem",
             }
         )
     }
 }
 
+/// All mnemonics with the operation they assemble to, sorted by name.  Some operations have more
+/// than one mnemonic, like "je" and "jz".
+static MNEMONICS: &[(&str, Operation)] = &[
+    ("aaa", Operation::AAA),
+    ("aad", Operation::AAD),
+    ("aam", Operation::AAM),
+    ("aas", Operation::AAS),
+    ("adc", Operation::ADC),
+    ("add", Operation::ADD),
+    ("add4s", Operation::ADD4S),
+    ("and", Operation::AND),
+    ("arpl", Operation::ARPL),
+    ("bound", Operation::BOUND),
+    ("brkem", Operation::BRKEM),
+    ("call", Operation::CALL),
+    ("cbw", Operation::CBW),
+    ("cdq", Operation::CDQ),
+    ("clc", Operation::CLC),
+    ("cld", Operation::CLD),
+    ("cli", Operation::CLI),
+    ("clr1", Operation::CLR1),
+    ("clts", Operation::CLTS),
+    ("cmc", Operation::CMC),
+    ("cmp", Operation::CMP),
+    ("cmp4s", Operation::CMP4S),
+    ("cmps", Operation::CMPS),
+    ("cmpsb", Operation::CMPSB),
+    ("cmpsd", Operation::CMPSD),
+    ("cmpsw", Operation::CMPSW),
+    ("cwd", Operation::CWD),
+    ("cwde", Operation::CWDE),
+    ("daa", Operation::DAA),
+    ("das", Operation::DAS),
+    ("dec", Operation::DEC),
+    ("div", Operation::DIV),
+    ("enter", Operation::ENTER),
+    ("esc", Operation::ESC),
+    ("ext", Operation::EXT),
+    ("f2xm1", Operation::F2XM1),
+    ("fabs", Operation::FABS),
+    ("fadd", Operation::FADD),
+    ("faddp", Operation::FADDP),
+    ("fbld", Operation::FBLD),
+    ("fbstp", Operation::FBSTP),
+    ("fchs", Operation::FCHS),
+    ("fclex", Operation::FCLEX),
+    ("fcom", Operation::FCOM),
+    ("fcomp", Operation::FCOMP),
+    ("fcompp", Operation::FCOMPP),
+    ("fdecstp", Operation::FDECSTP),
+    ("fdisi", Operation::FDISI),
+    ("fdiv", Operation::FDIV),
+    ("fdivp", Operation::FDIVP),
+    ("fdivr", Operation::FDIVR),
+    ("fdivrp", Operation::FDIVRP),
+    ("feni", Operation::FENI),
+    ("ffree", Operation::FFREE),
+    ("fiadd", Operation::FIADD),
+    ("ficom", Operation::FICOM),
+    ("ficomp", Operation::FICOMP),
+    ("fidiv", Operation::FIDIV),
+    ("fidivr", Operation::FIDIVR),
+    ("fild", Operation::FILD),
+    ("fimul", Operation::FIMUL),
+    ("fincstp", Operation::FINCSTP),
+    ("finit", Operation::FINIT),
+    ("fist", Operation::FIST),
+    ("fistp", Operation::FISTP),
+    ("fisub", Operation::FISUB),
+    ("fisubr", Operation::FISUBR),
+    ("fld", Operation::FLD),
+    ("fld1", Operation::FLD1),
+    ("fldcw", Operation::FLDCW),
+    ("fldenv", Operation::FLDENV),
+    ("fldl2e", Operation::FLDL2E),
+    ("fldl2t", Operation::FLDL2T),
+    ("fldlg2", Operation::FLDLG2),
+    ("fldln2", Operation::FLDLN2),
+    ("fldpi", Operation::FLDPI),
+    ("fldz", Operation::FLDZ),
+    ("fmul", Operation::FMUL),
+    ("fmulp", Operation::FMULP),
+    ("fnclex", Operation::FNCLEX),
+    ("fndisi", Operation::FNDISI),
+    ("fneni", Operation::FNENI),
+    ("fninit", Operation::FNINIT),
+    ("fnop", Operation::FNOP),
+    ("fnsave", Operation::FNSAVE),
+    ("fnstcw", Operation::FNSTCW),
+    ("fnstenv", Operation::FNSTENV),
+    ("fnstsw", Operation::FNSTSW),
+    ("fpatan", Operation::FPATAN),
+    ("fprem", Operation::FPREM),
+    ("fptan", Operation::FPTAN),
+    ("frndint", Operation::FRNDINT),
+    ("frstor", Operation::FRSTOR),
+    ("fsave", Operation::FSAVE),
+    ("fscale", Operation::FSCALE),
+    ("fsqrt", Operation::FSQRT),
+    ("fst", Operation::FST),
+    ("fstcw", Operation::FSTCW),
+    ("fstenv", Operation::FSTENV),
+    ("fstp", Operation::FSTP),
+    ("fstsw", Operation::FSTSW),
+    ("fsub", Operation::FSUB),
+    ("fsubp", Operation::FSUBP),
+    ("fsubr", Operation::FSUBR),
+    ("fsubrp", Operation::FSUBRP),
+    ("ftst", Operation::FTST),
+    ("fwait", Operation::WAIT),
+    ("fxam", Operation::FXAM),
+    ("fxch", Operation::FXCH),
+    ("fxtract", Operation::FXTRACT),
+    ("fyl2x", Operation::FYL2X),
+    ("fyl2xp1", Operation::FYL2XP1),
+    ("hlt", Operation::HLT),
+    ("idiv", Operation::IDIV),
+    ("imul", Operation::IMUL),
+    ("in", Operation::IN),
+    ("inc", Operation::INC),
+    ("ins", Operation::INS),
+    ("insb", Operation::INSB),
+    ("insd", Operation::INSD),
+    ("insw", Operation::INSW),
+    ("int", Operation::INT),
+    ("int1", Operation::INT1),
+    ("int3", Operation::INT3),
+    ("into", Operation::INTO),
+    ("iret", Operation::IRET),
+    ("iretd", Operation::IRETD),
+    ("ja", Operation::JNBE),
+    ("jae", Operation::JNB),
+    ("jb", Operation::JB),
+    ("jbe", Operation::JBE),
+    ("jc", Operation::JB),
+    ("jcxz", Operation::JCXZ),
+    ("je", Operation::JE),
+    ("jg", Operation::JNLE),
+    ("jge", Operation::JNL),
+    ("jl", Operation::JL),
+    ("jle", Operation::JLE),
+    ("jmp", Operation::JMP),
+    ("jna", Operation::JBE),
+    ("jnae", Operation::JB),
+    ("jnb", Operation::JNB),
+    ("jnbe", Operation::JNBE),
+    ("jnc", Operation::JNB),
+    ("jne", Operation::JNE),
+    ("jng", Operation::JLE),
+    ("jnge", Operation::JL),
+    ("jnl", Operation::JNL),
+    ("jnle", Operation::JNLE),
+    ("jno", Operation::JNO),
+    ("jnp", Operation::JNP),
+    ("jns", Operation::JNS),
+    ("jnz", Operation::JNE),
+    ("jo", Operation::JO),
+    ("jp", Operation::JP),
+    ("jpe", Operation::JP),
+    ("jpo", Operation::JNP),
+    ("js", Operation::JS),
+    ("jz", Operation::JE),
+    ("lahf", Operation::LAHF),
+    ("lar", Operation::LAR),
+    ("lds", Operation::LDS),
+    ("lea", Operation::LEA),
+    ("leave", Operation::LEAVE),
+    ("les", Operation::LES),
+    ("lgdt", Operation::LGDT),
+    ("lidt", Operation::LIDT),
+    ("lldt", Operation::LLDT),
+    ("lmsw", Operation::LMSW),
+    ("lock", Operation::LOCK),
+    ("lods", Operation::LODS),
+    ("lodsb", Operation::LODSB),
+    ("lodsd", Operation::LODSD),
+    ("lodsw", Operation::LODSW),
+    ("loop", Operation::LOOP),
+    ("loope", Operation::LOOPZ),
+    ("loopne", Operation::LOOPNZ),
+    ("loopnz", Operation::LOOPNZ),
+    ("loopz", Operation::LOOPZ),
+    ("lsl", Operation::LSL),
+    ("ltr", Operation::LTR),
+    ("mov", Operation::MOV),
+    ("movs", Operation::MOVS),
+    ("movsb", Operation::MOVSB),
+    ("movsd", Operation::MOVSD),
+    ("movsw", Operation::MOVSW),
+    ("mul", Operation::MUL),
+    ("neg", Operation::NEG),
+    ("nop", Operation::NOP),
+    ("not", Operation::NOT),
+    ("not1", Operation::NOT1),
+    ("or", Operation::OR),
+    ("out", Operation::OUT),
+    ("outs", Operation::OUTS),
+    ("outsb", Operation::OUTSB),
+    ("outsd", Operation::OUTSD),
+    ("outsw", Operation::OUTSW),
+    ("pop", Operation::POP),
+    ("popa", Operation::POPA),
+    ("popad", Operation::POPAD),
+    ("popf", Operation::POPF),
+    ("popfd", Operation::POPFD),
+    ("push", Operation::PUSH),
+    ("pusha", Operation::PUSHA),
+    ("pushad", Operation::PUSHAD),
+    ("pushf", Operation::PUSHF),
+    ("pushfd", Operation::PUSHFD),
+    ("rcl", Operation::RCL),
+    ("rcr", Operation::RCR),
+    ("rep", Operation::REP),
+    ("repne", Operation::REPNE),
+    ("repnz", Operation::REPNE),
+    ("ret", Operation::RET),
+    ("rol", Operation::ROL),
+    ("rol4", Operation::ROL4),
+    ("ror", Operation::ROR),
+    ("ror4", Operation::ROR4),
+    ("sahf", Operation::SAHF),
+    ("salc", Operation::SALC),
+    ("sar", Operation::SAR),
+    ("sbb", Operation::SBB),
+    ("scas", Operation::SCAS),
+    ("scasb", Operation::SCASB),
+    ("scasd", Operation::SCASD),
+    ("scasw", Operation::SCASW),
+    ("set1", Operation::SET1),
+    ("sgdt", Operation::SGDT),
+    ("shl", Operation::SHL),
+    ("shr", Operation::SHR),
+    ("sidt", Operation::SIDT),
+    ("sldt", Operation::SLDT),
+    ("smsw", Operation::SMSW),
+    ("ssb", Operation::SBB),
+    ("stc", Operation::STC),
+    ("std", Operation::STD),
+    ("sti", Operation::STI),
+    ("stos", Operation::STOS),
+    ("stosb", Operation::STOSB),
+    ("stosd", Operation::STOSD),
+    ("stosw", Operation::STOSW),
+    ("str", Operation::STR),
+    ("sub", Operation::SUB),
+    ("sub4s", Operation::SUB4S),
+    ("test", Operation::TEST),
+    ("test1", Operation::TEST1),
+    ("verr", Operation::VERR),
+    ("verw", Operation::VERW),
+    ("wait", Operation::WAIT),
+    ("xchg", Operation::XCHG),
+    ("xlat", Operation::XLATB),
+    ("xlatb", Operation::XLATB),
+    ("xor", Operation::XOR),
+];
+
 impl std::str::FromStr for Operation {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+
+        MNEMONICS
+            .binary_search_by(|(mnemonic, _)| (*mnemonic).cmp(s.as_str()))
+            .map(|index| MNEMONICS[index].1)
+            .map_err(|_| ())
+    }
+}
+
+impl Operation {
+    /// All mnemonics that are recognized as operations.
+    pub fn mnemonics() -> impl Iterator<Item = &'static str> {
+        MNEMONICS.iter().map(|(mnemonic, _)| *mnemonic)
+    }
+}
+
+impl Operation {
+    /// The oldest CPU that supports the operation.
+    pub fn minimum_cpu(&self) -> Cpu {
         use Operation::*;
 
-        Ok(match s.to_string().to_lowercase().as_str() {
-            "aaa" => AAA,
-            "aad" => AAD,
-            "aam" => AAM,
-            "aas" => AAS,
-            "adc" => ADC,
-            "add" => ADD,
-            "and" => AND,
-            "call" => CALL,
-            "cbw" => CBW,
-            "clc" => CLC,
-            "cld" => CLD,
-            "cli" => CLI,
-            "cmc" => CMC,
-            "cmp" => CMP,
-            "cmpsb" => CMPSB,
-            "cmpsw" => CMPSW,
-            "cwd" => CWD,
-            "daa" => DAA,
-            "das" => DAS,
-            "dec" => DEC,
-            "div" => DIV,
-            "esc" => ESC,
-            "hlt" => HLT,
-            "idiv" => IDIV,
-            "imul" => IMUL,
-            "in" => IN,
-            "inc" => INC,
-            "int" => INT,
-            "int1" => INT1,
-            "int3" => INT3,
-            "into" => INTO,
-            "iret" => IRET,
-            "ja" | "jnbe" => JNBE,
-            "jae" | "jnb" | "jnc" => JNB,
-            "jb" | "jc" | "jnae" => JB,
-            "jbe" | "jna" => JBE,
-            "jcxz" => JCXZ,
-            "je" | "jz" => JE,
-            "jg" | "jnle" => JNLE,
-            "jge" | "jnl" => JNL,
-            "jl" | "jnge" => JL,
-            "jle" | "jng" => JLE,
-            "jmp" => JMP,
-            "jne" | "jnz" => JNE,
-            "jno" => JNO,
-            "jnp" | "jpo" => JNP,
-            "jns" => JNS,
-            "jo" => JO,
-            "jp" | "jpe" => JP,
-            "js" => JS,
-            "lahf" => LAHF,
-            "lds" => LDS,
-            "lea" => LEA,
-            "les" => LES,
-            "lock" => LOCK,
-            "lodsb" => LODSB,
-            "lodsw" => LODSW,
-            "loop" => LOOP,
-            "loope" => LOOPZ,
-            "loopne" => LOOPNZ,
-            "loopnz" => LOOPNZ,
-            "loopz" => LOOPZ,
-            "mov" => MOV,
-            "movsb" => MOVSB,
-            "movsw" => MOVSW,
-            "mul" => MUL,
-            "neg" => NEG,
-            "nop" => NOP,
-            "not" => NOT,
-            "or" => OR,
-            "out" => OUT,
-            "pop" => POP,
-            "popf" => POPF,
-            "push" => PUSH,
-            "pushf" => PUSHF,
-            "rcl" => RCL,
-            "rcr" => RCR,
-            "rep" => REP,
-            "repne" => REPNE,
-            "repnz" => REPNE,
-            "ret" => RET,
-            "rol" => ROL,
-            "ror" => ROR,
-            "sahf" => SAHF,
-            "salc" => SALC,
-            "sar" => SAR,
-            "sbb" => SBB,
-            "scasb" => SCASB,
-            "scasw" => SCASW,
-            "shl" => SHL,
-            "shr" => SHR,
-            "ssb" => SBB,
-            "stc" => STC,
-            "std" => STD,
-            "sti" => STI,
-            "stosb" => STOSB,
-            "stosw" => STOSW,
-            "sub" => SUB,
-            "test" => TEST,
-            "wait" => WAIT,
-            "xchg" => XCHG,
-            "xlatb" => XLATB,
-            "xor" => XOR,
+        match self {
+            PUSHA | POPA | ENTER | LEAVE | BOUND | INSB | INSW | OUTSB | OUTSW | OUTS => Cpu::I186,
+            LGDT | SGDT | LIDT | SIDT | LLDT | SLDT | LTR | STR | LMSW | SMSW | ARPL | LAR
+            | LSL | VERR | VERW | CLTS => Cpu::I286,
+            CWDE | CDQ | MOVSD | CMPSD | STOSD | LODSD | SCASD | INSD | OUTSD | PUSHAD | POPAD
+            | PUSHFD | POPFD | IRETD => Cpu::I386,
+            TEST1 | CLR1 | SET1 | NOT1 | EXT | INS | ROL4 | ROR4 | ADD4S | SUB4S | CMP4S
+            | BRKEM => Cpu::V20,
+            _ => Cpu::I8086,
+        }
+    }
+
+    /// String operations without operands, that address memory through SI and/or DI.  A segment
+    /// override prefix on these applies to the memory addressed by SI.
+    pub fn is_implicit_string(&self) -> bool {
+        use Operation::*;
+
+        matches!(
+            self,
+            MOVSB
+                | MOVSW
+                | MOVSD
+                | CMPSB
+                | CMPSW
+                | CMPSD
+                | SCASB
+                | SCASW
+                | SCASD
+                | LODSB
+                | LODSW
+                | LODSD
+                | STOSB
+                | STOSW
+                | STOSD
+                | INSB
+                | INSW
+                | INSD
+                | OUTSB
+                | OUTSW
+                | OUTSD
+        )
+    }
+
+    /// Operations that address memory without operands, so a segment override is written as a
+    /// prefix, e.g. "es lodsb" or "cs xlatb".
+    pub fn has_implicit_memory_operand(&self) -> bool {
+        self.is_implicit_string() || *self == Operation::XLATB
+    }
+
+    /// Operations executed by the 8087 floating point unit.
+    pub fn is_fpu(&self) -> bool {
+        use Operation::*;
+
+        matches!(
+            self,
+            FLD | FST
+                | FSTP
+                | FILD
+                | FIST
+                | FISTP
+                | FBLD
+                | FBSTP
+                | FXCH
+                | FADD
+                | FADDP
+                | FIADD
+                | FSUB
+                | FSUBP
+                | FISUB
+                | FSUBR
+                | FSUBRP
+                | FISUBR
+                | FMUL
+                | FMULP
+                | FIMUL
+                | FDIV
+                | FDIVP
+                | FIDIV
+                | FDIVR
+                | FDIVRP
+                | FIDIVR
+                | FSQRT
+                | FSCALE
+                | FPREM
+                | FRNDINT
+                | FXTRACT
+                | FABS
+                | FCHS
+                | FCOM
+                | FCOMP
+                | FCOMPP
+                | FICOM
+                | FICOMP
+                | FTST
+                | FXAM
+                | FPTAN
+                | FPATAN
+                | F2XM1
+                | FYL2X
+                | FYL2XP1
+                | FLDZ
+                | FLD1
+                | FLDPI
+                | FLDL2T
+                | FLDL2E
+                | FLDLG2
+                | FLDLN2
+                | FINIT
+                | FNINIT
+                | FENI
+                | FNENI
+                | FDISI
+                | FNDISI
+                | FLDCW
+                | FSTCW
+                | FNSTCW
+                | FSTSW
+                | FNSTSW
+                | FCLEX
+                | FNCLEX
+                | FSTENV
+                | FNSTENV
+                | FLDENV
+                | FSAVE
+                | FNSAVE
+                | FRSTOR
+                | FINCSTP
+                | FDECSTP
+                | FFREE
+                | FNOP
+        )
+    }
+}
+
+/// The target CPU, which determines which operations are available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Cpu {
+    #[default]
+    I8086,
+    I186,
+    I286,
+    I386,
+    /// The NEC V20 supports the 80186 instructions and adds some of its own.
+    V20,
+}
+
+impl Cpu {
+    /// Returns true if this CPU can run operations that require the [required] CPU.
+    pub fn supports(&self, required: Cpu) -> bool {
+        use Cpu::*;
+
+        match required {
+            I8086 => true,
+            I186 => matches!(self, I186 | I286 | I386 | V20),
+            I286 => matches!(self, I286 | I386),
+            I386 => matches!(self, I386),
+            V20 => matches!(self, V20),
+        }
+    }
+}
+
+impl std::fmt::Display for Cpu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cpu::I8086 => write!(f, "8086"),
+            Cpu::I186 => write!(f, "186"),
+            Cpu::I286 => write!(f, "286"),
+            Cpu::I386 => write!(f, "386"),
+            Cpu::V20 => write!(f, "V20"),
+        }
+    }
+}
+
+impl std::str::FromStr for Cpu {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "8086" | "8088" => Cpu::I8086,
+            "186" | "80186" | "188" | "80188" => Cpu::I186,
+            "286" | "80286" => Cpu::I286,
+            "386" | "80386" => Cpu::I386,
+            "v20" | "v30" => Cpu::V20,
             _ => return Err(()),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn cpu() {
+        assert_eq!(Cpu::from_str("186"), Ok(Cpu::I186));
+        assert_eq!(Cpu::from_str("V20"), Ok(Cpu::V20));
+        assert_eq!(Cpu::from_str("486"), Err(()));
+
+        assert!(Cpu::I8086.supports(Cpu::I8086));
+        assert!(!Cpu::I8086.supports(Cpu::I186));
+        assert!(Cpu::I386.supports(Cpu::I286));
+        assert!(Cpu::V20.supports(Cpu::I186));
+        assert!(!Cpu::V20.supports(Cpu::I286));
+        assert!(!Cpu::I386.supports(Cpu::V20));
+    }
+
+    #[test]
+    fn mnemonics() {
+        // The table is searched with a binary search, so it has to be sorted.
+        assert!(MNEMONICS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        assert_eq!(Operation::from_str("MOV"), Ok(Operation::MOV));
+        assert_eq!(Operation::from_str("jz"), Ok(Operation::JE));
+        assert_eq!(Operation::from_str("fwait"), Ok(Operation::WAIT));
+        assert_eq!(Operation::from_str("mvo"), Err(()));
+        assert!(Operation::mnemonics().any(|mnemonic| mnemonic == "xlatb"));
+    }
+}
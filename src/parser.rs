@@ -1,6 +1,8 @@
 use crate::ast;
+use crate::encoding::cp437_from_char;
 use crate::lexer::{Cursor, LiteralKind, PunctuationKind, Token};
-use crate::operations::Operation;
+use crate::operations::{Cpu, Operation};
+use crate::suggest;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
@@ -15,6 +17,14 @@ pub enum ParserError {
     SegmentOrAddressExpected(ast::Span),
     InvalidIndirectEncoding(ast::Span, ast::Register, Option<ast::Register>),
     UnterminatedStringLiteral(ast::Span),
+    InvalidEscapeSequence(ast::Span),
+    CharacterNotEncodable(ast::Span, char),
+    CharacterConstantTooLong(ast::Span),
+    InvalidNumberLiteral(ast::Span),
+    NumberLiteralTooLarge(ast::Span),
+    InvalidSegmentOverride(ast::Span),
+    UnknownInstruction(ast::Span, String, Option<String>),
+    KeywordOutsideOfBlock(ast::Span, String, &'static str),
 }
 
 impl ParserError {
@@ -28,7 +38,39 @@ impl ParserError {
             | ParserError::DataDefinitionWithoutData(span)
             | ParserError::SegmentOrAddressExpected(span)
             | ParserError::InvalidIndirectEncoding(span, ..)
-            | ParserError::UnterminatedStringLiteral(span) => span,
+            | ParserError::UnterminatedStringLiteral(span)
+            | ParserError::InvalidEscapeSequence(span)
+            | ParserError::CharacterNotEncodable(span, _)
+            | ParserError::CharacterConstantTooLong(span)
+            | ParserError::InvalidNumberLiteral(span)
+            | ParserError::NumberLiteralTooLarge(span)
+            | ParserError::InvalidSegmentOverride(span)
+            | ParserError::UnknownInstruction(span, ..)
+            | ParserError::KeywordOutsideOfBlock(span, ..) => span,
+        }
+    }
+
+    /// A stable identifier for the kind of error, which can be looked up with
+    /// [crate::explain::explain].
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserError::Expected(..) => "E0001",
+            ParserError::InstructionExpected(..) => "E0002",
+            ParserError::SecondOperandExpected(..) => "E0003",
+            ParserError::OperandExpected(..) => "E0004",
+            ParserError::InvalidPrefixOperator(_) => "E0005",
+            ParserError::DataDefinitionWithoutData(_) => "E0006",
+            ParserError::SegmentOrAddressExpected(_) => "E0007",
+            ParserError::InvalidIndirectEncoding(..) => "E0008",
+            ParserError::UnterminatedStringLiteral(_) => "E0009",
+            ParserError::InvalidEscapeSequence(_) => "E0010",
+            ParserError::CharacterNotEncodable(..) => "E0011",
+            ParserError::CharacterConstantTooLong(_) => "E0012",
+            ParserError::InvalidNumberLiteral(_) => "E0013",
+            ParserError::NumberLiteralTooLarge(_) => "E0014",
+            ParserError::InvalidSegmentOverride(_) => "E0015",
+            ParserError::UnknownInstruction(..) => "E0016",
+            ParserError::KeywordOutsideOfBlock(..) => "E0017",
         }
     }
 }
@@ -76,10 +118,86 @@ impl Display for ParserError {
             ParserError::UnterminatedStringLiteral(_) => {
                 write!(f, "Unterminated string literal.")
             }
+            ParserError::InvalidEscapeSequence(_) => write!(f, "Invalid escape sequence."),
+            ParserError::CharacterNotEncodable(_, c) => {
+                write!(
+                    f,
+                    "The character '{}' can not be encoded in code page 437.",
+                    c
+                )
+            }
+            ParserError::CharacterConstantTooLong(_) => {
+                write!(
+                    f,
+                    "Character constant is too long, a maximum of 8 characters is allowed."
+                )
+            }
+            ParserError::InvalidNumberLiteral(_) => {
+                write!(
+                    f,
+                    "Number literal contains digits that are not valid for its base."
+                )
+            }
+            ParserError::NumberLiteralTooLarge(_) => {
+                write!(f, "Number literal does not fit into 64 bits.")
+            }
+            ParserError::InvalidSegmentOverride(_) => {
+                write!(
+                    f,
+                    "A segment override can only be applied to a single memory operand."
+                )
+            }
+            ParserError::UnknownInstruction(_, name, suggestion) => {
+                write!(f, "Unknown instruction \"{}\".", name)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " Did you mean \"{}\"?", suggestion)?;
+                }
+                Ok(())
+            }
+            ParserError::KeywordOutsideOfBlock(_, keyword, block) => {
+                write!(
+                    f,
+                    "\"{}\" is only valid inside a \"{}\" block.",
+                    keyword, block
+                )
+            }
         }
     }
 }
 
+/// The directives that can start a line, besides instructions.
+pub(crate) const DIRECTIVES: &[&str] = &[
+    "equ", "db", "dw", "dd", "dq", "times", "align", "alignb", "cpu", "bits", "struc", "istruc",
+];
+
+/// Keywords that are only valid inside a block, with the directive that starts the block.
+const BLOCK_KEYWORDS: &[(&str, &str)] = &[
+    ("resb", "struc"),
+    ("resw", "struc"),
+    ("resd", "struc"),
+    ("resq", "struc"),
+    ("rest", "struc"),
+    ("endstruc", "struc"),
+    ("at", "istruc"),
+    ("iend", "istruc"),
+];
+
+/// The directive that starts the block the keyword is valid in.
+fn block_of_keyword(identifier: &str) -> Option<&'static str> {
+    let identifier = identifier.to_lowercase();
+    BLOCK_KEYWORDS
+        .iter()
+        .find(|(keyword, _)| *keyword == identifier)
+        .map(|(_, block)| *block)
+}
+
+fn is_instruction_or_directive(identifier: &str) -> bool {
+    Operation::from_str(identifier).is_ok()
+        || ast::Segment::from_str(identifier).is_ok()
+        || DIRECTIVES.contains(&identifier.to_lowercase().as_str())
+        || block_of_keyword(identifier).is_some()
+}
+
 struct FoundToken<'a>(Token, &'a str);
 
 impl<'a> Display for FoundToken<'a> {
@@ -91,6 +209,9 @@ impl<'a> Display for FoundToken<'a> {
             }
             Token::Literal(_, literal_kind) => match literal_kind {
                 LiteralKind::Number(value) => write!(f, "number \"{}\"", value),
+                LiteralKind::InvalidNumber | LiteralKind::NumberTooLarge => {
+                    write!(f, "number \"{}\"", self.1)
+                }
                 LiteralKind::String(terminated) => {
                     if *terminated {
                         write!(f, "string \"{}\"", self.1)
@@ -111,13 +232,40 @@ impl<'a> Display for FoundToken<'a> {
 }
 
 impl ast::Operator {
-    pub fn evaluate(&self, left: i32, right: i32) -> i32 {
-        match self {
-            ast::Operator::Add => left + right,
-            ast::Operator::Subtract => left - right,
-            ast::Operator::Multiply => left * right,
-            ast::Operator::Divide => left / right,
-        }
+    /// Apply the operator to the given values.  Prefix operators are evaluated with a [left] value
+    /// of 0.  Returns [None] on division by zero and for the operators that require knowledge
+    /// about labels (`seg`, `wrt`, `sizeof` and `lengthof`), which are evaluated by the compiler.
+    pub fn evaluate(&self, left: i64, right: i64) -> Option<i64> {
+        use ast::Operator::*;
+
+        Some(match self {
+            Add => left.wrapping_add(right),
+            Subtract => left.wrapping_sub(right),
+            Multiply => left.wrapping_mul(right),
+            Divide => (left as u64).checked_div(right as u64)? as i64,
+            SignedDivide => (right != 0).then(|| left.wrapping_div(right))?,
+            Modulo => (left as u64).checked_rem(right as u64)? as i64,
+            SignedModulo => (right != 0).then(|| left.wrapping_rem(right))?,
+            ShiftLeft => left.checked_shl(right as u32).unwrap_or(0),
+            ShiftRight => (left as u64).checked_shr(right as u32).unwrap_or(0) as i64,
+            BitwiseAnd => left & right,
+            BitwiseOr => left | right,
+            BitwiseXor => left ^ right,
+            BitwiseNot => !right,
+            LogicalAnd => (left != 0 && right != 0) as i64,
+            LogicalOr => (left != 0 || right != 0) as i64,
+            LogicalXor => ((left != 0) ^ (right != 0)) as i64,
+            LogicalNot => (right == 0) as i64,
+            Equal => (left == right) as i64,
+            NotEqual => (left != right) as i64,
+            LessThan => (left < right) as i64,
+            LessThanOrEqual => (left <= right) as i64,
+            GreaterThan => (left > right) as i64,
+            GreaterThanOrEqual => (left >= right) as i64,
+            Low => right & 0xFF,
+            High => (right >> 8) & 0xFF,
+            Segment | WithRespectTo | SizeOf | LengthOf => return None,
+        })
     }
 }
 
@@ -133,6 +281,10 @@ pub struct Parser<'a> {
 
     // Position in the cursor where the last meaningful token ended.
     last_token_end: usize,
+
+    /// A segment override in front of a "rep" prefix, e.g. "es rep movsb".  It is applied to the
+    /// instruction after the prefix.
+    segment_prefix: Option<(ast::Span, ast::Segment)>,
 }
 
 #[derive(Clone)]
@@ -145,6 +297,7 @@ impl<'a> Parser<'a> {
             token: Token::EndOfFile(0),
             token_start: 0,
             last_token_end: 0,
+            segment_prefix: None,
         };
 
         // Initialize the current token with the first token that we can fetch from the lexer.
@@ -183,9 +336,16 @@ impl<'a> Parser<'a> {
             Token::Identifier(_) => {
                 if let Some(line) = self.parse_instruction_or_meta()? {
                     line
-                } else {
-                    // If we don't recognize the identifier, we assume it's a label.
+                } else if let Some(block) = block_of_keyword(self.token_source()) {
+                    return Err(ParserError::KeywordOutsideOfBlock(
+                        self.token_range(),
+                        self.token_source().to_owned(),
+                        block,
+                    ));
+                } else if self.is_label() {
                     ast::Line::Label(self.parse_label()?)
+                } else {
+                    return Err(self.unknown_instruction());
                 }
             }
 
@@ -193,6 +353,19 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    /// Where the current token starts in the source.
+    pub(crate) fn position(&self) -> usize {
+        self.token_start
+    }
+
+    /// Skip the rest of the current line, so parsing can continue after an error.
+    pub fn skip_line(&mut self) {
+        self.segment_prefix = None;
+        while !matches!(self.token, Token::NewLine(_) | Token::EndOfFile(_)) {
+            self.next_token();
+        }
+    }
+
     fn next_token(&mut self) {
         self.last_token_end = self.token_start + self.token.len();
         self.token_start = self.cursor.pos();
@@ -231,18 +404,64 @@ impl<'a> Parser<'a> {
         let identifier = self.token_source();
 
         if let Ok(operation) = Operation::from_str(identifier) {
-            Ok(Some(self.parse_instruction(operation)?))
+            let line = self.parse_instruction(operation)?;
+            match (self.segment_prefix.take(), line) {
+                (Some((prefix_span, segment)), ast::Line::Instruction(instruction)) => Ok(Some(
+                    Self::apply_segment_prefix(prefix_span, segment, instruction)?,
+                )),
+                (_, line) => Ok(Some(line)),
+            }
+        } else if let Ok(segment) = ast::Segment::from_str(identifier) {
+            Ok(Some(self.parse_segment_prefix(segment)?))
         } else {
             match identifier.to_lowercase().as_str() {
                 "equ" => Ok(Some(self.parse_constant()?)),
-                "db" => Ok(Some(self.parse_data(1)?)),
-                "dw" => Ok(Some(self.parse_data(2)?)),
+                "db" => Ok(Some(self.parse_data(ast::DataSize::Byte)?)),
+                "dw" => Ok(Some(self.parse_data(ast::DataSize::Word)?)),
+                "dd" => Ok(Some(self.parse_data(ast::DataSize::DWord)?)),
+                "dq" => Ok(Some(self.parse_data(ast::DataSize::QWord)?)),
                 "times" => Ok(Some(self.parse_times()?)),
+                "align" => Ok(Some(self.parse_align(false)?)),
+                "alignb" => Ok(Some(self.parse_align(true)?)),
+                "cpu" => Ok(Some(self.parse_cpu()?)),
+                "bits" => Ok(Some(self.parse_bits()?)),
+                "struc" => Ok(Some(self.parse_struct()?)),
+                "istruc" => Ok(Some(self.parse_struct_instance()?)),
                 _ => Ok(None),
             }
         }
     }
 
+    /// Returns true if the current identifier, which is not an instruction or directive, is a
+    /// label.  A label is followed by a colon, the end of the line or an instruction.  Anything else,
+    /// like the operands in "mvo ax, 1", means it was meant to be an instruction.
+    fn is_label(&self) -> bool {
+        let mut parser = self.clone();
+        parser.next_token();
+
+        match parser.token {
+            Token::Punctuation(_, PunctuationKind::Colon)
+            | Token::NewLine(_)
+            | Token::EndOfFile(_) => true,
+            Token::Identifier(_) => is_instruction_or_directive(parser.token_source()),
+            _ => false,
+        }
+    }
+
+    fn unknown_instruction(&self) -> ParserError {
+        let name = self.token_source();
+        let suggestion = suggest::best_match(
+            name,
+            Operation::mnemonics().chain(DIRECTIVES.iter().copied()),
+        );
+
+        ParserError::UnknownInstruction(
+            self.token_range(),
+            name.to_owned(),
+            suggestion.map(str::to_owned),
+        )
+    }
+
     fn parse_label(&mut self) -> Result<ast::Label, ParserError> {
         let start = self.token_start;
 
@@ -285,6 +504,95 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    /// Parse an instruction with a segment override prefix, e.g. "es lodsb" or "cs mov ax, [bx]".
+    /// The override is applied to the memory operand of the instruction.  Instructions that
+    /// address memory without operands get the segment as their only operand.
+    fn parse_segment_prefix(&mut self, segment: ast::Segment) -> Result<ast::Line, ParserError> {
+        let start = self.token_start;
+        let prefix_span = self.token_range();
+
+        // There can only be one override, e.g. not "es rep cs movsb".
+        if self.segment_prefix.is_some() {
+            return Err(ParserError::InvalidSegmentOverride(prefix_span));
+        }
+
+        // Consume the segment.
+        self.next_token();
+
+        let operation = match self.token {
+            Token::Identifier(_) => Operation::from_str(self.token_source()).ok(),
+            _ => None,
+        };
+        let mut instruction = match operation {
+            Some(operation) => match self.parse_instruction(operation)? {
+                ast::Line::Instruction(instruction) => instruction,
+                _ => unreachable!(),
+            },
+            None => return Err(self.instruction_expected()),
+        };
+
+        instruction.span = start..instruction.span.end;
+
+        // The "rep" prefix is a line of its own, the override goes to the instruction after it.
+        if matches!(instruction.operation, Operation::REP | Operation::REPNE) {
+            if !matches!(self.token, Token::Identifier(_)) {
+                return Err(self.instruction_expected());
+            }
+            self.segment_prefix = Some((prefix_span, segment));
+            return Ok(ast::Line::Instruction(instruction));
+        }
+
+        Self::apply_segment_prefix(prefix_span, segment, instruction)
+    }
+
+    fn apply_segment_prefix(
+        prefix_span: ast::Span,
+        segment: ast::Segment,
+        mut instruction: ast::Instruction,
+    ) -> Result<ast::Line, ParserError> {
+        if instruction.operation.has_implicit_memory_operand() {
+            if let ast::Operands::None(_) = instruction.operands {
+                instruction.operands = ast::Operands::Destination(
+                    prefix_span.clone(),
+                    ast::Operand::Segment(prefix_span, segment),
+                );
+                return Ok(ast::Line::Instruction(instruction));
+            }
+        }
+
+        let mut memory_operands = match &mut instruction.operands {
+            ast::Operands::None(_) => vec![],
+            ast::Operands::Destination(_, dst) => vec![dst],
+            ast::Operands::DestinationAndSource(_, dst, src) => vec![dst, src],
+            ast::Operands::DestinationSourceAndThird(_, dst, src, third) => vec![dst, src, third],
+        }
+        .into_iter()
+        .filter_map(|operand| operand.segment_override_mut())
+        .collect::<Vec<_>>();
+
+        // The string instructions with explicit operands address two memory operands, but only
+        // the source can be overridden, which the encoder validates.
+        if memory_operands.len() == 2
+            && matches!(instruction.operation, Operation::MOVS | Operation::CMPS)
+        {
+            let source = if instruction.operation == Operation::MOVS {
+                1
+            } else {
+                0
+            };
+            memory_operands.swap(0, source);
+            memory_operands.truncate(1);
+        }
+
+        match memory_operands.as_mut_slice() {
+            [segment_override] if segment_override.is_none() => {
+                **segment_override = Some(segment);
+                Ok(ast::Line::Instruction(instruction))
+            }
+            _ => Err(ParserError::InvalidSegmentOverride(prefix_span)),
+        }
+    }
+
     fn parse_operands(&mut self) -> Result<ast::Operands, ParserError> {
         if matches!(self.token, Token::NewLine(_) | Token::EndOfFile(_)) {
             Ok(ast::Operands::None(
@@ -305,6 +613,18 @@ impl<'a> Parser<'a> {
                     self.next_token();
                     let source = self.parse_operand(None)?;
 
+                    if matches!(self.token, Token::Punctuation(_, PunctuationKind::Comma)) {
+                        self.next_token();
+                        let third = self.parse_operand(None)?;
+
+                        return Ok(ast::Operands::DestinationSourceAndThird(
+                            start..self.last_token_end,
+                            destination,
+                            source,
+                            third,
+                        ));
+                    }
+
                     Ok(ast::Operands::DestinationAndSource(
                         start..self.last_token_end,
                         destination,
@@ -330,27 +650,107 @@ impl<'a> Parser<'a> {
 
             Token::Identifier(_) => {
                 let identifier = self.token_source();
-                if let Ok(register) = ast::Register::from_str(identifier) {
+                if identifier.eq_ignore_ascii_case("st") {
+                    self.next_token();
+                    let index = self.parse_st_index()?;
+                    Ok(ast::Operand::Register(
+                        start..self.last_token_end,
+                        ast::Register::St(index),
+                    ))
+                } else if let Ok(register) = ast::Register::from_str(identifier) {
                     self.next_token();
                     Ok(ast::Operand::Register(start..self.last_token_end, register))
                 } else if let Ok(segment) = ast::Segment::from_str(identifier) {
                     self.next_token();
-                    Ok(ast::Operand::Segment(start..self.last_token_end, segment))
+                    if let Token::Punctuation(_, PunctuationKind::Colon) = self.token {
+                        self.parse_segment_override_operand(start, segment, data_size)
+                    } else {
+                        Ok(ast::Operand::Segment(start..self.last_token_end, segment))
+                    }
                 } else if let Ok(data_size) = ast::DataSize::from_str(identifier) {
                     self.next_token();
                     self.parse_operand(Some(data_size))
                 } else {
-                    self.parse_immediate_or_far()
+                    self.parse_immediate_or_far(data_size)
                 }
             }
 
-            _ => self.parse_immediate_or_far(),
+            _ => self.parse_immediate_or_far(data_size),
         }?;
 
         Ok(result)
     }
 
-    fn parse_immediate_or_far(&mut self) -> Result<ast::Operand, ParserError> {
+    /// Parse a memory operand with the segment override in front of the brackets, e.g. "es:[bx]".
+    fn parse_segment_override_operand(
+        &mut self,
+        start: usize,
+        segment: ast::Segment,
+        data_size: Option<ast::DataSize>,
+    ) -> Result<ast::Operand, ParserError> {
+        // Consume the colon.
+        self.next_token();
+
+        if !matches!(
+            self.token,
+            Token::Punctuation(_, PunctuationKind::OpenBracket)
+        ) {
+            return Err(self.expected("memory operand after segment override".to_owned()));
+        }
+
+        let mut operand = self.parse_memory_operand(data_size)?;
+
+        match operand.segment_override_mut() {
+            Some(segment_override) if segment_override.is_none() => {
+                *segment_override = Some(segment);
+            }
+            _ => {
+                return Err(ParserError::InvalidSegmentOverride(
+                    start..self.last_token_end,
+                ))
+            }
+        }
+
+        let span = operand.span_mut();
+        *span = start..span.end;
+
+        Ok(operand)
+    }
+
+    /// Parse the optional "(i)" after "st" for the 8087 stack registers.  Without it, "st" is the
+    /// top of the stack.
+    fn parse_st_index(&mut self) -> Result<u8, ParserError> {
+        if !matches!(
+            self.token,
+            Token::Punctuation(_, PunctuationKind::OpenParenthesis)
+        ) {
+            return Ok(0);
+        }
+
+        // Consume the "(".
+        self.next_token();
+
+        let index = match self.token {
+            Token::Literal(_, LiteralKind::Number(index)) if (0..8).contains(&index) => index as u8,
+            _ => return Err(self.expected("register index (0 to 7)".to_owned())),
+        };
+        self.next_token();
+
+        if !matches!(
+            self.token,
+            Token::Punctuation(_, PunctuationKind::CloseParenthesis)
+        ) {
+            return Err(self.expected("closing parenthesis".to_owned()));
+        }
+        self.next_token();
+
+        Ok(index)
+    }
+
+    fn parse_immediate_or_far(
+        &mut self,
+        data_size: Option<ast::DataSize>,
+    ) -> Result<ast::Operand, ParserError> {
         let start = self.token_start;
 
         let expression = self.parse_expression()?;
@@ -367,6 +767,7 @@ impl<'a> Parser<'a> {
             Ok(ast::Operand::Immediate(
                 start..self.last_token_end,
                 expression,
+                data_size,
             ))
         }
     }
@@ -401,6 +802,7 @@ impl<'a> Parser<'a> {
 
         let mut segment_override = None;
         let mut indirect_encoding = None;
+        let mut address32 = None;
 
         let expression = match self.token {
             Token::Identifier(_) => {
@@ -419,7 +821,11 @@ impl<'a> Parser<'a> {
                     }
                 }
 
-                indirect_encoding = self.parse_indirect_encoding()?;
+                if let Some(ast::Register::DWord(_)) = self.parse_register()? {
+                    address32 = Some(self.parse_address32()?);
+                } else {
+                    indirect_encoding = self.parse_indirect_encoding()?;
+                }
 
                 if matches!(
                     self.token,
@@ -433,7 +839,10 @@ impl<'a> Parser<'a> {
                 }
             }
 
-            Token::Literal(_, LiteralKind::Number(_)) => Some(self.parse_expression()?),
+            Token::Literal(
+                _,
+                LiteralKind::Number(_) | LiteralKind::InvalidNumber | LiteralKind::NumberTooLarge,
+            ) => Some(self.parse_expression()?),
 
             _ => return Err(ParserError::SegmentOrAddressExpected(self.token_range())),
         };
@@ -447,7 +856,15 @@ impl<'a> Parser<'a> {
             return Err(self.expected("closing bracket for memory address".to_owned()));
         }
 
-        Ok(if let Some(indirect_encoding) = indirect_encoding {
+        Ok(if let Some(address) = address32 {
+            ast::Operand::Indirect32(
+                start..self.last_token_end,
+                address,
+                expression,
+                data_size,
+                segment_override,
+            )
+        } else if let Some(indirect_encoding) = indirect_encoding {
             ast::Operand::Indirect(
                 start..self.last_token_end,
                 indirect_encoding,
@@ -554,6 +971,91 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a 32-bit address of the form "base + index * scale", where either the base or the
+    /// index can be left out.
+    fn parse_address32(&mut self) -> Result<ast::Address32, ParserError> {
+        let start = self.token_start;
+
+        let (first, first_scale) = self.parse_address32_register()?;
+
+        let second = if let Token::Punctuation(_, PunctuationKind::Plus) = self.token {
+            let checkpoint = self.checkpoint();
+
+            // Consume the +.
+            self.next_token();
+
+            if let Some(ast::Register::DWord(_)) = self.parse_register()? {
+                Some(self.parse_address32_register()?)
+            } else {
+                // Not a register, so let the expression parser handle the +.
+                self.jump_to_checkpoint(checkpoint);
+                None
+            }
+        } else {
+            None
+        };
+
+        let invalid = |parser: &Self, second: Option<ast::DWordRegister>| {
+            ParserError::InvalidIndirectEncoding(
+                start..parser.last_token_end,
+                ast::Register::DWord(first),
+                second.map(ast::Register::DWord),
+            )
+        };
+
+        use ast::DWordRegister::Esp;
+
+        let (base, index) = match (first_scale, second) {
+            // A single register with a scale of 1 is a base.
+            (None | Some(1), None) => (Some(first), None),
+            (Some(scale), None) => (None, Some((first, scale))),
+
+            (Some(_), Some((_, Some(_)))) => return Err(invalid(self, second.map(|s| s.0))),
+            (Some(scale), Some((base, None))) => (Some(base), Some((first, scale))),
+            (None, Some((index, Some(scale)))) => (Some(first), Some((index, scale))),
+
+            // ESP can not be used as an index, so swap the registers if possible.
+            (None, Some((Esp, None))) if first != Esp => (Some(Esp), Some((first, 1))),
+            (None, Some((second, None))) => (Some(first), Some((second, 1))),
+        };
+
+        if matches!(index, Some((Esp, _))) {
+            return Err(invalid(self, second.map(|s| s.0)));
+        }
+
+        Ok(ast::Address32 { base, index })
+    }
+
+    /// Parse a 32-bit register used in an address with an optional scale.
+    fn parse_address32_register(
+        &mut self,
+    ) -> Result<(ast::DWordRegister, Option<u8>), ParserError> {
+        let register = match self.parse_register()? {
+            Some(ast::Register::DWord(register)) => register,
+            _ => return Err(self.expected("32-bit register".to_owned())),
+        };
+
+        // Consume the register.
+        self.next_token();
+
+        if !matches!(self.token, Token::Punctuation(_, PunctuationKind::Star)) {
+            return Ok((register, None));
+        }
+
+        // Consume the *.
+        self.next_token();
+
+        let scale = match self.token {
+            Token::Literal(_, LiteralKind::Number(scale @ (1 | 2 | 4 | 8))) => scale as u8,
+            _ => return Err(self.expected("scale (1, 2, 4 or 8)".to_owned())),
+        };
+
+        // Consume the scale.
+        self.next_token();
+
+        Ok((register, Some(scale)))
+    }
+
     fn parse_register(&mut self) -> Result<Option<ast::Register>, ParserError> {
         if let Token::Identifier(_) = self.token {
             let source = self.token_source();
@@ -584,7 +1086,7 @@ impl<'a> Parser<'a> {
         Ok(ast::Line::Constant(start..end, expression))
     }
 
-    fn parse_data(&mut self, bytes_per_value: usize) -> Result<ast::Line, ParserError> {
+    fn parse_data(&mut self, data_size: ast::DataSize) -> Result<ast::Line, ParserError> {
         debug_assert!(matches!(self.token, Token::Identifier(_)));
 
         let data_definition_token_span = self.token_range();
@@ -594,48 +1096,34 @@ impl<'a> Parser<'a> {
         // Consume the "Dx" keyword.
         self.next_token();
 
-        let mut data = Vec::<u8>::new();
+        let mut items = vec![];
 
         loop {
             match self.token {
                 Token::NewLine(_) | Token::EndOfFile(_) => break,
 
-                Token::Punctuation(_, PunctuationKind::Comma) => {
-                    self.next_token();
-                    continue;
-                }
-
-                Token::Literal(_, LiteralKind::String(terminated)) => {
-                    if !terminated {
-                        return Err(ParserError::UnterminatedStringLiteral(self.token_range()));
-                    }
-
-                    let source = self.token_source();
-                    let source = &source[1..source.len() - 1]; // Strip the quotes
-                    self.next_token();
+                Token::Literal(_, LiteralKind::String(_)) => {
+                    let span = self.token_range();
+                    let bytes = self.parse_string_literal()?;
 
-                    for b in source.as_bytes() {
-                        data.push(*b);
-                    }
+                    items.push(ast::DataItem::Bytes(span, bytes));
                 }
 
-                Token::Literal(_, LiteralKind::Number(number)) => {
-                    (number as u32)
-                        .to_le_bytes()
-                        .iter()
-                        .take(bytes_per_value)
-                        .for_each(|b| data.push(*b));
+                _ => items.push(ast::DataItem::Expression(self.parse_expression()?)),
+            }
 
-                    self.next_token();
-                }
+            match self.token {
+                Token::NewLine(_) | Token::EndOfFile(_) => break,
 
-                _ => return Err(self.expected("literal expected for data definition".to_owned())),
+                Token::Punctuation(_, PunctuationKind::Comma) => self.next_token(),
+
+                _ => return Err(self.expected("comma".to_owned())),
             }
         }
 
         let end = self.last_token_end;
 
-        if data.is_empty() {
+        if items.is_empty() {
             return Err(ParserError::DataDefinitionWithoutData(
                 data_definition_token_span,
             ));
@@ -643,72 +1131,514 @@ impl<'a> Parser<'a> {
 
         self.require_new_line()?;
 
-        Ok(ast::Line::Data(start..end, data))
+        Ok(ast::Line::Data(start..end, data_size, items))
     }
 
-    fn parse_times(&mut self) -> Result<ast::Line, ParserError> {
-        debug_assert!(matches!(self.token, Token::Identifier(_)));
-        debug_assert!(self.token_source().to_lowercase().as_str() == "times");
-
-        // Consume the "times" keyword.
-        self.next_token();
+    /// Returns true if the current token is the given keyword.
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.token, Token::Identifier(_))
+            && self.token_source().eq_ignore_ascii_case(keyword)
+    }
 
-        // Should be followed by the number of times to repeat the content.
-        let expression = self.parse_expression()?;
+    /// Returns the size of the elements reserved by the current token if it is one of the `resx`
+    /// keywords.
+    fn reserve_data_size(&self) -> Option<ast::DataSize> {
+        if !matches!(self.token, Token::Identifier(_)) {
+            return None;
+        }
 
-        if let Some(line_content) = self.parse_line()? {
-            Ok(ast::Line::Times(0..0, expression, Box::new(line_content)))
-        } else {
-            todo!()
+        match self.token_source().to_lowercase().as_str() {
+            "resb" => Some(ast::DataSize::Byte),
+            "resw" => Some(ast::DataSize::Word),
+            "resd" => Some(ast::DataSize::DWord),
+            "resq" => Some(ast::DataSize::QWord),
+            "rest" => Some(ast::DataSize::TWord),
+            _ => None,
         }
     }
 
-    fn expected(&self, expected: String) -> ParserError {
-        ParserError::Expected(
-            self.token_range(),
-            expected,
-            FoundToken(self.token.clone(), self.token_source()).to_string(),
-        )
+    /// Skip new lines, but report an error if we reach the end of the file while looking for the
+    /// end of a block.
+    fn skip_new_lines_in_block(&mut self, end_keyword: &str) -> Result<(), ParserError> {
+        loop {
+            match self.token {
+                Token::NewLine(_) => self.next_token(),
+                Token::EndOfFile(_) => return Err(self.expected(format!("\"{}\"", end_keyword))),
+                _ => return Ok(()),
+            }
+        }
     }
 
-    fn instruction_expected(&self) -> ParserError {
-        ParserError::InstructionExpected(
-            self.token_range(),
-            format!("{}", FoundToken(self.token.clone(), self.token_source())),
-        )
-    }
-}
+    fn parse_struct(&mut self) -> Result<ast::Line, ParserError> {
+        debug_assert!(self.is_keyword("struc"));
 
-/// Utility functions for tokens.
-impl Token {
-    fn operator(&self) -> Option<ast::Operator> {
-        Some(match self {
-            Token::Punctuation(_, punctuation) => match punctuation {
-                PunctuationKind::Plus => ast::Operator::Add,
-                PunctuationKind::Minus => ast::Operator::Subtract,
-                PunctuationKind::Star => ast::Operator::Multiply,
-                PunctuationKind::ForwardSlash => ast::Operator::Divide,
-                _ => return None,
-            },
-            _ => return None,
-        })
-    }
-}
+        let start = self.token_start;
 
-impl<'a> Parser<'a> {
-    fn prefix_precedence(operator: ast::Operator) -> Result<((), u8), ParserError> {
-        Ok(match operator {
-            ast::Operator::Add | ast::Operator::Subtract => ((), 5),
-            _ => return Err(ParserError::InvalidPrefixOperator(0..0)),
-        })
-    }
+        // Consume the "struc" keyword.
+        self.next_token();
 
-    fn infix_precedence(operator: ast::Operator) -> (u8, u8) {
-        match operator {
-            ast::Operator::Add | ast::Operator::Subtract => (1, 2),
-            ast::Operator::Multiply | ast::Operator::Divide => (3, 4),
+        if !matches!(self.token, Token::Identifier(_)) {
+            return Err(self.expected("structure name".to_owned()));
         }
-    }
+        let name = self.parse_label()?;
+        self.require_new_line()?;
+
+        let mut fields = vec![];
+
+        loop {
+            self.skip_new_lines_in_block("endstruc")?;
+
+            if self.is_keyword("endstruc") {
+                self.next_token();
+                break;
+            }
+
+            let field_start = self.token_start;
+
+            let label = if matches!(self.token, Token::Identifier(_))
+                && self.reserve_data_size().is_none()
+            {
+                Some(self.parse_label()?)
+            } else {
+                None
+            };
+
+            let data_size = match self.reserve_data_size() {
+                Some(data_size) => data_size,
+                None => {
+                    return Err(self
+                        .expected("\"resb\", \"resw\", \"resd\", \"resq\" or \"rest\"".to_owned()))
+                }
+            };
+
+            // Consume the "resx" keyword.
+            self.next_token();
+
+            let count = self.parse_expression()?;
+
+            fields.push(ast::StructField(
+                field_start..self.last_token_end,
+                label,
+                data_size,
+                count,
+            ));
+
+            self.require_new_line()?;
+        }
+
+        let end = self.last_token_end;
+
+        self.require_new_line()?;
+
+        Ok(ast::Line::Struct(start..end, name, fields))
+    }
+
+    fn parse_struct_instance(&mut self) -> Result<ast::Line, ParserError> {
+        debug_assert!(self.is_keyword("istruc"));
+
+        let start = self.token_start;
+
+        // Consume the "istruc" keyword.
+        self.next_token();
+
+        if !matches!(self.token, Token::Identifier(_)) {
+            return Err(self.expected("structure name".to_owned()));
+        }
+        let name = self.parse_label()?;
+        self.require_new_line()?;
+
+        let mut fields = vec![];
+
+        loop {
+            self.skip_new_lines_in_block("iend")?;
+
+            if self.is_keyword("iend") {
+                self.next_token();
+                break;
+            }
+
+            if !self.is_keyword("at") {
+                return Err(self.expected("\"at\" or \"iend\"".to_owned()));
+            }
+
+            let field_start = self.token_start;
+
+            // Consume the "at" keyword.
+            self.next_token();
+
+            let field = self.parse_expression()?;
+
+            if matches!(self.token, Token::Punctuation(_, PunctuationKind::Comma)) {
+                self.next_token();
+            } else {
+                return Err(self.expected("comma".to_owned()));
+            }
+
+            let data = match self.token {
+                Token::Identifier(_)
+                    if ["db", "dw", "dd", "dq"]
+                        .iter()
+                        .any(|keyword| self.is_keyword(keyword)) =>
+                {
+                    self.parse_instruction_or_meta()?.unwrap()
+                }
+                _ => return Err(self.expected("data definition".to_owned())),
+            };
+
+            fields.push(ast::StructInstanceField(
+                field_start..data.span().end,
+                field,
+                Box::new(data),
+            ));
+        }
+
+        let end = self.last_token_end;
+
+        self.require_new_line()?;
+
+        Ok(ast::Line::StructInstance(start..end, name, fields))
+    }
+
+    /// Parse `align N[, fill]` or `alignb N`.  `alignb` always pads with zeroes.
+    fn parse_align(&mut self, zero_fill: bool) -> Result<ast::Line, ParserError> {
+        debug_assert!(matches!(self.token, Token::Identifier(_)));
+
+        let start = self.token_start;
+        let keyword_span = self.token_range();
+
+        // Consume the "align" keyword.
+        self.next_token();
+
+        let alignment = self.parse_expression()?;
+
+        let fill = if zero_fill {
+            Some(ast::Expression::Value(
+                keyword_span,
+                ast::Value::Constant(0),
+            ))
+        } else if matches!(self.token, Token::Punctuation(_, PunctuationKind::Comma)) {
+            self.next_token();
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        let end = self.last_token_end;
+
+        self.require_new_line()?;
+
+        Ok(ast::Line::Align(start..end, alignment, fill))
+    }
+
+    /// Consume a string literal and return the bytes it represents.  Characters are encoded in
+    /// code page 437 and backquoted strings may contain C-style escape sequences.
+    fn parse_string_literal(&mut self) -> Result<Vec<u8>, ParserError> {
+        debug_assert!(matches!(
+            self.token,
+            Token::Literal(_, LiteralKind::String(_))
+        ));
+
+        if let Token::Literal(_, LiteralKind::String(false)) = self.token {
+            return Err(ParserError::UnterminatedStringLiteral(self.token_range()));
+        }
+
+        let source = self.token_source();
+        let start = self.token_start + 1;
+        let escapes = source.starts_with('`');
+
+        // Strip the quotes.
+        let mut chars = source[1..source.len() - 1].char_indices().peekable();
+
+        let mut bytes = vec![];
+
+        while let Some((index, c)) = chars.next() {
+            if escapes && c == '\\' {
+                let (_, escape) = chars.next().unwrap();
+                let byte = match escape {
+                    '\'' | '"' | '`' | '\\' | '?' => escape as u8,
+                    'a' => 0x07,
+                    'b' => 0x08,
+                    't' => 0x09,
+                    'n' => 0x0A,
+                    'v' => 0x0B,
+                    'f' => 0x0C,
+                    'r' => 0x0D,
+                    'e' => 0x1B,
+                    '0'..='7' => {
+                        let mut value = escape.to_digit(8).unwrap();
+                        for _ in 0..2 {
+                            match chars.peek() {
+                                Some((_, digit @ '0'..='7')) => {
+                                    value = value * 8 + digit.to_digit(8).unwrap();
+                                    chars.next();
+                                }
+                                _ => break,
+                            }
+                        }
+                        value as u8
+                    }
+                    'x' => {
+                        let mut value = None;
+                        for _ in 0..2 {
+                            match chars.peek() {
+                                Some((_, digit)) if digit.is_ascii_hexdigit() => {
+                                    value =
+                                        Some(value.unwrap_or(0) * 16 + digit.to_digit(16).unwrap());
+                                    chars.next();
+                                }
+                                _ => break,
+                            }
+                        }
+                        match value {
+                            Some(value) => value as u8,
+                            None => {
+                                return Err(ParserError::InvalidEscapeSequence(
+                                    start + index..start + index + 2,
+                                ))
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(ParserError::InvalidEscapeSequence(
+                            start + index..start + index + 1 + escape.len_utf8(),
+                        ))
+                    }
+                };
+                bytes.push(byte);
+            } else if let Some(byte) = cp437_from_char(c) {
+                bytes.push(byte);
+            } else {
+                return Err(ParserError::CharacterNotEncodable(
+                    start + index..start + index + c.len_utf8(),
+                    c,
+                ));
+            }
+        }
+
+        // Consume the literal.
+        self.next_token();
+
+        Ok(bytes)
+    }
+
+    fn parse_cpu(&mut self) -> Result<ast::Line, ParserError> {
+        debug_assert!(self.is_keyword("cpu"));
+
+        let start = self.token_start;
+
+        // Consume the "cpu" keyword.
+        self.next_token();
+
+        let cpu = match self.token {
+            Token::Identifier(_) | Token::Literal(_, LiteralKind::Number(_)) => {
+                Cpu::from_str(self.token_source()).map_err(|_| {
+                    self.expected("CPU type (8086, 186, 286, 386 or V20)".to_owned())
+                })?
+            }
+            _ => return Err(self.expected("CPU type (8086, 186, 286, 386 or V20)".to_owned())),
+        };
+
+        // Consume the CPU type.
+        self.next_token();
+
+        let end = self.last_token_end;
+
+        self.require_new_line()?;
+
+        Ok(ast::Line::Cpu(start..end, cpu))
+    }
+
+    fn parse_bits(&mut self) -> Result<ast::Line, ParserError> {
+        debug_assert!(self.is_keyword("bits"));
+
+        let start = self.token_start;
+
+        // Consume the "bits" keyword.
+        self.next_token();
+
+        let bits = match self.token {
+            Token::Literal(_, LiteralKind::Number(_)) => {
+                ast::Bits::from_str(self.token_source())
+                    .map_err(|_| self.expected("16 or 32".to_owned()))?
+            }
+            _ => return Err(self.expected("16 or 32".to_owned())),
+        };
+
+        // Consume the number of bits.
+        self.next_token();
+
+        let end = self.last_token_end;
+
+        self.require_new_line()?;
+
+        Ok(ast::Line::Bits(start..end, bits))
+    }
+
+    fn parse_times(&mut self) -> Result<ast::Line, ParserError> {
+        debug_assert!(matches!(self.token, Token::Identifier(_)));
+        debug_assert!(self.token_source().to_lowercase().as_str() == "times");
+
+        let start = self.token_start;
+
+        // Consume the "times" keyword.
+        self.next_token();
+
+        // Should be followed by the number of times to repeat the content.
+        let expression = self.parse_expression()?;
+
+        // The content to repeat has to be on the same line.
+        if matches!(self.token, Token::NewLine(_) | Token::EndOfFile(_)) {
+            return Err(self.instruction_expected());
+        }
+
+        match self.parse_line()? {
+            Some(line_content) => Ok(ast::Line::Times(
+                start..line_content.span().end,
+                expression,
+                Box::new(line_content),
+            )),
+            None => Err(self.instruction_expected()),
+        }
+    }
+
+    fn expected(&self, expected: String) -> ParserError {
+        ParserError::Expected(
+            self.token_range(),
+            expected,
+            FoundToken(self.token.clone(), self.token_source()).to_string(),
+        )
+    }
+
+    fn instruction_expected(&self) -> ParserError {
+        ParserError::InstructionExpected(
+            self.token_range(),
+            format!("{}", FoundToken(self.token.clone(), self.token_source())),
+        )
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the operator for the current token, including operators that are written as
+    /// identifiers, e.g. `seg` or `wrt`.
+    fn operator(&self) -> Option<ast::Operator> {
+        if let Token::Identifier(_) = self.token {
+            let operator = match self.token_source().to_lowercase().as_str() {
+                "seg" => ast::Operator::Segment,
+                "wrt" => ast::Operator::WithRespectTo,
+                "low" => ast::Operator::Low,
+                "high" => ast::Operator::High,
+                "sizeof" => ast::Operator::SizeOf,
+                "lengthof" => ast::Operator::LengthOf,
+                _ => return None,
+            };
+
+            // The functions are only operators when they are followed by their operand, so they
+            // can still be used as labels, e.g. "mov al, [low]".
+            match operator {
+                ast::Operator::Low
+                | ast::Operator::High
+                | ast::Operator::SizeOf
+                | ast::Operator::LengthOf
+                    if !self.is_operand_next() =>
+                {
+                    None
+                }
+                operator => Some(operator),
+            }
+        } else {
+            self.token.operator()
+        }
+    }
+
+    /// Does the token after the current one start an operand of a prefix operator?
+    fn is_operand_next(&self) -> bool {
+        let mut parser = self.clone();
+        parser.next_token();
+
+        matches!(
+            parser.token,
+            Token::Identifier(_)
+                | Token::Literal(..)
+                | Token::Punctuation(_, PunctuationKind::OpenParenthesis)
+        )
+    }
+}
+
+/// Utility functions for tokens.
+impl Token {
+    fn operator(&self) -> Option<ast::Operator> {
+        Some(match self {
+            Token::Punctuation(_, punctuation) => match punctuation {
+                PunctuationKind::Plus => ast::Operator::Add,
+                PunctuationKind::Minus => ast::Operator::Subtract,
+                PunctuationKind::Star => ast::Operator::Multiply,
+                PunctuationKind::ForwardSlash => ast::Operator::Divide,
+                PunctuationKind::DoubleForwardSlash => ast::Operator::SignedDivide,
+                PunctuationKind::Percent => ast::Operator::Modulo,
+                PunctuationKind::DoublePercent => ast::Operator::SignedModulo,
+                PunctuationKind::DoubleLessThan => ast::Operator::ShiftLeft,
+                PunctuationKind::DoubleGreaterThan => ast::Operator::ShiftRight,
+                PunctuationKind::Ampersand => ast::Operator::BitwiseAnd,
+                PunctuationKind::Pipe => ast::Operator::BitwiseOr,
+                PunctuationKind::Caret => ast::Operator::BitwiseXor,
+                PunctuationKind::Tilde => ast::Operator::BitwiseNot,
+                PunctuationKind::DoubleAmpersand => ast::Operator::LogicalAnd,
+                PunctuationKind::DoublePipe => ast::Operator::LogicalOr,
+                PunctuationKind::DoubleCaret => ast::Operator::LogicalXor,
+                PunctuationKind::Exclamation => ast::Operator::LogicalNot,
+                PunctuationKind::Equal | PunctuationKind::DoubleEqual => ast::Operator::Equal,
+                PunctuationKind::ExclamationEqual | PunctuationKind::LessThanGreaterThan => {
+                    ast::Operator::NotEqual
+                }
+                PunctuationKind::LessThan => ast::Operator::LessThan,
+                PunctuationKind::LessThanEqual => ast::Operator::LessThanOrEqual,
+                PunctuationKind::GreaterThan => ast::Operator::GreaterThan,
+                PunctuationKind::GreaterThanEqual => ast::Operator::GreaterThanOrEqual,
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn prefix_precedence(
+        operator: ast::Operator,
+        span: ast::Span,
+    ) -> Result<((), u8), ParserError> {
+        use ast::Operator::*;
+
+        Ok(match operator {
+            Add | Subtract | BitwiseNot | LogicalNot | Segment | Low | High | SizeOf | LengthOf => {
+                ((), 21)
+            }
+            _ => return Err(ParserError::InvalidPrefixOperator(span)),
+        })
+    }
+
+    /// Precedence follows NASM, from lowest to highest: `wrt`, `||`, `^^`, `&&`, comparisons, `|`,
+    /// `^`, `&`, shifts, `+ -` and `* / // % %%`.  Returns [None] for operators that can only be
+    /// used as a prefix.
+    fn infix_precedence(operator: ast::Operator) -> Option<(u8, u8)> {
+        use ast::Operator::*;
+
+        Some(match operator {
+            WithRespectTo => (0, 1),
+            LogicalOr => (1, 2),
+            LogicalXor => (3, 4),
+            LogicalAnd => (5, 6),
+            Equal | NotEqual | LessThan | LessThanOrEqual | GreaterThan | GreaterThanOrEqual => {
+                (7, 8)
+            }
+            BitwiseOr => (9, 10),
+            BitwiseXor => (11, 12),
+            BitwiseAnd => (13, 14),
+            ShiftLeft | ShiftRight => (15, 16),
+            Add | Subtract => (17, 18),
+            Multiply | Divide | SignedDivide | Modulo | SignedModulo => (19, 20),
+            BitwiseNot | LogicalNot | Segment | Low | High | SizeOf | LengthOf => return None,
+        })
+    }
 
     fn parse_expression_with_precedence(
         &mut self,
@@ -734,11 +1664,13 @@ impl<'a> Parser<'a> {
             }
 
             _ => {
-                if let Some(operator) = self.token.operator() {
+                if let Some(operator) = self.operator() {
+                    let ((), right_precedence) =
+                        Self::prefix_precedence(operator, self.token_range())?;
+
                     // Consume the operator.
                     self.next_token();
 
-                    let ((), right_precedence) = Self::prefix_precedence(operator)?;
                     let right = self.parse_expression_with_precedence(right_precedence)?;
 
                     let end = self.last_token_end;
@@ -755,7 +1687,7 @@ impl<'a> Parser<'a> {
                 Token::NewLine(_) | Token::EndOfFile(_) => break,
 
                 _ => {
-                    if let Some(operator) = self.token.operator() {
+                    if let Some(operator) = self.operator() {
                         operator
                     } else {
                         break;
@@ -763,7 +1695,10 @@ impl<'a> Parser<'a> {
                 }
             };
 
-            let (left_precedence, right_precedence) = Parser::infix_precedence(operator);
+            let (left_precedence, right_precedence) = match Parser::infix_precedence(operator) {
+                Some(precedence) => precedence,
+                None => break,
+            };
             if left_precedence < precedence {
                 break;
             }
@@ -791,20 +1726,28 @@ impl<'a> Parser<'a> {
                 Ok(ast::Value::Constant(value))
             }
 
-            Token::Literal(_, LiteralKind::String(terminated)) => {
-                let literal = self.token_source();
-                if !terminated {
-                    Err(self.expected("Unterminated string literal".to_owned()))
-                } else if literal.len() != 3 {
-                    Err(self.expected("Only character literals allowed.".to_owned()))
-                } else {
-                    // Consume the literal.
-                    self.next_token();
+            Token::Literal(_, LiteralKind::InvalidNumber) => {
+                Err(ParserError::InvalidNumberLiteral(self.token_range()))
+            }
+
+            Token::Literal(_, LiteralKind::NumberTooLarge) => {
+                Err(ParserError::NumberLiteralTooLarge(self.token_range()))
+            }
 
-                    let value = literal[1..literal.len() - 1].chars().next().unwrap() as i32;
+            Token::Literal(_, LiteralKind::String(_)) => {
+                let span = self.token_range();
+                let bytes = self.parse_string_literal()?;
 
-                    Ok(ast::Value::Constant(value))
+                // Character constants are stored little-endian, so 'AB' is 0x4241.
+                if bytes.len() > 8 {
+                    return Err(ParserError::CharacterConstantTooLong(span));
                 }
+                let value = bytes
+                    .iter()
+                    .rev()
+                    .fold(0_u64, |value, byte| (value << 8) | *byte as u64);
+
+                Ok(ast::Value::Constant(value as i64))
             }
 
             Token::Identifier(len) => {
@@ -944,30 +1887,103 @@ mod tests {
     }
 
     #[test]
-    fn with_labels() {
-        assert_parse!(
-            "begin: hlt\nend: hlt\nhlt",
-            vec![
-                ast::Line::Label(ast::Label(0..5, "begin".to_owned())),
-                ast::Line::Instruction(ast::Instruction {
-                    span: 7..10,
-                    operation: Operation::HLT,
-                    operands: ast::Operands::None(10..10),
-                }),
-                ast::Line::Label(ast::Label(11..14, "end".to_owned())),
-                ast::Line::Instruction(ast::Instruction {
-                    span: 16..19,
-                    operation: Operation::HLT,
-                    operands: ast::Operands::None(19..19),
-                }),
-                ast::Line::Instruction(ast::Instruction {
-                    span: 20..23,
-                    operation: Operation::HLT,
-                    operands: ast::Operands::None(23..23),
-                }),
-            ]
+    fn unknown_instruction() {
+        assert_parse_err!(
+            "mvo ax, 1",
+            ParserError::UnknownInstruction(0..3, "mvo".to_owned(), Some("mov".to_owned()))
+        );
+        assert_parse_err!(
+            "pushh [bx]",
+            ParserError::UnknownInstruction(0..5, "pushh".to_owned(), Some("push".to_owned()))
+        );
+        assert_parse_err!(
+            "xyzzy 1",
+            ParserError::UnknownInstruction(0..5, "xyzzy".to_owned(), None)
+        );
+        assert_eq!(
+            ParserError::UnknownInstruction(0..3, "mvo".to_owned(), Some("mov".to_owned()))
+                .to_string(),
+            "Unknown instruction \"mvo\". Did you mean \"mov\"?"
         );
-    }
+
+        // The keywords of structures are only valid inside a structure block.
+        assert_parse_err!(
+            "resb 4",
+            ParserError::KeywordOutsideOfBlock(0..4, "resb".to_owned(), "struc")
+        );
+        assert_parse_err!(
+            "iend",
+            ParserError::KeywordOutsideOfBlock(0..4, "iend".to_owned(), "istruc")
+        );
+        assert_eq!(
+            ParserError::KeywordOutsideOfBlock(0..4, "resb".to_owned(), "struc").to_string(),
+            "\"resb\" is only valid inside a \"struc\" block."
+        );
+        let mut parser = Parser::new("buffer resb 4");
+        assert_eq!(
+            parser.parse_line(),
+            Ok(Some(ast::Line::Label(ast::Label(
+                0..6,
+                "buffer".to_owned()
+            ))))
+        );
+        assert_eq!(
+            parser.parse_line(),
+            Err(ParserError::KeywordOutsideOfBlock(
+                7..11,
+                "resb".to_owned(),
+                "struc"
+            ))
+        );
+
+        // Labels without a colon can still be followed by an instruction or directive.
+        assert_parse!(
+            "ten equ 10\nmsg db 0\nlast",
+            vec![
+                ast::Line::Label(ast::Label(0..3, "ten".to_owned())),
+                ast::Line::Constant(
+                    4..10,
+                    ast::Expression::Value(8..10, ast::Value::Constant(10))
+                ),
+                ast::Line::Label(ast::Label(11..14, "msg".to_owned())),
+                ast::Line::Data(
+                    15..19,
+                    ast::DataSize::Byte,
+                    vec![ast::DataItem::Expression(ast::Expression::Value(
+                        18..19,
+                        ast::Value::Constant(0)
+                    ))]
+                ),
+                ast::Line::Label(ast::Label(20..24, "last".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn with_labels() {
+        assert_parse!(
+            "begin: hlt\nend: hlt\nhlt",
+            vec![
+                ast::Line::Label(ast::Label(0..5, "begin".to_owned())),
+                ast::Line::Instruction(ast::Instruction {
+                    span: 7..10,
+                    operation: Operation::HLT,
+                    operands: ast::Operands::None(10..10),
+                }),
+                ast::Line::Label(ast::Label(11..14, "end".to_owned())),
+                ast::Line::Instruction(ast::Instruction {
+                    span: 16..19,
+                    operation: Operation::HLT,
+                    operands: ast::Operands::None(19..19),
+                }),
+                ast::Line::Instruction(ast::Instruction {
+                    span: 20..23,
+                    operation: Operation::HLT,
+                    operands: ast::Operands::None(23..23),
+                }),
+            ]
+        );
+    }
 
     #[test]
     fn repeat_prefix() {
@@ -1023,32 +2039,202 @@ mod tests {
     fn data() {
         assert_parse!(
             "db 10, 20, 30",
-            vec![ast::Line::Data(0..13, vec![10, 20, 30])]
+            vec![ast::Line::Data(
+                0..13,
+                ast::DataSize::Byte,
+                vec![
+                    ast::DataItem::Expression(expr_const!(3..5, 10)),
+                    ast::DataItem::Expression(expr_const!(7..9, 20)),
+                    ast::DataItem::Expression(expr_const!(11..13, 30)),
+                ]
+            )]
         );
         assert_parse!(
-            "dw 10, 20, 30",
-            vec![ast::Line::Data(0..13, vec![10, 0, 20, 0, 30, 0])]
+            "dw 10, 'ab', label + 1",
+            vec![ast::Line::Data(
+                0..22,
+                ast::DataSize::Word,
+                vec![
+                    ast::DataItem::Expression(expr_const!(3..5, 10)),
+                    ast::DataItem::Bytes(7..11, vec![b'a', b'b']),
+                    ast::DataItem::Expression(expr_infix!(
+                        13..22,
+                        Add,
+                        expr_label!(13..18, "label"),
+                        expr_const!(21..22, 1)
+                    )),
+                ]
+            )]
         );
 
         assert_parse_err!("db ", ParserError::DataDefinitionWithoutData(0..2));
         assert_parse_err!(
-            "db test",
-            ParserError::Expected(
-                3..7,
-                "literal expected for data definition".to_owned(),
-                "identifier \"test\"".to_owned()
-            )
+            "db 10 20",
+            ParserError::Expected(6..8, "comma".to_owned(), "number \"20\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn times_without_content() {
+        assert_parse_err!(
+            "times 3",
+            ParserError::InstructionExpected(7..7, "end of file".to_owned())
+        );
+        // The content is not taken from the next line.
+        assert_parse_err!(
+            "times 3\nnop",
+            ParserError::InstructionExpected(7..8, "new line".to_owned())
+        );
+    }
+
+    #[test]
+    fn string_literals() {
+        assert_parse!(
+            "db `a\\tb\\0\\x41\\101\\``, \"\\n\", 'é'",
+            vec![ast::Line::Data(
+                0..33,
+                ast::DataSize::Byte,
+                vec![
+                    ast::DataItem::Bytes(3..21, vec![b'a', 0x09, b'b', 0x00, 0x41, 0x41, b'`']),
+                    ast::DataItem::Bytes(23..27, vec![b'\\', b'n']),
+                    ast::DataItem::Bytes(29..33, vec![0x82]),
+                ]
+            )]
+        );
+
+        assert_eq!(
+            parse_expression!("'AB'"),
+            ast::Expression::Value(0..4, ast::Value::Constant(0x4241))
+        );
+        assert_eq!(
+            parse_expression!("`\\n`"),
+            ast::Expression::Value(0..4, ast::Value::Constant(0x0A))
         );
+
+        assert_parse_err!("db `a\\q`", ParserError::InvalidEscapeSequence(5..7));
+        assert_parse_err!("db `\\x`", ParserError::InvalidEscapeSequence(4..6));
+        assert_parse_err!("db '€'", ParserError::CharacterNotEncodable(4..7, '€'));
+        assert_parse_err!(
+            "mov ax, 'ABCDEFGHI'",
+            ParserError::CharacterConstantTooLong(8..19)
+        );
+        assert_parse_err!("db 'abc", ParserError::UnterminatedStringLiteral(3..7));
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_parse!(
+            "dq 0xFFFF_FFFF_FFFF_FFFF, 'ABCDEFGH'",
+            vec![ast::Line::Data(
+                0..36,
+                ast::DataSize::QWord,
+                vec![
+                    ast::DataItem::Expression(expr_const!(3..24, -1)),
+                    ast::DataItem::Bytes(26..36, b"ABCDEFGH".to_vec()),
+                ]
+            )]
+        );
+
+        assert_eq!(
+            parse_expression!("'ABCDEFGH'"),
+            ast::Expression::Value(0..10, ast::Value::Constant(0x4847_4645_4443_4241))
+        );
+
+        assert_parse_err!("mov ax, 0b102", ParserError::InvalidNumberLiteral(8..13));
+        assert_parse_err!(
+            "dd 1 + 0x1_0000_0000_0000_0000",
+            ParserError::NumberLiteralTooLarge(7..30)
+        );
+        assert_parse_err!("mov ax, [12ab]", ParserError::InvalidNumberLiteral(9..13));
+        assert_parse_err!("db 0x", ParserError::InvalidNumberLiteral(3..5));
+    }
+
+    #[test]
+    fn cpu() {
+        assert_parse!("cpu 186", vec![ast::Line::Cpu(0..7, Cpu::I186)]);
+        assert_parse!("CPU v20", vec![ast::Line::Cpu(0..7, Cpu::V20)]);
         assert_parse_err!(
-            "db 10, test",
+            "cpu 486",
             ParserError::Expected(
-                7..11,
-                "literal expected for data definition".to_owned(),
-                "identifier \"test\"".to_owned()
+                4..7,
+                "CPU type (8086, 186, 286, 386 or V20)".to_owned(),
+                "number \"486\"".to_owned()
             )
         );
     }
 
+    #[test]
+    fn align() {
+        assert_parse!(
+            "align 16",
+            vec![ast::Line::Align(0..8, expr_const!(6..8, 16), None)]
+        );
+        assert_parse!(
+            "align 4, 0xCC",
+            vec![ast::Line::Align(
+                0..13,
+                expr_const!(6..7, 4),
+                Some(expr_const!(9..13, 0xCC))
+            )]
+        );
+        assert_parse!(
+            "alignb 2",
+            vec![ast::Line::Align(
+                0..8,
+                expr_const!(7..8, 2),
+                Some(expr_const!(0..6, 0))
+            )]
+        );
+    }
+
+    #[test]
+    fn structures() {
+        assert_parse!(
+            "struc point\n  .x: resw 1\n\n  .y: resw 1\n  resb 2\nendstruc",
+            vec![ast::Line::Struct(
+                0..56,
+                ast::Label(6..11, "point".to_owned()),
+                vec![
+                    ast::StructField(
+                        14..24,
+                        Some(ast::Label(14..16, ".x".to_owned())),
+                        ast::DataSize::Word,
+                        expr_const!(23..24, 1)
+                    ),
+                    ast::StructField(
+                        28..38,
+                        Some(ast::Label(28..30, ".y".to_owned())),
+                        ast::DataSize::Word,
+                        expr_const!(37..38, 1)
+                    ),
+                    ast::StructField(41..47, None, ast::DataSize::Byte, expr_const!(46..47, 2)),
+                ]
+            )]
+        );
+
+        assert_parse!(
+            "istruc point\n  at point.y, dw 10\niend",
+            vec![ast::Line::StructInstance(
+                0..37,
+                ast::Label(7..12, "point".to_owned()),
+                vec![ast::StructInstanceField(
+                    15..32,
+                    expr_label!(18..25, "point.y"),
+                    Box::new(ast::Line::Data(
+                        27..32,
+                        ast::DataSize::Word,
+                        vec![ast::DataItem::Expression(expr_const!(30..32, 10))]
+                    ))
+                )]
+            )]
+        );
+
+        assert_parse_err!(
+            "struc point\n  .x: resw 1\n",
+            ParserError::Expected(25..25, "\"endstruc\"".to_owned(), "end of file".to_owned())
+        );
+    }
+
     #[test]
     fn expression_with_precedence() {
         let expr = parse_expression!("2 + 3 * 4 + 5");
@@ -1106,6 +2292,137 @@ mod tests {
         );
     }
 
+    #[test]
+    fn expression_operator_precedence() {
+        assert_eq!(
+            parse_expression!("1 << 2 + 3"),
+            expr_infix!(
+                0..10,
+                ShiftLeft,
+                expr_const!(0..1, 1),
+                expr_infix!(5..10, Add, expr_const!(5..6, 2), expr_const!(9..10, 3))
+            )
+        );
+
+        assert_eq!(
+            parse_expression!("1 == 2 | 3 & 4"),
+            expr_infix!(
+                0..14,
+                Equal,
+                expr_const!(0..1, 1),
+                expr_infix!(
+                    5..14,
+                    BitwiseOr,
+                    expr_const!(5..6, 2),
+                    expr_infix!(
+                        9..14,
+                        BitwiseAnd,
+                        expr_const!(9..10, 3),
+                        expr_const!(13..14, 4)
+                    )
+                )
+            )
+        );
+
+        assert_eq!(
+            parse_expression!("1 || 2 && 3"),
+            expr_infix!(
+                0..11,
+                LogicalOr,
+                expr_const!(0..1, 1),
+                expr_infix!(
+                    5..11,
+                    LogicalAnd,
+                    expr_const!(5..6, 2),
+                    expr_const!(10..11, 3)
+                )
+            )
+        );
+
+        assert_eq!(
+            parse_expression!("~1 %% !2"),
+            expr_infix!(
+                0..8,
+                SignedModulo,
+                expr_prefix!(0..2, BitwiseNot, expr_const!(1..2, 1)),
+                expr_prefix!(6..8, LogicalNot, expr_const!(7..8, 2))
+            )
+        );
+
+        assert_eq!(
+            Parser::new("* 2").parse_expression(),
+            Err(ParserError::InvalidPrefixOperator(0..1))
+        );
+    }
+
+    #[test]
+    fn expression_functions() {
+        use ast::Operator::*;
+
+        assert_eq!(Low.evaluate(0, 0x1234), Some(0x34));
+        assert_eq!(High.evaluate(0, 0x1234), Some(0x12));
+        // These need the labels, which only the compiler knows about.
+        for operator in [Segment, WithRespectTo, SizeOf, LengthOf] {
+            assert_eq!(operator.evaluate(0, 1), None);
+        }
+
+        assert_eq!(
+            parse_expression!("seg label + 1"),
+            expr_infix!(
+                0..13,
+                Add,
+                expr_prefix!(0..9, Segment, expr_label!(4..9, "label")),
+                expr_const!(12..13, 1)
+            )
+        );
+
+        assert_eq!(
+            parse_expression!("label wrt 0x40"),
+            expr_infix!(
+                0..14,
+                WithRespectTo,
+                expr_label!(0..5, "label"),
+                expr_const!(10..14, 0x40)
+            )
+        );
+
+        assert_eq!(
+            parse_expression!("high(label)"),
+            expr_prefix!(0..11, High, expr_label!(5..10, "label"))
+        );
+
+        // Without an operand they are labels.
+        assert_eq!(
+            parse_expression!("low + high"),
+            expr_infix!(
+                0..10,
+                Add,
+                expr_label!(0..3, "low"),
+                expr_label!(6..10, "high")
+            )
+        );
+        assert_parse!(
+            "low: db 0\nmov al, [low]",
+            vec![
+                ast::Line::Label(ast::Label(0..3, "low".to_owned())),
+                ast::Line::Data(
+                    5..9,
+                    ast::DataSize::Byte,
+                    vec![ast::DataItem::Expression(expr_const!(8..9, 0))]
+                ),
+                ast::Line::Instruction(ast::Instruction {
+                    span: 10..23,
+                    operation: Operation::MOV,
+                    operands: ast::Operands::DestinationAndSource(
+                        14..23,
+                        ast::Operand::Register(14..16, ast::Register::Byte(ast::ByteRegister::Al)),
+                        ast::Operand::Direct(18..23, expr_label!(19..22, "low"), None, None)
+                    )
+                })
+            ]
+        );
+    }
+
     #[test]
     fn indirect_encoding() {
         assert_parse!(
@@ -1164,6 +2481,324 @@ mod tests {
         );
     }
 
+    #[test]
+    fn address32() {
+        use ast::DWordRegister::*;
+
+        assert_parse!(
+            "mov eax, [ebx+esi*4+0x10]",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..25,
+                operation: Operation::MOV,
+                operands: ast::Operands::DestinationAndSource(
+                    4..25,
+                    ast::Operand::Register(4..7, ast::Register::DWord(Eax)),
+                    ast::Operand::Indirect32(
+                        9..25,
+                        ast::Address32 {
+                            base: Some(Ebx),
+                            index: Some((Esi, 4)),
+                        },
+                        Some(expr_prefix!(19..24, Add, expr_const!(20..24, 0x10))),
+                        None,
+                        None,
+                    )
+                )
+            })]
+        );
+
+        assert_parse!(
+            "push dword [ecx*2]",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..18,
+                operation: Operation::PUSH,
+                operands: ast::Operands::Destination(
+                    5..18,
+                    ast::Operand::Indirect32(
+                        11..18,
+                        ast::Address32 {
+                            base: None,
+                            index: Some((Ecx, 2)),
+                        },
+                        None,
+                        Some(ast::DataSize::DWord),
+                        None,
+                    )
+                )
+            })]
+        );
+
+        // ESP can not be an index, so it becomes the base.
+        assert_parse!(
+            "mov ax, [eax+esp]",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..17,
+                operation: Operation::MOV,
+                operands: ast::Operands::DestinationAndSource(
+                    4..17,
+                    ast::Operand::Register(4..6, ast::Register::Word(ast::WordRegister::Ax)),
+                    ast::Operand::Indirect32(
+                        8..17,
+                        ast::Address32 {
+                            base: Some(Esp),
+                            index: Some((Eax, 1)),
+                        },
+                        None,
+                        None,
+                        None,
+                    )
+                )
+            })]
+        );
+
+        assert_parse_err!(
+            "mov ax, [esp*2]",
+            ParserError::InvalidIndirectEncoding(9..14, ast::Register::DWord(Esp), None)
+        );
+        assert_parse_err!(
+            "mov ax, [eax*2+ebx*2]",
+            ParserError::InvalidIndirectEncoding(
+                9..20,
+                ast::Register::DWord(Eax),
+                Some(ast::Register::DWord(Ebx))
+            )
+        );
+        assert_parse_err!(
+            "mov ax, [eax*3]",
+            ParserError::Expected(
+                13..14,
+                "scale (1, 2, 4 or 8)".to_owned(),
+                "number \"3\"".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn bits() {
+        assert_parse!("bits 32", vec![ast::Line::Bits(0..7, ast::Bits::Bits32)]);
+        assert_parse!("BITS 16", vec![ast::Line::Bits(0..7, ast::Bits::Bits16)]);
+        assert_parse_err!(
+            "bits 64",
+            ParserError::Expected(5..7, "16 or 32".to_owned(), "number \"64\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn segment_override_prefix() {
+        assert_parse!(
+            "mov ax, es:[bx]",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..15,
+                operation: Operation::MOV,
+                operands: ast::Operands::DestinationAndSource(
+                    4..15,
+                    ast::Operand::Register(4..6, ast::Register::Word(ast::WordRegister::Ax)),
+                    ast::Operand::Indirect(
+                        8..15,
+                        ast::IndirectEncoding::Bx,
+                        None,
+                        None,
+                        Some(ast::Segment::ES),
+                    )
+                )
+            })]
+        );
+
+        assert_parse!(
+            "es lodsb",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..8,
+                operation: Operation::LODSB,
+                operands: ast::Operands::Destination(
+                    0..2,
+                    ast::Operand::Segment(0..2, ast::Segment::ES),
+                )
+            })]
+        );
+
+        assert_parse!(
+            "cs mov ax, [bx]",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..15,
+                operation: Operation::MOV,
+                operands: ast::Operands::DestinationAndSource(
+                    7..15,
+                    ast::Operand::Register(7..9, ast::Register::Word(ast::WordRegister::Ax)),
+                    ast::Operand::Indirect(
+                        11..15,
+                        ast::IndirectEncoding::Bx,
+                        None,
+                        None,
+                        Some(ast::Segment::CS),
+                    )
+                )
+            })]
+        );
+
+        // Only the source of an explicit string instruction can be overridden.
+        assert_parse!(
+            "ss movs byte [di], [si]",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..23,
+                operation: Operation::MOVS,
+                operands: ast::Operands::DestinationAndSource(
+                    8..23,
+                    ast::Operand::Indirect(
+                        13..17,
+                        ast::IndirectEncoding::Di,
+                        None,
+                        Some(ast::DataSize::Byte),
+                        None,
+                    ),
+                    ast::Operand::Indirect(
+                        19..23,
+                        ast::IndirectEncoding::Si,
+                        None,
+                        None,
+                        Some(ast::Segment::SS),
+                    )
+                )
+            })]
+        );
+
+        assert_parse!(
+            "cs xlatb",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..8,
+                operation: Operation::XLATB,
+                operands: ast::Operands::Destination(
+                    0..2,
+                    ast::Operand::Segment(0..2, ast::Segment::CS),
+                )
+            })]
+        );
+
+        // The override in front of a prefix goes to the instruction after it.
+        assert_parse!(
+            "es rep movsb",
+            vec![
+                ast::Line::Instruction(ast::Instruction {
+                    span: 0..6,
+                    operation: Operation::REP,
+                    operands: ast::Operands::None(6..6),
+                }),
+                ast::Line::Instruction(ast::Instruction {
+                    span: 7..12,
+                    operation: Operation::MOVSB,
+                    operands: ast::Operands::Destination(
+                        0..2,
+                        ast::Operand::Segment(0..2, ast::Segment::ES),
+                    )
+                }),
+            ]
+        );
+        assert_parse_err!(
+            "es rep\nmovsb",
+            ParserError::InstructionExpected(6..7, "new line".to_owned())
+        );
+        let mut parser = Parser::new("es rep cs movsb");
+        assert!(parser.parse_line().is_ok());
+        assert_eq!(
+            parser.parse_line(),
+            Err(ParserError::InvalidSegmentOverride(7..9))
+        );
+
+        assert_parse_err!("es nop", ParserError::InvalidSegmentOverride(0..2));
+        assert_parse_err!(
+            "es mov ax, [cs:bx]",
+            ParserError::InvalidSegmentOverride(0..2)
+        );
+        assert_parse_err!(
+            "mov ax, es:[cs:bx]",
+            ParserError::InvalidSegmentOverride(8..18)
+        );
+        assert_parse_err!(
+            "mov ax, es:bx",
+            ParserError::Expected(
+                11..13,
+                "memory operand after segment override".to_owned(),
+                "identifier \"bx\"".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn three_operands() {
+        assert_parse!(
+            "imul ax, bx, 10",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..15,
+                operation: Operation::IMUL,
+                operands: ast::Operands::DestinationSourceAndThird(
+                    5..15,
+                    ast::Operand::Register(5..7, ast::Register::Word(ast::WordRegister::Ax)),
+                    ast::Operand::Register(9..11, ast::Register::Word(ast::WordRegister::Bx)),
+                    ast::Operand::Immediate(13..15, expr_const!(13..15, 10), None),
+                )
+            })]
+        );
+    }
+
+    #[test]
+    fn fpu_operands() {
+        assert_parse!(
+            "fadd st(0), st3",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..15,
+                operation: Operation::FADD,
+                operands: ast::Operands::DestinationAndSource(
+                    5..15,
+                    ast::Operand::Register(5..10, ast::Register::St(0)),
+                    ast::Operand::Register(12..15, ast::Register::St(3)),
+                )
+            })]
+        );
+        assert_parse!(
+            "fld tword [0x10]",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..16,
+                operation: Operation::FLD,
+                operands: ast::Operands::Destination(
+                    4..16,
+                    ast::Operand::Direct(
+                        10..16,
+                        expr_const!(11..15, 0x10),
+                        Some(ast::DataSize::TWord),
+                        None
+                    ),
+                )
+            })]
+        );
+        assert_parse!(
+            "fxch st",
+            vec![ast::Line::Instruction(ast::Instruction {
+                span: 0..7,
+                operation: Operation::FXCH,
+                operands: ast::Operands::Destination(
+                    5..7,
+                    ast::Operand::Register(5..7, ast::Register::St(0)),
+                )
+            })]
+        );
+
+        assert_parse_err!(
+            "fld st(8)",
+            ParserError::Expected(
+                7..8,
+                "register index (0 to 7)".to_owned(),
+                "number \"8\"".to_owned()
+            )
+        );
+        assert_parse_err!(
+            "fld st(1",
+            ParserError::Expected(
+                8..8,
+                "closing parenthesis".to_owned(),
+                "end of file".to_owned()
+            )
+        );
+    }
+
     #[test]
     fn far_operands() {
         assert_parse!(
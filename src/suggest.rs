@@ -0,0 +1,98 @@
+//! Find close matches for misspelled names, to suggest them in error messages.
+
+/// The names of all general purpose, segment and FPU registers.  Word registers come first, so they
+/// win a tie.
+pub(crate) const REGISTERS: &[&str] = &[
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "al", "cl", "dl", "bl", "ah", "ch", "dh", "bh",
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "es", "cs", "ss", "ds", "fs", "gs",
+    "st0", "st1", "st2", "st3", "st4", "st5", "st6", "st7",
+];
+
+/// The number of single character insertions, deletions, substitutions and transpositions of
+/// adjacent characters needed to turn one string into the other, ignoring case.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<_>>();
+    let b = b.to_lowercase().chars().collect::<Vec<_>>();
+
+    // Three rows of the distance matrix, for the transposition we need the row before the last.
+    let mut before = vec![0; b.len() + 1];
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// The candidate closest to [name], if it is close enough to be a likely misspelling.  Names of
+/// up to 3 characters allow one edit, longer names one edit for every 3 characters.  On a tie, the
+/// first candidate wins.
+pub(crate) fn best_match<'c>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'c str>,
+) -> Option<&'c str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| !candidate.eq_ignore_ascii_case(name))
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+    use std::str::FromStr;
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("mov", "MOV"), 0);
+        assert_eq!(edit_distance("mov", ""), 3);
+        assert_eq!(edit_distance("mvo", "mov"), 1);
+        assert_eq!(edit_distance("movv", "mov"), 1);
+        assert_eq!(edit_distance("mv", "mov"), 1);
+        assert_eq!(edit_distance("nov", "mov"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn best() {
+        let candidates = ["mov", "movsb", "movsw", "cmp"];
+        assert_eq!(best_match("mvo", candidates), Some("mov"));
+        assert_eq!(best_match("movsq", candidates), Some("movsb"));
+        assert_eq!(best_match("MOV", candidates), None);
+        assert_eq!(best_match("xyz", candidates), None);
+        assert_eq!(best_match("bz", REGISTERS.iter().copied()), Some("bx"));
+    }
+
+    #[test]
+    fn registers() {
+        for name in REGISTERS {
+            assert!(
+                ast::Register::from_str(name).is_ok() || ast::Segment::from_str(name).is_ok(),
+                "{}",
+                name
+            );
+        }
+    }
+}
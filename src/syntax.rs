@@ -0,0 +1,503 @@
+//! A concrete syntax tree that keeps every token of the source, including whitespace, comments and
+//! new lines.  Each line of the AST is a node with the tokens it was parsed from, everything
+//! between the lines is kept as tokens in the tree.  Writing out all the tokens gives back the
+//! original source, so tools can change parts of the source without touching the rest.
+
+use crate::ast;
+use crate::compiler::Scope;
+use crate::lexer::{Cursor, Token};
+use crate::parser::{Parser, ParserError};
+use std::fmt::{Display, Formatter};
+
+/// A token with its position in the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxToken {
+    pub token: Token,
+    pub span: ast::Span,
+}
+
+impl SyntaxToken {
+    /// Whitespace and comments, which do not change the meaning of the source.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.token, Token::Whitespace(_) | Token::Comment(_))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxNode {
+    pub span: ast::Span,
+    /// The parsed line, or [None] if the tokens have a syntax error.
+    pub line: Option<Box<ast::Line>>,
+    /// All tokens in the span, including the trivia and new lines inside it, e.g. in a structure.
+    pub tokens: Vec<SyntaxToken>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyntaxElement {
+    /// Trivia, new lines and the colons after labels between the lines.
+    Token(SyntaxToken),
+    Node(SyntaxNode),
+}
+
+pub struct SyntaxTree<'a> {
+    source: &'a str,
+    pub elements: Vec<SyntaxElement>,
+    /// The syntax errors, one for each node without a line.
+    pub errors: Vec<ParserError>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// Parse the source into a tree.  Parsing continues on the next line after a syntax error, so
+    /// there is a tree for any source.
+    pub fn parse(source: &'a str) -> Self {
+        let tokens = tokenize(source);
+
+        let mut parser = Parser::new(source);
+        let mut nodes = vec![];
+        let mut errors = vec![];
+
+        loop {
+            let start = parser.position();
+            match parser.parse_line() {
+                Ok(Some(line)) => {
+                    let span = line.span().clone();
+                    nodes.push((span, Some(Box::new(line))));
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    // The node starts at the first token of the line, after any empty lines.
+                    let start = tokens
+                        .iter()
+                        .find(|token| {
+                            token.span.start >= start
+                                && !token.is_trivia()
+                                && !matches!(token.token, Token::NewLine(_))
+                        })
+                        .map_or(start, |token| token.span.start)
+                        .min(err.span().start);
+                    parser.skip_line();
+                    nodes.push((start..parser.position().max(start), None));
+                    errors.push(err);
+                }
+            }
+        }
+
+        let mut elements = vec![];
+        let mut tokens = tokens.into_iter().peekable();
+
+        for (span, line) in nodes {
+            while let Some(token) = tokens.next_if(|token| token.span.start < span.start) {
+                elements.push(SyntaxElement::Token(token));
+            }
+
+            let mut node_tokens = vec![];
+            while let Some(token) = tokens.next_if(|token| token.span.start < span.end) {
+                node_tokens.push(token);
+            }
+
+            elements.push(SyntaxElement::Node(SyntaxNode {
+                span,
+                line,
+                tokens: node_tokens,
+            }));
+        }
+
+        elements.extend(tokens.map(SyntaxElement::Token));
+
+        Self {
+            source,
+            elements,
+            errors,
+        }
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// The source text of a token or node.
+    pub fn text(&self, span: &ast::Span) -> &'a str {
+        &self.source[span.clone()]
+    }
+
+    /// All tokens in the order they are in the source.
+    pub fn tokens(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.elements.iter().flat_map(|element| match element {
+            SyntaxElement::Token(token) => std::slice::from_ref(token).iter(),
+            SyntaxElement::Node(node) => node.tokens.iter(),
+        })
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.elements.iter().filter_map(|element| match element {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// The lines of the AST, without the lines that have syntax errors.
+    pub fn lines(&self) -> impl Iterator<Item = &ast::Line> {
+        self.nodes().filter_map(|node| node.line.as_deref())
+    }
+
+    pub fn into_lines(self) -> Vec<ast::Line> {
+        self.elements
+            .into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Node(node) => node.line.map(|line| *line),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /// The token that contains the offset in the source.
+    pub fn token_at(&self, offset: usize) -> Option<&SyntaxToken> {
+        self.tokens().find(|token| token.span.contains(&offset))
+    }
+
+    /// The node that contains the offset in the source.
+    pub fn node_at(&self, offset: usize) -> Option<&SyntaxNode> {
+        self.nodes().find(|node| node.span.contains(&offset))
+    }
+
+    /// Write the source with the text in each span replaced.  The spans must not overlap, the
+    /// rest of the source is written as it is.
+    pub fn replace(&self, replacements: &[(ast::Span, &str)]) -> String {
+        let mut replacements = replacements.to_vec();
+        replacements.sort_by_key(|(span, _)| span.start);
+
+        let mut result = String::new();
+        let mut end = 0;
+        for (span, text) in replacements {
+            debug_assert!(span.start >= end, "replacements overlap");
+            result += &self.source[end..span.start];
+            result += text;
+            end = span.end;
+        }
+        result += &self.source[end..];
+
+        result
+    }
+
+    /// Every label that is defined or referred to, with the name it resolves to in its scope, e.g.
+    /// `.loop` after `f:` resolves to `f.loop`.  Local fields of a structure resolve to names
+    /// qualified with the structure name.
+    pub fn qualified_labels(&self) -> Vec<(&ast::Label, String)> {
+        let mut scope = Scope::default();
+        let mut labels = vec![];
+
+        for line in self.lines() {
+            let fields = match line {
+                ast::Line::Struct(_, name, fields) => Some((name, fields)),
+                _ => None,
+            };
+
+            for label in line.labels() {
+                let field = fields.filter(|(_, fields)| {
+                    fields
+                        .iter()
+                        .any(|field| field.1.as_ref().is_some_and(|l| std::ptr::eq(l, label)))
+                });
+                let name = match field {
+                    Some((name, _)) if label.is_local() => format!("{}{}", name.1, label.1),
+                    _ => scope.qualified_name(label),
+                };
+                labels.push((label, name));
+            }
+
+            if let ast::Line::Label(label) = line {
+                scope.enter(label);
+            }
+        }
+
+        labels
+    }
+
+    /// Write the source with every definition of and reference to the label renamed.  The label
+    /// is given by the name it resolves to, e.g. `f.loop` for `.loop` after `f:`, so labels with
+    /// the same name in other scopes are left alone.  The parts of qualified names are renamed
+    /// too, e.g. `f` in `f.l` when `f` is renamed and `.l` in `f.l` when `f.l` is renamed.
+    pub fn rename_label(&self, name: &str, new_name: &str) -> String {
+        // The scope of a local label, e.g. `f` for `f.l`.
+        let scope = name
+            .rfind('.')
+            .filter(|dot| *dot > 0)
+            .map(|dot| &name[..dot]);
+
+        let replacements = self
+            .qualified_labels()
+            .into_iter()
+            .filter_map(|(label, qualified)| {
+                let span = &label.0;
+                if qualified == name {
+                    match scope {
+                        // A local label written with its scope, e.g. `f.l`, keeps the scope.
+                        Some(scope) if new_name.starts_with('.') && !label.is_local() => {
+                            Some((span.start + scope.len()..span.end, new_name))
+                        }
+                        _ => Some((span.clone(), new_name)),
+                    }
+                } else if qualified.starts_with(name)
+                    && qualified[name.len()..].starts_with('.')
+                    && !label.is_local()
+                {
+                    // The scope of a local label written with its scope, e.g. `f` in `f.l`.
+                    Some((span.start..span.start + name.len(), new_name))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.replace(&replacements)
+    }
+}
+
+impl Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for token in self.tokens() {
+            write!(f, "{}", self.text(&token.span))?;
+        }
+        Ok(())
+    }
+}
+
+fn tokenize(source: &str) -> Vec<SyntaxToken> {
+    let mut cursor = Cursor::new(source);
+    let mut tokens = vec![];
+
+    loop {
+        let start = cursor.pos();
+        let token = cursor.next_token();
+        if let Token::EndOfFile(_) = token {
+            break;
+        }
+        tokens.push(SyntaxToken {
+            token,
+            span: start..cursor.pos(),
+        });
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = concat!(
+        "; Copy a block.\n",
+        "start:  mov cx, count   ; the size\n",
+        "\n",
+        ".loop:\tmovsb\n",
+        "        loop .loop\n",
+        "count   equ 10\n",
+        "        times 2 movsb\n",
+    );
+
+    #[test]
+    fn lossless() {
+        for source in [
+            SOURCE,
+            "",
+            "\n\n",
+            "struc point\n  .x: resw 1 ; x\n\n  .y: resw 1\nendstruc\n",
+            "mov ax,\nmvo ax, 1\r\nnop",
+            "db 'unterminated\nnop ; no new line",
+        ] {
+            assert_eq!(SyntaxTree::parse(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn nodes() {
+        let tree = SyntaxTree::parse(SOURCE);
+
+        assert!(tree.errors.is_empty());
+        let nodes = tree
+            .nodes()
+            .map(|node| tree.text(&node.span))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                "start",
+                "mov cx, count",
+                ".loop",
+                "movsb",
+                "loop .loop",
+                "count",
+                "equ 10",
+                "times 2 movsb"
+            ]
+        );
+
+        // Everything else is kept between the nodes.
+        let trivia = tree
+            .elements
+            .iter()
+            .take(4)
+            .map(|element| match element {
+                SyntaxElement::Token(token) => tree.text(&token.span),
+                SyntaxElement::Node(_) => "<node>",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(trivia, vec!["; Copy a block.", "\n", "<node>", ":"]);
+    }
+
+    #[test]
+    fn lines() {
+        let tree = SyntaxTree::parse(SOURCE);
+
+        let mut parser = Parser::new(SOURCE);
+        let mut expected = vec![];
+        while let Some(line) = parser.parse_line().unwrap() {
+            expected.push(line);
+        }
+
+        assert_eq!(tree.lines().cloned().collect::<Vec<_>>(), expected);
+        assert_eq!(tree.into_lines(), expected);
+    }
+
+    #[test]
+    fn errors() {
+        let tree = SyntaxTree::parse("nop\n\n  mov ax,   ; comment\nmvo ax, 1\nnop\n");
+
+        assert_eq!(
+            tree.errors
+                .iter()
+                .map(ParserError::code)
+                .collect::<Vec<_>>(),
+            vec!["E0004", "E0016"]
+        );
+        let nodes = tree
+            .nodes()
+            .map(|node| (tree.text(&node.span), node.line.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                ("nop", true),
+                ("mov ax,   ; comment", false),
+                ("mvo ax, 1", false),
+                ("nop", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn lookup() {
+        let tree = SyntaxTree::parse(SOURCE);
+
+        let token = tree.token_at(33).unwrap();
+        assert_eq!(tree.text(&token.span), "count");
+        assert_eq!(tree.text(&tree.node_at(33).unwrap().span), "mov cx, count");
+
+        let comment = tree.token_at(42).unwrap();
+        assert!(comment.is_trivia());
+        assert_eq!(tree.text(&comment.span), "; the size");
+        assert_eq!(tree.node_at(42), None);
+
+        // The repeated instruction is part of the times node.
+        let offset = SOURCE.rfind("movsb").unwrap();
+        assert_eq!(tree.text(&tree.token_at(offset).unwrap().span), "movsb");
+        assert_eq!(
+            tree.text(&tree.node_at(offset).unwrap().span),
+            "times 2 movsb"
+        );
+    }
+
+    #[test]
+    fn rename() {
+        let tree = SyntaxTree::parse(SOURCE);
+
+        assert_eq!(
+            tree.rename_label("count", "size"),
+            SOURCE.replace("count", "size")
+        );
+        assert_eq!(
+            tree.rename_label("start.loop", ".again"),
+            SOURCE.replace(".loop", ".again")
+        );
+        // Only labels are renamed, not the "loop" instruction.
+        assert_eq!(tree.rename_label("loop", "x"), SOURCE);
+
+        // Qualified references are renamed with the scope or the local label.
+        let tree = SyntaxTree::parse("f:\n.l: nop\njmp f.l\n");
+        assert_eq!(tree.rename_label("f", "g"), "g:\n.l: nop\njmp g.l\n");
+        assert_eq!(tree.rename_label("f.l", ".m"), "f:\n.m: nop\njmp f.m\n");
+
+        // Local labels with the same name in other scopes are left alone.
+        let tree = SyntaxTree::parse(concat!(
+            "a:\n",
+            ".loop: jmp .loop\n",
+            "b:\n",
+            ".loop: jmp .loop\n",
+            "jmp a.loop\n",
+        ));
+        assert_eq!(
+            tree.rename_label("a.loop", ".again"),
+            concat!(
+                "a:\n",
+                ".again: jmp .again\n",
+                "b:\n",
+                ".loop: jmp .loop\n",
+                "jmp a.again\n",
+            )
+        );
+        assert_eq!(
+            tree.rename_label("b.loop", ".again"),
+            concat!(
+                "a:\n",
+                ".loop: jmp .loop\n",
+                "b:\n",
+                ".again: jmp .again\n",
+                "jmp a.loop\n",
+            )
+        );
+        assert_eq!(tree.rename_label(".loop", ".again"), tree.to_string());
+
+        let tree = SyntaxTree::parse(concat!(
+            "struc point\n",
+            ".x: resw 1\n",
+            "endstruc\n",
+            "origin: istruc point\n",
+            "at point.x, dw 1\n",
+            "iend\n",
+            "mov ax, point.x\n",
+        ));
+        assert_eq!(
+            tree.rename_label("point", "vector"),
+            concat!(
+                "struc vector\n",
+                ".x: resw 1\n",
+                "endstruc\n",
+                "origin: istruc vector\n",
+                "at vector.x, dw 1\n",
+                "iend\n",
+                "mov ax, vector.x\n",
+            )
+        );
+        assert_eq!(
+            tree.rename_label("point.x", ".y"),
+            concat!(
+                "struc point\n",
+                ".y: resw 1\n",
+                "endstruc\n",
+                "origin: istruc point\n",
+                "at point.y, dw 1\n",
+                "iend\n",
+                "mov ax, point.y\n",
+            )
+        );
+    }
+
+    #[test]
+    fn replace() {
+        let tree = SyntaxTree::parse("mov ax, 1 ; one\n");
+        assert_eq!(
+            tree.replace(&[(10..15, "; uno"), (0..3, "MOV")]),
+            "MOV ax, 1 ; uno\n"
+        );
+    }
+}